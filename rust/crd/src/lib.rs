@@ -1,20 +1,97 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     time::Duration,
 };
 
-use k8s_openapi::{api::core::v1::ObjectReference, apimachinery::pkg::apis::meta::v1::Condition};
+use k8s_openapi::{
+    api::core::v1::ObjectReference,
+    apimachinery::pkg::apis::meta::v1::{Condition, Time},
+    chrono::Utc,
+};
 use kube::{
     api::{DynamicObject, GroupVersionKind},
     core::gvk::ParseGroupVersionError,
-    CustomResource, ResourceExt,
+    CustomResource, CustomResourceExt, ResourceExt,
 };
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use snafu::{OptionExt, ResultExt, Snafu};
 
 pub const APP_NAME: &str = "kcl-instance";
 
+/// Condition type summarizing whether the last reconciliation succeeded.
+pub const READY_CONDITION: &str = "Ready";
+
+/// Reason set when the instance was reconciled and is requeued after `interval`.
+pub const RECONCILIATION_SUCCEEDED_REASON: &str = "ReconciliationSucceeded";
+/// Reason set when the reconciliation failed and is retried after `retryInterval`.
+pub const RECONCILIATION_FAILED_REASON: &str = "ReconciliationFailed";
+/// Reason set when the reconciliation did not finish within `timeout`.
+pub const RECONCILE_TIMEOUT_REASON: &str = "ReconcileTimeout";
+/// Reason set when the KCL module failed to compile, e.g. on syntax or type errors.
+pub const MODULE_COMPILE_FAILED_REASON: &str = "ModuleCompileFailed";
+/// Reason set when imports or dependencies of the KCL module could not be resolved.
+pub const MODULE_RESOLUTION_FAILED_REASON: &str = "ModuleResolutionFailed";
+/// Prefix of the KCL arguments holding source artifact metadata.
+pub const ARTIFACT_METADATA_ARGUMENT_PREFIX: &str = "artifact.";
+/// Reason set when the KCL module emitted objects for a namespace the instance may not target.
+pub const NAMESPACE_NOT_ALLOWED_REASON: &str = "NamespaceNotAllowed";
+/// Reason set when an apply hook rejected the rendered objects.
+pub const VALIDATION_FAILED_REASON: &str = "ValidationFailed";
+/// Reason set when the spec itself is invalid, e.g. `path` does not exist in the source.
+/// The instance is not retried until it changes.
+pub const INVALID_SPEC_REASON: &str = "InvalidSpec";
+
+/// Condition type set while the instance failed with a permanent error in the KCL module, e.g. a
+/// syntax or type error. The instance is only reconciled again once it or its source changes.
+pub const STALLED_CONDITION: &str = "Stalled";
+/// Reason of the `Stalled` condition set once the instance failed more consecutive times than
+/// the operator retries. The instance is only reconciled again once its spec or annotations change.
+pub const RETRIES_EXHAUSTED_REASON: &str = "RetriesExhausted";
+
+/// Reason of the `Ready` condition when the instance was reconciled, but some of its
+/// `readyConditions` are not True.
+pub const READY_CONDITIONS_UNMET_REASON: &str = "ReadyConditionsUnmet";
+
+/// Condition type listing the objects that failed to apply, set when `continueOnError` is enabled.
+pub const APPLIED_CONDITION: &str = "Applied";
+/// Reason set when every rendered object was applied.
+pub const OBJECTS_APPLIED_REASON: &str = "ObjectsApplied";
+/// Reason set when some objects failed to apply while the others were applied.
+pub const OBJECTS_FAILED_REASON: &str = "ObjectsFailed";
+
+/// Condition type mirroring the `Ready` condition of the Flux source of the instance, telling
+/// source failures apart from render and apply failures reported in `Ready`.
+pub const SOURCE_READY_CONDITION: &str = "SourceReady";
+/// Reason of the `SourceReady` condition when the source does not exist.
+pub const SOURCE_NOT_FOUND_REASON: &str = "SourceNotFound";
+/// Reason of the `SourceReady` condition when the source reports no reason of its own, e.g.
+/// before its first reconciliation.
+pub const SOURCE_NOT_READY_REASON: &str = "SourceNotReady";
+/// Reason of the `SourceReady` condition of instances whose module is read from a ConfigMap,
+/// which is ready as soon as it exists.
+pub const SOURCE_CONFIG_MAP_REASON: &str = "ConfigMapSource";
+
+/// Annotation of rendered objects that are never deleted by the operator when set to
+/// `disabled`, neither when they are pruned nor when the instance is deleted, e.g. for volumes
+/// holding data.
+pub const PRUNE_ANNOTATION: &str = "kcl.evrone.com/prune";
+/// Value of `PRUNE_ANNOTATION` protecting an object from deletion.
+pub const PRUNE_DISABLED: &str = "disabled";
+
+/// Annotation switching an instance to plan mode when set to `true`: the module is rendered and
+/// dry-run applied, and the changes are reported without touching the cluster.
+pub const PLAN_ANNOTATION: &str = "kcl.evrone.com/plan";
+/// Condition type summarizing the changes planned in plan mode, see `PLAN_ANNOTATION`.
+pub const PLAN_CONDITION: &str = "Planned";
+/// Reason set when the changes of an instance in plan mode were computed.
+pub const PLAN_SUCCEEDED_REASON: &str = "PlanSucceeded";
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
 #[derive(Snafu, Debug)]
 pub enum Error {
     #[snafu(display("object has no namespace associated"))]
@@ -25,6 +102,12 @@ pub enum Error {
 
     #[snafu(display("Failed to parse GVK: {}", source))]
     FailedToParseGvk { source: ParseGroupVersionError },
+
+    #[snafu(display("Failed to serialize CRD: {}", source))]
+    SerializeCrd { source: serde_json::Error },
+
+    #[snafu(display("CRD schema does not match the serde representation: {}", mismatches))]
+    SchemaMismatch { mismatches: String },
 }
 
 #[derive(Clone, CustomResource, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
@@ -48,6 +131,24 @@ pub struct KclInstanceSpec {
 
     pub suspend: Option<bool>,
     pub interval: Option<String>,
+
+    /// Prune enables garbage collection of the objects that are no longer rendered.
+    /// Defaults to true.
+    pub prune: Option<bool>,
+
+    /// RetryInterval is the interval at which to retry a failed reconciliation.
+    /// Defaults to ‘interval’ when not set. It doubles on consecutive failures, up to ‘interval’.
+    pub retry_interval: Option<String>,
+
+    /// Timeout for the whole reconciliation (download, render and apply).
+    /// Defaults to ‘10m’.
+    pub timeout: Option<String>,
+
+    /// ServiceAccountName is the name of a ServiceAccount in the namespace of the instance to
+    /// impersonate when applying the rendered objects, so they are authorized by its RBAC rather
+    /// than the operator's. The status, finalizer, prune and cleanup requests keep the
+    /// operator's identity. Defaults to the operator's identity.
+    pub service_account_name: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize, Default)]
@@ -58,6 +159,317 @@ pub struct KclInstanceConfig {
     pub show_hidden: bool,
     pub arguments: HashMap<String, String>,
     pub arguments_from: Vec<ArgumentsReference>,
+
+    /// ArgumentTypes declares the type of arguments by name, valid values are
+    /// (‘string’, ‘int’, ‘float’, ‘bool’). Values are checked and passed to KCL with that type,
+    /// e.g. a ‘string’ argument "007" stays a string. Undeclared arguments are passed as they are.
+    #[serde(default)]
+    pub argument_types: HashMap<String, ArgumentType>,
+
+    /// Entries lists the entry files of the module relative to ‘path’, overriding the entries
+    /// of the `kcl.mod` profile. Glob patterns such as ‘k8s/*.k’ expand to the matching files
+    /// and must match at least one. Defaults to ‘[]’, which uses the profile entries.
+    #[serde(default)]
+    pub entries: Vec<String>,
+
+    /// Overrides lists KCL override expressions applied to the module at compile time, like
+    /// `kcl -O`, e.g. ‘app.replicas=3’ or ‘__main__:app.labels-’ to delete a field.
+    /// Defaults to ‘[]’.
+    #[serde(default)]
+    pub overrides: Vec<String>,
+
+    /// ExternalPackages maps a KCL package name to a local path overriding the package
+    /// resolved from `kcl.mod`. Overrides always take precedence over resolved dependencies.
+    #[serde(default)]
+    pub external_packages: HashMap<String, String>,
+
+    /// ExtractIgnore lists gitignore-style patterns (e.g. ‘docs/’ or ‘*_test.k’) of the source
+    /// files that are not extracted, to save disk space and speed up large sources. Paths are
+    /// relative to the root of the source. Defaults to ‘[]’.
+    #[serde(default)]
+    pub extract_ignore: Vec<String>,
+
+    /// SourceHost overrides the operator's `SOURCE_HOST` for the downloads of this instance's
+    /// artifacts, e.g. ‘http://source-proxy.flux-system.svc’ for sources that must be proxied.
+    /// The path of the artifact URL is appended to the path of the host. Defaults to the
+    /// operator's setting.
+    pub source_host: Option<String>,
+
+    /// GitSparsePaths maps the name of a git dependency from `kcl.mod` to the subdirectory of
+    /// its repository to check out, so only that part of large monorepos is fetched.
+    #[serde(default)]
+    pub git_sparse_paths: HashMap<String, String>,
+
+    /// SemverFilter is a regex restricting the registry tags considered when resolving
+    /// dependencies declared with a semver range (e.g. `k8s = "^1.31"`).
+    pub semver_filter: Option<String>,
+
+    /// FieldManager overrides the server-side apply field manager used when applying
+    /// the rendered objects. Defaults to ‘kcl-instance-controller’.
+    /// The `app.kubernetes.io/managed-by` label is always set to the operator name, so
+    /// prune and cleanup keep working regardless of the chosen field manager.
+    pub field_manager: Option<String>,
+
+    /// Force makes the server-side apply take ownership of conflicting fields owned by
+    /// other field managers (e.g. Flux Kustomize or Helm controllers).
+    /// Defaults to false.
+    #[serde(default)]
+    pub force: bool,
+
+    /// ContinueOnError keeps applying the other rendered objects when one fails to apply. The
+    /// failed objects and their errors are listed in the ‘Applied’ condition, and are retried at
+    /// the next reconciliation. Defaults to false, which stops the apply at the first failure.
+    #[serde(default)]
+    pub continue_on_error: bool,
+
+    /// ApplyTimeout bounds the apply of each rendered object, e.g. ‘30s’, so an object held up
+    /// by a slow admission webhook fails on its own instead of stalling the whole apply, and
+    /// with ‘continueOnError’ the other objects are still applied. Defaults to no bound besides
+    /// ‘timeout’.
+    pub apply_timeout: Option<String>,
+
+    /// Atomic rolls back the objects applied in a reconciliation when another one fails to
+    /// apply: the objects it created are deleted and the ones it changed are restored to their
+    /// prior state, best effort. It takes precedence over ‘continueOnError’. Defaults to false,
+    /// which leaves the objects applied before the failure.
+    #[serde(default)]
+    pub atomic: bool,
+
+    /// ReadyConditions lists the conditions, valid values are (‘SourceReady’, ‘Applied’), that
+    /// must also be True for ‘Ready’ to be True once the instance was reconciled, e.g. so that
+    /// ‘kubectl wait --for=condition=Ready’ waits for the source too. A listed condition that is
+    /// not set holds ‘Ready’ back. Defaults to ‘[]’, where ‘Ready’ only reflects the
+    /// reconciliation.
+    #[serde(default)]
+    pub ready_conditions: Vec<ReadyConditionType>,
+
+    /// WaitForDeletion makes the deletion of the instance wait until the objects it deleted are
+    /// gone from the cluster, e.g. once their own finalizers ran, before the instance itself is
+    /// removed. The wait is bounded by ‘timeout’. Defaults to false.
+    #[serde(default)]
+    pub wait_for_deletion: bool,
+
+    /// SyncStrategy is how the rendered objects are written to the cluster, valid values are
+    /// (‘apply’, ‘replace’, ‘create-if-absent’). ‘apply’ uses server-side apply, ‘replace’
+    /// replaces whole objects, e.g. when server-side apply fails on immutable field conflicts,
+    /// and ‘create-if-absent’ creates missing objects but never updates existing ones.
+    /// Defaults to ‘apply’.
+    #[serde(default)]
+    pub sync_strategy: SyncStrategy,
+
+    /// SyncStrategies overrides ‘syncStrategy’ for the rendered objects matching a target
+    /// selector. The first matching rule wins. Defaults to ‘[]’.
+    #[serde(default)]
+    pub sync_strategies: Vec<SyncStrategyRule>,
+
+    /// AllowedNamespaces restricts the namespaces the rendered namespaced objects may target.
+    /// Objects for any other namespace are rejected before anything is applied.
+    /// Defaults to ‘[]’, which allows every namespace unless the operator runs in
+    /// same-namespace-only mode.
+    #[serde(default)]
+    pub allowed_namespaces: Vec<String>,
+
+    /// NamespaceTemplate computes the namespace the rendered namespaced objects are applied to,
+    /// overriding their own, e.g. ‘tenant-{name}’. ‘{name}’ and ‘{namespace}’ expand to the name
+    /// and namespace of the instance, and ‘{labels.<key>}’ to the value of one of its labels.
+    /// The result must be a valid namespace name, and is subject to ‘allowedNamespaces’.
+    /// Defaults to the ‘--namespace-template’ of the operator, if any.
+    pub namespace_template: Option<String>,
+
+    /// ArtifactMetadata lists the source artifact metadata keys (e.g. OCI annotations such as
+    /// `org.opencontainers.image.revision`) passed to the KCL program as arguments, named after
+    /// the key prefixed with `artifact.`. Keys missing from the artifact are skipped, and explicit
+    /// arguments with the same name take precedence.
+    #[serde(default)]
+    pub artifact_metadata: Vec<String>,
+
+    /// UnmanagedFields lists paths of fields, in dot notation (e.g. ‘spec.clusterIP’), removed
+    /// from every rendered object before it is applied, so the operator does not take ownership
+    /// of fields assigned by other controllers. A ‘*’ segment matches every item of a list,
+    /// e.g. ‘spec.ports.*.nodePort’. Defaults to ‘[]’.
+    #[serde(default)]
+    pub unmanaged_fields: Vec<String>,
+
+    /// Ignore lists selectors of rendered objects that are neither applied nor tracked in the
+    /// inventory, e.g. helper objects not wanted in an environment. Objects that are still
+    /// rendered but ignored are not pruned either. Defaults to ‘[]’.
+    #[serde(default)]
+    pub ignore: Vec<ObjectSelector>,
+
+    /// ApiVersions maps a kind to the apiVersion of the rendered objects of that kind whose
+    /// apiVersion has no group (e.g. ‘v1’), for kinds served by several groups, e.g.
+    /// ‘Certificate: cert-manager.io/v1’. Without a mapping, such objects of a kind the core
+    /// group does not serve take the single group serving it, and fail when there are several.
+    /// Defaults to ‘{}’.
+    #[serde(default)]
+    pub api_versions: HashMap<String, String>,
+
+    /// Include lists selectors restricting the applied objects to the rendered objects matching
+    /// one of them, e.g. to apply only the Deployments of a module with this instance and the
+    /// rest with another. Other objects are skipped like ignored ones, and ‘ignore’ takes
+    /// precedence. Defaults to ‘[]’, which includes every object.
+    #[serde(default)]
+    pub include: Vec<ObjectSelector>,
+
+    /// ValuesFiles lists YAML files, relative to ‘path’ in the source, whose top-level keys are
+    /// passed to the KCL program as arguments. Later files override earlier ones, and
+    /// ‘arguments’ and ‘argumentsFrom’ override the files. A missing file fails the
+    /// reconciliation. Defaults to ‘[]’.
+    #[serde(default)]
+    pub values_files: Vec<String>,
+
+    /// ExportRendered records the rendered output of every applied revision in a ConfigMap or
+    /// Secret in the namespace of the instance, for audit. Defaults to no export.
+    pub export_rendered: Option<RenderedExport>,
+}
+
+impl KclInstanceConfig {
+    /// Whether the rendered `object` matches one of the `ignore` selectors.
+    pub fn is_ignored(&self, object: &DynamicObject) -> bool {
+        self.ignore.iter().any(|selector| selector.matches(object))
+    }
+
+    /// Whether the rendered `object` is selected by `include`, which includes every object
+    /// when empty.
+    pub fn is_included(&self, object: &DynamicObject) -> bool {
+        self.include.is_empty() || self.include.iter().any(|selector| selector.matches(object))
+    }
+
+    /// Whether the rendered `object` is neither applied nor tracked: it is ignored, or not
+    /// included.
+    pub fn is_skipped(&self, object: &DynamicObject) -> bool {
+        self.is_ignored(object) || !self.is_included(object)
+    }
+
+    /// Strategy the rendered `object` is written with, from the first `syncStrategies` rule
+    /// it matches, or `syncStrategy`.
+    pub fn sync_strategy_of(&self, object: &DynamicObject) -> SyncStrategy {
+        self.sync_strategies
+            .iter()
+            .find(|rule| rule.target.matches(object))
+            .map_or(self.sync_strategy, |rule| rule.strategy)
+    }
+}
+
+/// How rendered objects are written to the cluster.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncStrategy {
+    /// Server-side apply.
+    #[default]
+    Apply,
+    /// Replaces the whole object, creating it when missing.
+    Replace,
+    /// Creates the object when missing, and leaves it as it is otherwise.
+    CreateIfAbsent,
+}
+
+/// Object the rendered output of an instance is recorded in.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedExport {
+    /// Kind of the object, valid values are (‘ConfigMap’, ‘Secret’). Secrets suit manifests
+    /// holding sensitive values. Defaults to ‘ConfigMap’.
+    #[serde(default)]
+    pub kind: RenderedExportKind,
+
+    /// Name of the object. Defaults to the name of the instance suffixed with ‘-rendered’.
+    pub name: Option<String>,
+
+    /// Encrypt the output with the export encryption key of the operator. Only Secrets may be
+    /// encrypted. Defaults to false.
+    #[serde(default)]
+    pub encrypt: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+pub enum RenderedExportKind {
+    #[default]
+    ConfigMap,
+    Secret,
+}
+
+/// Condition set by the operator that `readyConditions` may gate `Ready` on.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+pub enum ReadyConditionType {
+    SourceReady,
+    Applied,
+}
+
+impl ReadyConditionType {
+    /// Type of the condition in the status.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadyConditionType::SourceReady => SOURCE_READY_CONDITION,
+            ReadyConditionType::Applied => APPLIED_CONDITION,
+        }
+    }
+}
+
+/// Sync strategy of the rendered objects matching a selector.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStrategyRule {
+    /// Target selects the objects the strategy applies to.
+    pub target: ObjectSelector,
+
+    /// Strategy of the selected objects, valid values are (‘apply’, ‘replace’,
+    /// ‘create-if-absent’).
+    pub strategy: SyncStrategy,
+}
+
+/// Selects rendered objects. Every field that is set must match.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectSelector {
+    /// Group of the objects, ‘’ for the core group.
+    pub group: Option<String>,
+
+    /// Version of the objects, e.g. ‘v1’.
+    pub version: Option<String>,
+
+    /// Kind of the objects, e.g. ‘ConfigMap’.
+    pub kind: Option<String>,
+
+    /// Name of the objects.
+    pub name: Option<String>,
+
+    /// Labels the objects must have.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl ObjectSelector {
+    /// Whether `object` matches the selector.
+    pub fn matches(&self, object: &DynamicObject) -> bool {
+        let gvk = object
+            .types
+            .as_ref()
+            .and_then(|types| GroupVersionKind::try_from(types).ok());
+        let field_matches = |expected: &Option<String>, actual: Option<&str>| {
+            expected
+                .as_deref()
+                .is_none_or(|expected| Some(expected) == actual)
+        };
+
+        field_matches(&self.group, gvk.as_ref().map(|gvk| gvk.group.as_str()))
+            && field_matches(&self.version, gvk.as_ref().map(|gvk| gvk.version.as_str()))
+            && field_matches(&self.kind, gvk.as_ref().map(|gvk| gvk.kind.as_str()))
+            && field_matches(&self.name, object.metadata.name.as_deref())
+            && self
+                .labels
+                .iter()
+                .all(|(key, value)| object.labels().get(key) == Some(value))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgumentType {
+    String,
+    Int,
+    Float,
+    Bool,
 }
 
 #[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
@@ -76,10 +488,12 @@ pub struct ArgumentsReference {
     pub kind: ArgumentsReferenceKind,
 
     /// ArgumentsKey is the data key where the arguments.yaml or a specific value can be found at.
-    /// Defaults to ‘arguments.yaml’.
+    /// A YAML/JSON mapping found at the key is flattened into one argument per top-level key,
+    /// nested values are passed as JSON.
+    /// Defaults to ‘None’, which results in every key of the referent becoming an argument.
     pub arguments_key: Option<String>,
 
-    /// TargetPath is the YAML dot notation path the value should be merged at.
+    /// TargetPath is the argument name the value of ArgumentsKey is passed as.
     /// When set, the ArgumentsKey is expected to be a single flat value.
     /// Defaults to ‘None’, which results in the values getting merged at the root.
     pub target_path: Option<String>,
@@ -92,7 +506,52 @@ pub struct ArgumentsReference {
     pub optional: bool,
 }
 
-#[derive(Deserialize, Serialize, Clone, Default, Debug, Eq, PartialEq, Hash, JsonSchema)]
+/// Outcome of the resolution of an ArgumentsReference.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+pub enum ArgSourceState {
+    /// The referent was found and its arguments were passed to the module.
+    Resolved,
+    /// The optional referent does not exist, no arguments were passed from it.
+    OptionalMissing,
+    /// The referent could not be resolved, see the message.
+    Error,
+}
+
+/// Resolution status of an ArgumentsReference, in the order of `argumentsFrom`.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArgSourceStatus {
+    /// Name of the values referent.
+    pub name: String,
+
+    /// Kind of the values referent.
+    pub kind: ArgumentsReferenceKind,
+
+    /// Outcome of the resolution.
+    pub state: ArgSourceState,
+
+    /// Why the resolution failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl ArgSourceStatus {
+    /// Status of `arg_ref` in the given state.
+    pub fn new(
+        arg_ref: &ArgumentsReference,
+        state: ArgSourceState,
+        message: Option<String>,
+    ) -> Self {
+        ArgSourceStatus {
+            name: arg_ref.name.clone(),
+            kind: arg_ref.kind.clone(),
+            state,
+            message,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Gvk {
     pub name: String,
@@ -100,6 +559,32 @@ pub struct Gvk {
     pub version: String,
     pub kind: String,
     pub namespace: Option<String>,
+    /// Version of the object as last applied, telling whether the next apply changed it. Left
+    /// out of the comparison of entries, which designate the same object whatever its version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_version: Option<String>,
+}
+
+impl PartialEq for Gvk {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.group == other.group
+            && self.version == other.version
+            && self.kind == other.kind
+            && self.namespace == other.namespace
+    }
+}
+
+impl Eq for Gvk {}
+
+impl std::hash::Hash for Gvk {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.group.hash(state);
+        self.version.hash(state);
+        self.kind.hash(state);
+        self.namespace.hash(state);
+    }
 }
 
 /// An unique identifier for a Kubernetes resource within a deployment.
@@ -114,6 +599,29 @@ pub struct Gvk {
 ///
 /// Used to track resources managed by a `KclInstance` controller.
 
+impl Gvk {
+    /// The `apiVersion` of the object, e.g. `apps/v1`, or just the version for the core group.
+    pub fn api_version(&self) -> String {
+        if self.group.is_empty() {
+            self.version.clone()
+        } else {
+            format!("{}/{}", self.group, self.version)
+        }
+    }
+}
+
+impl From<&Gvk> for ObjectReference {
+    fn from(val: &Gvk) -> Self {
+        ObjectReference {
+            api_version: Some(val.api_version()),
+            kind: Some(val.kind.clone()),
+            name: Some(val.name.clone()),
+            namespace: val.namespace.clone(),
+            ..ObjectReference::default()
+        }
+    }
+}
+
 impl From<Gvk> for GroupVersionKind {
     fn from(val: Gvk) -> Self {
         GroupVersionKind {
@@ -139,11 +647,12 @@ impl TryFrom<DynamicObject> for Gvk {
             version: g_gvk.version,
             kind: g_gvk.kind,
             namespace: value.namespace(),
+            resource_version: value.metadata.resource_version,
         })
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct KclInstanceStatus {
     #[serde(default)]
@@ -153,15 +662,108 @@ pub struct KclInstanceStatus {
     pub last_applied_revision: Option<String>,
     pub last_attempted_revision: Option<String>,
 
+    /// Hash of the source revision, resolved arguments and render configuration
+    /// of the last successful apply.
+    pub last_applied_config_hash: Option<String>,
+
+    /// Whether the last reconciliation created, changed or pruned objects.
+    #[serde(default)]
+    pub changed: bool,
+
+    /// Time of the last reconciliation that created, changed or pruned objects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_changed_time: Option<Time>,
+
     /// Conditions holds the conditions for the KclInstance.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub conditions: Option<Vec<Condition>>,
+
+    /// Resolution status of each ArgumentsReference of the last reconciliation.
+    #[serde(default)]
+    pub arguments_status: Vec<ArgSourceStatus>,
+
+    /// Consecutive failed reconciliations retried by the operator, reset by a successful one.
+    #[serde(default)]
+    pub failure_count: u32,
 }
 
 impl KclInstanceStatus {
+    /// Returns the condition of the given type, if set.
+    pub fn condition(&self, type_: &str) -> Option<&Condition> {
+        self.conditions
+            .as_ref()?
+            .iter()
+            .find(|condition| condition.type_ == type_)
+    }
+
+    /// Describes the conditions among `types` that are not True, e.g. for `readyConditions`.
+    ///
+    /// # Returns
+    /// One description per unmet condition, e.g. `SourceReady is False (SourceNotReady)` or
+    /// `Applied is not set`, in the order of `types`
+    pub fn unmet_conditions(&self, types: &[ReadyConditionType]) -> Vec<String> {
+        types
+            .iter()
+            .map(ReadyConditionType::as_str)
+            .filter_map(|type_| match self.condition(type_) {
+                None => Some(format!("{type_} is not set")),
+                Some(condition) if condition.status == "True" => None,
+                Some(condition) => Some(format!(
+                    "{type_} is {} ({})",
+                    condition.status, condition.reason
+                )),
+            })
+            .collect()
+    }
+
+    /// Sets the condition of the given type, replacing any existing condition of that type.
+    /// The transition time is kept when the condition status did not change.
+    pub fn set_condition(
+        &mut self,
+        type_: &str,
+        status: bool,
+        reason: &str,
+        message: impl Into<String>,
+        generation: i64,
+    ) {
+        let status = if status { "True" } else { "False" }.to_string();
+        let conditions = self.conditions.get_or_insert_with(Vec::new);
+        let last_transition_time = conditions
+            .iter()
+            .find(|c| c.type_ == type_ && c.status == status)
+            .map(|c| c.last_transition_time.clone())
+            .unwrap_or_else(|| Time(Utc::now()));
+
+        conditions.retain(|c| c.type_ != type_);
+        conditions.push(Condition {
+            type_: type_.to_string(),
+            status,
+            reason: reason.to_string(),
+            message: message.into(),
+            observed_generation: Some(generation),
+            last_transition_time,
+        });
+    }
+
+    /// Removes the condition of the given type, if any.
+    pub fn remove_condition(&mut self, type_: &str) {
+        if let Some(conditions) = self.conditions.as_mut() {
+            conditions.retain(|c| c.type_ != type_);
+        }
+    }
+
+    /// Records whether the reconciliation changed anything, keeping the time of the last change.
+    pub fn record_change(&mut self, changed: bool) {
+        self.changed = changed;
+        if changed {
+            self.last_changed_time = Some(Time(Utc::now()));
+        }
+    }
+
     pub fn register_applied(&mut self, objects: Vec<DynamicObject>) -> Result<(), Error> {
         for object in objects {
-            self.inventory.insert(object.try_into()?);
+            // Replaced, so the entry holds the resourceVersion of the last apply
+            self.inventory.replace(object.try_into()?);
         }
         Ok(())
     }
@@ -170,9 +772,435 @@ impl KclInstanceStatus {
 impl KclInstance {
     pub fn interval(&self) -> std::time::Duration {
         if let Some(interval) = &self.spec.interval {
-            humantime::parse_duration(interval).unwrap_or(Duration::from_secs(10))
+            humantime::parse_duration(interval).unwrap_or(DEFAULT_INTERVAL)
+        } else {
+            DEFAULT_INTERVAL
+        }
+    }
+
+    /// Whether the instance is in plan mode, see `PLAN_ANNOTATION`.
+    pub fn is_plan(&self) -> bool {
+        self.annotations()
+            .get(PLAN_ANNOTATION)
+            .is_some_and(|value| value == "true")
+    }
+
+    /// Whether objects that are no longer rendered are deleted.
+    pub fn prune(&self) -> bool {
+        self.spec.prune.unwrap_or(true)
+    }
+
+    /// Interval to requeue after a failed reconciliation, falling back to `interval`.
+    pub fn retry_interval(&self) -> std::time::Duration {
+        self.spec
+            .retry_interval
+            .as_deref()
+            .and_then(|interval| humantime::parse_duration(interval).ok())
+            .unwrap_or_else(|| self.interval())
+    }
+
+    /// Computes a stable hash of everything that determines the rendered output: the source
+    /// revision, the resolved arguments and the render configuration.
+    ///
+    /// Arguments resolved from Secrets or ConfigMaps can change without a new source revision,
+    /// so comparing this hash catches argument-driven changes as well.
+    pub fn config_hash(&self, revision: &str, args: &HashMap<String, String>) -> String {
+        let config = &self.spec.config;
+        let mut hasher = Sha256::new();
+        hasher.update(revision);
+        hasher.update([0u8]);
+        hasher.update(&self.spec.path);
+        hasher.update([0u8]);
+        for (key, value) in args.iter().collect::<BTreeMap<_, _>>() {
+            hasher.update(format!("arg:{key}={value}"));
+            hasher.update([0u8]);
+        }
+        for (key, type_) in config.argument_types.iter().collect::<BTreeMap<_, _>>() {
+            hasher.update(format!("type:{key}={type_:?}"));
+            hasher.update([0u8]);
+        }
+        for pattern in &config.extract_ignore {
+            hasher.update(format!("extractIgnore:{pattern}"));
+            hasher.update([0u8]);
+        }
+        for file in &config.values_files {
+            hasher.update(format!("values:{file}"));
+            hasher.update([0u8]);
+        }
+        for (name, path) in config.external_packages.iter().collect::<BTreeMap<_, _>>() {
+            hasher.update(format!("pkg:{name}={path}"));
+            hasher.update([0u8]);
+        }
+        hasher.update(format!(
+            "vendor={},sortKeys={},showHidden={}",
+            config.vendor, config.sort_keys, config.show_hidden
+        ));
+        format!("sha256:{:x}", hasher.finalize())
+    }
+
+    /// Checks that the published CRD schema agrees with how `KclInstanceConfig` and
+    /// `ArgumentsReference` are (de)serialized: every field must be present in the schema with
+    /// a matching type, and be required exactly when serde can't deserialize without it.
+    ///
+    /// This catches `#[serde(default)]`/`Option` mismatches between the structs and the schema.
+    pub fn validate_schema() -> Result<(), Error> {
+        let crd = serde_json::to_value(Self::crd()).context(SerializeCrdSnafu)?;
+        let config_pointer =
+            "/spec/versions/0/schema/openAPIV3Schema/properties/spec/properties/config";
+        let config_schema = crd.pointer(config_pointer).context(MissingObjectKeySnafu {
+            key: config_pointer,
+        })?;
+        let arguments_from_schema = config_schema
+            .pointer("/properties/argumentsFrom/items")
+            .context(MissingObjectKeySnafu {
+                key: "config/argumentsFrom/items",
+            })?;
+
+        let mut mismatches = vec![];
+        check_schema::<KclInstanceConfig>(
+            "config",
+            config_schema,
+            &serde_json::to_value(KclInstanceConfig::default()).context(SerializeCrdSnafu)?,
+            &mut mismatches,
+        );
+        check_schema::<ArgumentsReference>(
+            "config.argumentsFrom[]",
+            arguments_from_schema,
+            &serde_json::to_value(ArgumentsReference {
+                name: "values".to_string(),
+                kind: ArgumentsReferenceKind::ConfigMap,
+                arguments_key: None,
+                target_path: None,
+                optional: false,
+            })
+            .context(SerializeCrdSnafu)?,
+            &mut mismatches,
+        );
+
+        if mismatches.is_empty() {
+            Ok(())
         } else {
-            Duration::from_secs(10)
+            SchemaMismatchSnafu {
+                mismatches: mismatches.join("; "),
+            }
+            .fail()
         }
     }
+
+    /// Upper bound for a single reconciliation.
+    pub fn timeout(&self) -> std::time::Duration {
+        self.spec
+            .timeout
+            .as_deref()
+            .and_then(|timeout| humantime::parse_duration(timeout).ok())
+            .unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    /// Bound of the apply of each rendered object, see `applyTimeout`, None when unset or
+    /// invalid.
+    pub fn apply_timeout(&self) -> Option<std::time::Duration> {
+        self.spec
+            .config
+            .apply_timeout
+            .as_deref()
+            .and_then(|timeout| humantime::parse_duration(timeout).ok())
+    }
+
+    /// The objects managed by the instance, as references usable with the `kube` APIs.
+    /// They are sorted by `apiVersion`, kind, namespace and name, so the order is stable.
+    pub fn inventory_refs(&self) -> Vec<ObjectReference> {
+        let Some(status) = &self.status else {
+            return Vec::new();
+        };
+        let mut refs: Vec<ObjectReference> =
+            status.inventory.iter().map(ObjectReference::from).collect();
+        refs.sort_by(|a, b| {
+            (&a.api_version, &a.kind, &a.namespace, &a.name).cmp(&(
+                &b.api_version,
+                &b.kind,
+                &b.namespace,
+                &b.name,
+            ))
+        });
+        refs
+    }
+}
+
+/// Compares the schema of an object with a serialized `sample` of `T`, recording mismatches.
+fn check_schema<T: DeserializeOwned>(
+    name: &str,
+    schema: &Value,
+    sample: &Value,
+    mismatches: &mut Vec<String>,
+) {
+    let (Some(properties), Some(sample)) = (
+        schema.get("properties").and_then(Value::as_object),
+        sample.as_object(),
+    ) else {
+        mismatches.push(format!("{name} is not an object"));
+        return;
+    };
+    let required: HashSet<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    for (field, value) in sample {
+        let Some(property) = properties.get(field) else {
+            mismatches.push(format!("{name}.{field} is missing from the schema"));
+            continue;
+        };
+        if !schema_accepts(property, value) {
+            mismatches.push(format!(
+                "{name}.{field} serializes as {value} which the schema does not accept"
+            ));
+        }
+
+        let mut without = sample.clone();
+        without.remove(field);
+        let optional = serde_json::from_value::<T>(Value::Object(without)).is_ok();
+        if optional == required.contains(field.as_str()) {
+            mismatches.push(format!(
+                "{name}.{field} is {} in the schema but {} for serde",
+                if optional { "required" } else { "optional" },
+                if optional { "optional" } else { "required" },
+            ));
+        }
+    }
+
+    for field in properties
+        .keys()
+        .filter(|field| !sample.contains_key(*field))
+    {
+        mismatches.push(format!(
+            "{name}.{field} is in the schema but never serialized"
+        ));
+    }
+}
+
+/// Whether a property schema accepts the JSON type of `value`.
+fn schema_accepts(property: &Value, value: &Value) -> bool {
+    let type_ = property.get("type").and_then(Value::as_str);
+    match value {
+        Value::Null => property.get("nullable") == Some(&Value::Bool(true)),
+        Value::Bool(_) => type_ == Some("boolean"),
+        Value::Number(_) => matches!(type_, Some("integer" | "number")),
+        Value::String(_) => type_ == Some("string"),
+        Value::Array(_) => type_ == Some("array"),
+        Value::Object(_) => type_ == Some("object"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance() -> KclInstance {
+        KclInstance::new(
+            "test",
+            KclInstanceSpec {
+                source: ObjectReference::default(),
+                path: "examples/app".to_string(),
+                config: KclInstanceConfig::default(),
+                suspend: None,
+                interval: None,
+                prune: None,
+                retry_interval: None,
+                timeout: None,
+                service_account_name: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_inventory_refs() {
+        let mut instance = instance();
+        assert!(instance.inventory_refs().is_empty());
+
+        let gvk = |group: &str, kind: &str, name: &str, namespace: Option<&str>| Gvk {
+            name: name.to_string(),
+            group: group.to_string(),
+            version: "v1".to_string(),
+            kind: kind.to_string(),
+            namespace: namespace.map(str::to_string),
+            resource_version: None,
+        };
+        instance.status = Some(KclInstanceStatus {
+            inventory: HashSet::from([
+                gvk("apps", "Deployment", "app", Some("default")),
+                gvk("", "ConfigMap", "config", Some("default")),
+                gvk("", "Namespace", "default", None),
+            ]),
+            ..KclInstanceStatus::default()
+        });
+
+        let refs: Vec<_> = instance
+            .inventory_refs()
+            .into_iter()
+            .map(|r| (r.api_version, r.kind, r.namespace, r.name))
+            .collect();
+        let expected = |api_version: &str, kind: &str, namespace: Option<&str>, name: &str| {
+            (
+                Some(api_version.to_string()),
+                Some(kind.to_string()),
+                namespace.map(str::to_string),
+                Some(name.to_string()),
+            )
+        };
+        assert_eq!(
+            refs,
+            [
+                expected("apps/v1", "Deployment", Some("default"), "app"),
+                expected("v1", "ConfigMap", Some("default"), "config"),
+                expected("v1", "Namespace", None, "default"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        KclInstance::validate_schema().unwrap();
+    }
+
+    #[test]
+    fn test_config_hash_is_stable() {
+        let args = HashMap::from([
+            ("env".to_string(), "dev".to_string()),
+            ("replicas".to_string(), "3".to_string()),
+        ]);
+        let instance = instance();
+        assert_eq!(
+            instance.config_hash("main@sha1:abc", &args),
+            instance.config_hash("main@sha1:abc", &args.clone())
+        );
+    }
+
+    #[test]
+    fn test_config_hash_changes_with_args_at_same_revision() {
+        let instance = instance();
+        let before = HashMap::from([("env".to_string(), "dev".to_string())]);
+        let after = HashMap::from([("env".to_string(), "prod".to_string())]);
+        assert_ne!(
+            instance.config_hash("main@sha1:abc", &before),
+            instance.config_hash("main@sha1:abc", &after)
+        );
+    }
+
+    #[test]
+    fn test_config_hash_changes_with_revision() {
+        let instance = instance();
+        let args = HashMap::new();
+        assert_ne!(
+            instance.config_hash("main@sha1:abc", &args),
+            instance.config_hash("main@sha1:def", &args)
+        );
+    }
+
+    #[test]
+    fn test_record_change_keeps_last_change_time() {
+        let mut status = KclInstanceStatus::default();
+        status.record_change(true);
+        assert!(status.changed);
+        let changed_at = status.last_changed_time.clone();
+        assert!(changed_at.is_some());
+
+        status.record_change(false);
+        assert!(!status.changed);
+        assert_eq!(status.last_changed_time, changed_at);
+    }
+
+    #[test]
+    fn test_object_selector_matches() {
+        let object: DynamicObject = serde_json::from_value(serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": "seed-db", "labels": { "env": "dev" } },
+        }))
+        .unwrap();
+        let selector = |value: Value| serde_json::from_value::<ObjectSelector>(value).unwrap();
+
+        assert!(selector(serde_json::json!({ "kind": "Job" })).matches(&object));
+        assert!(selector(serde_json::json!({
+            "group": "batch",
+            "name": "seed-db",
+            "labels": { "env": "dev" },
+        }))
+        .matches(&object));
+        assert!(!selector(serde_json::json!({ "group": "", "kind": "Job" })).matches(&object));
+        assert!(!selector(serde_json::json!({ "labels": { "env": "prod" } })).matches(&object));
+    }
+
+    #[test]
+    fn test_is_skipped() {
+        let object = |kind: &str, name: &str| -> DynamicObject {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "apps/v1",
+                "kind": kind,
+                "metadata": { "name": name },
+            }))
+            .unwrap()
+        };
+        let selectors =
+            |value: Value| serde_json::from_value::<Vec<ObjectSelector>>(value).unwrap();
+        let deployment = object("Deployment", "app");
+        let debug = object("Deployment", "debug");
+        let service = object("Service", "app");
+
+        // Everything is included by default
+        let mut config = KclInstanceConfig::default();
+        assert!(![&deployment, &debug, &service]
+            .iter()
+            .any(|object| config.is_skipped(object)));
+
+        config.include = selectors(serde_json::json!([{ "kind": "Deployment" }]));
+        assert!(!config.is_skipped(&deployment));
+        assert!(!config.is_skipped(&debug));
+        assert!(config.is_skipped(&service));
+
+        // Ignore wins over include
+        config.ignore = selectors(serde_json::json!([{ "name": "debug" }]));
+        assert!(!config.is_skipped(&deployment));
+        assert!(config.is_skipped(&debug));
+        assert!(config.is_skipped(&service));
+    }
+
+    #[test]
+    fn test_sync_strategy_of() {
+        let object = |api_version: &str, kind: &str| -> DynamicObject {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": api_version,
+                "kind": kind,
+                "metadata": { "name": "seed-db" },
+            }))
+            .unwrap()
+        };
+        let rules = serde_json::from_value(serde_json::json!([
+            { "target": { "kind": "Job" }, "strategy": "create-if-absent" },
+            { "target": { "group": "batch" }, "strategy": "apply" },
+        ]))
+        .unwrap();
+        let config = KclInstanceConfig {
+            sync_strategy: SyncStrategy::Replace,
+            sync_strategies: rules,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.sync_strategy_of(&object("batch/v1", "Job")),
+            SyncStrategy::CreateIfAbsent
+        );
+        assert_eq!(
+            config.sync_strategy_of(&object("batch/v1", "CronJob")),
+            SyncStrategy::Apply
+        );
+        assert_eq!(
+            config.sync_strategy_of(&object("v1", "Pod")),
+            SyncStrategy::Replace
+        );
+        assert_eq!(
+            KclInstanceConfig::default().sync_strategy,
+            SyncStrategy::Apply
+        );
+    }
 }
@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+/// Default number of consecutive failures of a source opening its circuit.
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+/// Default time downloads of a source fail fast once its circuit is open.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// State of the downloads of a single source.
+#[derive(Debug, Default)]
+struct Circuit {
+    /// Consecutive failed downloads.
+    failures: u32,
+    /// When the circuit opened, downloads fail fast until the cooldown elapsed.
+    opened_at: Option<Instant>,
+    /// Whether a download probes the recovery of the source after the cooldown.
+    probing: bool,
+}
+
+/// Circuit breaker of the downloads of each source.
+///
+/// After `threshold` consecutive failures of a source, its circuit opens and its downloads
+/// fail fast for the `cooldown`, instead of hitting an endpoint that is down. The circuit is
+/// then half-open: a single download probes the source, closing the circuit when it succeeds
+/// and reopening it for another cooldown when it fails.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreaker {
+    /// Breaker opening after `threshold` consecutive failures, disabled when it is 0.
+    pub(crate) fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a download of `source` may proceed.
+    ///
+    /// # Returns
+    /// `None` when it may, or the time left before the source is probed again when its
+    /// circuit is open
+    pub(crate) fn check(&self, source: &str) -> Option<Duration> {
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        let circuit = circuits.get_mut(source)?;
+        let opened_at = circuit.opened_at?;
+        let elapsed = opened_at.elapsed();
+        if elapsed < self.cooldown {
+            return Some(self.cooldown - elapsed);
+        }
+        if circuit.probing {
+            // Another download is probing the source, wait for its outcome
+            return Some(Duration::ZERO);
+        }
+        info!("Probing source {} after {:?}", source, self.cooldown);
+        circuit.probing = true;
+        None
+    }
+
+    /// Closes the circuit of `source` after a successful download.
+    pub(crate) fn record_success(&self, source: &str) {
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        if circuits
+            .remove(source)
+            .is_some_and(|circuit| circuit.opened_at.is_some())
+        {
+            info!("Source {} recovered", source);
+        }
+    }
+
+    /// Counts a failed download of `source`, opening its circuit at the threshold or when
+    /// the download probed its recovery.
+    pub(crate) fn record_failure(&self, source: &str) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        let circuit = circuits.entry(source.to_string()).or_default();
+        circuit.failures += 1;
+        if circuit.probing || circuit.failures >= self.threshold {
+            warn!(
+                "Source {} failed {} times in a row, suspending its downloads for {:?}",
+                source, circuit.failures, self.cooldown
+            );
+            circuit.opened_at = Some(Instant::now());
+            circuit.probing = false;
+        }
+    }
+
+    /// Ends a download of `source` that neither succeeded nor failed because of the source,
+    /// e.g. cancelled, so another download can probe it.
+    pub(crate) fn release(&self, source: &str) {
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(circuit) = circuits.get_mut(source) {
+            circuit.probing = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+
+        breaker.record_failure("default/app");
+        assert_eq!(breaker.check("default/app"), None);
+        breaker.record_failure("default/app");
+        assert!(breaker.check("default/app").is_some());
+        // Other sources are not affected
+        assert_eq!(breaker.check("default/other"), None);
+
+        // Half-open after the cooldown: a single probe goes through, and reopens on failure
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.check("default/app"), None);
+        assert!(breaker.check("default/app").is_some());
+        breaker.record_failure("default/app");
+        assert!(breaker.check("default/app").is_some());
+
+        // A successful probe closes the circuit
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.check("default/app"), None);
+        breaker.record_success("default/app");
+        assert_eq!(breaker.check("default/app"), None);
+        breaker.record_failure("default/app");
+        assert_eq!(breaker.check("default/app"), None);
+    }
+
+    #[test]
+    fn test_breaker_release_and_disabled() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure("default/app");
+        assert_eq!(breaker.check("default/app"), None);
+        // A cancelled probe lets the next download probe the source
+        breaker.release("default/app");
+        assert_eq!(breaker.check("default/app"), None);
+
+        let disabled = CircuitBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..10 {
+            disabled.record_failure("default/app");
+        }
+        assert_eq!(disabled.check("default/app"), None);
+    }
+}
@@ -21,9 +21,62 @@ pub enum DownloaderError {
     #[snafu(display("Cannot get filename"))]
     FilenameWrong,
 
+    #[snafu(display("Invalid extract ignore pattern: {}", source))]
+    InvalidExtractIgnore { source: ignore::Error },
+
     #[snafu(display("Cannot download: {}", source))]
     CannotDownload { source: reqwest_middleware::Error },
 
+    #[snafu(display("Source host {} is unreachable ({}): {}", host, source, hint))]
+    SourceUnreachable {
+        host: String,
+        hint: &'static str,
+        source: reqwest::Error,
+    },
+
     #[snafu(display("Cannot get body: {}", source))]
     CannotGetBody { source: reqwest::Error },
+
+    #[snafu(display(
+        "Artifact of {} bytes exceeds the maximum size of {} bytes",
+        size,
+        limit
+    ))]
+    ArtifactTooLarge { size: u64, limit: u64 },
+
+    #[snafu(display("Downloaded {} bytes, expected {}", actual, expected))]
+    SizeMismatch { expected: u64, actual: u64 },
+
+    #[snafu(display("Download was cancelled"))]
+    Cancelled,
+
+    #[snafu(display(
+        "Downloads of source {} are suspended after repeated failures, retrying in {:?}",
+        name,
+        retry_in
+    ))]
+    CircuitOpen {
+        name: String,
+        retry_in: std::time::Duration,
+    },
+
+    #[snafu(display("Storage directory {} is not writable: {}", path.display(), source))]
+    StorageDirNotWritable {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+}
+
+impl DownloaderError {
+    /// Whether the error comes from the source serving the artifact, e.g. unreachable or
+    /// answering with an error, which counts towards opening its circuit.
+    pub fn is_source_failure(&self) -> bool {
+        matches!(
+            self,
+            DownloaderError::CannotDownload { .. }
+                | DownloaderError::SourceUnreachable { .. }
+                | DownloaderError::CannotGetBody { .. }
+                | DownloaderError::SizeMismatch { .. }
+        )
+    }
 }
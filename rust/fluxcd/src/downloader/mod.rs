@@ -1,26 +1,61 @@
 use std::{
-    fs::{create_dir_all, File},
-    io::Cursor,
-    path::PathBuf,
+    collections::{BTreeMap, HashMap},
+    fs::{remove_dir_all, remove_file, rename, DirBuilder, File, OpenOptions},
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
 };
 
-use crate::downloader::error::*;
+use crate::downloader::{breaker::CircuitBreaker, error::*, progress::ProgressTracker};
 use flate2::read::GzDecoder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, CONTENT_DISPOSITION, CONTENT_RANGE, ETAG, IF_MODIFIED_SINCE,
+        IF_NONE_MATCH, LAST_MODIFIED, RANGE,
+    },
+    Response, StatusCode,
+};
 use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use snafu::{OptionExt, ResultExt};
 use tar::Archive;
-use tracing::info;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 use url::Url;
 
+mod breaker;
 pub mod error;
+pub mod progress;
+
+pub use breaker::{DEFAULT_COOLDOWN, DEFAULT_FAILURE_THRESHOLD};
+pub use progress::{DownloadProgress, ProgressCallback};
 
 type Result<T, E = DownloaderError> = std::result::Result<T, E>;
 
+/// Default directory the artifacts are downloaded and extracted to.
+pub const DEFAULT_STORAGE_DIR: &str = "/tmp/kcl";
+
+/// Default maximum size of the downloaded artifacts, 1 GiB.
+pub const DEFAULT_MAX_ARTIFACT_SIZE: u64 = 1 << 30;
+
 pub struct Downloader {
     client: ClientWithMiddleware,
     host: Option<String>,
 
     storage_dir: PathBuf,
+    dir_mode: Option<u32>,
+    max_artifact_size: u64,
+
+    progress: Option<ProgressCallback>,
+
+    /// Locks of the artifacts being downloaded or extracted, by artifact path.
+    in_flight: Mutex<HashMap<PathBuf, Weak<tokio::sync::Mutex<()>>>>,
+
+    /// Fails the downloads of repeatedly failing sources fast.
+    breaker: CircuitBreaker,
 }
 
 impl Downloader {
@@ -29,14 +64,92 @@ impl Downloader {
         host: Option<String>,
         storage_dir: Option<PathBuf>,
     ) -> Self {
-        let storage_dir = storage_dir.unwrap_or_else(|| PathBuf::from("/tmp/kcl"));
+        let storage_dir = storage_dir.unwrap_or_else(|| PathBuf::from(DEFAULT_STORAGE_DIR));
         Self {
             client,
             host,
             storage_dir,
+            dir_mode: None,
+            max_artifact_size: DEFAULT_MAX_ARTIFACT_SIZE,
+            progress: None,
+            in_flight: Mutex::new(HashMap::new()),
+            breaker: CircuitBreaker::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN),
         }
     }
 
+    /// Sets the mode (e.g. `0o750`) of the directories created in the storage dir.
+    /// The process umask still applies on top of it.
+    pub fn with_dir_mode(mut self, mode: u32) -> Self {
+        self.dir_mode = Some(mode);
+        self
+    }
+
+    /// Sets the maximum size in bytes of the downloaded artifacts, `DEFAULT_MAX_ARTIFACT_SIZE`
+    /// by default. Larger artifacts are rejected before (or while) being written to disk.
+    pub fn with_max_artifact_size(mut self, limit: u64) -> Self {
+        self.max_artifact_size = limit;
+        self
+    }
+
+    /// Sets the circuit breaker of the downloads of each source: after `threshold` consecutive
+    /// failures, downloads of the source fail with `CircuitOpen` for the `cooldown`, after which
+    /// a single download probes whether it recovered. A `threshold` of 0 disables it.
+    /// Defaults to `DEFAULT_FAILURE_THRESHOLD` and `DEFAULT_COOLDOWN`.
+    pub fn with_circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.breaker = CircuitBreaker::new(threshold, cooldown);
+        self
+    }
+
+    /// Creates the storage dir and checks it is writable, so a misconfigured volume
+    /// fails at startup instead of in the middle of a reconcile.
+    pub fn init(&self) -> Result<()> {
+        let not_writable = |source| DownloaderError::StorageDirNotWritable {
+            path: self.storage_dir.clone(),
+            source,
+        };
+        self.create_dir(&self.storage_dir).map_err(not_writable)?;
+
+        let probe = self.storage_dir.join(".write-probe");
+        File::create(&probe).map_err(not_writable)?;
+        remove_file(&probe).map_err(not_writable)?;
+        info!("Using storage directory {}", self.storage_dir.display());
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> std::io::Result<()> {
+        let mut builder = DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        if let Some(mode) = self.dir_mode {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(mode);
+        }
+        builder.create(path)
+    }
+
+    /// Returns the lock of the artifact at `target_path`, shared by all concurrent downloads
+    /// of the same namespace, repository and revision.
+    fn artifact_lock(&self, target_path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        in_flight.retain(|_, lock| lock.strong_count() > 0);
+        if let Some(lock) = in_flight.get(target_path).and_then(Weak::upgrade) {
+            return lock;
+        }
+        let lock = Arc::new(tokio::sync::Mutex::new(()));
+        in_flight.insert(target_path.to_path_buf(), Arc::downgrade(&lock));
+        lock
+    }
+
+    /// Registers a callback receiving the progress of downloads, for embedders that want to
+    /// surface it. Progress is also logged through `tracing`.
+    pub fn with_progress_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&DownloadProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
     /// # Type: Directory downloader
     ///
     /// Helper to download files over http into a directory
@@ -44,66 +157,545 @@ impl Downloader {
     /// # Example:
     /// ```ignore
     /// let file_downloader = Downloader::new(client, host);
-    /// let path = file_downloader.download("http://example.com/file.tar.gz", "my-repo", dest_path).await?;
+    /// let path = file_downloader.download("http://example.com/file.tar.gz", "my-repo", dest_path, &cancel).await?;
     /// ```
     ///
+    /// The download stops with `Cancelled` as soon as `cancel` is cancelled, leaving no
+    /// partial artifact behind. Downloads interrupted otherwise (e.g. by a restart) are
+    /// resumed with a range request, or restarted if the server doesn't support ranges.
+    ///
+    /// Concurrent downloads of the same artifact are deduplicated: only one of them fetches
+    /// and extracts it, the others wait for it and reuse the result.
+    ///
+    /// When the archive is gone but its extraction is still in place, the artifact is requested
+    /// with the `ETag` and `Last-Modified` of its last download, and the extraction is reused
+    /// when the server answers that it is not modified.
+    ///
+    /// The archive is named after the `Content-Disposition` filename sent by the server, else
+    /// after the last segment of the URL, with a digest of the query of the URL if it has one,
+    /// and extracted to a directory of the same name without the `.tar.gz` extension.
+    ///
     /// # Errors:
     /// Returns a DownloaderError in the following cases:
     /// - If the file cannot be downloaded
     /// - If the file cannot be written to disk
+    /// - If the downloaded file doesn't have the size announced by the server
+    /// - If the file is larger than the maximum artifact size
     /// - If the tar.gz file cannot be extracted
     /// - If the URL is invalid
+    /// - If the download was cancelled
+    ///
+    pub async fn download(
+        &self,
+        url: &str,
+        repo_name: &str,
+        namespace: &str,
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        self.download_with_ignore(url, repo_name, namespace, &[], cancel)
+            .await
+    }
+
+    /// Downloads and extracts an artifact like `download`, skipping the entries matching the
+    /// gitignore-style `extract_ignore` patterns (e.g. `docs/` or `*.png`).
+    ///
+    /// Artifacts extracted with patterns go to their own directory, so instances with different
+    /// patterns can share the download.
     ///
-    pub async fn download(&self, url: &str, repo_name: &str, namespace: &str) -> Result<PathBuf> {
-        let url = build_url(url, self.host.clone())?;
+    /// Downloads of a source that failed repeatedly fail fast with `CircuitOpen`, see
+    /// `with_circuit_breaker`.
+    pub async fn download_with_ignore(
+        &self,
+        url: &str,
+        repo_name: &str,
+        namespace: &str,
+        extract_ignore: &[String],
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        self.download_from(url, None, repo_name, namespace, extract_ignore, cancel)
+            .await
+    }
+
+    /// Downloads and extracts an artifact like `download_with_ignore`, fetching it from `host`
+    /// instead of the downloader's host when set, e.g. for the sources of an instance that must
+    /// go through a proxy.
+    pub async fn download_from(
+        &self,
+        url: &str,
+        host: Option<&str>,
+        repo_name: &str,
+        namespace: &str,
+        extract_ignore: &[String],
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        let source = format!("{namespace}/{repo_name}");
+        if let Some(retry_in) = self.breaker.check(&source) {
+            return CircuitOpenSnafu {
+                name: source,
+                retry_in,
+            }
+            .fail();
+        }
+        let res = self
+            .download_artifact(url, host, repo_name, namespace, extract_ignore, cancel)
+            .await;
+        match &res {
+            Ok(_) => self.breaker.record_success(&source),
+            Err(e) if e.is_source_failure() => self.breaker.record_failure(&source),
+            Err(_) => self.breaker.release(&source),
+        }
+        res
+    }
+
+    async fn download_artifact(
+        &self,
+        url: &str,
+        host: Option<&str>,
+        repo_name: &str,
+        namespace: &str,
+        extract_ignore: &[String],
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        let ignore = extract_ignore_matcher(extract_ignore)?;
+        let host = host.map(str::to_string).or_else(|| self.host.clone());
+        let url = build_url(url, host)?;
         let path = self.storage_dir.join(namespace).join(repo_name);
 
-        let target = url
+        // The partial download, validators and filename of the artifact are named after the
+        // last segment of its URL and its query, or a digest of the URL when it has no segment
+        let request_name = url
             .path_segments()
             .and_then(|segments| segments.last())
-            .context(FilenameWrongSnafu)?;
-
-        let target_path = path.join(target);
+            .filter(|segment| !segment.is_empty())
+            .map_or_else(
+                || format!("{:x}", Sha256::digest(url.as_str()))[..16].to_string(),
+                |segment| with_query_digest(segment, url.query()),
+            );
 
         // Create the directory if it doesn't exist
         if !path.exists() {
             info!("Creating directory {}", path.display());
-            create_dir_all(&path).context(CannotCreateFileSnafu)?;
+            self.create_dir(&path).context(CannotCreateFileSnafu)?;
         }
 
+        // Only one download of the artifact proceeds at a time, the others find it in place
+        let lock = self.artifact_lock(&path.join(&request_name));
+        let _guard = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => return CancelledSnafu.fail(),
+            guard = lock.lock() => guard,
+        };
+
+        // The archive is named after the Content-Disposition filename of its last download,
+        // else after its URL
+        let filename_path = path.join(format!("{request_name}.filename"));
+        let target = std::fs::read_to_string(&filename_path)
+            .ok()
+            .filter(|filename| is_plain_filename(filename))
+            .unwrap_or_else(|| request_name.clone());
+        let dir_path_of = |target: &str| {
+            let dir_name = extraction_dir_name(target);
+            if extract_ignore.is_empty() {
+                path.join(dir_name)
+            } else {
+                let digest = format!("{:x}", Sha256::digest(extract_ignore.join("\n")));
+                path.join(format!("{dir_name}-{}", &digest[..12]))
+            }
+        };
+        let mut target_path = path.join(&target);
+        let mut dir_path = dir_path_of(&target);
+
         //  Check if the file already exists and download it if not
         if !target_path.exists() {
+            // Stream into a partial file, so an interrupted download is never mistaken
+            // for a complete one, and can be resumed from where it stopped
+            let partial_path = path.join(format!("{request_name}.part"));
+            let validators_path = path.join(format!("{request_name}.validators"));
+            let resume_from = std::fs::metadata(&partial_path).map_or(0, |m| m.len());
+            // The extraction outlived the archive, so only download it again if it changed
+            let validators = if resume_from == 0 && dir_path.exists() {
+                Validators::load(&validators_path)
+            } else {
+                Validators::default()
+            };
             info!("Downloading stream from {}", url);
-            let response = self
-                .client
-                .get(url.clone())
-                .send()
-                .await
-                .context(CannotDownloadSnafu)?;
-
-            // Open a file to write the downloaded content
-            let mut file = File::create(&target_path).context(CannotCreateFileSnafu)?;
-            // Copy the content from the response to the file
-            let mut content = Cursor::new(response.bytes().await.context(CannotGetBodySnafu)?);
-            std::io::copy(&mut content, &mut file).context(CannotCreateFileSnafu)?;
-        }
-
-        // dir_path is the name of file without the extension
-        // Check if the directory exists
-        let dir_path = path.join(target.trim_end_matches(".tar.gz"));
+            let mut response = self.get(&url, resume_from, &validators, cancel).await?;
+            if response.status() == StatusCode::NOT_MODIFIED {
+                info!("Artifact {} not modified, reusing its extraction", url);
+                return Ok(dir_path);
+            }
+            if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                info!("Cannot resume download of {}, restarting it", url);
+                response = self.get(&url, 0, &Validators::default(), cancel).await?;
+            }
+            let mut response = response.error_for_status().context(CannotGetBodySnafu)?;
+            let fresh_validators = Validators::from_headers(response.headers());
+            let disposition_filename = content_disposition_filename(response.headers())
+                .map(|filename| with_query_digest(&filename, url.query()));
+
+            // Servers ignoring the range send the whole artifact with a 200
+            let (mut file, expected_size) = if response.status() == StatusCode::PARTIAL_CONTENT {
+                info!("Resuming download of {} at byte {}", url, resume_from);
+                let file = OpenOptions::new()
+                    .append(true)
+                    .open(&partial_path)
+                    .context(CannotCreateFileSnafu)?;
+                (file, content_range_size(response.headers()))
+            } else {
+                let file = File::create(&partial_path).context(CannotCreateFileSnafu)?;
+                (file, response.content_length())
+            };
+            let limit = self.max_artifact_size;
+            if let Some(size) = expected_size.filter(|size| *size > limit) {
+                drop(file);
+                let _ = remove_file(&partial_path);
+                return ArtifactTooLargeSnafu { size, limit }.fail();
+            }
+            let mut written = file.metadata().context(CannotCreateFileSnafu)?.len();
+            let mut tracker =
+                ProgressTracker::new(url.as_str(), expected_size, self.progress.clone());
+            tracker.advance(written);
+            loop {
+                let chunk = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => {
+                        drop(file);
+                        let _ = remove_file(&partial_path);
+                        return CancelledSnafu.fail();
+                    }
+                    chunk = response.chunk() => chunk.context(CannotGetBodySnafu)?,
+                };
+                let Some(chunk) = chunk else { break };
+                // Servers may omit the Content-Length, so the cap is enforced while streaming too
+                written += chunk.len() as u64;
+                if written > limit {
+                    drop(file);
+                    let _ = remove_file(&partial_path);
+                    return ArtifactTooLargeSnafu {
+                        size: written,
+                        limit,
+                    }
+                    .fail();
+                }
+                file.write_all(&chunk).context(CannotCreateFileSnafu)?;
+                tracker.advance(chunk.len() as u64);
+            }
+            tracker.finish();
+            drop(file);
+
+            let actual = std::fs::metadata(&partial_path)
+                .context(CannotCreateFileSnafu)?
+                .len();
+            if let Some(expected) = expected_size.filter(|expected| *expected != actual) {
+                let _ = remove_file(&partial_path);
+                return SizeMismatchSnafu { expected, actual }.fail();
+            }
+            if let Some(filename) = disposition_filename.filter(|f| *f != target) {
+                info!(
+                    "Naming artifact {} {} after its Content-Disposition",
+                    url, filename
+                );
+                if let Err(e) = std::fs::write(&filename_path, &filename) {
+                    warn!("Cannot store the filename of {}: {}", url, e);
+                }
+                target_path = path.join(&filename);
+                dir_path = dir_path_of(&filename);
+            }
+            rename(&partial_path, &target_path).context(CannotCreateFileSnafu)?;
+            if let Err(e) = fresh_validators.save(&validators_path) {
+                warn!("Cannot store the validators of {}: {}", url, e);
+            }
+        }
+
+        if cancel.is_cancelled() {
+            return CancelledSnafu.fail();
+        }
         if !dir_path.exists() {
-            // Extract the tar.gz file to the target directory
+            // Extract next to the target directory and move it in place once complete, so the
+            // instances sharing the source never reuse a partial extraction
             info!("Extracting file to {}", &dir_path.display());
-            let tar_gz = File::open(&target_path).context(CannotCreateFileSnafu)?;
-            let mut archive = Archive::new(GzDecoder::new(tar_gz));
-            archive.unpack(&dir_path).context(CannotCreateFileSnafu)?;
+            let mut extracting_path = dir_path.clone().into_os_string();
+            extracting_path.push(".extracting");
+            let extracting_path = PathBuf::from(extracting_path);
+            if extracting_path.exists() {
+                remove_dir_all(&extracting_path).context(CannotCreateFileSnafu)?;
+            }
+            extract(&target_path, &extracting_path, &ignore)?;
+            rename(&extracting_path, &dir_path).context(CannotCreateFileSnafu)?;
             info!("Extracted file to {}", &dir_path.display());
         }
 
         Ok(dir_path)
     }
+
+    /// Writes the files of a module that is not served as an artifact, e.g. one read from the
+    /// keys of a ConfigMap, to `<storage dir>/<namespace>/<name>/<dir_name>`.
+    ///
+    /// `dir_name` must identify the content, as a directory already in place is reused.
+    ///
+    /// # Arguments
+    /// * `files` - Contents of the files by name, names being plain file names
+    ///
+    /// # Returns
+    /// The directory holding the files
+    pub async fn store_files(
+        &self,
+        namespace: &str,
+        name: &str,
+        dir_name: &str,
+        files: &BTreeMap<String, Vec<u8>>,
+    ) -> Result<PathBuf> {
+        let path = self.storage_dir.join(namespace).join(name);
+        let dir_path = path.join(dir_name);
+
+        // Only one write of the files proceeds at a time, the others find them in place
+        let lock = self.artifact_lock(&dir_path);
+        let _guard = lock.lock().await;
+        if dir_path.exists() {
+            return Ok(dir_path);
+        }
+
+        // Written next to the target directory and moved in place once complete, like
+        // extractions
+        let mut writing_path = dir_path.clone().into_os_string();
+        writing_path.push(".extracting");
+        let writing_path = PathBuf::from(writing_path);
+        if writing_path.exists() {
+            remove_dir_all(&writing_path).context(CannotCreateFileSnafu)?;
+        }
+        self.create_dir(&writing_path)
+            .context(CannotCreateFileSnafu)?;
+        for (file_name, content) in files {
+            if file_name.is_empty()
+                || file_name.contains(['/', '\\'])
+                || file_name == "."
+                || file_name == ".."
+            {
+                return FilenameWrongSnafu.fail();
+            }
+            std::fs::write(writing_path.join(file_name), content).context(CannotCreateFileSnafu)?;
+        }
+        rename(&writing_path, &dir_path).context(CannotCreateFileSnafu)?;
+        info!("Stored {} files in {}", files.len(), dir_path.display());
+        Ok(dir_path)
+    }
+
+    /// Requests the artifact at `url`, from byte `offset` on when it isn't 0, and only if it
+    /// changed since the download described by `validators`.
+    async fn get(
+        &self,
+        url: &Url,
+        offset: u64,
+        validators: &Validators,
+        cancel: &CancellationToken,
+    ) -> Result<Response> {
+        let mut request = self.client.get(url.clone());
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => CancelledSnafu.fail(),
+            response = request.send() => response.map_err(|e| download_error(url, e)),
+        }
+    }
+}
+
+/// Validators of a downloaded artifact, stored next to it to request it again only if it
+/// changed.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
 }
 
+impl Validators {
+    /// Validators sent by the server along with the artifact.
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let value = |name: HeaderName| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Validators {
+            etag: value(ETAG),
+            last_modified: value(LAST_MODIFIED),
+        }
+    }
+
+    /// Validators stored at `path`, none when they are missing or unreadable.
+    fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the validators at `path`, if the server sent any.
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        if *self == Validators::default() {
+            return Ok(());
+        }
+        std::fs::write(path, serde_json::to_vec(self)?)
+    }
+}
+
+/// Builds the matcher of gitignore-style extract ignore patterns.
+fn extract_ignore_matcher(patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .context(InvalidExtractIgnoreSnafu)?;
+    }
+    builder.build().context(InvalidExtractIgnoreSnafu)
+}
+
+/// Extracts the `.tar.gz` archive at `archive_path` into `dir_path`, skipping the entries
+/// matching `ignore`, and those that are absolute or escape `dir_path`.
+pub(crate) fn extract(archive_path: &Path, dir_path: &Path, ignore: &Gitignore) -> Result<()> {
+    let tar_gz = File::open(archive_path).context(CannotCreateFileSnafu)?;
+    let mut archive = Archive::new(GzDecoder::new(tar_gz));
+    DirBuilder::new()
+        .recursive(true)
+        .create(dir_path)
+        .context(CannotCreateFileSnafu)?;
+    for entry in archive.entries().context(CannotGetEntriesSnafu)? {
+        let mut entry = entry.context(CannotGetEntriesSnafu)?;
+        let path = entry.path().context(CannotGetEntriesSnafu)?;
+
+        // Relative path of the entry, e.g. `docs/index.md` for `./docs/index.md`
+        let mut relative = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                _ => {
+                    warn!(
+                        "Skipping archive entry {} outside of the source",
+                        path.display()
+                    );
+                    relative.clear();
+                    break;
+                }
+            }
+        }
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+
+        let is_dir = entry.header().entry_type().is_dir();
+        if ignore
+            .matched_path_or_any_parents(&relative, is_dir)
+            .is_ignore()
+        {
+            continue;
+        }
+        entry.unpack_in(dir_path).context(CannotCreateFileSnafu)?;
+    }
+    Ok(())
+}
+
+/// Hint of `SourceUnreachable`, as the source-controller host is a frequent misconfiguration.
+const SOURCE_UNREACHABLE_HINT: &str = "check that the SOURCE_HOST setting points to the \
+    source-controller service (by default source-controller.flux-system.svc) and that network \
+    policies allow the operator to reach it";
+
+/// Maps a failed request to `SourceUnreachable` when the host could not be resolved or
+/// connected to, and to `CannotDownload` otherwise.
+fn download_error(url: &Url, error: reqwest_middleware::Error) -> DownloaderError {
+    match error {
+        reqwest_middleware::Error::Reqwest(source) if source.is_connect() => {
+            DownloaderError::SourceUnreachable {
+                host: url.host_str().unwrap_or_default().to_string(),
+                hint: SOURCE_UNREACHABLE_HINT,
+                source,
+            }
+        }
+        source => DownloaderError::CannotDownload { source },
+    }
+}
+
+/// Total size of the artifact from the `Content-Range` header of a partial response,
+/// e.g. `bytes 100-999/1000`.
+pub(crate) fn content_range_size(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit_once('/')?
+        .1
+        .parse()
+        .ok()
+}
+
+/// Name of the directory an archive named `filename` is extracted to: the filename without
+/// its `.tar.gz` extension.
+fn extraction_dir_name(filename: &str) -> &str {
+    filename.trim_end_matches(".tar.gz")
+}
+
+/// Appends a digest of the `query` of an artifact URL to `filename`, before its `.tar.gz`
+/// extension, so that the artifacts served at the same path under different queries, e.g.
+/// `/download?artifact=abc` and `/download?artifact=def`, don't share their files.
+fn with_query_digest(filename: &str, query: Option<&str>) -> String {
+    let Some(query) = query else {
+        return filename.to_string();
+    };
+    let stem = extraction_dir_name(filename);
+    let digest = format!("{:x}", Sha256::digest(query));
+    format!("{stem}-{}{}", &digest[..12], &filename[stem.len()..])
+}
+
+/// Whether `filename` names a file of the directory it is joined to, not a path.
+fn is_plain_filename(filename: &str) -> bool {
+    !filename.is_empty()
+        && filename != "."
+        && filename != ".."
+        && !filename.contains(['/', '\\'])
+        && !extraction_dir_name(filename).is_empty()
+}
+
+/// Filename of the `Content-Disposition` header of a response, e.g. `abc.tar.gz` for
+/// `attachment; filename="abc.tar.gz"`, if it is a plain filename. The `filename*` form is
+/// only used with plain ASCII values.
+pub(crate) fn content_disposition_filename(headers: &HeaderMap) -> Option<String> {
+    let disposition = headers.get(CONTENT_DISPOSITION)?.to_str().ok()?;
+    let mut filename = None;
+    for param in disposition.split(';').skip(1) {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            // RFC 5987 `charset'language'value`, preferred over `filename`
+            "filename*" => {
+                let value = value.splitn(3, '\'').nth(2).unwrap_or_default();
+                if is_plain_filename(value) && !value.contains('%') {
+                    return Some(value.to_string());
+                }
+            }
+            "filename" => filename = Some(value.trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    filename.filter(|f| is_plain_filename(f))
+}
+
+/// Rewrites `url` to be fetched from `override_host`, e.g. `SOURCE_HOST`.
+///
+/// The scheme, host and port come from the override, and the path of `url` is appended to the
+/// override's base path, so a proxy behind a path prefix (e.g. `http://proxy/base`) keeps it.
+/// The query and fragment of `url` are preserved.
 pub(crate) fn build_url(url: &str, override_host: Option<String>) -> Result<Url> {
     tracing::info!(
         "Building url {} with override host {}",
@@ -113,8 +705,11 @@ pub(crate) fn build_url(url: &str, override_host: Option<String>) -> Result<Url>
     let parsed_url = url::Url::parse(url).context(InvalidParseUrlSnafu)?;
     if let Some(host) = override_host {
         let mut override_parsed = url::Url::parse(host.as_str()).context(InvalidParseUrlSnafu)?;
-        override_parsed.set_path(parsed_url.path());
+        let base_path = override_parsed.path().trim_end_matches('/');
+        let path = format!("{base_path}{}", parsed_url.path());
+        override_parsed.set_path(&path);
         override_parsed.set_query(parsed_url.query());
+        override_parsed.set_fragment(parsed_url.fragment());
         Ok(override_parsed)
     } else {
         Ok(parsed_url)
@@ -142,6 +737,64 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_build_url_with_base_path_override() -> Result<()> {
+        let url = "http://source-controller.flux-system.svc/gitrepository/default/app/abc.tar.gz?x=1#part";
+        for host in ["https://proxy:8443/base", "https://proxy:8443/base/"] {
+            let result = build_url(url, Some(host.to_string()))?;
+            assert_eq!(
+                result.to_string(),
+                "https://proxy:8443/base/gitrepository/default/app/abc.tar.gz?x=1#part"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_range_size() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(content_range_size(&headers), None);
+        headers.insert(CONTENT_RANGE, "bytes 100-999/1000".parse().unwrap());
+        assert_eq!(content_range_size(&headers), Some(1000));
+        headers.insert(CONTENT_RANGE, "bytes 100-999/*".parse().unwrap());
+        assert_eq!(content_range_size(&headers), None);
+    }
+
+    #[test]
+    fn test_content_disposition_filename() {
+        let filename = |disposition: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_DISPOSITION, disposition.parse().unwrap());
+            content_disposition_filename(&headers)
+        };
+        assert_eq!(content_disposition_filename(&HeaderMap::new()), None);
+        assert_eq!(
+            filename("attachment; filename=\"abc.tar.gz\"").as_deref(),
+            Some("abc.tar.gz")
+        );
+        assert_eq!(
+            filename("attachment; filename=abc.tar.gz").as_deref(),
+            Some("abc.tar.gz")
+        );
+        assert_eq!(
+            filename("attachment; filename=\"old.tar.gz\"; filename*=UTF-8''new.tar.gz").as_deref(),
+            Some("new.tar.gz")
+        );
+        assert_eq!(filename("attachment"), None);
+        assert_eq!(filename("attachment; filename=\"../abc.tar.gz\""), None);
+        assert_eq!(filename("attachment; filename=\"..\""), None);
+        assert_eq!(filename("attachment; filename=\".tar.gz\""), None);
+    }
+
+    #[test]
+    fn test_with_query_digest() {
+        assert_eq!(with_query_digest("abc.tar.gz", None), "abc.tar.gz");
+        let abc = with_query_digest("abc.tar.gz", Some("artifact=abc"));
+        assert!(abc.starts_with("abc-") && abc.ends_with(".tar.gz"), "{abc}");
+        assert_eq!(extraction_dir_name(&abc).len(), "abc-".len() + 12);
+        assert_ne!(abc, with_query_digest("abc.tar.gz", Some("artifact=def")));
+    }
+
     #[test]
     fn test_build_url_invalid_url() {
         let url = "not a url";
@@ -156,4 +809,206 @@ mod tests {
         let result = build_url(url, override_host);
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_unreachable_source_host() {
+        let storage_dir = std::env::temp_dir().join(format!("unreachable-{}", std::process::id()));
+        let downloader = Downloader::new(
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+            Some("http://127.0.0.1:1".to_string()),
+            Some(storage_dir.clone()),
+        );
+
+        let res = downloader
+            .download(
+                "http://source-controller.flux-system.svc/gitrepository/default/app/abc.tar.gz",
+                "app",
+                "default",
+                &CancellationToken::new(),
+            )
+            .await;
+        match res {
+            Err(DownloaderError::SourceUnreachable { host, .. }) => assert_eq!(host, "127.0.0.1"),
+            res => panic!("expected SourceUnreachable, got {res:?}"),
+        }
+        std::fs::remove_dir_all(storage_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_circuit_open_after_failures() {
+        let storage_dir = std::env::temp_dir().join(format!("circuit-{}", std::process::id()));
+        let downloader = Downloader::new(
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+            Some("http://127.0.0.1:1".to_string()),
+            Some(storage_dir.clone()),
+        )
+        .with_circuit_breaker(2, Duration::from_secs(60));
+        let (downloader, cancel) = (&downloader, &CancellationToken::new());
+        let download = move |repo_name: &'static str| {
+            downloader.download(
+                "http://source-controller.flux-system.svc/gitrepository/default/app/abc.tar.gz",
+                repo_name,
+                "default",
+                cancel,
+            )
+        };
+
+        for _ in 0..2 {
+            assert!(matches!(
+                download("app").await,
+                Err(DownloaderError::SourceUnreachable { .. })
+            ));
+        }
+        match download("app").await {
+            Err(DownloaderError::CircuitOpen { name, .. }) => assert_eq!(name, "default/app"),
+            res => panic!("expected CircuitOpen, got {res:?}"),
+        }
+        assert!(matches!(
+            download("other").await,
+            Err(DownloaderError::SourceUnreachable { .. })
+        ));
+        std::fs::remove_dir_all(storage_dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_skips_ignored_entries() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("extract-ignore-{}", std::process::id()));
+        let archive_path = dir.join("source.tar.gz");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            File::create(&archive_path).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for name in [
+            "./main.k",
+            "./docs/index.md",
+            "./app/main_test.k",
+            "./app/server.k",
+            "./bin/tool.exe",
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, &b"x"[..]).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let target = dir.join("source");
+        let ignore = extract_ignore_matcher(&[
+            "docs/".to_string(),
+            "*_test.k".to_string(),
+            "/bin".to_string(),
+        ])?;
+        extract(&archive_path, &target, &ignore)?;
+
+        assert!(target.join("main.k").is_file());
+        assert!(target.join("app/server.k").is_file());
+        assert!(!target.join("app/main_test.k").exists());
+        assert!(!target.join("docs").exists());
+        assert!(!target.join("bin").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    /// Serves a single response with `body`, announcing its length or not.
+    fn serve_once(body: Vec<u8>, content_length: bool) -> String {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 4096]);
+            let length = if content_length {
+                format!("Content-Length: {}\r\n", body.len())
+            } else {
+                String::new()
+            };
+            let head = format!("HTTP/1.1 200 OK\r\n{length}Connection: close\r\n\r\n");
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_shared_source_extracted_once() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "./main.k", &b"x"[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let storage_dir =
+            std::env::temp_dir().join(format!("shared-source-{}", std::process::id()));
+        // The server answers a single request, so a second download would fail
+        let downloader = Downloader::new(
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+            Some(serve_once(archive, true)),
+            Some(storage_dir.clone()),
+        );
+
+        // Two instances, whatever their namespaces, referencing flux-system/app
+        let url =
+            "http://source-controller.flux-system.svc/gitrepository/flux-system/app/abc.tar.gz";
+        let cancel = CancellationToken::new();
+        let (first, second) = tokio::join!(
+            downloader.download(url, "app", "flux-system", &cancel),
+            downloader.download(url, "app", "flux-system", &cancel),
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(first, second);
+        assert_eq!(first, storage_dir.join("flux-system/app/abc"));
+        assert!(first.join("main.k").is_file());
+        assert!(!storage_dir.join("flux-system/app/abc.extracting").exists());
+
+        std::fs::remove_dir_all(storage_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_artifact_too_large() {
+        for content_length in [true, false] {
+            let storage_dir = std::env::temp_dir()
+                .join(format!("too-large-{}-{content_length}", std::process::id()));
+            let downloader = Downloader::new(
+                reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+                Some(serve_once(vec![0; 4096], content_length)),
+                Some(storage_dir.clone()),
+            )
+            .with_max_artifact_size(1024);
+
+            let res = downloader
+                .download(
+                    "http://source-controller.flux-system.svc/gitrepository/default/app/abc.tar.gz",
+                    "app",
+                    "default",
+                    &CancellationToken::new(),
+                )
+                .await;
+            match res {
+                Err(DownloaderError::ArtifactTooLarge { size, limit }) => {
+                    assert!(size > limit, "{size} <= {limit}");
+                    assert_eq!(limit, 1024);
+                    if content_length {
+                        assert_eq!(size, 4096);
+                    }
+                }
+                res => panic!("expected ArtifactTooLarge, got {res:?}"),
+            }
+            let app_dir = storage_dir.join("default/app");
+            assert!(!app_dir.join("abc.tar.gz").exists());
+            assert!(!app_dir.join("abc.tar.gz.part").exists());
+            std::fs::remove_dir_all(storage_dir).unwrap();
+        }
+    }
 }
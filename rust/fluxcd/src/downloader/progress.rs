@@ -0,0 +1,134 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+/// Minimum time between two progress reports.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// Minimum number of bytes between two progress reports.
+const REPORT_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Progress of a single artifact download.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadProgress {
+    /// The URL being downloaded.
+    pub url: String,
+    /// Bytes downloaded so far.
+    pub downloaded: u64,
+    /// Total size of the artifact, if the server sent a `Content-Length`.
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// Percentage of the download completed, if the total size is known.
+    pub fn percent(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| self.downloaded as f64 * 100.0 / total as f64)
+    }
+}
+
+/// Callback invoked with the download progress, throttled like the progress logs.
+pub type ProgressCallback = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// Tracks the progress of a download and reports it through tracing and the optional callback.
+pub(crate) struct ProgressTracker {
+    progress: DownloadProgress,
+    callback: Option<ProgressCallback>,
+    last_report: Instant,
+    last_reported_bytes: u64,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(url: &str, total: Option<u64>, callback: Option<ProgressCallback>) -> Self {
+        Self {
+            progress: DownloadProgress {
+                url: url.to_string(),
+                downloaded: 0,
+                total,
+            },
+            callback,
+            last_report: Instant::now(),
+            last_reported_bytes: 0,
+        }
+    }
+
+    /// Records `bytes` more downloaded bytes, reporting at most every second or every 8 MiB.
+    pub(crate) fn advance(&mut self, bytes: u64) {
+        self.progress.downloaded += bytes;
+        if self.last_report.elapsed() >= REPORT_INTERVAL
+            || self.progress.downloaded - self.last_reported_bytes >= REPORT_BYTES
+        {
+            self.report();
+        }
+    }
+
+    /// Reports the final progress.
+    pub(crate) fn finish(&mut self) {
+        self.report();
+    }
+
+    fn report(&mut self) {
+        let progress = &self.progress;
+        match (progress.total, progress.percent()) {
+            (Some(total), Some(percent)) => info!(
+                "Downloaded {}/{} bytes ({:.1}%) from {}",
+                progress.downloaded, total, percent, progress.url
+            ),
+            _ => info!(
+                "Downloaded {} bytes from {}",
+                progress.downloaded, progress.url
+            ),
+        }
+        if let Some(callback) = &self.callback {
+            callback(progress);
+        }
+        self.last_report = Instant::now();
+        self.last_reported_bytes = progress.downloaded;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn test_progress_percent() {
+        let progress = DownloadProgress {
+            url: "http://example.com/file.tar.gz".to_string(),
+            downloaded: 25,
+            total: Some(100),
+        };
+        assert_eq!(progress.percent(), Some(25.0));
+        assert_eq!(
+            DownloadProgress {
+                total: None,
+                ..progress
+            }
+            .percent(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_progress_tracker_throttles_reports() {
+        let reports = Arc::new(Mutex::new(vec![]));
+        let sink = reports.clone();
+        let callback: ProgressCallback = Arc::new(move |progress: &DownloadProgress| {
+            sink.lock().unwrap().push(progress.downloaded)
+        });
+
+        let mb = 1024 * 1024;
+        let mut tracker = ProgressTracker::new("http://example.com", Some(20 * mb), Some(callback));
+        for _ in 0..20 {
+            tracker.advance(mb);
+        }
+        tracker.finish();
+
+        assert_eq!(*reports.lock().unwrap(), vec![8 * mb, 16 * mb, 20 * mb]);
+    }
+}
@@ -1,5 +1,8 @@
 pub mod downloader;
 pub mod types;
 
+#[cfg(feature = "integration")]
+pub mod testing;
+
 pub use downloader::*;
 pub use types::*;
@@ -0,0 +1,196 @@
+//! Harness for integration tests, serving Flux source artifacts from a mock source-controller.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use flate2::{write::GzEncoder, Compression};
+use rand::Rng;
+use wiremock::{
+    matchers::{header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+/// Directory of the fixtures shared by the integration tests of the workspace.
+pub fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/fixtures")
+}
+
+/// Returns a fresh storage directory for a `Downloader` under the system temp dir.
+pub fn temp_storage_dir() -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "flux-kcl-operator-test-{:016x}",
+        rand::thread_rng().gen::<u64>()
+    ))
+}
+
+/// Packs a directory into a `.tar.gz` archive, like source-controller does for artifacts.
+pub fn tar_gz_dir(dir: &Path) -> std::io::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    builder.append_dir_all(".", dir)?;
+    builder.into_inner()?.finish()
+}
+
+/// A local HTTP server standing in for Flux source-controller.
+pub struct MockSourceController {
+    server: MockServer,
+}
+
+impl MockSourceController {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// Base URL of the server, usable as the `Downloader` host override.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Serves the fixture directory `fixture` as a `.tar.gz` artifact at `artifact_path`.
+    ///
+    /// # Returns
+    /// The URL of the artifact, as it would appear in the source status.
+    pub async fn serve_fixture(
+        &self,
+        artifact_path: &str,
+        fixture: &str,
+    ) -> std::io::Result<String> {
+        self.serve_slow_fixture(artifact_path, fixture, Duration::ZERO)
+            .await
+    }
+
+    /// Like `serve_fixture`, but only responds after `delay`.
+    pub async fn serve_slow_fixture(
+        &self,
+        artifact_path: &str,
+        fixture: &str,
+        delay: Duration,
+    ) -> std::io::Result<String> {
+        let body = tar_gz_dir(&fixtures_dir().join(fixture))?;
+        Mock::given(method("GET"))
+            .and(path(artifact_path))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(body)
+                    .set_delay(delay),
+            )
+            .mount(&self.server)
+            .await;
+        Ok(format!("{}{}", self.server.uri(), artifact_path))
+    }
+
+    /// Like `serve_fixture`, but answers `Range: bytes=<offset>-` requests with the rest of
+    /// the artifact, like a server supporting resumable downloads.
+    ///
+    /// # Returns
+    /// The URL of the artifact and its content.
+    pub async fn serve_resumable_fixture(
+        &self,
+        artifact_path: &str,
+        fixture: &str,
+        offset: usize,
+    ) -> std::io::Result<(String, Vec<u8>)> {
+        let body = tar_gz_dir(&fixtures_dir().join(fixture))?;
+        Mock::given(method("GET"))
+            .and(path(artifact_path))
+            .and(header("range", format!("bytes={offset}-").as_str()))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header(
+                        "content-range",
+                        format!("bytes {}-{}/{}", offset, body.len() - 1, body.len()).as_str(),
+                    )
+                    .set_body_bytes(&body[offset..]),
+            )
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+        let url = self.serve_fixture(artifact_path, fixture).await?;
+        Ok((url, body))
+    }
+
+    /// Like `serve_fixture`, but names the artifact `filename` with a `Content-Disposition`.
+    /// `artifact_path` may have a query, e.g. `/download?artifact=abc`, matched by the requests.
+    pub async fn serve_fixture_with_filename(
+        &self,
+        artifact_path: &str,
+        fixture: &str,
+        filename: &str,
+    ) -> std::io::Result<String> {
+        let body = tar_gz_dir(&fixtures_dir().join(fixture))?;
+        let (artifact_dir, query) = artifact_path.split_once('?').unwrap_or((artifact_path, ""));
+        let mut mock = Mock::given(method("GET")).and(path(artifact_dir));
+        for (key, value) in query.split('&').filter_map(|param| param.split_once('=')) {
+            mock = mock.and(query_param(key, value));
+        }
+        mock.respond_with(
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "content-disposition",
+                    format!("attachment; filename=\"{}\"", filename).as_str(),
+                )
+                .set_body_bytes(body),
+        )
+        .mount(&self.server)
+        .await;
+        Ok(format!("{}{}", self.server.uri(), artifact_path))
+    }
+
+    /// Like `serve_fixture`, but sends the artifact with an `ETag` and answers requests
+    /// carrying it in `If-None-Match` with a `304 Not Modified`.
+    pub async fn serve_fixture_with_etag(
+        &self,
+        artifact_path: &str,
+        fixture: &str,
+        etag: &str,
+    ) -> std::io::Result<String> {
+        let body = tar_gz_dir(&fixtures_dir().join(fixture))?;
+        Mock::given(method("GET"))
+            .and(path(artifact_path))
+            .and(header("if-none-match", etag))
+            .respond_with(ResponseTemplate::new(304).insert_header("etag", etag))
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(artifact_path))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", etag)
+                    .set_body_bytes(body),
+            )
+            .mount(&self.server)
+            .await;
+        Ok(format!("{}{}", self.server.uri(), artifact_path))
+    }
+
+    /// Values of the `name` header of the requests received so far.
+    pub async fn request_headers(&self, name: &str) -> Vec<String> {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|request| request.headers.get(name))
+            .filter_map(|value| value.to_str().ok())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// User agents of the requests received so far.
+    pub async fn user_agents(&self) -> Vec<String> {
+        self.request_headers("user-agent").await
+    }
+
+    /// Number of requests received so far.
+    pub async fn request_count(&self) -> usize {
+        self.server
+            .received_requests()
+            .await
+            .map(|requests| requests.len())
+            .unwrap_or_default()
+    }
+}
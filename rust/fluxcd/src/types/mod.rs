@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 pub mod git_repository;
 pub mod oci_repository;
 
@@ -8,6 +10,9 @@ pub use oci_repository::*;
 pub enum FluxSourceArtefact {
     Git(GitRepositoryStatusArtifact),
     Oci(OCIRepositoryStatusArtifact),
+    /// A module read from the keys of a ConfigMap rather than served by Flux, described like
+    /// a Flux artifact. Its files are stored with `Downloader::store_files`.
+    ConfigMap(GitRepositoryStatusArtifact),
 }
 
 impl FluxSourceArtefact {
@@ -15,6 +20,116 @@ impl FluxSourceArtefact {
         match self {
             FluxSourceArtefact::Git(artefact) => artefact.url.clone(),
             FluxSourceArtefact::Oci(artefact) => artefact.url.clone(),
+            FluxSourceArtefact::ConfigMap(artefact) => artefact.url.clone(),
+        }
+    }
+
+    pub fn revision(&self) -> String {
+        match self {
+            FluxSourceArtefact::Git(artefact) => artefact.revision.clone(),
+            FluxSourceArtefact::Oci(artefact) => artefact.revision.clone(),
+            FluxSourceArtefact::ConfigMap(artefact) => artefact.revision.clone(),
+        }
+    }
+
+    /// The part of the revision identifying the content: the digest (or commit) of revisions
+    /// such as `v1.0.0@sha256:<digest>`, `main@sha1:<commit>` or the legacy `v1.0.0/<digest>`.
+    ///
+    /// Digest-pinned sources keep the same content revision for good, even if the reported
+    /// revision format changes between Flux versions.
+    pub fn content_revision(&self) -> String {
+        let revision = self.revision();
+        match revision
+            .rsplit_once('@')
+            .or_else(|| revision.rsplit_once('/'))
+        {
+            Some((_, digest)) if !digest.is_empty() => digest.to_string(),
+            _ => revision,
+        }
+    }
+
+    /// Digest of the artefact in the form of '<algorithm>:<checksum>', if reported.
+    pub fn digest(&self) -> Option<String> {
+        match self {
+            FluxSourceArtefact::Git(artefact) => artefact.digest.clone(),
+            FluxSourceArtefact::Oci(artefact) => artefact.digest.clone(),
+            FluxSourceArtefact::ConfigMap(artefact) => artefact.digest.clone(),
+        }
+    }
+
+    /// Size of the artefact in bytes, if reported.
+    pub fn size(&self) -> Option<i64> {
+        match self {
+            FluxSourceArtefact::Git(artefact) => artefact.size,
+            FluxSourceArtefact::Oci(artefact) => artefact.size,
+            FluxSourceArtefact::ConfigMap(artefact) => artefact.size,
+        }
+    }
+
+    /// Upstream metadata of the artefact, such as OCI annotations.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        match self {
+            FluxSourceArtefact::Git(artefact) => artefact.metadata.clone(),
+            FluxSourceArtefact::Oci(artefact) => artefact.metadata.clone(),
+            FluxSourceArtefact::ConfigMap(artefact) => artefact.metadata.clone(),
+        }
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artefact_json() -> serde_json::Value {
+        serde_json::json!({
+            "digest": "sha256:abc",
+            "lastUpdateTime": "2024-01-01T00:00:00Z",
+            "metadata": { "org.opencontainers.image.revision": "main@sha1:abc" },
+            "path": "ocirepository/default/app/abc.tar.gz",
+            "revision": "v1.0.0@sha256:abc",
+            "size": 1024,
+            "url": "http://source-controller/ocirepository/default/app/abc.tar.gz",
+        })
+    }
+
+    #[test]
+    fn test_artefact_accessors() {
+        let git = FluxSourceArtefact::Git(serde_json::from_value(artefact_json()).unwrap());
+        let oci = FluxSourceArtefact::Oci(serde_json::from_value(artefact_json()).unwrap());
+        for artefact in [git, oci] {
+            assert_eq!(artefact.revision(), "v1.0.0@sha256:abc");
+            assert_eq!(artefact.digest().as_deref(), Some("sha256:abc"));
+            assert_eq!(artefact.size(), Some(1024));
+            assert_eq!(artefact.metadata().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_content_revision() {
+        for (revision, expected) in [
+            ("v1.0.0@sha256:abc", "sha256:abc"),
+            ("sha256:abc", "sha256:abc"),
+            ("latest/abc", "abc"),
+            ("main@sha1:6b7aab8", "sha1:6b7aab8"),
+            ("refs/heads/main@sha1:6b7aab8", "sha1:6b7aab8"),
+        ] {
+            let mut json = artefact_json();
+            json["revision"] = revision.into();
+            let artefact = FluxSourceArtefact::Oci(serde_json::from_value(json).unwrap());
+            assert_eq!(artefact.content_revision(), expected, "{revision}");
+        }
+    }
+
+    #[test]
+    fn test_artefact_accessors_optional_fields() {
+        let mut json = artefact_json();
+        for key in ["digest", "metadata", "size"] {
+            json.as_object_mut().unwrap().remove(key);
         }
+        let artefact = FluxSourceArtefact::Git(serde_json::from_value(json).unwrap());
+        assert_eq!(artefact.digest(), None);
+        assert_eq!(artefact.size(), None);
+        assert!(artefact.metadata().is_empty());
     }
 }
@@ -0,0 +1,350 @@
+#![cfg(feature = "integration")]
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use fluxcd_rs::{
+    downloader::error::DownloaderError,
+    testing::{temp_storage_dir, MockSourceController},
+    Downloader,
+};
+use reqwest_middleware::ClientBuilder;
+use tokio_util::sync::CancellationToken;
+
+const ARTIFACT_PATH: &str =
+    "/gitrepository/default/hello/6b7aab8a10d6ee8b895b0a5048f4ab0966ed29ff.tar.gz";
+
+fn downloader(host: Option<String>, storage_dir: PathBuf) -> Downloader {
+    Downloader::new(
+        ClientBuilder::new(reqwest::Client::new()).build(),
+        host,
+        Some(storage_dir),
+    )
+}
+
+#[tokio::test]
+async fn test_download_and_extract() {
+    let source_controller = MockSourceController::start().await;
+    let url = source_controller
+        .serve_fixture(ARTIFACT_PATH, "hello-kcl")
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = downloader(None, storage_dir.clone());
+    downloader.init().unwrap();
+
+    let path = downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        path,
+        storage_dir.join("default/hello/6b7aab8a10d6ee8b895b0a5048f4ab0966ed29ff")
+    );
+    assert!(path.join("kcl.mod").is_file());
+    assert!(path.join("main.k").is_file());
+
+    // The artifact is downloaded once per revision
+    downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(source_controller.request_count().await, 1);
+
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_named_by_content_disposition() {
+    let source_controller = MockSourceController::start().await;
+    let url = source_controller
+        .serve_fixture_with_filename("/download/abc.tar.gz", "hello-kcl", "hello.tar.gz")
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = downloader(None, storage_dir.clone());
+    downloader.init().unwrap();
+
+    let path = downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(path, storage_dir.join("default/hello/hello"));
+    assert!(path.join("main.k").is_file());
+    assert!(storage_dir.join("default/hello/hello.tar.gz").is_file());
+
+    // The archive is found under its Content-Disposition name
+    downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(source_controller.request_count().await, 1);
+
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_distinct_queries() {
+    let source_controller = MockSourceController::start().await;
+    let abc = source_controller
+        .serve_fixture_with_filename("/download?artifact=abc", "hello-kcl", "abc.tar.gz")
+        .await
+        .unwrap();
+    let def = source_controller
+        .serve_fixture_with_filename("/download?artifact=def", "rbac", "abc.tar.gz")
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = downloader(None, storage_dir.clone());
+    downloader.init().unwrap();
+
+    let cancel = CancellationToken::new();
+    let abc_path = downloader
+        .download(&abc, "hello", "default", &cancel)
+        .await
+        .unwrap();
+    let def_path = downloader
+        .download(&def, "hello", "default", &cancel)
+        .await
+        .unwrap();
+
+    // Same path and Content-Disposition, but distinct artifacts
+    assert_ne!(abc_path, def_path);
+    let name = abc_path.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("abc-"), "{name}");
+    assert_ne!(
+        std::fs::read_to_string(abc_path.join("main.k")).unwrap(),
+        std::fs::read_to_string(def_path.join("main.k")).unwrap()
+    );
+    assert_eq!(source_controller.request_count().await, 2);
+
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_with_host_override() {
+    let source_controller = MockSourceController::start().await;
+    source_controller
+        .serve_fixture(ARTIFACT_PATH, "hello-kcl")
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = downloader(Some(source_controller.uri()), storage_dir.clone());
+
+    let url = format!("http://source-controller.flux-system.svc.cluster.local.{ARTIFACT_PATH}");
+    let path = downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert!(path.join("main.k").is_file());
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_cancel_download() {
+    let source_controller = MockSourceController::start().await;
+    let url = source_controller
+        .serve_slow_fixture(ARTIFACT_PATH, "hello-kcl", Duration::from_secs(30))
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = downloader(None, storage_dir.clone());
+
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        }
+    });
+    let started = Instant::now();
+    let res = downloader.download(&url, "hello", "default", &cancel).await;
+
+    assert!(matches!(res, Err(DownloaderError::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let repo_dir = storage_dir.join("default/hello");
+    assert_eq!(std::fs::read_dir(&repo_dir).unwrap().count(), 0);
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_concurrent_downloads_deduplicated() {
+    let source_controller = MockSourceController::start().await;
+    let url = source_controller
+        .serve_slow_fixture(ARTIFACT_PATH, "hello-kcl", Duration::from_millis(200))
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = Arc::new(downloader(None, storage_dir.clone()));
+
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let downloader = downloader.clone();
+            let url = url.clone();
+            tokio::spawn(async move {
+                downloader
+                    .download(&url, "hello", "default", &CancellationToken::new())
+                    .await
+            })
+        })
+        .collect();
+    for task in tasks {
+        let path = task.await.unwrap().unwrap();
+        assert!(path.join("main.k").is_file());
+    }
+
+    assert_eq!(source_controller.request_count().await, 1);
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_sends_user_agent() {
+    let source_controller = MockSourceController::start().await;
+    let url = source_controller
+        .serve_fixture(ARTIFACT_PATH, "hello-kcl")
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let client = reqwest::Client::builder()
+        .user_agent("flux-kcl-operator/test")
+        .build()
+        .unwrap();
+    let downloader = Downloader::new(
+        ClientBuilder::new(client).build(),
+        None,
+        Some(storage_dir.clone()),
+    );
+
+    downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        source_controller.user_agents().await,
+        ["flux-kcl-operator/test"]
+    );
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_resume_partial_download() {
+    let source_controller = MockSourceController::start().await;
+    let offset = 100;
+    let (url, body) = source_controller
+        .serve_resumable_fixture(ARTIFACT_PATH, "hello-kcl", offset)
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = downloader(None, storage_dir.clone());
+
+    // A download interrupted by a restart left the beginning of the artifact behind
+    let repo_dir = storage_dir.join("default/hello");
+    std::fs::create_dir_all(&repo_dir).unwrap();
+    std::fs::write(
+        repo_dir.join("6b7aab8a10d6ee8b895b0a5048f4ab0966ed29ff.tar.gz.part"),
+        &body[..offset],
+    )
+    .unwrap();
+
+    let path = downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert!(path.join("main.k").is_file());
+    assert_eq!(
+        source_controller.request_headers("range").await,
+        [format!("bytes={offset}-")]
+    );
+    assert_eq!(
+        std::fs::read(repo_dir.join("6b7aab8a10d6ee8b895b0a5048f4ab0966ed29ff.tar.gz")).unwrap(),
+        body
+    );
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_conditional_download_reuses_extraction() {
+    let source_controller = MockSourceController::start().await;
+    let etag = "\"6b7aab8a\"";
+    let url = source_controller
+        .serve_fixture_with_etag(ARTIFACT_PATH, "hello-kcl", etag)
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    let downloader = downloader(None, storage_dir.clone());
+
+    let path = downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+
+    // The archive is gone, e.g. removed to save space, but its extraction is still in place
+    let archive = storage_dir.join("default/hello/6b7aab8a10d6ee8b895b0a5048f4ab0966ed29ff.tar.gz");
+    std::fs::remove_file(&archive).unwrap();
+    let reused = downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(reused, path);
+    assert!(reused.join("main.k").is_file());
+    assert!(!archive.exists());
+    assert_eq!(
+        source_controller.request_headers("if-none-match").await,
+        [etag]
+    );
+
+    // Without the extraction, the artifact is downloaded in full
+    std::fs::remove_dir_all(&path).unwrap();
+    let path = downloader
+        .download(&url, "hello", "default", &CancellationToken::new())
+        .await
+        .unwrap();
+    assert!(path.join("main.k").is_file());
+    assert!(archive.exists());
+    assert_eq!(source_controller.request_count().await, 3);
+    assert_eq!(
+        source_controller.request_headers("if-none-match").await,
+        [etag]
+    );
+
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_host_override() {
+    let source_controller = MockSourceController::start().await;
+    source_controller
+        .serve_fixture(ARTIFACT_PATH, "hello-kcl")
+        .await
+        .unwrap();
+    let storage_dir = temp_storage_dir();
+    // The global host is unreachable, the instance's own host takes precedence
+    let downloader = downloader(Some("http://127.0.0.1:1".to_string()), storage_dir.clone());
+    let url = format!("http://source-controller.flux-system.svc{ARTIFACT_PATH}");
+
+    let path = downloader
+        .download_from(
+            &url,
+            Some(&source_controller.uri()),
+            "hello",
+            "default",
+            &[],
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+    assert!(path.join("main.k").is_file());
+    assert_eq!(source_controller.request_count().await, 1);
+    std::fs::remove_dir_all(storage_dir).unwrap();
+}
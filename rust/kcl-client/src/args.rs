@@ -0,0 +1,134 @@
+use snafu::ensure;
+
+use crate::{InvalidArgumentSnafu, Result};
+
+/// Type of a top-level KCL argument.
+///
+/// KCL parses argument values as JSON where possible, so `"3"` reaches the program as an
+/// int and `"true"` as a bool. Declaring the type checks the value and keeps string
+/// arguments strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgumentType {
+    String,
+    Int,
+    Float,
+    Bool,
+}
+
+impl ArgumentType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ArgumentType::String => "string",
+            ArgumentType::Int => "int",
+            ArgumentType::Float => "float",
+            ArgumentType::Bool => "bool",
+        }
+    }
+}
+
+/// Converts an argument value to the form KCL parses as `type_`.
+///
+/// # Arguments
+/// * `name` - Name of the argument, for error messages
+/// * `value` - Value of the argument
+/// * `type_` - Declared type of the argument
+///
+/// # Returns
+///
+/// The value to pass in `ast::Argument`, or `Error::InvalidArgument` if it isn't a `type_`.
+pub(crate) fn typed_argument_value(name: &str, value: &str, type_: ArgumentType) -> Result<String> {
+    let invalid = InvalidArgumentSnafu {
+        name,
+        value,
+        expected: type_.as_str(),
+    };
+    let trimmed = value.trim();
+    match type_ {
+        ArgumentType::String => Ok(serde_json::Value::from(value).to_string()),
+        ArgumentType::Int => trimmed
+            .parse::<i64>()
+            .map(|int| int.to_string())
+            .map_err(|_| invalid.build()),
+        ArgumentType::Float => {
+            let float = trimmed.parse::<f64>().map_err(|_| invalid.build())?;
+            ensure!(float.is_finite(), invalid);
+            // Keep the fraction so that KCL doesn't parse e.g. `1.0` as an int
+            Ok(format!("{float:?}"))
+        }
+        ArgumentType::Bool => trimmed
+            .parse::<bool>()
+            .map(|bool| bool.to_string())
+            .map_err(|_| invalid.build()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use kclvm_driver::toolchain::Metadata;
+
+    use super::*;
+    use crate::{Error, ModClient};
+
+    #[test]
+    fn test_typed_argument_value() -> Result<()> {
+        assert_eq!(typed_argument_value("n", " 3 ", ArgumentType::Int)?, "3");
+        assert_eq!(typed_argument_value("n", "1", ArgumentType::Float)?, "1.0");
+        assert_eq!(
+            typed_argument_value("n", "0.5", ArgumentType::Float)?,
+            "0.5"
+        );
+        assert_eq!(
+            typed_argument_value("n", "true", ArgumentType::Bool)?,
+            "true"
+        );
+        assert_eq!(
+            typed_argument_value("n", "007", ArgumentType::String)?,
+            r#""007""#
+        );
+        assert_eq!(
+            typed_argument_value("n", r#"say "hi""#, ArgumentType::String)?,
+            r#""say \"hi\"""#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_argument_value_invalid() {
+        for (value, type_) in [
+            ("three", ArgumentType::Int),
+            ("1.5", ArgumentType::Int),
+            ("NaN", ArgumentType::Float),
+            ("yes", ArgumentType::Bool),
+        ] {
+            let res = typed_argument_value("n", value, type_);
+            assert!(matches!(res, Err(Error::InvalidArgument { .. })), "{value}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_arguments_reach_kcl() -> Result<()> {
+        let work_dir =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/fixtures/typed-args");
+        let mut client = ModClient::new(work_dir)?;
+        client.set_argument_types(HashMap::from([
+            ("replicas".to_string(), ArgumentType::Int),
+            ("debug".to_string(), ArgumentType::Bool),
+            ("version".to_string(), ArgumentType::String),
+        ]));
+        let args = HashMap::from([
+            ("replicas".to_string(), "3".to_string()),
+            ("debug".to_string(), "false".to_string()),
+            ("version".to_string(), "1".to_string()),
+        ]);
+
+        let output = client.run(Metadata::default(), &args).await?;
+
+        let types: HashMap<String, String> = serde_yaml::from_str(&output).unwrap();
+        assert_eq!(types["replicas"], "int");
+        assert_eq!(types["debug"], "bool");
+        assert_eq!(types["version"], "str");
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 #[inline]
 pub(crate) fn directory_is_not_empty<P: AsRef<Path>>(path: P) -> bool {
@@ -6,3 +6,20 @@ pub(crate) fn directory_is_not_empty<P: AsRef<Path>>(path: P) -> bool {
         .map(|mut entries| entries.next().is_some())
         .is_ok()
 }
+
+/// Resolves the `.` and `..` components of a path lexically, without touching the filesystem.
+pub(crate) fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
@@ -6,22 +6,40 @@ use crate::fs::directory_is_not_empty;
 use anyhow::Result;
 use kclvm_utils::path::PathPrefix;
 
+/// First git version supporting `git sparse-checkout`.
+const SPARSE_CHECKOUT_MIN_VERSION: (u32, u32) = (2, 25);
+
+/// Clones a git repository to `path` and checks out the requested tag or commit.
+///
+/// When `sparse_path` is set, the repository is partially cloned and only that subdirectory
+/// (plus the files at the repository root) is checked out. Git versions without
+/// `sparse-checkout` support fall back to a full clone.
 pub(crate) fn cmd_clone_git_repo_to(
     url: &str,
     branch: &Option<String>,
     tag: &Option<String>,
     commit: &Option<String>,
+    sparse_path: Option<&str>,
     path: &Path,
 ) -> Result<PathBuf> {
     if directory_is_not_empty(path) {
         return Ok(path.to_path_buf());
     }
     let path = path.adjust_canonicalization();
+    let sparse_path = sparse_path.filter(|sparse_path| {
+        let supported = git_supports_sparse_checkout();
+        if !supported {
+            tracing::warn!(
+                "git does not support sparse-checkout, cloning all of {} instead of {}",
+                url,
+                sparse_path
+            );
+        }
+        supported
+    });
+
     let mut git_clone_cmd = Command::new("git");
-    git_clone_cmd.args(["clone", url]);
-    if let Some(branch_name) = branch {
-        git_clone_cmd.args(["--branch", branch_name]);
-    }
+    git_clone_cmd.args(clone_args(url, branch, sparse_path.is_some()));
     git_clone_cmd.arg(&path);
 
     let output = git_clone_cmd.output()?;
@@ -33,6 +51,35 @@ pub(crate) fn cmd_clone_git_repo_to(
             String::from_utf8(output.stderr).unwrap()
         );
     }
+    if let Some(sparse_path) = sparse_path {
+        let output = Command::new("git")
+            .args(sparse_checkout_args(sparse_path))
+            .current_dir(&path)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to configure sparse-checkout of {}: stdout: {} stderr: {}",
+                sparse_path,
+                String::from_utf8(output.stdout).unwrap(),
+                String::from_utf8(output.stderr).unwrap()
+            );
+        }
+        // The clone ran with `--no-checkout`, populate the work tree of the default branch
+        if tag.is_none() && commit.is_none() {
+            let output = Command::new("git")
+                .arg("checkout")
+                .current_dir(&path)
+                .output()?;
+            if !output.status.success() {
+                bail!(
+                    "Failed to checkout Git repository {}: stdout: {} stderr: {}",
+                    url,
+                    String::from_utf8(output.stdout).unwrap(),
+                    String::from_utf8(output.stderr).unwrap()
+                );
+            }
+        }
+    }
     if let Some(tag_name) = tag {
         let output = Command::new("git")
             .args(["checkout", tag_name])
@@ -63,3 +110,98 @@ pub(crate) fn cmd_clone_git_repo_to(
 
     Ok(path.into())
 }
+
+/// Arguments of the `git clone` command, without the destination path.
+fn clone_args(url: &str, branch: &Option<String>, sparse: bool) -> Vec<String> {
+    let mut args = vec!["clone".to_string(), url.to_string()];
+    if let Some(branch_name) = branch {
+        args.extend(["--branch".to_string(), branch_name.clone()]);
+    }
+    if sparse {
+        // Only fetch the blobs of the checked out files, and check out after sparse-checkout
+        args.extend([
+            "--filter=blob:none".to_string(),
+            "--no-checkout".to_string(),
+        ]);
+    }
+    args
+}
+
+/// Arguments of the `git sparse-checkout` command restricting the work tree to `sparse_path`.
+fn sparse_checkout_args(sparse_path: &str) -> Vec<String> {
+    vec![
+        "sparse-checkout".to_string(),
+        "set".to_string(),
+        "--cone".to_string(),
+        sparse_path.to_string(),
+    ]
+}
+
+/// Whether the installed git supports `git sparse-checkout`.
+fn git_supports_sparse_checkout() -> bool {
+    Command::new("git")
+        .arg("version")
+        .output()
+        .ok()
+        .and_then(|output| parse_git_version(&String::from_utf8_lossy(&output.stdout)))
+        .is_some_and(|version| version >= SPARSE_CHECKOUT_MIN_VERSION)
+}
+
+/// Parses the major and minor version from the output of `git version`,
+/// e.g. `git version 2.39.2` or `git version 2.39.3 (Apple Git-146)`.
+fn parse_git_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.trim().strip_prefix("git version ")?;
+    let mut parts = version.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clone_args_sparse() {
+        let args = clone_args(
+            "https://github.com/org/repo",
+            &Some("main".to_string()),
+            true,
+        );
+        assert_eq!(
+            args,
+            [
+                "clone",
+                "https://github.com/org/repo",
+                "--branch",
+                "main",
+                "--filter=blob:none",
+                "--no-checkout"
+            ]
+        );
+        assert_eq!(
+            clone_args("https://github.com/org/repo", &None, false),
+            ["clone", "https://github.com/org/repo"]
+        );
+    }
+
+    #[test]
+    fn test_sparse_checkout_args() {
+        assert_eq!(
+            sparse_checkout_args("modules/app"),
+            ["sparse-checkout", "set", "--cone", "modules/app"]
+        );
+    }
+
+    #[test]
+    fn test_parse_git_version() {
+        assert_eq!(parse_git_version("git version 2.39.2\n"), Some((2, 39)));
+        assert_eq!(
+            parse_git_version("git version 2.39.3 (Apple Git-146)"),
+            Some((2, 39))
+        );
+        assert_eq!(parse_git_version("git version 1.8.3.1"), Some((1, 8)));
+        assert_eq!(parse_git_version("not git"), None);
+        assert!(parse_git_version("git version 2.24.0").unwrap() < SPARSE_CHECKOUT_MIN_VERSION);
+    }
+}
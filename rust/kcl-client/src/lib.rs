@@ -1,17 +1,30 @@
+mod args;
 mod fs;
 mod git;
+mod names;
 mod oci;
+mod registry;
+
+pub use args::ArgumentType;
+pub use names::{import_name, PackageNames};
+pub use oci::{new_oci_client, DEFAULT_LAYER_MEDIA_TYPES, DEFAULT_OCI_TIMEOUT};
+pub use oci_distribution::Client as OciClient;
+pub use registry::{RegistryCredentials, RegistryLimiter, RegistryProvider};
 
 use std::collections::HashMap;
 use std::path::Path;
-use std::{path::PathBuf, sync::Arc};
+use std::time::Duration;
+use std::{
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
 
 use git::cmd_clone_git_repo_to;
 use indexmap::IndexSet;
 use kclvm_ast::ast;
 use kclvm_config::modfile::{
     get_vendor_home, load_mod_file, load_mod_lock_file, Dependency, GitSource, LockDependency,
-    ModFile, ModLockFile, OciSource,
+    ModFile, ModLockFile, OciSource, KCL_MOD_LOCK_FILE,
 };
 use kclvm_driver::toolchain::{Metadata, Package};
 use kclvm_parser::ParseSessionRef;
@@ -20,9 +33,11 @@ use kclvm_utils::fslock::open_lock_file;
 use oci_distribution::errors::OciDistributionError;
 use oci_distribution::secrets::RegistryAuth;
 use oci_distribution::{Client, ParseError, Reference, RegistryOperation};
+use regex::Regex;
 
-use snafu::{ResultExt, Snafu};
+use snafu::{ensure, ResultExt, Snafu};
 use strum::{EnumDiscriminants, IntoStaticStr};
+use tokio_util::sync::CancellationToken;
 
 pub const DEFAULT_OCI_REGISTRY: &str = "ghcr.io/kcl-lang";
 pub const KCL_SRC_URL_ENV_VAR: &str = "KCL_SRC_URL";
@@ -42,6 +57,9 @@ pub enum Error {
     #[snafu(display("Failed to auth OCI client: {}", source))]
     OciAuth { source: OciDistributionError },
 
+    #[snafu(display("Registry provider {} is not supported", provider))]
+    UnsupportedRegistryProvider { provider: RegistryProvider },
+
     #[snafu(display("Failed to lock mod file: {}", source))]
     LockGuard { source: std::io::Error },
 
@@ -54,18 +72,177 @@ pub enum Error {
     #[snafu(display("Failed to create recursive dirs: {}", source))]
     CreateAllDirs { source: std::io::Error },
 
+    #[snafu(display("Vendor home {} is not writable: {}", path.display(), source))]
+    VendorHomeNotWritable {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Failed to pull and extract: {}", source))]
     OciPullAndExtract { source: anyhow::Error },
 
+    #[snafu(display("Pulling {} timed out after {:?}", name, timeout))]
+    OciPullTimeout { name: String, timeout: Duration },
+
     #[snafu(display("Failed to exec and render program: {}", source))]
     ExecProgram { source: anyhow::Error },
 
-    #[snafu(display("Failed to exec and render program, message: {}", message))]
-    RawExecProgram { message: String },
+    #[snafu(display("Invalid entry pattern {}: {}", pattern, source))]
+    InvalidEntryPattern {
+        pattern: String,
+        source: glob::PatternError,
+    },
+
+    #[snafu(display("Entry pattern {} matches no file in {}", pattern, work_dir.display()))]
+    EntryNoMatch { pattern: String, work_dir: PathBuf },
+
+    #[snafu(display("Failed to resolve version range of {}: {}", name, source))]
+    ResolveVersionRange { name: String, source: anyhow::Error },
+
+    #[snafu(display("Failed to serialize mod lock file: {}", source))]
+    SerializeModLockFile { source: toml::ser::Error },
+
+    #[snafu(display("Failed to write mod lock file: {}", source))]
+    WriteModLockFile { source: std::io::Error },
+
+    #[snafu(display("Invalid semver filter: {}", source))]
+    InvalidSemverFilter { source: regex::Error },
+
+    #[snafu(display(
+        "Invalid override {}, expected [pkg:]path.to.field=value or [pkg:]path.to.field-",
+        spec
+    ))]
+    InvalidOverride { spec: String },
+
+    #[snafu(display("Failed to resolve KCL module imports: {}", message))]
+    ModuleResolution { message: String },
+
+    #[snafu(display("Failed to compile KCL module: {}", message))]
+    ModuleCompile { message: String },
+
+    #[snafu(display("Argument {} must be a {}, got {:?}", name, expected, value))]
+    InvalidArgument {
+        name: String,
+        value: String,
+        expected: &'static str,
+    },
+
+    #[snafu(display(
+        "Local dependency {} at {} is outside of the source",
+        name,
+        path.display()
+    ))]
+    LocalDepOutsideSource { name: String, path: PathBuf },
+
+    #[snafu(display("Packages {} and {} are both imported as {}", first, second, import))]
+    PackageNameCollision {
+        import: String,
+        first: String,
+        second: String,
+    },
+
+    #[snafu(display("Resolving or running the KCL module was cancelled"))]
+    Cancelled,
+}
+
+/// Markers in KCL error messages pointing at a missing import or dependency path rather
+/// than an error in the module itself.
+const MODULE_RESOLUTION_MARKERS: &[&str] = &[
+    "CannotFindModule",
+    "Cannot find the module",
+    "cannot find the module",
+    "No such file or directory",
+    "pkgpath",
+];
+
+impl Error {
+    /// Whether the error is likely transient (e.g. a dependency not downloaded yet) and
+    /// worth retrying soon, as opposed to an authoring error in the module.
+    pub fn is_transient(&self) -> bool {
+        !matches!(
+            self,
+            Error::ModuleCompile { .. }
+                | Error::InvalidArgument { .. }
+                | Error::LocalDepOutsideSource { .. }
+                | Error::PackageNameCollision { .. }
+                | Error::EntryNoMatch { .. }
+                | Error::InvalidOverride { .. }
+        )
+    }
+}
+
+/// Classifies the error message of a failed KCL program execution.
+pub(crate) fn exec_program_error(message: String) -> Error {
+    if MODULE_RESOLUTION_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+    {
+        Error::ModuleResolution { message }
+    } else {
+        Error::ModuleCompile { message }
+    }
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Vendor home of the clients without a vendor of their own, see `set_default_vendor_home`.
+static DEFAULT_VENDOR_HOME: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the vendor home of the clients without a vendor of their own (see
+/// `ModClient::set_vendor`) instead of the KCL default, which may not be writable in
+/// containers with a read-only home.
+///
+/// The directory is created, with `mode` if set, and checked to be writable. Only the first
+/// call has an effect.
+///
+/// # Returns
+/// The default vendor home in effect
+pub fn set_default_vendor_home<P: AsRef<Path>>(
+    vendor_home: P,
+    mode: Option<u32>,
+) -> Result<PathBuf> {
+    let path = create_vendor_home(vendor_home.as_ref(), mode)?;
+    Ok(DEFAULT_VENDOR_HOME.get_or_init(|| path).clone())
+}
+
+/// Creates the vendor home `path`, with `mode` if set, and checks that it is writable.
+fn create_vendor_home(path: &Path, mode: Option<u32>) -> Result<PathBuf> {
+    let path = path.to_path_buf();
+    let not_writable = |source| Error::VendorHomeNotWritable {
+        path: path.clone(),
+        source,
+    };
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    builder.create(&path).map_err(not_writable)?;
+
+    let probe = path.join(".write-probe");
+    std::fs::File::create(&probe).map_err(not_writable)?;
+    std::fs::remove_file(&probe).map_err(not_writable)?;
+    Ok(path)
+}
+
+/// Vendor home of the clients without a vendor of their own: the default one, see
+/// `set_default_vendor_home`, or else the KCL one.
+pub fn vendor_home() -> PathBuf {
+    vendor_home_or_kcl(DEFAULT_VENDOR_HOME.get())
+}
+
+/// `default`, the default vendor home if set, or else the KCL one.
+fn vendor_home_or_kcl(default: Option<&PathBuf>) -> PathBuf {
+    match default {
+        Some(vendor_home) => vendor_home.clone(),
+        None => PathBuf::from(get_vendor_home()),
+    }
+}
+
 #[derive(Default)]
 pub struct ModClient {
     /// The mod file config of current module.
@@ -76,6 +253,34 @@ pub struct ModClient {
     work_dir: PathBuf,
     /// Optional vendor home.
     vendor: Option<PathBuf>,
+    /// Optional base directory of the git dependency clones, the vendor home when unset.
+    git_vendor: Option<PathBuf>,
+    /// External package overrides, taking precedence over the resolved dependencies.
+    external_packages: HashMap<String, String>,
+    /// Optional filter for the tags considered when resolving version ranges.
+    semver_filter: Option<Regex>,
+    /// Package and import names of the resolved dependencies.
+    package_names: PackageNames,
+    /// Subdirectories to sparse-checkout, by git dependency name.
+    git_sparse_paths: HashMap<String, String>,
+    /// Expected media types of the layers of OCI packages.
+    layer_media_types: Vec<String>,
+    /// Root of the downloaded source, local dependencies must resolve within it.
+    source_root: Option<PathBuf>,
+    /// Declared types of the arguments passed to `run`, by name.
+    argument_types: HashMap<String, ArgumentType>,
+    /// Entry files overriding those of the `kcl.mod` profile, may be glob patterns.
+    entries: Vec<String>,
+    /// Override expressions applied to the program, like `kcl -O`.
+    overrides: Vec<String>,
+    /// Cancels dependency downloads and program execution that did not start yet.
+    cancel: CancellationToken,
+    /// Credentials of the registries of OCI dependencies.
+    registry_credentials: Arc<RegistryCredentials>,
+    /// Limit of the concurrent OCI pulls per registry host, shared with other clients.
+    registry_limiter: Arc<RegistryLimiter>,
+    /// Timeout of each OCI pull, `DEFAULT_OCI_TIMEOUT` when unset.
+    oci_timeout: Option<Duration>,
     /// A lazy OCI client.
     oci_client: Arc<Client>,
 }
@@ -96,6 +301,23 @@ impl ModClient {
             mod_file: load_mod_file(&work_dir).context(LoadModFileSnafu)?,
             mod_lock_file: load_mod_lock_file(&work_dir).ok(),
             vendor: None,
+            git_vendor: None,
+            external_packages: HashMap::new(),
+            semver_filter: None,
+            package_names: PackageNames::default(),
+            git_sparse_paths: HashMap::new(),
+            layer_media_types: DEFAULT_LAYER_MEDIA_TYPES
+                .iter()
+                .map(|media_type| media_type.to_string())
+                .collect(),
+            source_root: None,
+            argument_types: HashMap::new(),
+            entries: Vec::new(),
+            overrides: Vec::new(),
+            cancel: CancellationToken::new(),
+            registry_credentials: Arc::default(),
+            registry_limiter: Arc::default(),
+            oci_timeout: None,
             oci_client,
         })
     }
@@ -107,35 +329,41 @@ impl ModClient {
             work_dir: self.work_dir.to_str().map(|s| s.to_string()),
             args: args
                 .iter()
-                .map(|(k, v)| ast::Argument {
-                    name: k.clone(),
-                    value: v.clone(),
+                .map(|(k, v)| {
+                    let value = match self.argument_types.get(k) {
+                        Some(type_) => args::typed_argument_value(k, v, *type_)?,
+                        None => v.clone(),
+                    };
+                    Ok(ast::Argument {
+                        name: k.clone(),
+                        value,
+                    })
                 })
-                .collect(),
+                .collect::<Result<_>>()?,
             ..Default::default()
         };
 
-        let packages_map: HashMap<String, String> = metadata
-            .packages
-            .into_iter()
-            .map(|(name, package)| (name, package.manifest_path.to_string_lossy().to_string()))
-            .collect();
+        let packages_map = external_pkg_map(metadata, &self.external_packages, &self.package_names);
 
         exec_args.set_external_pkg_from_package_maps(packages_map);
 
-        if let Some(profile) = &self.mod_file.profile {
+        if !self.entries.is_empty() {
+            exec_args.k_filename_list = self.entries.clone();
+        } else if let Some(profile) = &self.mod_file.profile {
             exec_args.k_filename_list = profile
                 .entries
                 .clone()
                 .unwrap_or(vec!["main.k".to_string()]);
         }
+        exec_args.k_filename_list = expand_entries(&self.work_dir, &exec_args.k_filename_list)?;
+        exec_args.overrides = self.overrides.clone();
 
+        // The KCL execution itself can't be interrupted, so check right before starting it
+        self.check_cancelled()?;
         let res = kclvm_runner::exec_program(sess, &exec_args).context(ExecProgramSnafu)?;
 
         if !res.err_message.is_empty() {
-            return Err(Error::RawExecProgram {
-                message: res.err_message,
-            });
+            return Err(exec_program_error(res.err_message));
         }
 
         Ok(res.yaml_result)
@@ -183,6 +411,121 @@ impl ModClient {
         self
     }
 
+    /// Set the base directory of the git dependency clones, e.g. on a larger volume than the
+    /// vendor home of the other dependencies.
+    pub fn set_git_vendor<P: AsRef<Path>>(&mut self, git_vendor: P) -> &mut Self {
+        self.git_vendor = Some(git_vendor.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the external package overrides, mapping a package name to a local path.
+    ///
+    /// Overrides take precedence over the packages resolved from `kcl.mod`.
+    pub fn set_external_packages(&mut self, packages: HashMap<String, String>) -> &mut Self {
+        self.external_packages = packages;
+        self
+    }
+
+    /// Set the subdirectories to sparse-checkout for git dependencies, mapping a dependency
+    /// name to the path within its repository.
+    ///
+    /// Only that subdirectory and the files at the repository root are checked out, which
+    /// avoids checking out whole monorepos.
+    pub fn set_git_sparse_paths(&mut self, paths: HashMap<String, String>) -> &mut Self {
+        self.git_sparse_paths = paths;
+        self
+    }
+
+    /// Set the expected media types of the layers of OCI packages, for registries populated
+    /// by other KCL tool versions. Defaults to `DEFAULT_LAYER_MEDIA_TYPES`.
+    ///
+    /// Packages with a single layer are accepted whatever its media type.
+    pub fn set_layer_media_types(&mut self, media_types: Vec<String>) -> &mut Self {
+        self.layer_media_types = media_types;
+        self
+    }
+
+    /// Set the credentials used to pull OCI dependencies, anonymous by default.
+    pub fn set_registry_credentials(&mut self, credentials: Arc<RegistryCredentials>) -> &mut Self {
+        self.registry_credentials = credentials;
+        self
+    }
+
+    /// Set the limiter of the concurrent OCI pulls per registry host, shared by the clients
+    /// of all modules. Pulls are unlimited by default.
+    pub fn set_registry_limiter(&mut self, limiter: Arc<RegistryLimiter>) -> &mut Self {
+        self.registry_limiter = limiter;
+        self
+    }
+
+    /// Set the timeout of each OCI pull, e.g. the `timeout` of the Flux OCIRepository the
+    /// module comes from. Defaults to `DEFAULT_OCI_TIMEOUT`.
+    pub fn set_oci_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.oci_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the root of the downloaded source the module is part of. Local dependencies
+    /// resolving outside of it fail with `Error::LocalDepOutsideSource`.
+    pub fn set_source_root<P: AsRef<Path>>(&mut self, source_root: P) -> &mut Self {
+        self.source_root = Some(source_root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the declared types of arguments by name, checked and converted by `run`.
+    ///
+    /// Undeclared arguments are passed as they are and parsed by KCL as JSON where possible.
+    pub fn set_argument_types(&mut self, types: HashMap<String, ArgumentType>) -> &mut Self {
+        self.argument_types = types;
+        self
+    }
+
+    /// Set the entry files of the module, overriding the entries of the `kcl.mod` profile.
+    ///
+    /// Entries are relative to the work directory and may be glob patterns such as
+    /// `k8s/*.k`, see `run`.
+    pub fn set_entries(&mut self, entries: Vec<String>) -> &mut Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Set the token cancelling `resolve_all_deps` and `run`, e.g. when the reconciliation
+    /// is superseded. Both return `Error::Cancelled` once it is cancelled.
+    pub fn set_cancellation_token(&mut self, cancel: CancellationToken) -> &mut Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return CancelledSnafu.fail();
+        }
+        Ok(())
+    }
+
+    /// Set the regex filter applied to registry tags when resolving version ranges.
+    pub fn set_semver_filter(&mut self, filter: &str) -> Result<&mut Self> {
+        self.semver_filter = Some(Regex::new(filter).context(InvalidSemverFilterSnafu)?);
+        Ok(self)
+    }
+
+    /// Set the override expressions applied to the program at compile time, like `kcl -O`,
+    /// e.g. `app.replicas=3` or `__main__:app.labels-` to delete a field.
+    ///
+    /// Returns `Error::InvalidOverride` on the first malformed expression.
+    pub fn set_overrides(&mut self, overrides: Vec<String>) -> Result<&mut Self> {
+        if let Some(spec) = overrides.iter().find(|spec| !is_valid_override(spec)) {
+            return InvalidOverrideSnafu { spec }.fail();
+        }
+        self.overrides = overrides;
+        Ok(self)
+    }
+
+    /// Package and import names of the dependencies resolved by `resolve_all_deps`.
+    pub fn package_names(&self) -> &PackageNames {
+        &self.package_names
+    }
+
     /// Lock the kcl.mod file and resolve package deps to metadata, note this function will download
     /// deps from remote sources. If the dependency is downloaded to the local path, calculate the
     /// package metadata.
@@ -202,33 +545,66 @@ impl ModClient {
     /// If the dependency is downloaded to the local path, calculate the package metadata.
     pub async fn resolve_all_deps(&mut self, update: bool) -> Result<Metadata> {
         let mut metadata = Metadata::default();
-        match &self.mod_file.dependencies {
+        match self.mod_file.dependencies.clone() {
             Some(dependencies) if !dependencies.is_empty() => {
                 let vendor = self.get_vendor_path()?;
+                let git_vendor = self.get_git_vendor_path()?;
                 let mut paths: IndexSet<PathBuf> = IndexSet::default();
-                for (name, dep) in dependencies {
+                // Concrete tags of the version range dependencies, by name
+                let mut resolved_tags = Vec::new();
+                for (name, dep) in &dependencies {
+                    self.check_cancelled()?;
                     let path = if update {
-                        let path = self.download_dep_to_vendor(name, dep, &vendor).await?;
+                        let resolved = self.resolve_version_range(name, dep).await?;
+                        if let (Dependency::Version(range), Dependency::Version(tag)) =
+                            (dep, &resolved)
+                        {
+                            if range != tag {
+                                resolved_tags.push((name.clone(), tag.clone()));
+                            }
+                        }
+                        let dep = &resolved;
+                        let vendor = match dep {
+                            Dependency::Git(_) => &git_vendor,
+                            _ => &vendor,
+                        };
+                        let path = tokio::select! {
+                            biased;
+                            _ = self.cancel.cancelled() => return CancelledSnafu.fail(),
+                            path = self.download_dep_to_vendor(name, dep, vendor) => path?,
+                        };
                         paths.insert(path.clone());
                         path
                     } else {
                         Default::default()
                     };
+                    let import = self.register_package_name(name)?;
                     metadata.packages.insert(
-                        name.replace('-', "_"),
+                        import,
                         Package {
                             name: name.to_string(),
                             manifest_path: path,
                         },
                     );
                 }
+                self.lock_resolved_tags(resolved_tags)?;
                 for path in paths {
                     if let Ok(mut client) =
                         ModClient::new_with_oci_client(path, self.oci_client.clone())
                     {
+                        client.vendor = self.vendor.clone();
+                        client.git_vendor = self.git_vendor.clone();
+                        client.semver_filter = self.semver_filter.clone();
+                        client.layer_media_types = self.layer_media_types.clone();
+                        client.cancel = self.cancel.clone();
+                        client.registry_credentials = self.registry_credentials.clone();
+                        client.registry_limiter = self.registry_limiter.clone();
+                        client.oci_timeout = self.oci_timeout;
+                        client.source_root = self.source_root.clone();
                         let new_metadata = Box::pin(client.resolve_all_deps(update)).await?;
-                        for (name, package) in new_metadata.packages {
-                            metadata.packages.entry(name).or_insert(package);
+                        for (import, package) in new_metadata.packages {
+                            self.register_package_name(&package.name)?;
+                            metadata.packages.entry(import).or_insert(package);
                         }
                     }
                 }
@@ -238,7 +614,81 @@ impl ModClient {
         }
     }
 
+    /// Registers the package `name` of a dependency, see `PackageNames::insert`.
+    ///
+    /// # Returns
+    /// The name KCL imports the package by.
+    fn register_package_name(&mut self, name: &str) -> Result<String> {
+        self.package_names
+            .insert(name)
+            .map_err(|first| Error::PackageNameCollision {
+                import: import_name(name),
+                first,
+                second: name.to_string(),
+            })
+    }
+
+    /// Records the concrete tags resolved for version range dependencies in the kcl.mod.lock
+    /// file of the module, so that it pins the packages that were pulled.
+    ///
+    /// # Arguments
+    /// * `resolved_tags` - Names of the version range dependencies and their resolved tags
+    fn lock_resolved_tags(&mut self, resolved_tags: Vec<(String, String)>) -> Result<()> {
+        if resolved_tags.is_empty() {
+            return Ok(());
+        }
+        let registry = self.default_oci_registry();
+        let lock_file = self.mod_lock_file.get_or_insert_with(ModLockFile::default);
+        let dependencies = lock_file.dependencies.get_or_insert_with(Default::default);
+        for (name, tag) in resolved_tags {
+            let image = oci::oci_reg_repo_join(&registry, &name);
+            let reference = Reference::try_from(oci::strip_oci_scheme_prefix(&image))
+                .context(ParseOciRegistrySnafu)?;
+            let full_name = oci_dep_path(&name, &Some(tag.clone()));
+            dependencies.insert(
+                name.clone(),
+                LockDependency {
+                    name,
+                    full_name: Some(full_name),
+                    version: Some(tag.clone()),
+                    reg: Some(reference.registry().to_string()),
+                    repo: Some(reference.repository().to_string()),
+                    oci_tag: Some(tag),
+                    ..Default::default()
+                },
+            );
+        }
+        let content = toml::to_string(lock_file).context(SerializeModLockFileSnafu)?;
+        std::fs::write(self.work_dir.join(KCL_MOD_LOCK_FILE), content)
+            .context(WriteModLockFileSnafu)
+    }
+
+    /// Resolve a version range dependency (e.g. `^1.2`) to the highest matching tag in the
+    /// registry. Other dependencies, including exact versions, are returned as they are.
+    pub async fn resolve_version_range(&self, name: &str, dep: &Dependency) -> Result<Dependency> {
+        let Dependency::Version(version) = dep else {
+            return Ok(dep.clone());
+        };
+        let Some(req) = oci::parse_version_range(version) else {
+            return Ok(dep.clone());
+        };
+        let tag = oci::resolve_semver_tag(
+            &self.oci_client,
+            &self.registry_credentials,
+            &oci::oci_reg_repo_join(&self.default_oci_registry(), name),
+            &req,
+            self.semver_filter.as_ref(),
+        )
+        .await
+        .context(ResolveVersionRangeSnafu { name })?;
+        tracing::info!("Resolved {} version range {} to {}", name, version, tag);
+        Ok(Dependency::Version(tag))
+    }
+
     /// Download a dependency to the local path.
+    ///
+    /// The vendor directory is shared, so the package is written under its file lock to
+    /// serialize concurrent writers of the same package.
     pub async fn download_dep_to_vendor(
         &self,
         name: &str,
@@ -247,6 +697,10 @@ impl ModClient {
     ) -> Result<PathBuf> {
         let path = self.get_local_path_from_dep(name, dep);
         let path = Path::new(vendor).join(path);
+        let _lock = match dep {
+            Dependency::Local(_) => None,
+            _ => Some(lock_vendor_package(vendor, &path).await?),
+        };
         match dep {
             Dependency::Version(version) => {
                 self.download_oci_source_to(
@@ -255,35 +709,63 @@ impl ModClient {
                         oci: oci::oci_reg_repo_join(&self.default_oci_registry(), name),
                         tag: Some(version.to_string()),
                     },
+                    vendor,
+                )
+                .await
+            }
+            Dependency::Git(git_source) => {
+                self.download_git_source_to(
+                    git_source,
+                    self.git_sparse_paths.get(name).map(String::as_str),
                     &path,
                 )
                 .await
             }
-            Dependency::Git(git_source) => self.download_git_source_to(git_source, &path).await,
             Dependency::Oci(oci_source) => {
-                self.download_oci_source_to(name, oci_source, &path).await
+                self.download_oci_source_to(name, oci_source, vendor).await
             }
             Dependency::Local(_) => {
-                // Nothing to do for the local source.
+                // Nothing to download for the local source, but it must be part of the source
+                if let Some(source_root) = &self.source_root {
+                    check_local_dep(name, &path, source_root)?;
+                }
                 Ok(path)
             }
         }
     }
 
-    /// Get the vendor path.
+    /// Get the vendor path: the vendor of the client, or else the default vendor home, see
+    /// `set_default_vendor_home`, or else the KCL one.
     pub fn get_vendor_path(&self) -> Result<PathBuf> {
+        self.vendor_path_or(vendor_home())
+    }
+
+    /// The vendor of the client, created if missing, or else `vendor_home`.
+    fn vendor_path_or(&self, vendor_home: PathBuf) -> Result<PathBuf> {
         Ok(match &self.vendor {
             Some(vendor) => {
                 std::fs::create_dir_all(vendor).context(CreateAllDirsSnafu)?;
                 vendor.to_path_buf()
             }
-            None => PathBuf::from(get_vendor_home()),
+            None => vendor_home,
         })
     }
 
+    /// Get the base directory of the git dependency clones, see `set_git_vendor`.
+    pub fn get_git_vendor_path(&self) -> Result<PathBuf> {
+        match &self.git_vendor {
+            Some(git_vendor) => {
+                std::fs::create_dir_all(git_vendor).context(CreateAllDirsSnafu)?;
+                Ok(git_vendor.to_path_buf())
+            }
+            None => self.get_vendor_path(),
+        }
+    }
+
     pub async fn download_git_source_to(
         &self,
         git_source: &GitSource,
+        sparse_path: Option<&str>,
         path: &Path,
     ) -> Result<PathBuf> {
         let path = cmd_clone_git_repo_to(
@@ -291,26 +773,47 @@ impl ModClient {
             &git_source.branch,
             &git_source.tag,
             &git_source.commit,
+            sparse_path,
             path,
         )
         .context(GitCloneRepoSnafu)?;
         Ok(path)
     }
 
+    /// Pulls an OCI package into `save_dir`/`{name}_{tag}`.
     pub async fn download_oci_source_to(
         &self,
         name: &str,
         oci_source: &OciSource,
-        path: &Path,
+        save_dir: &Path,
     ) -> Result<PathBuf> {
-        let path = oci::pull_oci_and_extract_layer(
-            &self.oci_client,
-            name,
-            &oci_source.oci,
-            &oci_source.tag,
-            path,
+        // Waiting for a pull slot of the registry doesn't count in the pull timeout
+        let registry = Reference::try_from(oci::strip_oci_scheme_prefix(&oci_source.oci))
+            .map(|reference| reference.resolve_registry().to_string())
+            .unwrap_or_default();
+        let _permit = tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => return CancelledSnafu.fail(),
+            permit = self.registry_limiter.acquire(&registry) => permit,
+        };
+        let timeout = self.oci_timeout.unwrap_or(DEFAULT_OCI_TIMEOUT);
+        let path = tokio::time::timeout(
+            timeout,
+            oci::pull_oci_and_extract_layer(
+                &self.oci_client,
+                &self.registry_credentials,
+                name,
+                &oci_source.oci,
+                &oci_source.tag,
+                &self.layer_media_types,
+                save_dir,
+            ),
         )
         .await
+        .map_err(|_| Error::OciPullTimeout {
+            name: name.to_string(),
+            timeout,
+        })?
         .context(OciPullAndExtractSnafu)?;
         Ok(path)
     }
@@ -332,8 +835,7 @@ impl ModClient {
                     format!("{name}_latest")
                 }
             }
-            // Just returns the folder.
-            Dependency::Oci(_) => "".to_string(),
+            Dependency::Oci(oci_source) => oci_dep_path(name, &oci_source.tag),
             Dependency::Local(local_source) => {
                 let local_path = PathBuf::from(&local_source.path);
                 if local_path.is_absolute() {
@@ -351,7 +853,10 @@ impl ModClient {
     /// Get the lock dependency store path
     pub fn get_local_path_from_lock_dep(&self, lock_dep: &LockDependency) -> Option<String> {
         if lock_dep.reg.is_some() {
-            lock_dep.full_name.clone()
+            match &lock_dep.oci_tag {
+                Some(_) => Some(oci_dep_path(&lock_dep.name, &lock_dep.oci_tag)),
+                None => lock_dep.full_name.clone(),
+            }
         } else if let Some(git_url) = &lock_dep.url {
             Some(self.get_local_path_from_dep(
                 &lock_dep.name,
@@ -379,10 +884,16 @@ impl ModClient {
         if let Some(mod_lock_file) = &self.mod_lock_file {
             if let Some(dependencies) = &mod_lock_file.dependencies {
                 let vendor = self.get_vendor_path().ok()?;
+                let git_vendor = self.get_git_vendor_path().ok()?;
                 let mut metadata = Metadata::default();
                 for (name, dep) in dependencies {
+                    let vendor = if dep.reg.is_none() && dep.url.is_some() {
+                        &git_vendor
+                    } else {
+                        &vendor
+                    };
                     metadata.packages.insert(
-                        name.replace('-', "_").to_string(),
+                        import_name(name),
                         Package {
                             name: name.to_string(),
                             manifest_path: match self.get_local_path_from_lock_dep(dep) {
@@ -398,3 +909,645 @@ impl ModClient {
         None
     }
 }
+
+/// Builds the external package map passed to the KCL runner, keyed by import name. Entries from
+/// `overrides`, keyed by package or import name, take precedence over the packages resolved in
+/// `metadata`.
+pub(crate) fn external_pkg_map(
+    metadata: Metadata,
+    overrides: &HashMap<String, String>,
+    names: &PackageNames,
+) -> HashMap<String, String> {
+    metadata
+        .packages
+        .into_iter()
+        .map(|(name, package)| (name, package.manifest_path.to_string_lossy().to_string()))
+        .chain(
+            overrides
+                .iter()
+                .map(|(name, path)| (names.resolve(name), path.clone())),
+        )
+        .collect()
+}
+
+/// Whether `spec` is a KCL override expression: `[pkg:]path.to.field` followed by `=value`,
+/// `:value` or `+=value`, or by `-` to delete the field.
+fn is_valid_override(spec: &str) -> bool {
+    static OVERRIDE: OnceLock<Regex> = OnceLock::new();
+    OVERRIDE
+        .get_or_init(|| {
+            Regex::new(r"^(?:[A-Za-z_][\w.]*:)?[A-Za-z_]\w*(?:\.[A-Za-z_]\w*)*(?:(?:=|:|\+=).+|-)$")
+                .unwrap()
+        })
+        .is_match(spec)
+}
+
+/// Expands the glob patterns (e.g. `k8s/*.k`) among the entry files of a module into the
+/// files they match, in alphabetical order. Other entries are kept as they are.
+///
+/// # Arguments
+/// * `work_dir` - Directory of the module, patterns are relative to it
+/// * `entries` - Entry files and patterns
+///
+/// # Returns
+/// The entry files, or `Error::EntryNoMatch` when a pattern matches no file
+pub(crate) fn expand_entries(work_dir: &Path, entries: &[String]) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in entries {
+        if !entry.contains(['*', '?', '[']) {
+            files.push(entry.clone());
+            continue;
+        }
+        let pattern = format!(
+            "{}/{}",
+            glob::Pattern::escape(&work_dir.to_string_lossy()),
+            entry
+        );
+        let mut matches: Vec<String> = glob::glob(&pattern)
+            .context(InvalidEntryPatternSnafu { pattern: entry })?
+            .filter_map(|path| path.ok())
+            .filter(|path| path.is_file())
+            .map(|path| {
+                path.strip_prefix(work_dir)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        ensure!(
+            !matches.is_empty(),
+            EntryNoMatchSnafu {
+                pattern: entry,
+                work_dir
+            }
+        );
+        matches.sort();
+        files.extend(matches);
+    }
+    Ok(files)
+}
+
+/// Checks that the local dependency `name` at `path` resolves within `source_root`, as a
+/// relative path like `../shared` may escape the extracted source.
+fn check_local_dep(name: &str, path: &Path, source_root: &Path) -> Result<()> {
+    let path = fs::normalize_path(path);
+    if !path.starts_with(fs::normalize_path(source_root)) {
+        return LocalDepOutsideSourceSnafu { name, path }.fail();
+    }
+    Ok(())
+}
+
+/// Takes the file lock of the vendored package at `path`, so that concurrent writers of the
+/// same package, in this or another process, serialize. The lock is released when dropped.
+async fn lock_vendor_package(vendor: &Path, path: &Path) -> Result<impl Sized> {
+    std::fs::create_dir_all(vendor).context(CreateAllDirsSnafu)?;
+    let lock_path = format!("{}.lock", path.to_string_lossy());
+    tokio::task::spawn_blocking(move || {
+        let mut lock_guard = open_lock_file(&lock_path).context(OpenLockFileSnafu)?;
+        lock_guard.lock().context(LockGuardSnafu)?;
+        Ok(lock_guard)
+    })
+    .await
+    .map_err(|e| Error::LockGuard {
+        source: std::io::Error::other(e),
+    })?
+}
+
+/// Vendor directory of an OCI dependency, keyed by name and tag so that distinct
+/// dependencies don't overwrite each other and can be shared across instances.
+fn oci_dep_path(name: &str, tag: &Option<String>) -> String {
+    format!("{}_{}", name, tag.as_deref().unwrap_or("latest"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use rand::Rng;
+
+    use super::*;
+
+    fn temp_dir(prefix: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("{prefix}-{:016x}", rand::thread_rng().gen::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    }
+
+    #[test]
+    fn test_external_pkg_map_override_wins() {
+        let mut metadata = Metadata::default();
+        metadata.packages.insert(
+            "k8s".to_string(),
+            Package {
+                name: "k8s".to_string(),
+                manifest_path: PathBuf::from("/vendor/k8s_1.31"),
+            },
+        );
+        metadata.packages.insert(
+            "my_lib".to_string(),
+            Package {
+                name: "my-lib".to_string(),
+                manifest_path: PathBuf::from("/vendor/my-lib_0.1.0"),
+            },
+        );
+        let overrides = HashMap::from([("my-lib".to_string(), "/debug/my-lib".to_string())]);
+
+        let map = external_pkg_map(metadata, &overrides, &PackageNames::default());
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["k8s"], "/vendor/k8s_1.31");
+        assert_eq!(map["my_lib"], "/debug/my-lib");
+    }
+
+    #[test]
+    fn test_oci_dep_path_distinct() {
+        let k8s = oci_dep_path("k8s", &Some("1.31.2".to_string()));
+        let konfig = oci_dep_path("konfig", &Some("v0.9.0".to_string()));
+        assert_eq!(k8s, "k8s_1.31.2");
+        assert_eq!(konfig, "konfig_v0.9.0");
+        assert_ne!(k8s, oci_dep_path("k8s", &Some("1.30.0".to_string())));
+        assert_eq!(oci_dep_path("k8s", &None), "k8s_latest");
+    }
+
+    #[tokio::test]
+    async fn test_download_dep_to_vendor_path() {
+        let dir = std::env::temp_dir().join(format!("vendor-path-{}", std::process::id()));
+        let work_dir = dir.join("module");
+        std::fs::create_dir_all(&work_dir).unwrap();
+        std::fs::write(
+            work_dir.join("kcl.mod"),
+            "[package]\nname = \"app\"\nedition = \"v0.10.0\"\nversion = \"0.0.1\"\n",
+        )
+        .unwrap();
+        // Already vendored, so nothing is pulled
+        let vendor = dir.join("vendor");
+        std::fs::create_dir_all(vendor.join("k8s_1.31.2")).unwrap();
+        std::fs::write(vendor.join("k8s_1.31.2/kcl.mod"), "").unwrap();
+
+        let client = ModClient::new(&work_dir).unwrap();
+        let path = client
+            .download_dep_to_vendor("k8s", &Dependency::Version("1.31.2".to_string()), &vendor)
+            .await
+            .unwrap();
+        assert_eq!(path, vendor.join("k8s_1.31.2"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_exec_program_error_resolution() {
+        let message = "error[E2F04]: CannotFindModule\n --> main.k:1:1\n  |\n1 | import k8s.api.core.v1\n  | ^ Cannot find the module k8s.api.core.v1 from /vendor/k8s_1.31/api/core/v1\n";
+        let error = exec_program_error(message.to_string());
+        assert!(matches!(error, Error::ModuleResolution { .. }));
+        assert!(error.is_transient());
+    }
+
+    #[test]
+    fn test_exec_program_error_compile() {
+        for message in [
+            "error[E1001]: InvalidSyntax\n --> main.k:3:5\n  |\n3 |     name =\n  |     ^ expected one of [\"identifier\", \"literal\"] got newline\n",
+            "error[E2G22]: TypeError\n --> main.k:5:5\n  |\n5 |     replicas: \"3\"\n  |     ^ expected int, got str(3)\n",
+        ] {
+            let error = exec_program_error(message.to_string());
+            assert!(matches!(error, Error::ModuleCompile { .. }));
+            assert!(!error.is_transient());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_resolves_share_vendor() {
+        let repo = temp_dir("kcl-client-lib");
+        std::fs::write(
+            repo.join("kcl.mod"),
+            "[package]\nname = \"lib\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        std::fs::write(repo.join("main.k"), "greeting = \"hello\"\n").unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "init"]);
+        git(&repo, &["tag", "v1"]);
+
+        let module = temp_dir("kcl-client-module");
+        std::fs::write(
+            module.join("kcl.mod"),
+            format!(
+                "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nlib = {{ git = \"file://{}\", tag = \"v1\" }}\n",
+                repo.display()
+            ),
+        )
+        .unwrap();
+        let vendor = temp_dir("kcl-client-vendor");
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let module = module.clone();
+                let vendor = vendor.clone();
+                tokio::spawn(async move {
+                    let mut client = ModClient::new(&module)?;
+                    client.set_vendor(&vendor);
+                    client.resolve_all_deps(true).await
+                })
+            })
+            .collect();
+        for task in tasks {
+            let metadata = task.await.unwrap().unwrap();
+            assert!(metadata.packages["lib"].manifest_path.ends_with("lib_v1"));
+        }
+
+        let package = vendor.join("lib_v1");
+        assert_eq!(
+            std::fs::read_to_string(package.join("main.k")).unwrap(),
+            "greeting = \"hello\"\n"
+        );
+        assert!(package.join("kcl.mod").is_file());
+
+        for dir in [repo, module, vendor] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_default_vendor_home() {
+        let module = temp_dir("kcl-client-default-vendor-module");
+        std::fs::write(
+            module.join("kcl.mod"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        let vendor_home = temp_dir("kcl-client-default-vendor").join("vendor");
+
+        // Created on the fly and used by the clients without a vendor of their own. The
+        // process-wide default is left alone, as it can't be reset for the other tests
+        let default = create_vendor_home(&vendor_home, Some(0o750)).unwrap();
+        assert_eq!(default, vendor_home);
+        assert!(vendor_home.is_dir());
+        assert_eq!(vendor_home_or_kcl(Some(&default)), vendor_home);
+        assert_eq!(vendor_home_or_kcl(None), PathBuf::from(get_vendor_home()));
+        let mut client = ModClient::new(&module).unwrap();
+        assert_eq!(client.vendor_path_or(default.clone()).unwrap(), vendor_home);
+        let vendor = temp_dir("kcl-client-own-vendor");
+        client.set_vendor(&vendor);
+        assert_eq!(client.vendor_path_or(default).unwrap(), vendor);
+
+        // Files can't be vendor homes
+        let file = vendor.join("file");
+        std::fs::write(&file, "").unwrap();
+        assert!(matches!(
+            create_vendor_home(&file, None),
+            Err(Error::VendorHomeNotWritable { .. })
+        ));
+
+        for dir in [module, vendor, vendor_home.parent().unwrap().to_path_buf()] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_git_vendor_dir() {
+        let repo = temp_dir("kcl-client-git-lib");
+        std::fs::write(
+            repo.join("kcl.mod"),
+            "[package]\nname = \"lib\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "init"]);
+        git(&repo, &["tag", "v1"]);
+
+        let module = temp_dir("kcl-client-git-module");
+        std::fs::write(
+            module.join("kcl.mod"),
+            format!(
+                "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nlib = {{ git = \"file://{}\", tag = \"v1\" }}\n",
+                repo.display()
+            ),
+        )
+        .unwrap();
+        let vendor = temp_dir("kcl-client-oci-vendor");
+        let git_vendor = temp_dir("kcl-client-git-vendor").join("clones");
+
+        let mut client = ModClient::new(&module).unwrap();
+        client.set_vendor(&vendor).set_git_vendor(&git_vendor);
+        let metadata = client.resolve_all_deps(true).await.unwrap();
+
+        // Git dependencies are cloned under their own base, created on demand
+        assert_eq!(
+            metadata.packages["lib"].manifest_path,
+            git_vendor.join("lib_v1")
+        );
+        assert!(git_vendor.join("lib_v1/kcl.mod").is_file());
+        assert!(!vendor.join("lib_v1").exists());
+
+        for dir in [
+            repo,
+            module,
+            vendor,
+            git_vendor.parent().unwrap().to_path_buf(),
+        ] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oci_vendor_dir() {
+        let module = temp_dir("kcl-client-oci-module");
+        std::fs::write(
+            module.join("kcl.mod"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nk8s = \"1.31.2\"\n",
+        )
+        .unwrap();
+        // Already vendored, so nothing is pulled
+        let vendor = temp_dir("kcl-client-oci-deps");
+        std::fs::create_dir_all(vendor.join("k8s_1.31.2")).unwrap();
+        std::fs::write(vendor.join("k8s_1.31.2/kcl.mod"), "").unwrap();
+        let git_vendor = temp_dir("kcl-client-oci-git-vendor").join("clones");
+
+        let mut client = ModClient::new(&module).unwrap();
+        client.set_vendor(&vendor).set_git_vendor(&git_vendor);
+        let metadata = client.resolve_all_deps(true).await.unwrap();
+
+        // OCI dependencies stay in the vendor, not under the git base
+        assert_eq!(
+            metadata.packages["k8s"].manifest_path,
+            vendor.join("k8s_1.31.2")
+        );
+        assert!(!git_vendor.join("k8s_1.31.2").exists());
+
+        for dir in [module, vendor, git_vendor.parent().unwrap().to_path_buf()] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_version_range_locked() {
+        use oci_distribution::client::{ClientConfig, ClientProtocol};
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/kcl-lang/k8s/tags/list"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "kcl-lang/k8s",
+                "tags": ["1.30.0", "1.31.2", "2.0.0"],
+            })))
+            .mount(&server)
+            .await;
+        std::env::set_var(
+            KCL_SRC_URL_ENV_VAR,
+            format!("{}/kcl-lang", server.address()),
+        );
+
+        let module = temp_dir("kcl-client-range-module");
+        std::fs::write(
+            module.join("kcl.mod"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nk8s = \"^1.30\"\n",
+        )
+        .unwrap();
+        // Already vendored, so nothing is pulled
+        let vendor = temp_dir("kcl-client-range-deps");
+        std::fs::create_dir_all(vendor.join("k8s_1.31.2")).unwrap();
+        std::fs::write(vendor.join("k8s_1.31.2/kcl.mod"), "").unwrap();
+
+        let oci_client = Arc::new(Client::new(ClientConfig {
+            protocol: ClientProtocol::Http,
+            ..Default::default()
+        }));
+        let mut client = ModClient::new_with_oci_client(&module, oci_client).unwrap();
+        client.set_vendor(&vendor);
+        let metadata = client.resolve_all_deps(true).await.unwrap();
+        std::env::remove_var(KCL_SRC_URL_ENV_VAR);
+        assert_eq!(
+            metadata.packages["k8s"].manifest_path,
+            vendor.join("k8s_1.31.2")
+        );
+
+        // The lock file pins the concrete tag, not the range
+        let lock_file = load_mod_lock_file(&module).unwrap();
+        let locked = &lock_file.dependencies.unwrap()["k8s"];
+        assert_eq!(locked.oci_tag.as_deref(), Some("1.31.2"));
+        assert_eq!(locked.version.as_deref(), Some("1.31.2"));
+        assert_eq!(locked.repo.as_deref(), Some("kcl-lang/k8s"));
+        let mut client = ModClient::new(&module).unwrap();
+        client.set_vendor(&vendor);
+        assert_eq!(
+            client.get_metadata_from_mod_lock_file().unwrap().packages["k8s"].manifest_path,
+            vendor.join("k8s_1.31.2")
+        );
+
+        for dir in [module, vendor] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_hyphenated_package_names() {
+        let module = temp_dir("kcl-client-names");
+        let write_mod = |dir: &Path, name: &str, dependencies: &str| {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(
+                dir.join("kcl.mod"),
+                format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{dependencies}"),
+            )
+            .unwrap();
+        };
+        write_mod(&module, "app", "my-lib = { path = \"./my-lib\" }\n");
+        write_mod(
+            &module.join("my-lib"),
+            "my-lib",
+            "base-lib = { path = \"../base-lib\" }\n",
+        );
+        write_mod(&module.join("base-lib"), "base-lib", "");
+
+        let mut client = ModClient::new(&module).unwrap();
+        client.set_vendor(module.join("vendor"));
+        let metadata = client.resolve_all_deps(true).await.unwrap();
+
+        assert_eq!(metadata.packages["my_lib"].name, "my-lib");
+        assert_eq!(metadata.packages["base_lib"].name, "base-lib");
+        assert_eq!(
+            client.package_names().package_name("base_lib"),
+            Some("base-lib")
+        );
+        let map = external_pkg_map(metadata, &HashMap::new(), client.package_names());
+        assert!(map["my_lib"].ends_with("my-lib"));
+        assert!(map["base_lib"].ends_with("base-lib"));
+
+        // `my_lib` would be imported like `my-lib`
+        write_mod(
+            &module,
+            "app",
+            "my-lib = { path = \"./my-lib\" }\nmy_lib = { path = \"./base-lib\" }\n",
+        );
+        let mut client = ModClient::new(&module).unwrap();
+        client.set_vendor(module.join("vendor"));
+        let res = client.resolve_all_deps(true).await;
+        assert!(matches!(res, Err(Error::PackageNameCollision { .. })));
+
+        std::fs::remove_dir_all(module).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_oci_pull_timeout() {
+        use oci_distribution::client::{ClientConfig, ClientProtocol};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        // A registry too slow to answer within the timeout
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let module = temp_dir("kcl-client-timeout");
+        std::fs::write(
+            module.join("kcl.mod"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        let oci_client = Arc::new(Client::new(ClientConfig {
+            protocol: ClientProtocol::Http,
+            ..Default::default()
+        }));
+        let mut client = ModClient::new_with_oci_client(&module, oci_client).unwrap();
+        client.set_oci_timeout(Duration::from_millis(200));
+
+        let source = OciSource {
+            oci: format!("oci://{}/lib", server.address()),
+            tag: Some("0.1.0".to_string()),
+        };
+        let res = client
+            .download_oci_source_to("lib", &source, &module.join("vendor"))
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::OciPullTimeout { timeout, .. }) if timeout == Duration::from_millis(200)
+        ));
+
+        std::fs::remove_dir_all(module).unwrap();
+    }
+
+    #[test]
+    fn test_is_valid_override() {
+        for spec in [
+            "app.replicas=3",
+            "__main__:app.image=\"nginx:1.27\"",
+            "app.labels:{env = \"dev\"}",
+            "app.ports+=[8080]",
+            "app.labels-",
+        ] {
+            assert!(is_valid_override(spec), "{spec}");
+        }
+        for spec in [
+            "",
+            "app.replicas",
+            "=3",
+            "app..replicas=3",
+            "1app=3",
+            "app.replicas=",
+        ] {
+            assert!(!is_valid_override(spec), "{spec}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_with_overrides() -> Result<()> {
+        let module = temp_dir("kcl-client-overrides");
+        std::fs::write(
+            module.join("kcl.mod"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        std::fs::write(module.join("main.k"), "app = {\n    replicas = 1\n}\n").unwrap();
+
+        let mut client = ModClient::new(&module)?;
+        let metadata = client.resolve_all_deps(false).await?;
+        assert!(client
+            .run(metadata.clone(), &HashMap::new())
+            .await?
+            .contains("replicas: 1"));
+
+        client.set_overrides(vec!["app.replicas=3".to_string()])?;
+        assert!(client
+            .run(metadata, &HashMap::new())
+            .await?
+            .contains("replicas: 3"));
+
+        assert!(matches!(
+            client.set_overrides(vec!["app.replicas".to_string()]),
+            Err(Error::InvalidOverride { spec }) if spec == "app.replicas"
+        ));
+
+        std::fs::remove_dir_all(module).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_entries() -> Result<()> {
+        let module = temp_dir("kcl-client-entries");
+        std::fs::create_dir_all(module.join("k8s")).unwrap();
+        for file in [
+            "k8s/service.k",
+            "k8s/deployment.k",
+            "k8s/README.md",
+            "main.k",
+        ] {
+            std::fs::write(module.join(file), "").unwrap();
+        }
+
+        let entries = expand_entries(&module, &["main.k".to_string(), "k8s/*.k".to_string()])?;
+        assert_eq!(entries, ["main.k", "k8s/deployment.k", "k8s/service.k"]);
+
+        let res = expand_entries(&module, &["apps/*.k".to_string()]);
+        assert!(matches!(
+            res,
+            Err(Error::EntryNoMatch { pattern, .. }) if pattern == "apps/*.k"
+        ));
+
+        std::fs::remove_dir_all(module).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_local_dep_in_tree() -> Result<()> {
+        let source_root = Path::new("/tmp/kcl/default/repo/abc");
+        check_local_dep(
+            "shared",
+            &source_root.join("apps/web/../../shared"),
+            source_root,
+        )?;
+        check_local_dep("shared", &source_root.join("./shared"), source_root)
+    }
+
+    #[test]
+    fn test_check_local_dep_escaping() {
+        let source_root = Path::new("/tmp/kcl/default/repo/abc");
+        for path in [
+            source_root.join("apps/../../shared"),
+            PathBuf::from("/opt/shared"),
+            PathBuf::from("/tmp/kcl/default/repo/abcd"),
+        ] {
+            let res = check_local_dep("shared", &path, source_root);
+            assert!(
+                matches!(res, Err(Error::LocalDepOutsideSource { .. })),
+                "{}",
+                path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+/// Name under which KCL imports the package `package`.
+///
+/// `-` is not valid in KCL identifiers, so `my-lib` is imported as `my_lib`, while its vendor
+/// directory keeps the package name (e.g. `my-lib_0.1.0`).
+pub fn import_name(package: &str) -> String {
+    package.replace('-', "_")
+}
+
+/// Package names of the resolved dependencies and the names KCL imports them by, both ways.
+#[derive(Clone, Debug, Default)]
+pub struct PackageNames {
+    by_import: HashMap<String, String>,
+    by_package: HashMap<String, String>,
+}
+
+impl PackageNames {
+    /// Registers the package `package`.
+    ///
+    /// # Returns
+    /// The import name of the package, or the package already imported under the same name
+    /// (e.g. `my_lib` for `my-lib`) as an error.
+    pub fn insert(&mut self, package: &str) -> Result<String, String> {
+        let import = import_name(package);
+        match self.by_import.get(&import) {
+            Some(existing) if existing != package => Err(existing.clone()),
+            Some(_) => Ok(import),
+            None => {
+                self.by_import.insert(import.clone(), package.to_string());
+                self.by_package.insert(package.to_string(), import.clone());
+                Ok(import)
+            }
+        }
+    }
+
+    /// Name KCL imports `package` by, if it was registered.
+    pub fn import_name(&self, package: &str) -> Option<&str> {
+        self.by_package.get(package).map(String::as_str)
+    }
+
+    /// Package imported as `import`, if it was registered.
+    pub fn package_name(&self, import: &str) -> Option<&str> {
+        self.by_import.get(import).map(String::as_str)
+    }
+
+    /// Resolves the import name of `name`, given either as a package or an import name, so
+    /// that overrides may use either.
+    pub fn resolve(&self, name: &str) -> String {
+        match (self.import_name(name), self.package_name(name)) {
+            (Some(import), _) => import.to_string(),
+            (None, Some(_)) => name.to_string(),
+            (None, None) => import_name(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_names_both_ways() {
+        let mut names = PackageNames::default();
+        assert_eq!(names.insert("my-lib"), Ok("my_lib".to_string()));
+        assert_eq!(names.insert("my-lib"), Ok("my_lib".to_string()));
+        assert_eq!(names.insert("k8s"), Ok("k8s".to_string()));
+
+        assert_eq!(names.import_name("my-lib"), Some("my_lib"));
+        assert_eq!(names.package_name("my_lib"), Some("my-lib"));
+        assert_eq!(names.resolve("my-lib"), "my_lib");
+        assert_eq!(names.resolve("my_lib"), "my_lib");
+        assert_eq!(names.resolve("other-lib"), "other_lib");
+
+        // Another package imported under the same name can't be told apart
+        assert_eq!(names.insert("my_lib"), Err("my-lib".to_string()));
+    }
+}
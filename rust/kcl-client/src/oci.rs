@@ -1,11 +1,38 @@
 use crate::fs::directory_is_not_empty;
-use anyhow::Result;
-use oci_distribution::manifest::IMAGE_LAYER_MEDIA_TYPE;
+use crate::registry::RegistryCredentials;
+use anyhow::{anyhow, bail, Result};
+use flate2::read::GzDecoder;
+use oci_distribution::client::ClientConfig;
+use oci_distribution::manifest::{
+    ImageIndexEntry, OciDescriptor, OciImageIndex, OciManifest, IMAGE_LAYER_MEDIA_TYPE,
+};
 use oci_distribution::secrets::RegistryAuth;
 use oci_distribution::{Client, Reference};
+use regex::Regex;
+use semver::{Version, VersionReq};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 const OCI_SCHEME_PREFIX: &str = "oci://";
+const VERSION_RANGE_OPERATORS: &[char] = &['^', '~', '>', '<', '=', '*', ','];
+/// Annotation of the index entries that are not images, e.g. build attestations.
+const REFERENCE_TYPE_ANNOTATION: &str = "vnd.docker.reference.type";
+
+/// Layer media types of KCL packages pushed by the KCL tooling.
+pub const DEFAULT_LAYER_MEDIA_TYPES: &[&str] = &[IMAGE_LAYER_MEDIA_TYPE];
+
+/// Timeout of OCI pulls, the default `timeout` of Flux OCIRepositories.
+pub const DEFAULT_OCI_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Creates an OCI client identifying itself to registries with `user_agent`, to be shared by
+/// the `ModClient`s through `ModClient::new_with_oci_client`.
+pub fn new_oci_client(user_agent: &'static str) -> Arc<Client> {
+    Arc::new(Client::new(ClientConfig {
+        user_agent,
+        ..Default::default()
+    }))
+}
 
 #[inline]
 pub(crate) fn strip_oci_scheme_prefix(image: &str) -> &str {
@@ -27,29 +54,221 @@ pub(crate) fn oci_reg_repo_join(reg: &str, repo: &str) -> String {
     format!("{reg}/{repo}")
 }
 
+/// Parses a dependency version as a semver range. Returns `None` for exact versions, which
+/// are used as tags as they are.
+pub(crate) fn parse_version_range(version: &str) -> Option<VersionReq> {
+    if !version.contains(VERSION_RANGE_OPERATORS) {
+        return None;
+    }
+    VersionReq::parse(version).ok()
+}
+
+/// Selects the highest tag matching the semver range, optionally restricted to tags matching
+/// the filter. Tags that are not valid semver (with an optional `v` prefix) are ignored.
+pub(crate) fn select_semver_tag(
+    tags: Vec<String>,
+    req: &VersionReq,
+    filter: Option<&Regex>,
+) -> Option<String> {
+    tags.into_iter()
+        .filter(|tag| filter.is_none_or(|filter| filter.is_match(tag)))
+        .filter_map(|tag| {
+            Version::parse(tag.trim_start_matches('v'))
+                .ok()
+                .map(|version| (version, tag))
+        })
+        .filter(|(version, _)| req.matches(version))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag)
+}
+
+/// Lists the tags of the image and resolves the highest one matching the semver range.
+pub(crate) async fn resolve_semver_tag(
+    client: &Client,
+    credentials: &RegistryCredentials,
+    image: &str,
+    req: &VersionReq,
+    filter: Option<&Regex>,
+) -> Result<String> {
+    let image = strip_oci_scheme_prefix(image);
+    let img_ref = Reference::try_from(image)?;
+    let auth = credentials
+        .registry_auth(img_ref.resolve_registry())
+        .await?;
+    let resp = client.list_tags(&img_ref, &auth, None, None).await?;
+    select_semver_tag(resp.tags, req, filter)
+        .ok_or_else(|| anyhow!("No tag of {image} matches version range {req}"))
+}
+
+/// Selects the layers holding the package: the ones with an expected media type or, failing
+/// that, the only layer of the artifact.
+pub(crate) fn select_layers<'a>(
+    layers: &'a [OciDescriptor],
+    media_types: &[String],
+) -> Result<Vec<&'a OciDescriptor>> {
+    let matching: Vec<_> = layers
+        .iter()
+        .filter(|layer| media_types.contains(&layer.media_type))
+        .collect();
+    if !matching.is_empty() {
+        return Ok(matching);
+    }
+    match layers {
+        [layer] => {
+            tracing::warn!(
+                "No layer with media type {:?}, using the single layer of type {}",
+                media_types,
+                layer.media_type
+            );
+            Ok(vec![layer])
+        }
+        _ => bail!(
+            "No layer with media type {:?} found, available media types: {:?}",
+            media_types,
+            layers
+                .iter()
+                .map(|layer| layer.media_type.as_str())
+                .collect::<Vec<_>>()
+        ),
+    }
+}
+
+/// OCI architecture of the platform the operator runs on.
+fn current_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
+/// Selects the manifest of the package in an image index (multi-platform artifact).
+///
+/// Entries that are not images (e.g. attestations) are skipped. The single remaining entry is
+/// selected, or the single one without a platform (or an `unknown` one), or the single one for
+/// the platform the operator runs on. Fails when there is no way to choose between several.
+pub(crate) fn select_index_manifest(index: &OciImageIndex) -> Result<&ImageIndexEntry> {
+    let images: Vec<_> = index
+        .manifests
+        .iter()
+        .filter(|entry| {
+            entry
+                .annotations
+                .as_ref()
+                .is_none_or(|annotations| !annotations.contains_key(REFERENCE_TYPE_ANNOTATION))
+        })
+        .collect();
+    fn single(entries: Vec<&ImageIndexEntry>) -> Option<&ImageIndexEntry> {
+        match entries[..] {
+            [entry] => Some(entry),
+            _ => None,
+        }
+    }
+
+    if let Some(entry) = single(images.clone()) {
+        return Ok(entry);
+    }
+    let platformless = images
+        .iter()
+        .copied()
+        .filter(|entry| {
+            entry.platform.as_ref().is_none_or(|platform| {
+                platform.os == "unknown" || platform.architecture == "unknown"
+            })
+        })
+        .collect();
+    if let Some(entry) = single(platformless) {
+        return Ok(entry);
+    }
+    let current = images
+        .iter()
+        .copied()
+        .filter(|entry| {
+            entry.platform.as_ref().is_some_and(|platform| {
+                platform.os == std::env::consts::OS
+                    && platform.architecture == current_architecture()
+            })
+        })
+        .collect();
+    if let Some(entry) = single(current) {
+        return Ok(entry);
+    }
+    bail!(
+        "Cannot choose a manifest in the image index, available platforms: {:?}",
+        images
+            .iter()
+            .map(|entry| entry
+                .platform
+                .as_ref()
+                .map_or("none".to_string(), |p| format!(
+                    "{}/{}",
+                    p.os, p.architecture
+                )))
+            .collect::<Vec<_>>()
+    )
+}
+
+/// Pulls the package layers of the image and unpacks them into `path`.
+///
+/// Image indexes are resolved to the manifest of the package, see `select_index_manifest`.
+async fn pull_and_unpack(
+    client: &Client,
+    img_ref: &Reference,
+    auth: &RegistryAuth,
+    media_types: &[String],
+    path: &Path,
+) -> Result<()> {
+    let manifest = match client.pull_manifest(img_ref, auth).await?.0 {
+        OciManifest::Image(manifest) => manifest,
+        OciManifest::ImageIndex(index) => {
+            let entry = select_index_manifest(&index)?;
+            tracing::info!(
+                "Selected manifest {} of image index {}",
+                entry.digest,
+                img_ref
+            );
+            let child_ref = Reference::with_digest(
+                img_ref.registry().to_string(),
+                img_ref.repository().to_string(),
+                entry.digest.clone(),
+            );
+            client.pull_image_manifest(&child_ref, auth).await?.0
+        }
+    };
+    for layer in select_layers(&manifest.layers, media_types)? {
+        let mut data = Vec::new();
+        client.pull_blob(img_ref, layer, &mut data).await?;
+        if layer.media_type.ends_with("+gzip") {
+            tar::Archive::new(GzDecoder::new(data.as_slice())).unpack(path)?;
+        } else {
+            tar::Archive::new(data.as_slice()).unpack(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Pulls a KCL package into `save_dir`/`{name}_{tag}`, resolving the newest tag if none is
+/// given.
+///
+/// Layers are selected by `media_types`, see `select_layers`.
 pub(crate) async fn pull_oci_and_extract_layer(
     client: &Client,
+    credentials: &RegistryCredentials,
     name: &str,
     image: &str,
     tag: &Option<String>,
+    media_types: &[String],
     save_dir: &Path,
 ) -> Result<PathBuf> {
     let image = strip_oci_scheme_prefix(image);
-    let auth = RegistryAuth::Anonymous;
-    let (img_data, path) = match &tag {
-        Some(tag) => {
-            let path = save_dir.join(format!("{name}_{tag}"));
-            if directory_is_not_empty(&path) {
-                return Ok(path);
-            }
-            let img_ref = Reference::try_from(format!("{image}:{tag}"))?;
-            (
-                client
-                    .pull(&img_ref, &auth, vec![IMAGE_LAYER_MEDIA_TYPE])
-                    .await?,
-                path,
-            )
-        }
+    let auth = credentials
+        .registry_auth(Reference::try_from(image)?.resolve_registry())
+        .await?;
+    let (img_ref, path) = match &tag {
+        Some(tag) => (
+            Reference::try_from(format!("{image}:{tag}"))?,
+            save_dir.join(format!("{name}_{tag}")),
+        ),
         None => {
             let img_ref = Reference::try_from(image)?;
             let resp = client.list_tags(&img_ref, &auth, None, None).await?;
@@ -57,29 +276,168 @@ pub(crate) async fn pull_oci_and_extract_layer(
             let mut semver_tags: Vec<String> =
                 tags.into_iter().filter(|tag| tag != "latest").collect();
             semver_tags.sort_by(|a, b| b.cmp(a));
-            let (tag, img_tag_ref) = if let Some(newest_tag) = semver_tags.first() {
-                (
-                    newest_tag.as_str(),
+            match semver_tags.first() {
+                Some(newest_tag) => (
                     Reference::try_from(format!("{image}:{newest_tag}"))?,
-                )
-            } else {
-                ("latest", img_ref)
-            };
-            let path = save_dir.join(format!("{name}_{tag}"));
-            if directory_is_not_empty(&path) {
-                return Ok(path);
+                    save_dir.join(format!("{name}_{newest_tag}")),
+                ),
+                None => (img_ref, save_dir.join(format!("{name}_latest"))),
             }
-            (
-                client
-                    .pull(&img_tag_ref, &auth, vec![IMAGE_LAYER_MEDIA_TYPE])
-                    .await?,
-                path,
-            )
         }
     };
-    for layer in &img_data.layers {
-        let buf = layer.data.as_slice();
-        tar::Archive::new(buf).unpack(&path)?;
+    if directory_is_not_empty(&path) {
+        return Ok(path);
     }
+    pull_and_unpack(client, &img_ref, &auth, media_types, &path).await?;
     Ok(path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> Vec<String> {
+        ["1.0.0", "1.2.0", "v1.3.1", "1.4.0-rc.1", "2.0.0", "latest"]
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_version_range() {
+        assert!(parse_version_range("1.2.0").is_none());
+        assert!(parse_version_range("latest").is_none());
+        assert!(parse_version_range("^1.2").is_some());
+        assert!(parse_version_range(">=1.0, <2.0").is_some());
+    }
+
+    #[test]
+    fn test_select_semver_tag_highest_match() {
+        let req = VersionReq::parse("^1.2").unwrap();
+        assert_eq!(
+            select_semver_tag(tags(), &req, None),
+            Some("v1.3.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_select_semver_tag_with_filter() {
+        let req = VersionReq::parse(">=1.0").unwrap();
+        let filter = Regex::new(r"^\d").unwrap();
+        assert_eq!(
+            select_semver_tag(tags(), &req, Some(&filter)),
+            Some("2.0.0".to_string())
+        );
+        let filter = Regex::new(r"^1\.[01]").unwrap();
+        assert_eq!(
+            select_semver_tag(tags(), &req, Some(&filter)),
+            Some("1.0.0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_select_semver_tag_no_match() {
+        let req = VersionReq::parse("^3").unwrap();
+        assert_eq!(select_semver_tag(tags(), &req, None), None);
+    }
+
+    fn layer(media_type: &str) -> OciDescriptor {
+        OciDescriptor {
+            media_type: media_type.to_string(),
+            digest: format!("sha256:{media_type}"),
+            ..Default::default()
+        }
+    }
+
+    fn default_media_types() -> Vec<String> {
+        DEFAULT_LAYER_MEDIA_TYPES
+            .iter()
+            .map(|media_type| media_type.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_select_layers_expected_media_type() {
+        let layers = [
+            layer(IMAGE_LAYER_MEDIA_TYPE),
+            layer("application/vnd.oci.image.config.v1+json"),
+        ];
+        let selected = select_layers(&layers, &default_media_types()).unwrap();
+        assert_eq!(selected, [&layers[0]]);
+    }
+
+    #[test]
+    fn test_select_layers_single_alternate_media_type() {
+        let layers = [layer("application/vnd.kcl.package.layer.v1.tar+gzip")];
+        let selected = select_layers(&layers, &default_media_types()).unwrap();
+        assert_eq!(selected, [&layers[0]]);
+    }
+
+    #[test]
+    fn test_select_layers_lists_available_media_types() {
+        let layers = [
+            layer("application/vnd.example.a"),
+            layer("application/vnd.example.b"),
+        ];
+        let err = select_layers(&layers, &default_media_types()).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("application/vnd.example.a"));
+        assert!(message.contains("application/vnd.example.b"));
+    }
+
+    fn index(platforms: &[Option<(&str, &str)>]) -> OciImageIndex {
+        serde_json::from_value(serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": platforms
+                .iter()
+                .enumerate()
+                .map(|(i, platform)| serde_json::json!({
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": format!("sha256:{i}"),
+                    "size": 1,
+                    "platform": platform.map(|(os, architecture)| serde_json::json!({
+                        "os": os,
+                        "architecture": architecture,
+                    })),
+                }))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_select_index_manifest_skips_attestations() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../tests/fixtures/oci/module-index.json");
+        let index: OciImageIndex =
+            serde_json::from_str(&std::fs::read_to_string(fixture).unwrap()).unwrap();
+        let entry = select_index_manifest(&index).unwrap();
+        assert_eq!(
+            entry.digest,
+            "sha256:4b2e0ed9c9ad2bc4d3bd3f2a3c7d8b5a1e96a2ae5b8f1d2c3e4f5a6b7c8d9e0f"
+        );
+    }
+
+    #[test]
+    fn test_select_index_manifest_by_platform() {
+        let entry = select_index_manifest(&index(&[
+            Some(("linux", "s390x")),
+            Some(("unknown", "unknown")),
+        ]))
+        .unwrap();
+        assert_eq!(entry.digest, "sha256:1");
+
+        let entry = select_index_manifest(&index(&[
+            Some(("windows", "s390x")),
+            Some((std::env::consts::OS, current_architecture())),
+        ]))
+        .unwrap();
+        assert_eq!(entry.digest, "sha256:1");
+
+        let res = select_index_manifest(&index(&[
+            Some(("windows", "s390x")),
+            Some(("plan9", "s390x")),
+        ]));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use oci_distribution::secrets::RegistryAuth;
+use regex::Regex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[cfg(feature = "aws")]
+use anyhow::Context;
+#[cfg(feature = "aws")]
+use std::time::SystemTime;
+
+/// Tokens are refreshed this long before they expire.
+#[cfg(feature = "aws")]
+const TOKEN_REFRESH_MARGIN: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Lifetime of ECR tokens, when ECR doesn't say when they expire.
+#[cfg(feature = "aws")]
+const ECR_TOKEN_TTL: std::time::Duration = std::time::Duration::from_secs(12 * 60 * 60);
+
+/// Cloud provider of the credentials used to pull module dependencies, like the `provider`
+/// of Flux OCIRepositories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegistryProvider {
+    /// Anonymous pulls.
+    #[default]
+    Generic,
+    /// ECR tokens exchanged for the AWS credentials of the operator (e.g. IRSA).
+    Aws,
+    Azure,
+    Gcp,
+}
+
+impl RegistryProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegistryProvider::Generic => "generic",
+            RegistryProvider::Aws => "aws",
+            RegistryProvider::Azure => "azure",
+            RegistryProvider::Gcp => "gcp",
+        }
+    }
+}
+
+impl fmt::Display for RegistryProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RegistryProvider {
+    type Err = String;
+
+    fn from_str(provider: &str) -> Result<Self, Self::Err> {
+        match provider {
+            "generic" => Ok(RegistryProvider::Generic),
+            "aws" => Ok(RegistryProvider::Aws),
+            "azure" => Ok(RegistryProvider::Azure),
+            "gcp" => Ok(RegistryProvider::Gcp),
+            _ => Err(format!(
+                "unknown registry provider {provider}, expected generic, aws, azure or gcp"
+            )),
+        }
+    }
+}
+
+/// Whether `registry` (e.g. `123456789012.dkr.ecr.eu-west-1.amazonaws.com`) is an ECR registry.
+pub(crate) fn is_ecr_registry(registry: &str) -> bool {
+    static ECR_HOST: OnceLock<Regex> = OnceLock::new();
+    let host = registry.split(':').next().unwrap_or(registry);
+    ECR_HOST
+        .get_or_init(|| {
+            Regex::new(r"^\d{12}\.dkr\.ecr(-fips)?\.[a-z0-9-]+\.amazonaws\.com(\.cn)?$").unwrap()
+        })
+        .is_match(host)
+}
+
+/// Credentials of the registries of module dependencies, resolved from the cloud provider the
+/// operator runs on.
+///
+/// Registries of other providers are pulled anonymously. Tokens are cached until shortly before
+/// they expire.
+#[derive(Default)]
+pub struct RegistryCredentials {
+    provider: RegistryProvider,
+    /// ECR client configuration, loaded from the environment when unset.
+    #[cfg(feature = "aws")]
+    ecr_config: Option<aws_sdk_ecr::Config>,
+    /// Last token and its expiry.
+    #[cfg(feature = "aws")]
+    cached: tokio::sync::Mutex<Option<(RegistryAuth, SystemTime)>>,
+}
+
+impl RegistryCredentials {
+    /// Constructs the credentials of `provider`.
+    ///
+    /// # Errors
+    /// Fails when the provider is not supported (yet, or by this build).
+    pub fn new(provider: RegistryProvider) -> crate::Result<Self> {
+        let supported = match provider {
+            RegistryProvider::Generic => true,
+            RegistryProvider::Aws => cfg!(feature = "aws"),
+            RegistryProvider::Azure | RegistryProvider::Gcp => false,
+        };
+        if !supported {
+            return crate::UnsupportedRegistryProviderSnafu { provider }.fail();
+        }
+        Ok(Self {
+            provider,
+            ..Default::default()
+        })
+    }
+
+    /// Sets the configuration of the ECR client, e.g. a VPC endpoint or static credentials.
+    #[cfg(feature = "aws")]
+    pub fn with_ecr_config(mut self, config: aws_sdk_ecr::Config) -> Self {
+        self.ecr_config = Some(config);
+        self
+    }
+
+    /// Returns the credentials to pull from `registry`.
+    pub(crate) async fn registry_auth(&self, registry: &str) -> anyhow::Result<RegistryAuth> {
+        match self.provider {
+            RegistryProvider::Aws if is_ecr_registry(registry) => self.cached_token().await,
+            _ => Ok(RegistryAuth::Anonymous),
+        }
+    }
+
+    /// Returns the cached token, exchanging a new one when it is about to expire.
+    #[cfg(feature = "aws")]
+    async fn cached_token(&self) -> anyhow::Result<RegistryAuth> {
+        let mut cached = self.cached.lock().await;
+        if let Some((auth, expires_at)) = cached.as_ref() {
+            if SystemTime::now() + TOKEN_REFRESH_MARGIN < *expires_at {
+                return Ok(auth.clone());
+            }
+        }
+        let (auth, expires_at) = self.ecr_token().await?;
+        *cached = Some((auth.clone(), expires_at));
+        Ok(auth)
+    }
+
+    #[cfg(not(feature = "aws"))]
+    async fn cached_token(&self) -> anyhow::Result<RegistryAuth> {
+        anyhow::bail!("registry provider {} is not supported", self.provider)
+    }
+
+    /// Exchanges the AWS credentials of the operator for an ECR token.
+    #[cfg(feature = "aws")]
+    async fn ecr_token(&self) -> anyhow::Result<(RegistryAuth, SystemTime)> {
+        use base64::Engine;
+
+        let client = match &self.ecr_config {
+            Some(config) => aws_sdk_ecr::Client::from_conf(config.clone()),
+            None => aws_sdk_ecr::Client::new(
+                &aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await,
+            ),
+        };
+        let output = client
+            .get_authorization_token()
+            .send()
+            .await
+            .context("Failed to get an ECR authorization token")?;
+        let data = output
+            .authorization_data()
+            .first()
+            .context("ECR returned no authorization data")?;
+        let token = base64::engine::general_purpose::STANDARD
+            .decode(data.authorization_token().unwrap_or_default())
+            .context("Malformed ECR authorization token")?;
+        let (username, password) = std::str::from_utf8(&token)
+            .ok()
+            .and_then(|token| token.split_once(':'))
+            .context("Malformed ECR authorization token")?;
+        let expires_at = data
+            .expires_at()
+            .and_then(|expires_at| SystemTime::try_from(*expires_at).ok())
+            .unwrap_or_else(|| SystemTime::now() + ECR_TOKEN_TTL);
+        tracing::info!("Exchanged AWS credentials for an ECR token");
+        Ok((
+            RegistryAuth::Basic(username.to_string(), password.to_string()),
+            expires_at,
+        ))
+    }
+}
+
+/// Limits the concurrent pulls from each registry host, to smooth the bursts of instances
+/// pulling from the same registry (e.g. ghcr.io) and avoid being throttled.
+///
+/// It is shared by the `ModClient`s through `ModClient::set_registry_limiter`.
+#[derive(Debug, Default)]
+pub struct RegistryLimiter {
+    /// Concurrent pulls allowed per host, unlimited when 0.
+    limit: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl RegistryLimiter {
+    /// Constructs a limiter allowing `limit` concurrent pulls per registry host, or any number
+    /// of them when `limit` is 0.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Waits for a pull slot of `host`, released when the returned permit is dropped.
+    ///
+    /// # Returns
+    /// The permit, or `None` when pulls are unlimited
+    pub(crate) async fn acquire(&self, host: &str) -> Option<OwnedSemaphorePermit> {
+        if self.limit == 0 {
+            return None;
+        }
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limit)))
+            .clone();
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_registry_limiter_serializes_pulls() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let limiter = Arc::new(RegistryLimiter::new(1));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let pulls = (0..3).map(|_| {
+            let (limiter, running, max_running) =
+                (limiter.clone(), running.clone(), max_running.clone());
+            tokio::spawn(async move {
+                let _permit = limiter.acquire("ghcr.io").await;
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        });
+        for pull in pulls.collect::<Vec<_>>() {
+            pull.await.unwrap();
+        }
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+
+        // Other hosts have their own slots
+        let _ghcr = limiter.acquire("ghcr.io").await;
+        let docker = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            limiter.acquire("docker.io"),
+        );
+        assert!(docker.await.unwrap().is_some());
+
+        // Unlimited by default
+        assert!(RegistryLimiter::default()
+            .acquire("ghcr.io")
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_is_ecr_registry() {
+        assert!(is_ecr_registry(
+            "123456789012.dkr.ecr.eu-west-1.amazonaws.com"
+        ));
+        assert!(is_ecr_registry(
+            "123456789012.dkr.ecr-fips.us-east-1.amazonaws.com:443"
+        ));
+        assert!(is_ecr_registry(
+            "123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn"
+        ));
+        assert!(!is_ecr_registry("ghcr.io"));
+        assert!(!is_ecr_registry("public.ecr.aws"));
+        assert!(!is_ecr_registry(
+            "123456789012.dkr.ecr.eu-west-1.amazonaws.com.evil.io"
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_registry_provider() {
+        assert!(RegistryCredentials::new(RegistryProvider::Generic).is_ok());
+        assert!(RegistryCredentials::new(RegistryProvider::Gcp).is_err());
+        assert_eq!("aws".parse(), Ok(RegistryProvider::Aws));
+        assert!("docker".parse::<RegistryProvider>().is_err());
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn test_ecr_token_exchange() {
+        use base64::Engine;
+        use std::time::SystemTime;
+        use wiremock::{
+            matchers::{header, method},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        let token = base64::engine::general_purpose::STANDARD.encode("AWS:secret-token");
+        let expires_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        Mock::given(method("POST"))
+            .and(header(
+                "x-amz-target",
+                "AmazonEC2ContainerRegistry_V20150921.GetAuthorizationToken",
+            ))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(
+                    serde_json::json!({
+                        "authorizationData": [{
+                            "authorizationToken": token,
+                            "expiresAt": expires_at,
+                            "proxyEndpoint": "https://123456789012.dkr.ecr.eu-west-1.amazonaws.com",
+                        }],
+                    })
+                    .to_string(),
+                    "application/x-amz-json-1.1",
+                ),
+            )
+            // The token is cached
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = aws_sdk_ecr::Config::builder()
+            .behavior_version(aws_sdk_ecr::config::BehaviorVersion::latest())
+            .region(aws_sdk_ecr::config::Region::new("eu-west-1"))
+            .credentials_provider(aws_sdk_ecr::config::Credentials::new(
+                "AKIDEXAMPLE",
+                "secret",
+                None,
+                None,
+                "test",
+            ))
+            .endpoint_url(server.uri())
+            .build();
+        let credentials = RegistryCredentials::new(RegistryProvider::Aws)
+            .unwrap()
+            .with_ecr_config(config);
+
+        for _ in 0..2 {
+            let auth = credentials
+                .registry_auth("123456789012.dkr.ecr.eu-west-1.amazonaws.com")
+                .await
+                .unwrap();
+            assert_eq!(
+                auth,
+                RegistryAuth::Basic("AWS".to_string(), "secret-token".to_string())
+            );
+        }
+        // Other registries are pulled anonymously
+        let auth = credentials.registry_auth("ghcr.io").await.unwrap();
+        assert_eq!(auth, RegistryAuth::Anonymous);
+    }
+}
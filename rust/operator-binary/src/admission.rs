@@ -0,0 +1,117 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Memory a render is assumed to take at least, in MiB.
+const MIN_RENDER_MIB: u64 = 64;
+/// Rendering a module takes about this many times the size of its source artifact.
+const SOURCE_SIZE_FACTOR: u64 = 16;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Admission of the renders under a soft memory limit, so that large modules don't render
+/// concurrently and run the operator out of memory.
+///
+/// Each render is weighted by the estimated memory it takes, from the size of its source
+/// artifact. Renders that would exceed the limit are not started, and are counted as rejected.
+/// The outcomes are exported by `Metrics::observe_render_admission`.
+pub struct RenderAdmission {
+    limit_mib: u64,
+    semaphore: Arc<Semaphore>,
+    rejected: AtomicU64,
+}
+
+impl RenderAdmission {
+    /// Constructs a new render admission.
+    ///
+    /// # Arguments:
+    /// - `max_render_memory`: Soft limit of the memory taken by the concurrent renders, in bytes.
+    pub fn new(max_render_memory: u64) -> Self {
+        let limit_mib = (max_render_memory / MIB)
+            .max(MIN_RENDER_MIB)
+            .min(u64::from(u32::MAX));
+        Self {
+            limit_mib,
+            semaphore: Arc::new(Semaphore::new(limit_mib as usize)),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Estimated memory of a render, in MiB, capped at the limit so any module can render alone.
+    ///
+    /// # Arguments:
+    /// - `source_size`: Size of the source artifact in bytes, if known.
+    pub fn weight(&self, source_size: Option<i64>) -> u64 {
+        let estimated = source_size
+            .and_then(|size| u64::try_from(size).ok())
+            .map_or(0, |size| {
+                size.saturating_mul(SOURCE_SIZE_FACTOR).div_ceil(MIB)
+            });
+        estimated.max(MIN_RENDER_MIB).min(self.limit_mib)
+    }
+
+    /// Admits a render if it fits in the remaining memory.
+    ///
+    /// # Returns
+    /// A permit to hold until the render completes, or `None` when the render should be retried
+    /// later.
+    pub fn try_admit(&self, source_size: Option<i64>) -> Option<OwnedSemaphorePermit> {
+        let weight = self.weight(source_size) as u32;
+        match self.semaphore.clone().try_acquire_many_owned(weight) {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Number of renders rejected so far, which were requeued.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Estimated memory taken by the running renders, in MiB.
+    pub fn in_use_mib(&self) -> u64 {
+        self.limit_mib - self.semaphore.available_permits() as u64
+    }
+
+    /// Soft limit of the memory taken by the running renders, in MiB.
+    pub fn limit_mib(&self) -> u64 {
+        self.limit_mib
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_weight() {
+        let admission = RenderAdmission::new(1024 * MIB);
+        assert_eq!(admission.weight(None), MIN_RENDER_MIB);
+        assert_eq!(admission.weight(Some(1024)), MIN_RENDER_MIB);
+        assert_eq!(admission.weight(Some(32 * MIB as i64)), 512);
+        // Huge modules still render, alone
+        assert_eq!(admission.weight(Some(1024 * MIB as i64)), 1024);
+    }
+
+    #[test]
+    fn test_render_admission_rejects_over_limit() {
+        let admission = RenderAdmission::new(1024 * MIB);
+        let large = Some(32 * MIB as i64);
+
+        let first = admission.try_admit(large);
+        let second = admission.try_admit(large);
+        assert!(first.is_some() && second.is_some());
+        assert_eq!(admission.in_use_mib(), 1024);
+        assert!(admission.try_admit(None).is_none());
+
+        drop(first);
+        assert!(admission.try_admit(None).is_some());
+        assert_eq!(admission.rejected(), 1);
+    }
+}
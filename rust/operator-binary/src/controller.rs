@@ -1,18 +1,44 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
 
-use flux_kcl_operator_crd::KclInstance;
-use fluxcd_rs::Downloader;
+use flux_kcl_operator_crd::{
+    ArgSourceStatus, Gvk, KclInstance, KclInstanceStatus, ReadyConditionType, APPLIED_CONDITION,
+    INVALID_SPEC_REASON, MODULE_COMPILE_FAILED_REASON, MODULE_RESOLUTION_FAILED_REASON,
+    NAMESPACE_NOT_ALLOWED_REASON, OBJECTS_APPLIED_REASON, OBJECTS_FAILED_REASON, PLAN_CONDITION,
+    PLAN_SUCCEEDED_REASON, READY_CONDITION, READY_CONDITIONS_UNMET_REASON,
+    RECONCILE_TIMEOUT_REASON, RECONCILIATION_FAILED_REASON, RECONCILIATION_SUCCEEDED_REASON,
+    RETRIES_EXHAUSTED_REASON, SOURCE_READY_CONDITION, STALLED_CONDITION, VALIDATION_FAILED_REASON,
+};
+use fluxcd_rs::{Downloader, FluxSourceArtefact};
 use humantime::format_duration;
-use kube::{runtime::controller::Action, Client, Discovery, Resource, ResourceExt};
+use kube::{
+    api::DynamicObject,
+    runtime::{
+        controller::Action,
+        events::Reporter,
+        reflector::{ObjectRef, Store},
+        watcher,
+    },
+    Client, Resource, ResourceExt,
+};
 use snafu::{OptionExt, ResultExt, Snafu};
 use strum::{EnumDiscriminants, IntoStaticStr};
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, field, info, instrument, warn, Span};
 
 use crate::{
-    engine::{self, Engine},
+    admission::RenderAdmission,
+    engine::{self, Engine, FailedObject, Plan, SourceReadiness},
+    export::ExportKey,
     finalizer,
     instance_ext::{self, InstanceExt},
-    utils::multidoc_deserialize,
+    metrics::Metrics,
+    staleness::ReconcileTracker,
+    utils::{self, multidoc_deserialize, CachedDiscovery},
 };
 
 #[derive(Snafu, Debug, EnumDiscriminants)]
@@ -57,14 +83,245 @@ pub enum Error {
     #[snafu(display("Failed to get arguments: {}", source))]
     ProcessArgs { source: instance_ext::Error },
 
+    #[snafu(display("Failed to read values files: {}", source))]
+    ReadValuesFiles { source: instance_ext::Error },
+
+    #[snafu(display("Failed to compute the target namespace: {}", source))]
+    NamespaceTemplate { source: instance_ext::Error },
+
+    #[snafu(display("Failed to export the rendered output: {}", source))]
+    ExportRendered { source: crate::export::Error },
+
+    #[snafu(display("Failed to serialize manifests: {}", source))]
+    SerializeManifests { source: serde_yaml::Error },
+
     #[snafu(display("Failed to register applied: {}", source))]
     RegisterApplied {
         source: flux_kcl_operator_crd::Error,
     },
+
+    #[snafu(display("Invalid label selector: {}", selector))]
+    InvalidLabelSelector { selector: String },
+
+    #[snafu(display("Reconciliation timed out after {}", timeout))]
+    ReconcileTimeout { timeout: String },
+
+    #[snafu(display(
+        "Render deferred, {} MiB of render memory is not available",
+        required_mib
+    ))]
+    RenderDeferred { required_mib: u64 },
+}
+
+impl Error {
+    /// Stable, machine-readable code of the error (e.g. `CannotRenderKclModule`),
+    /// used as the reason of published events.
+    pub fn reason(&self) -> &'static str {
+        ErrorDiscriminants::from(self).into()
+    }
+
+    /// Returns the KCL client error that caused the render to fail, if any.
+    fn kcl_client_error(&self) -> Option<&kcl_client::Error> {
+        match self {
+            Error::CannotRenderKclModule { source } => source.kcl_client_error(),
+            _ => None,
+        }
+    }
+
+    /// Reason of the `Ready` condition for this error.
+    fn condition_reason(&self) -> &'static str {
+        if self.class() == ErrorClass::User {
+            return INVALID_SPEC_REASON;
+        }
+        match (self, self.kcl_client_error()) {
+            (Error::ReconcileTimeout { .. }, _) => RECONCILE_TIMEOUT_REASON,
+            (
+                Error::EngineAction {
+                    source: engine::Error::NamespaceNotAllowed { .. },
+                },
+                _,
+            ) => NAMESPACE_NOT_ALLOWED_REASON,
+            (
+                Error::EngineAction {
+                    source: engine::Error::HookFailed { .. },
+                },
+                _,
+            ) => VALIDATION_FAILED_REASON,
+            (_, Some(kcl_client::Error::ModuleCompile { .. })) => MODULE_COMPILE_FAILED_REASON,
+            (_, Some(kcl_client::Error::ModuleResolution { .. })) => {
+                MODULE_RESOLUTION_FAILED_REASON
+            }
+            _ => RECONCILIATION_FAILED_REASON,
+        }
+    }
+
+    /// Whether the reconciliation was cancelled because the instance changed meanwhile.
+    fn is_cancelled(&self) -> bool {
+        match self {
+            Error::ArtefactsPathNotFound { source } | Error::CannotRenderKclModule { source } => {
+                source.is_cancelled()
+            }
+            _ => false,
+        }
+    }
+
+    /// Classifies the error to decide how the reconciliation is retried.
+    fn class(&self) -> ErrorClass {
+        match self {
+            Error::KclInstanceMissingNamespace { .. }
+            | Error::NamespaceTemplate { .. }
+            | Error::ExportRendered {
+                source: crate::export::Error::EncryptedConfigMap | crate::export::Error::MissingKey,
+            }
+            | Error::CannotRenderKclModule {
+                source:
+                    engine::Error::InvalidModule { .. }
+                    | engine::Error::KclClientActions {
+                        source:
+                            kcl_client::Error::InvalidSemverFilter { .. }
+                            | kcl_client::Error::InvalidEntryPattern { .. }
+                            | kcl_client::Error::InvalidOverride { .. },
+                    },
+            } => ErrorClass::User,
+            Error::EngineAction {
+                source:
+                    engine::Error::NamespaceNotAllowed { .. }
+                    | engine::Error::HookFailed { .. }
+                    | engine::Error::AmbiguousKind { .. },
+            }
+            | Error::ReadValuesFiles { .. }
+            | Error::ExportRendered {
+                source: crate::export::Error::ExportTooLarge { .. },
+            } => ErrorClass::Module,
+            _ if self.kcl_client_error().is_some_and(|e| !e.is_transient()) => ErrorClass::Stalled,
+            _ => ErrorClass::System,
+        }
+    }
+
+    /// Delay before the next reconciliation after the error, see `ErrorClass`.
+    ///
+    /// # Arguments
+    /// - `kcl_instance`: The instance that failed to reconcile.
+    /// - `failures`: Number of consecutive failures of the instance, including this one.
+    /// - `max_backoff`: Ceiling of the backoff of system errors, see `ContextData::with_max_backoff`.
+    ///
+    /// # Returns
+    /// `None` when the instance should only be reconciled again once it changes.
+    fn retry_after(
+        &self,
+        kcl_instance: &KclInstance,
+        failures: u32,
+        max_backoff: Option<Duration>,
+    ) -> Option<Duration> {
+        match self.class() {
+            ErrorClass::User | ErrorClass::Stalled => None,
+            ErrorClass::Module => Some(kcl_instance.interval()),
+            ErrorClass::System => {
+                let backoff = retry_backoff(
+                    kcl_instance.retry_interval(),
+                    kcl_instance.interval(),
+                    failures,
+                );
+                Some(max_backoff.map_or(backoff, |max| backoff.min(max)))
+            }
+        }
+    }
+}
+
+/// How a failed reconciliation is retried.
+#[derive(Debug, PartialEq, Eq, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+enum ErrorClass {
+    /// The spec itself is invalid (e.g. `path` does not exist in the source). Retrying won't
+    /// help, so the instance is only reconciled again once it changes.
+    User,
+    /// Authoring error in the rendered objects, which won't fix itself but may with a new
+    /// source revision, so it is retried at the regular interval.
+    Module,
+    /// Permanent error in the KCL module, e.g. a syntax or type error. Rendering it again
+    /// fails the same way, so the instance is only reconciled again once it or its source
+    /// changes, and is marked `Stalled` meanwhile.
+    Stalled,
+    /// Likely transient failure, e.g. an API outage, retried after the retry interval with
+    /// exponential backoff.
+    System,
+}
+
+/// Delay before retrying after `failures` consecutive system errors: the retry interval,
+/// doubled on each failure, up to the regular interval.
+fn retry_backoff(retry_interval: Duration, interval: Duration, failures: u32) -> Duration {
+    let max = interval.max(retry_interval);
+    retry_interval
+        .checked_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .map_or(max, |delay| delay.min(max))
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Delay between two checks of the objects deleted with the instance, see `waitForDeletion`.
+const DELETION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Maximum size of an event note accepted by the API server.
+const MAX_EVENT_NOTE_BYTES: usize = 1024;
+
+/// Delay before retrying a render deferred by the render admission.
+const RENDER_DEFER_DELAY: Duration = Duration::from_secs(10);
+
+/// Rendered objects checked and applied at a time, see `apply_rendered`.
+const APPLY_BATCH_SIZE: usize = 500;
+
+/// Consecutive failed reconciliations of an instance, see `ContextData::record_failure`.
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    /// Generation of the instance when it failed.
+    generation: Option<i64>,
+    /// Annotations of the instance when it failed.
+    annotations: BTreeMap<String, String>,
+    /// When the failed reconciliation is retried, see `ContextData::pending_retry`.
+    retry_at: Option<Instant>,
+    /// Whether the retries are exhausted, see `ContextData::with_max_failures`.
+    gave_up: bool,
+}
+
+impl Failures {
+    fn of(instance: &KclInstance) -> Self {
+        Failures {
+            count: 0,
+            generation: instance.metadata.generation,
+            annotations: instance.annotations().clone(),
+            retry_at: None,
+            gave_up: false,
+        }
+    }
+
+    /// Failures recorded in the status of `instance` by a previous run of the operator, so that
+    /// a restart neither starts the count over nor retries a given up instance. They are
+    /// assumed to be of the current annotations of the instance.
+    fn from_status(instance: &KclInstance) -> Option<Self> {
+        let status = instance.status.as_ref()?;
+        let of_generation = status
+            .condition(READY_CONDITION)
+            .is_some_and(|ready| ready.observed_generation == instance.metadata.generation);
+        if status.failure_count == 0 || !of_generation {
+            return None;
+        }
+        Some(Failures {
+            count: status.failure_count,
+            gave_up: status.condition(STALLED_CONDITION).is_some_and(|stalled| {
+                stalled.status == "True" && stalled.reason == RETRIES_EXHAUSTED_REASON
+            }),
+            ..Failures::of(instance)
+        })
+    }
+
+    /// Whether the failures are of the current spec and annotations of `instance`.
+    fn is_of(&self, instance: &KclInstance) -> bool {
+        self.generation == instance.metadata.generation
+            && &self.annotations == instance.annotations()
+    }
+}
+
 /// Context injected with each `reconcile` and `on_error` method invocation.
 pub struct ContextData {
     /// Kubernetes client to make Kubernetes API requests with. Required for K8S resource management.
@@ -72,7 +329,44 @@ pub struct ContextData {
 
     downloader: Downloader,
     engine: Engine,
-    discovery: Discovery,
+    discovery: Arc<CachedDiscovery>,
+
+    /// Reporter of the published events.
+    reporter: Reporter,
+
+    /// Reconciliations in progress, by instance, with the generation they work on.
+    in_flight: Mutex<HashMap<ObjectRef<KclInstance>, (i64, CancellationToken)>>,
+
+    /// Serializes the reconciliations of each instance.
+    reconcile_locks: ReconcileLocks,
+
+    /// Consecutive failed reconciliations, by instance, to back off retries.
+    failures: Mutex<HashMap<ObjectRef<KclInstance>, Failures>>,
+
+    /// Consecutive retried failures after which an instance is given up until its spec or
+    /// annotations change, never given up when 0.
+    max_failures: u32,
+
+    /// Ceiling of the backoff of system errors, the instance's `interval` when unset.
+    max_backoff: Option<Duration>,
+
+    /// Admission of the renders under the render memory limit, unlimited when unset.
+    render_admission: Option<RenderAdmission>,
+
+    /// Last reconciliations of the instances, to report when the operator falls behind.
+    reconciles: ReconcileTracker,
+
+    /// Arguments of every instance, overridden by the values files and arguments of the instance.
+    default_args: HashMap<String, String>,
+
+    /// Namespace template of the instances without one, see `namespaceTemplate`.
+    namespace_template: Option<String>,
+
+    /// Key of the encrypted exports of the rendered output, see `exportRendered`.
+    export_key: Option<ExportKey>,
+
+    /// Outcomes and durations of the reconciliations.
+    metrics: Metrics,
 }
 
 impl ContextData {
@@ -85,14 +379,249 @@ impl ContextData {
         client: Client,
         downloader: Downloader,
         engine: Engine,
-        discovery: Discovery,
+        discovery: Arc<CachedDiscovery>,
     ) -> Self {
         ContextData {
             client,
             downloader,
             engine,
             discovery,
+            reporter: crate::event::reporter(None, None),
+            in_flight: Mutex::new(HashMap::new()),
+            reconcile_locks: ReconcileLocks::default(),
+            failures: Mutex::new(HashMap::new()),
+            max_failures: 0,
+            max_backoff: None,
+            render_admission: None,
+            reconciles: ReconcileTracker::default(),
+            default_args: HashMap::new(),
+            namespace_template: None,
+            export_key: None,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Sets the reporter of the published events, see `event::reporter`.
+    pub fn with_reporter(mut self, reporter: Reporter) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Limits the concurrent renders, see `RenderAdmission`.
+    pub fn with_render_admission(mut self, render_admission: RenderAdmission) -> Self {
+        self.render_admission = Some(render_admission);
+        self
+    }
+
+    /// Sets the operator-level arguments of every instance, e.g. the cluster name or region.
+    pub fn with_default_args(mut self, default_args: HashMap<String, String>) -> Self {
+        self.default_args = default_args;
+        self
+    }
+
+    /// Sets the namespace template of the instances without one, e.g. `tenant-{name}`.
+    pub fn with_namespace_template(mut self, template: Option<String>) -> Self {
+        self.namespace_template = template;
+        self
+    }
+
+    /// Sets the key encrypting the exports of the rendered output, see `exportRendered`.
+    pub fn with_export_key(mut self, key: ExportKey) -> Self {
+        self.export_key = Some(key);
+        self
+    }
+
+    /// Gives up an instance after `max_failures` consecutive retried failures: it is marked
+    /// `Stalled` and only reconciled again once its spec or annotations change. 0 never gives up.
+    pub fn with_max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Caps the backoff of the retries after system errors, e.g. to retry more often than the
+    /// instances' `interval` during an outage.
+    pub fn with_max_backoff(mut self, max_backoff: Option<Duration>) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Last reconciliations of the instances, see `ReconcileTracker::staleness`.
+    pub fn reconciles(&self) -> &ReconcileTracker {
+        &self.reconciles
+    }
+
+    /// Outcomes and durations of the reconciliations, see `Metrics`.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Cancels the reconciliation in progress for `instance`, if it works on an older
+    /// generation or the instance is being deleted.
+    pub fn cancel_superseded(&self, instance: &KclInstance) {
+        let in_flight = self.in_flight.lock().unwrap();
+        if let Some((generation, cancel)) = in_flight.get(&ObjectRef::from_obj(instance)) {
+            if instance.metadata.deletion_timestamp.is_some()
+                || instance.metadata.generation.unwrap_or(0) > *generation
+            {
+                info!(
+                    "KclInstance {} changed, cancelling its reconciliation",
+                    instance.name_any()
+                );
+                cancel.cancel();
+            }
+        }
+    }
+
+    /// Records a failed reconciliation of `instance`. The count starts over when the spec or
+    /// annotations of the instance changed since its last failure, and continues the one of
+    /// its status after a restart, see `Failures::from_status`.
+    ///
+    /// # Returns
+    /// The number of consecutive failures of the instance, including this one.
+    fn record_failure(&self, instance: &KclInstance) -> u32 {
+        let mut failures = self.failures.lock().unwrap();
+        let entry = failures
+            .entry(ObjectRef::from_obj(instance))
+            .or_insert_with(|| {
+                Failures::from_status(instance).unwrap_or_else(|| Failures::of(instance))
+            });
+        if !entry.is_of(instance) {
+            *entry = Failures::of(instance);
         }
+        entry.count += 1;
+        entry.count
+    }
+
+    /// Whether an instance is given up after `failures` consecutive retried failures.
+    fn gives_up(&self, failures: u32) -> bool {
+        self.max_failures > 0 && failures >= self.max_failures
+    }
+
+    /// Records when the failed reconciliation of `instance` is retried, `None` when it is only
+    /// reconciled again once it changes.
+    ///
+    /// # Arguments
+    /// - `gave_up`: Whether the retries are exhausted, see `with_max_failures`.
+    fn schedule_retry(&self, instance: &KclInstance, retry_after: Option<Duration>, gave_up: bool) {
+        if let Some(entry) = self
+            .failures
+            .lock()
+            .unwrap()
+            .get_mut(&ObjectRef::from_obj(instance))
+        {
+            entry.retry_at = retry_after.map(|delay| Instant::now() + delay);
+            entry.gave_up = gave_up;
+        }
+    }
+
+    /// Whether the reconciliation of a failed `instance` must wait, because it was given up or
+    /// its retry is not due yet. Status updates, such as the ones reporting the failure,
+    /// trigger reconciliations that would otherwise bypass the backoff.
+    ///
+    /// # Returns
+    /// The action to take instead of reconciling, `None` when the instance is reconciled
+    fn pending_retry(&self, instance: &KclInstance) -> Option<Action> {
+        let mut failures = self.failures.lock().unwrap();
+        let key = ObjectRef::from_obj(instance);
+        if !failures.contains_key(&key) {
+            failures.insert(key.clone(), Failures::from_status(instance)?);
+        }
+        let entry = &failures[&key];
+        if !entry.is_of(instance) {
+            // Changed since it failed, so reconciled right away and counted anew
+            failures.remove(&key);
+            return None;
+        }
+        if entry.gave_up {
+            return Some(Action::await_change());
+        }
+        let remaining = entry
+            .retry_at?
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())?;
+        Some(Action::requeue(remaining))
+    }
+
+    /// Resets the consecutive failures of `instance` after a successful reconciliation.
+    fn reset_failures(&self, instance: &KclInstance) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&ObjectRef::from_obj(instance));
+    }
+
+    /// Registers a reconciliation of `instance`, which can be cancelled through the returned
+    /// guard's token until the guard is dropped.
+    fn start_work(&self, instance: &KclInstance) -> InFlightWork<'_> {
+        let key = ObjectRef::from_obj(instance);
+        let cancel = CancellationToken::new();
+        self.in_flight.lock().unwrap().insert(
+            key.clone(),
+            (instance.metadata.generation.unwrap_or(0), cancel.clone()),
+        );
+        InFlightWork {
+            context: self,
+            key,
+            cancel,
+        }
+    }
+}
+
+/// A reconciliation in progress, see `ContextData::start_work`.
+struct InFlightWork<'a> {
+    context: &'a ContextData,
+    key: ObjectRef<KclInstance>,
+    cancel: CancellationToken,
+}
+
+impl Drop for InFlightWork<'_> {
+    fn drop(&mut self) {
+        // Also stops work left behind by a timed out reconciliation
+        self.cancel.cancel();
+        self.context.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Per-instance locks, so that at most one reconciliation runs per `KclInstance` at a time
+/// and the download, render and apply steps of an instance never interleave.
+#[derive(Default)]
+struct ReconcileLocks {
+    locks: Mutex<HashMap<ObjectRef<KclInstance>, Weak<tokio::sync::Mutex<()>>>>,
+}
+
+impl ReconcileLocks {
+    /// Waits until no other reconciliation of the instance `key` runs, holding its lock
+    /// until the returned guard is dropped.
+    async fn lock(&self, key: ObjectRef<KclInstance>) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            locks.retain(|_, lock| lock.strong_count() > 0);
+            match locks.get(&key).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    locks.insert(key, Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
+}
+
+/// Builds the watcher configuration for the `KclInstance` controller.
+///
+/// # Arguments
+/// - `selector`: Optional label selector restricting which instances are reconciled.
+///
+/// # Errors
+/// Returns `InvalidLabelSelector` if the selector is not a valid Kubernetes label selector.
+pub fn watcher_config(selector: Option<&str>) -> Result<watcher::Config> {
+    let config = watcher::Config::default();
+    match selector.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(selector) if utils::is_valid_label_selector(selector) => Ok(config.labels(selector)),
+        Some(selector) => InvalidLabelSelectorSnafu { selector }.fail(),
+        None => Ok(config),
     }
 }
 
@@ -107,6 +636,20 @@ enum KclInstanceAction {
     Update,
     /// This resource is in desired state and requires no actions to be taken
     NoOp,
+    /// Render and report the changes without touching the cluster, see `PLAN_ANNOTATION`
+    Plan,
+}
+
+impl KclInstanceAction {
+    /// Outcome label of the successful reconciliations performing the action, see `Metrics`.
+    fn outcome(&self) -> &'static str {
+        match self {
+            KclInstanceAction::Create => "created",
+            KclInstanceAction::Update => "updated",
+            KclInstanceAction::Delete => "deleted",
+            KclInstanceAction::NoOp | KclInstanceAction::Plan => "noop",
+        }
+    }
 }
 
 /// Processes a KclInstance by downloading artifacts, rendering manifests, and applying changes
@@ -116,14 +659,25 @@ enum KclInstanceAction {
 /// * `kcl_instance` - Reference to the KclInstance being processed
 /// * `engine` - Engine used for downloading and rendering KCL artifacts
 /// * `context` - Context data containing the downloader and other resources
+/// * `force` - Render and apply even if the source revision and arguments did not change
 ///
 /// # Returns
 ///
 /// Returns Ok(()) if successful, or an Error if any step fails
+#[instrument(
+    skip_all,
+    fields(
+        instance = %kcl_instance.name_any(),
+        namespace = kcl_instance.namespace(),
+        revision = field::Empty,
+        objects = field::Empty,
+    )
+)]
 async fn process_instance(
     kcl_instance: &Arc<KclInstance>,
     engine: &Engine,
     context: &ContextData,
+    force: bool,
 ) -> Result<()> {
     if kcl_instance.spec.suspend.unwrap_or(false) {
         info!("Instance suspended, skipping");
@@ -132,31 +686,86 @@ async fn process_instance(
 
     // Get or create default status for the instance
     let mut status = kcl_instance.status.clone().unwrap_or_default();
+    let failed_before = status.failure_count > 0;
 
-    // Get namespace for the instance
-    let namespace = kcl_instance
-        .namespace()
-        .context(KclInstanceMissingNamespaceSnafu {
-            name: kcl_instance.name_any(),
-        })?;
-
-    // Prepare the arguments for the kcl render
-    let kcl_args = kcl_instance
-        .get_all_args(&context.client, &namespace)
-        .await
-        .context(ProcessArgsSnafu)?;
+    // Resolve the source and the arguments to render it with
+    let mut arguments_status = Vec::new();
+    let mut source_ready = None;
+    let resolved = resolve_source(
+        kcl_instance,
+        engine,
+        context,
+        &mut arguments_status,
+        &mut source_ready,
+    )
+    .await;
+    let arguments_status_changed = status.arguments_status != arguments_status;
+    status.arguments_status = arguments_status;
+    let source_ready_changed = source_ready.is_some_and(|readiness| {
+        set_source_ready(
+            &mut status,
+            readiness,
+            kcl_instance.metadata.generation.unwrap_or(0),
+        )
+    });
+    let arguments_status_changed = arguments_status_changed || source_ready_changed;
+    if resolved.is_err() && arguments_status_changed {
+        // Show which arguments reference or source is broken
+        engine
+            .update_status(
+                kcl_instance.clone(),
+                status.clone(),
+                status.observed_generation,
+            )
+            .await
+            .context(EngineActionSnafu)?;
+    }
+    let (artefact, oci_timeout, kcl_args) = resolved?;
+    let revision = artefact.revision();
+    Span::current().record("revision", revision.as_str());
 
-    // Download KCL artifacts using the engine and downloader
-    let artifacts_path = engine
-        .download(kcl_instance.clone(), &context.downloader)
-        .await
-        .context(ArtefactsPathNotFoundSnafu)?;
+    // Arguments may change without a new revision, so compare the hash of both. Only the
+    // content part of the revision counts, so digest-pinned sources settle for good.
+    let config_hash = kcl_instance.config_hash(
+        &artefact.content_revision(),
+        &instance_ext::merge_args(&context.default_args, HashMap::new(), kcl_args.clone()),
+    );
+    // Forced applies of the last applied state only change the objects that drifted
+    let correct_drift = is_up_to_date(&status, &config_hash);
+    if !force && correct_drift {
+        info!("Revision {} and arguments unchanged, skipping", revision);
+        // The gating conditions, e.g. `SourceReady`, may change without a new revision
+        let ready_conditions = &kcl_instance.spec.config.ready_conditions;
+        let ready_changed = !ready_conditions.is_empty() && {
+            let message = applied_message(kcl_instance, &status);
+            let generation = status.observed_generation;
+            set_ready(&mut status, ready_conditions, message, generation)
+        };
+        if arguments_status_changed || failed_before || ready_changed {
+            status.failure_count = 0;
+            engine
+                .update_status(
+                    kcl_instance.clone(),
+                    status.clone(),
+                    status.observed_generation,
+                )
+                .await
+                .context(EngineActionSnafu)?;
+        }
+        return Ok(());
+    }
+    status.last_attempted_revision = Some(revision.clone());
 
-    // Render the KCL manifests from the artifacts
-    let manifests = engine
-        .render(kcl_instance.clone(), &artifacts_path, &kcl_args)
-        .await
-        .context(CannotRenderKclModuleSnafu)?;
+    let manifests: Arc<str> = render_instance(
+        kcl_instance,
+        engine,
+        context,
+        &artefact,
+        oci_timeout,
+        kcl_args,
+    )
+    .await?
+    .into();
 
     // Get current generation number for status tracking
     let current_generation = kcl_instance.metadata.generation.unwrap_or(0);
@@ -166,38 +775,170 @@ async fn process_instance(
     // Clear the inventory before processing each manifest
     status.inventory.clear();
 
-    // Process each manifests in the rendered output
-    let deserialized = multidoc_deserialize(manifests.as_str()).context(SplitYamlManifestsSnafu)?;
-    let applied = engine
-        .apply(&deserialized, &context.discovery)
+    // Process each manifests in the rendered output, leaving out the ignored objects
+    let RenderedSummary {
+        total,
+        crds,
+        ignored,
+    } = scan_rendered(kcl_instance, context, &manifests).await?;
+    Span::current().record("objects", total);
+    if total == 0 {
+        let note = if !kcl_instance.prune() && !old_inventory.is_empty() {
+            warn!(
+                "KclInstance {} rendered 0 objects, {} previous objects remain as prune is disabled",
+                kcl_instance.name_any(),
+                old_inventory.len()
+            );
+            format!(
+                "Rendered 0 objects, {} previous objects remain as prune is disabled",
+                old_inventory.len()
+            )
+        } else {
+            format!(
+                "Rendered 0 objects, pruning {} previous objects",
+                old_inventory.len()
+            )
+        };
+        crate::event::publish_normal_event(
+            kcl_instance.clone(),
+            context.client.clone(),
+            context.reporter.clone(),
+            "Reconcile".into(),
+            "RenderedEmpty".into(),
+            Some(note),
+        )
         .await
-        .context(EngineActionSnafu)?;
+        .context(PublishEventSnafu)?;
+    }
+    let applied = apply_rendered(
+        kcl_instance,
+        engine,
+        context,
+        &manifests,
+        crds,
+        correct_drift,
+    )
+    .await?;
+    let mut changed = applied.changed;
+    let failed_summary =
+        (!applied.failed.is_empty()).then(|| failed_summary(&applied.failed, total));
+    // Objects that failed to apply stay in the inventory, so they are not pruned
+    for entry in applied.inventory {
+        // Replaced, so the entry holds the resourceVersion of the last apply
+        status.inventory.replace(entry);
+    }
     status
-        .register_applied(applied)
+        .register_applied(applied.failed.into_iter().map(|f| f.object).collect())
         .context(RegisterAppliedSnafu)?;
 
+    if let Some(export) = &kcl_instance.spec.config.export_rendered {
+        crate::export::export_rendered(
+            &context.client,
+            kcl_instance,
+            export,
+            &manifests,
+            context.export_key.as_ref(),
+            engine.managed_by_label(),
+        )
+        .await
+        .context(ExportRenderedSnafu)?;
+    }
+
     // Process all manifests in the old inventory and remove any that were not present in the
     // new manifests rendered from the instance. This handles cleanup of removed resources.
-    for old_dyno in old_inventory.into_iter() {
-        if !status.inventory.contains(&old_dyno) {
-            // Remove the old manifest from the status inventory
+    for old_dyno in stale_objects(&old_inventory, &status.inventory, &ignored) {
+        if !kcl_instance.prune() {
             warn!(
-                "Removing old manifest from status inventory: {:?}",
+                "Prune disabled, leaving object no longer rendered in place: {:?}",
                 old_dyno
             );
-            engine
-                .delete_resource(
-                    &old_dyno.clone().into(),
-                    &old_dyno.name,
-                    &old_dyno.namespace,
-                    &context.discovery,
-                )
-                .await
-                .context(EngineActionSnafu)?;
+            continue;
+        }
+        // Remove the old manifest from the status inventory
+        warn!(
+            "Removing old manifest from status inventory: {:?}",
+            old_dyno
+        );
+        let deleted = engine
+            .delete_resource(
+                kcl_instance,
+                &old_dyno.clone().into(),
+                &old_dyno.name,
+                &old_dyno.namespace,
+                &context.discovery,
+            )
+            .await
+            .context(EngineActionSnafu)?;
+        changed += usize::from(deleted);
+    }
+
+    // Notify only about the reconciliations that changed something
+    status.record_change(changed > 0);
+    if changed > 0 {
+        crate::event::publish_normal_event(
+            kcl_instance.clone(),
+            context.client.clone(),
+            context.reporter.clone(),
+            "Reconcile".into(),
+            "Changed".into(),
+            Some(format!(
+                "Created, changed or pruned {} objects at revision {}",
+                changed, revision
+            )),
+        )
+        .await
+        .context(PublishEventSnafu)?;
+    }
+
+    if let Some(summary) = failed_summary {
+        // The revision is not recorded as applied, so the failed objects are retried
+        warn!("KclInstance {}: {}", kcl_instance.name_any(), summary);
+        crate::event::publish_event(
+            kcl_instance.clone(),
+            context.client.clone(),
+            context.reporter.clone(),
+            "Reconcile".into(),
+            OBJECTS_FAILED_REASON.into(),
+            Some(summary.clone()),
+        )
+        .await
+        .context(PublishEventSnafu)?;
+        status.set_condition(
+            APPLIED_CONDITION,
+            false,
+            OBJECTS_FAILED_REASON,
+            summary.clone(),
+            current_generation,
+        );
+        status.set_condition(
+            READY_CONDITION,
+            false,
+            OBJECTS_FAILED_REASON,
+            summary,
+            current_generation,
+        );
+    } else {
+        status.last_applied_revision = Some(revision);
+        status.last_applied_config_hash = Some(config_hash);
+        status.remove_condition(STALLED_CONDITION);
+        let ready_conditions = &kcl_instance.spec.config.ready_conditions;
+        if kcl_instance.spec.config.continue_on_error
+            || ready_conditions.contains(&ReadyConditionType::Applied)
+        {
+            status.set_condition(
+                APPLIED_CONDITION,
+                true,
+                OBJECTS_APPLIED_REASON,
+                format!("Applied {} objects", total),
+                current_generation,
+            );
         }
+        let message = applied_message(kcl_instance, &status);
+        set_ready(&mut status, ready_conditions, message, current_generation);
     }
 
     // Update the instance status with changes
+    status.failure_count = 0;
     engine
         .update_status(kcl_instance.clone(), status, current_generation)
         .await
@@ -205,45 +946,854 @@ async fn process_instance(
     Ok(())
 }
 
-pub async fn reconcile(
+/// Message of the `Ready` condition of an instance whose objects were applied.
+fn applied_message(kcl_instance: &KclInstance, status: &KclInstanceStatus) -> String {
+    format!(
+        "Applied {} objects. Next run in {}",
+        status.inventory.len(),
+        format_duration(kcl_instance.interval())
+    )
+}
+
+/// Sets the `Ready` condition of a reconciled instance: True with `message`, unless some of
+/// the `ready_conditions` of the instance are not True.
+///
+/// # Returns
+/// Whether the condition changed
+fn set_ready(
+    status: &mut KclInstanceStatus,
+    ready_conditions: &[ReadyConditionType],
+    message: String,
+    generation: i64,
+) -> bool {
+    let before = status.condition(READY_CONDITION).cloned();
+    let unmet = status.unmet_conditions(ready_conditions);
+    if unmet.is_empty() {
+        status.set_condition(
+            READY_CONDITION,
+            true,
+            RECONCILIATION_SUCCEEDED_REASON,
+            message,
+            generation,
+        );
+    } else {
+        status.set_condition(
+            READY_CONDITION,
+            false,
+            READY_CONDITIONS_UNMET_REASON,
+            format!("Not ready as {}. {}", unmet.join(", "), message),
+            generation,
+        );
+    }
+    status.condition(READY_CONDITION) != before.as_ref()
+}
+
+/// Waits until the objects deleted with an instance are gone, within its `timeout`, reporting
+/// the progress in events. The instance is deleted anyway once the wait timed out.
+async fn wait_for_deletion(
+    kcl_instance: &Arc<KclInstance>,
+    context: &ContextData,
+    deleted: Vec<engine::DeletedObject>,
+) -> Result<()> {
+    let progress = |remaining: usize| {
+        crate::event::publish_normal_event(
+            kcl_instance.clone(),
+            context.client.clone(),
+            context.reporter.clone(),
+            "Reconcile".into(),
+            "WaitingForDeletion".into(),
+            Some(format!("Waiting for the deletion of {} objects", remaining)),
+        )
+    };
+    progress(deleted.len()).await.context(PublishEventSnafu)?;
+
+    let result = context
+        .engine
+        .wait_deleted(
+            deleted,
+            kcl_instance.timeout(),
+            DELETION_POLL_INTERVAL,
+            |remaining| {
+                tokio::spawn(progress(remaining));
+            },
+        )
+        .await;
+    if let Err(e) = result {
+        warn!("KclInstance {}: {}", kcl_instance.name_any(), e);
+        crate::event::publish_event(
+            kcl_instance.clone(),
+            context.client.clone(),
+            context.reporter.clone(),
+            "Reconcile".into(),
+            "DeletionTimeout".into(),
+            Some(e.to_string()),
+        )
+        .await
+        .context(PublishEventSnafu)?;
+    }
+    Ok(())
+}
+
+/// Renders an instance as a reconciliation would, without applying anything nor changing its
+/// status, for the `preview` command
+///
+/// # Returns
+///
+/// The rendered revision and the objects a reconciliation would apply, as a multi-document
+/// YAML stream
+pub async fn preview_instance(
     kcl_instance: Arc<KclInstance>,
-    context: Arc<ContextData>,
-) -> Result<Action, Error> {
-    let client = context.client.clone();
+    context: &ContextData,
+) -> Result<(String, String)> {
     let engine = &context.engine;
-    let name = &kcl_instance.name_any();
-
-    let namespace = kcl_instance
-        .namespace()
-        .context(KclInstanceMissingNamespaceSnafu { name })?;
+    let (artefact, oci_timeout, kcl_args) =
+        resolve_source(&kcl_instance, engine, context, &mut Vec::new(), &mut None).await?;
+    let manifests = render_instance(
+        &kcl_instance,
+        engine,
+        context,
+        &artefact,
+        oci_timeout,
+        kcl_args,
+    )
+    .await?;
+    let (objects, ignored) = split_rendered(&kcl_instance, context, &manifests).await?;
+    if !ignored.is_empty() {
+        info!("Left out {} ignored objects", ignored.len());
+    }
+    let (objects, duplicates) = dedup_rendered(objects);
+    if !duplicates.is_empty() {
+        warn!(
+            "Rendered {} several times, only the first one would be applied",
+            duplicates.join(", ")
+        );
+    }
 
-    match determine_action(&kcl_instance) {
-        KclInstanceAction::Create => {
-            info!("KclInstance {} is being created", name);
+    let documents = objects
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<_>, _>>()
+        .context(SerializeManifestsSnafu)?;
+    Ok((artefact.revision(), documents.join("---\n")))
+}
 
-            crate::event::publish_event(
-                kcl_instance.clone(),
-                client.clone(),
-                "Reconcile".into(),
-                "Creating".into(),
-                Some(format!("Start creating resources {}", name)),
-            )
-            .await
-            .context(PublishEventSnafu)?;
+/// Renders an instance in plan mode and reports the changes a reconciliation would make in the
+/// `Planned` condition and an event, without changing any object, the finalizers or the inventory
+#[instrument(
+    skip_all,
+    fields(
+        instance = %kcl_instance.name_any(),
+        namespace = kcl_instance.namespace(),
+        revision = field::Empty,
+        objects = field::Empty,
+    )
+)]
+async fn plan_instance(
+    kcl_instance: &Arc<KclInstance>,
+    engine: &Engine,
+    context: &ContextData,
+) -> Result<()> {
+    if kcl_instance.spec.suspend.unwrap_or(false) {
+        info!("Instance suspended, skipping");
+        return Ok(());
+    }
 
-            // Add finalizer to prevent resource deletion until we're done with cleanup
-            finalizer::add(client.clone(), name, &namespace)
-                .await
-                .context(AddFinalizerSnafu)?;
-            info!("Added finalizer to resource {}", name);
+    let (artefact, oci_timeout, kcl_args) =
+        resolve_source(kcl_instance, engine, context, &mut Vec::new(), &mut None).await?;
+    let revision = artefact.revision();
+    Span::current().record("revision", revision.as_str());
+    let manifests = render_instance(
+        kcl_instance,
+        engine,
+        context,
+        &artefact,
+        oci_timeout,
+        kcl_args,
+    )
+    .await?;
+    let (deserialized, ignored) = split_rendered(kcl_instance, context, &manifests).await?;
+    let deserialized = skip_duplicates(kcl_instance, context, deserialized).await?;
+    Span::current().record("objects", deserialized.len());
 
-            process_instance(&kcl_instance, engine, &context).await?;
+    let plan = engine
+        .plan(kcl_instance, &deserialized, &context.discovery)
+        .await
+        .context(EngineActionSnafu)?;
+    let rendered = deserialized
+        .into_iter()
+        .map(Gvk::try_from)
+        .collect::<Result<HashSet<_>, _>>()
+        .context(RegisterAppliedSnafu)?;
+    let inventory = kcl_instance
+        .status
+        .as_ref()
+        .map(|status| status.inventory.clone())
+        .unwrap_or_default();
+    let prune = if kcl_instance.prune() {
+        stale_objects(&inventory, &rendered, &ignored)
+    } else {
+        Vec::new()
+    };
 
-            crate::event::publish_event(
-                kcl_instance.clone(),
-                client.clone(),
-                "Reconcile".into(),
-                "Ready".into(),
+    let summary = plan_summary(&revision, &plan, &prune);
+    info!("{}", summary);
+    crate::event::publish_normal_event(
+        kcl_instance.clone(),
+        context.client.clone(),
+        context.reporter.clone(),
+        "Plan".into(),
+        "Planned".into(),
+        Some(summary.clone()),
+    )
+    .await
+    .context(PublishEventSnafu)?;
+    if !plan.diffs.is_empty() {
+        let diff = plan.diffs.concat();
+        info!("Planned changes:\n{}", diff);
+        crate::event::publish_normal_event(
+            kcl_instance.clone(),
+            context.client.clone(),
+            context.reporter.clone(),
+            "Plan".into(),
+            "PlanDiff".into(),
+            Some(truncate_note(diff, MAX_EVENT_NOTE_BYTES)),
+        )
+        .await
+        .context(PublishEventSnafu)?;
+    }
+    engine
+        .patch_condition(
+            kcl_instance.clone(),
+            PLAN_CONDITION,
+            true,
+            PLAN_SUCCEEDED_REASON,
+            summary,
+        )
+        .await
+        .context(EngineActionSnafu)?;
+    Ok(())
+}
+
+/// Truncates an event note to at most `max` bytes on a character boundary, marking the cut.
+fn truncate_note(mut note: String, max: usize) -> String {
+    const MARKER: &str = "\n... (truncated)";
+    if note.len() <= max {
+        return note;
+    }
+    let mut end = max.saturating_sub(MARKER.len());
+    while !note.is_char_boundary(end) {
+        end -= 1;
+    }
+    note.truncate(end);
+    note.push_str(MARKER);
+    note
+}
+
+/// Summarizes the changes planned for an instance, e.g.
+/// `Plan for revision main@sha1:abc: 1 to create (ConfigMap default/app), 0 to change, 2 unchanged, 0 to prune`.
+fn plan_summary(revision: &str, plan: &Plan, prune: &[Gvk]) -> String {
+    let list = |ids: &[String]| {
+        if ids.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", ids.join(", "))
+        }
+    };
+    let prune: Vec<String> = prune
+        .iter()
+        .map(|gvk| match &gvk.namespace {
+            Some(namespace) => format!("{} {}/{}", gvk.kind, namespace, gvk.name),
+            None => format!("{} {}", gvk.kind, gvk.name),
+        })
+        .collect();
+    format!(
+        "Plan for revision {}: {} to create{}, {} to change{}, {} unchanged, {} to prune{}",
+        revision,
+        plan.create.len(),
+        list(&plan.create),
+        plan.change.len(),
+        list(&plan.change),
+        plan.unchanged,
+        prune.len(),
+        list(&prune),
+    )
+}
+
+/// Resolves the source artefact of an instance and the arguments to render it with.
+///
+/// # Arguments
+///
+/// * `arguments_status` - Receives the resolution status of the `argumentsFrom` references
+/// * `source_ready` - Receives the readiness of the source, once it was fetched
+///
+/// # Returns
+///
+/// The artefact with the timeout of the OCI pulls of its module dependencies, and the
+/// arguments, without those of the values files, which need the downloaded source
+async fn resolve_source(
+    kcl_instance: &Arc<KclInstance>,
+    engine: &Engine,
+    context: &ContextData,
+    arguments_status: &mut Vec<ArgSourceStatus>,
+    source_ready: &mut Option<SourceReadiness>,
+) -> Result<(FluxSourceArtefact, Duration, HashMap<String, String>)> {
+    // Get namespace for the instance
+    let namespace = kcl_instance
+        .namespace()
+        .context(KclInstanceMissingNamespaceSnafu {
+            name: kcl_instance.name_any(),
+        })?;
+
+    // Prepare the arguments for the kcl render
+    let mut kcl_args = kcl_instance
+        .get_all_args(&context.client, &namespace, arguments_status)
+        .await
+        .context(ProcessArgsSnafu)?;
+
+    // Resolve the source artefact to learn the revision to render
+    let (artefact, oci_timeout) = engine
+        .get_artefact(kcl_instance, source_ready)
+        .await
+        .context(ArtefactsPathNotFoundSnafu)?;
+    instance_ext::add_artifact_metadata_args(
+        &mut kcl_args,
+        &kcl_instance.spec.config.artifact_metadata,
+        &artefact.metadata(),
+    );
+    Ok((artefact, oci_timeout, kcl_args))
+}
+
+/// Sets the `SourceReady` condition of an instance from the readiness of its source.
+///
+/// # Returns
+/// Whether the condition changed
+fn set_source_ready(
+    status: &mut KclInstanceStatus,
+    readiness: SourceReadiness,
+    generation: i64,
+) -> bool {
+    let before = status.condition(SOURCE_READY_CONDITION).cloned();
+    status.set_condition(
+        SOURCE_READY_CONDITION,
+        readiness.ready,
+        &readiness.reason,
+        readiness.message,
+        generation,
+    );
+    status.condition(SOURCE_READY_CONDITION) != before.as_ref()
+}
+
+/// Downloads the source artefact of an instance and renders its module.
+///
+/// # Arguments
+///
+/// * `artefact` - The source artefact, see `resolve_source`
+/// * `oci_timeout` - Timeout of the OCI pulls of the module dependencies, see `resolve_source`
+/// * `kcl_args` - Arguments of the instance, overriding those of the values files and the
+///   operator defaults
+///
+/// # Returns
+///
+/// The rendered manifests
+async fn render_instance(
+    kcl_instance: &Arc<KclInstance>,
+    engine: &Engine,
+    context: &ContextData,
+    artefact: &FluxSourceArtefact,
+    oci_timeout: Duration,
+    kcl_args: HashMap<String, String>,
+) -> Result<String> {
+    // Large modules wait for the running renders to finish rather than exhaust the memory
+    let render_permit = match &context.render_admission {
+        Some(admission) => {
+            let permit = admission.try_admit(artefact.size());
+            context.metrics.observe_render_admission(permit.is_some());
+            let Some(permit) = permit else {
+                info!(
+                    "Deferring render, {}/{} MiB of render memory in use ({} renders deferred)",
+                    admission.in_use_mib(),
+                    admission.limit_mib(),
+                    admission.rejected()
+                );
+                return RenderDeferredSnafu {
+                    required_mib: admission.weight(artefact.size()),
+                }
+                .fail();
+            };
+            Some(permit)
+        }
+        None => None,
+    };
+
+    // Download KCL artifacts using the engine and downloader
+    let work = context.start_work(kcl_instance);
+    let artifacts_path = engine
+        .download(
+            kcl_instance.clone(),
+            artefact,
+            &context.downloader,
+            &work.cancel,
+        )
+        .await
+        .context(ArtefactsPathNotFoundSnafu)?;
+
+    // Values files override the operator defaults and are overridden by the other arguments
+    let values = instance_ext::read_values_files(
+        &artifacts_path.join(&kcl_instance.spec.path),
+        &kcl_instance.spec.config.values_files,
+    )
+    .context(ReadValuesFilesSnafu)?;
+    let kcl_args = instance_ext::merge_args(&context.default_args, values, kcl_args);
+
+    // Render the KCL manifests from the artifacts
+    let manifests = engine
+        .render(
+            kcl_instance.clone(),
+            &artifacts_path,
+            &kcl_args,
+            oci_timeout,
+            &work.cancel,
+        )
+        .await
+        .context(CannotRenderKclModuleSnafu)?;
+    drop(render_permit);
+    Ok(manifests)
+}
+
+/// Splits rendered manifests into the objects to apply and the ignored objects, see
+/// `KclInstanceConfig::ignore`, after moving them to the namespace of the namespace template
+/// of the instance, or else of the operator, if any.
+async fn split_rendered(
+    kcl_instance: &KclInstance,
+    context: &ContextData,
+    manifests: &str,
+) -> Result<(Vec<DynamicObject>, Vec<Gvk>)> {
+    let mut objects = multidoc_deserialize(manifests).context(SplitYamlManifestsSnafu)?;
+    engine::resolve_api_versions(
+        &mut objects,
+        &kcl_instance.spec.config.api_versions,
+        &context.discovery,
+    )
+    .await
+    .context(EngineActionSnafu)?;
+    let template = kcl_instance
+        .spec
+        .config
+        .namespace_template
+        .as_deref()
+        .or(context.namespace_template.as_deref());
+    if let Some(template) = template {
+        let namespace = instance_ext::expand_namespace_template(template, kcl_instance)
+            .context(NamespaceTemplateSnafu)?;
+        engine::set_target_namespace(&mut objects, &namespace, &context.discovery).await;
+    }
+
+    let (deserialized, ignored): (Vec<_>, Vec<_>) = objects
+        .into_iter()
+        .partition(|object| !kcl_instance.spec.config.is_skipped(object));
+    if !ignored.is_empty() {
+        info!(
+            "Skipping {} rendered objects ignored or not included",
+            ignored.len()
+        );
+    }
+    let ignored = ignored
+        .into_iter()
+        .map(Gvk::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .context(RegisterAppliedSnafu)?;
+    Ok((deserialized, ignored))
+}
+
+/// Identifies a rendered object by its apiVersion, kind, namespace and name, see
+/// `dedup_rendered`.
+type RenderedKey = (
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+fn rendered_key(object: &DynamicObject) -> RenderedKey {
+    let types = object.types.as_ref();
+    (
+        types.map(|types| types.api_version.clone()),
+        types.map(|types| types.kind.clone()),
+        object.metadata.namespace.clone(),
+        object.metadata.name.clone(),
+    )
+}
+
+/// Splits rendered objects into the first object rendered for each group, version, kind,
+/// namespace and name, and the descriptions of the duplicates, which would overwrite it.
+fn dedup_rendered(objects: Vec<DynamicObject>) -> (Vec<DynamicObject>, Vec<String>) {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    let objects = objects
+        .into_iter()
+        .filter(|object| {
+            let first = seen.insert(rendered_key(object));
+            if !first {
+                duplicates.push(engine::object_id(object));
+            }
+            first
+        })
+        .collect();
+    (objects, duplicates)
+}
+
+/// Skips the objects rendered several times, keeping the first one, and warns about them with
+/// a `DuplicateObject` event.
+async fn skip_duplicates(
+    kcl_instance: &Arc<KclInstance>,
+    context: &ContextData,
+    objects: Vec<DynamicObject>,
+) -> Result<Vec<DynamicObject>> {
+    let (objects, duplicates) = dedup_rendered(objects);
+    warn_duplicates(kcl_instance, context, &duplicates).await?;
+    Ok(objects)
+}
+
+/// Warns about the objects rendered several times with a `DuplicateObject` event, if any.
+async fn warn_duplicates(
+    kcl_instance: &Arc<KclInstance>,
+    context: &ContextData,
+    duplicates: &[String],
+) -> Result<()> {
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    let note = format!(
+        "Rendered {} several times, only the first one is applied",
+        duplicates.join(", ")
+    );
+    warn!("KclInstance {}: {}", kcl_instance.name_any(), note);
+    crate::event::publish_event(
+        kcl_instance.clone(),
+        context.client.clone(),
+        context.reporter.clone(),
+        "Reconcile".into(),
+        "DuplicateObject".into(),
+        Some(note),
+    )
+    .await
+    .context(PublishEventSnafu)
+}
+
+/// A rendered object read by a `RenderedPass`.
+enum Rendered {
+    /// An object to apply
+    Object(DynamicObject),
+    /// An object ignored or not included, see `KclInstanceConfig::ignore`
+    Ignored(DynamicObject),
+    /// An object rendered again, which would overwrite the first one, see `dedup_rendered`
+    Duplicate(DynamicObject),
+}
+
+/// A pass over the rendered manifests that parses the documents one at a time, so that large
+/// renders are never held in memory as a whole. The objects go through the same steps as
+/// with `split_rendered` and `skip_duplicates`.
+struct RenderedPass<'a> {
+    kcl_instance: &'a KclInstance,
+    context: &'a ContextData,
+    /// Namespace of the namespace template, see `split_rendered`
+    namespace: Option<String>,
+    documents: tokio::sync::mpsc::Receiver<anyhow::Result<DynamicObject>>,
+    seen: HashSet<RenderedKey>,
+}
+
+impl<'a> RenderedPass<'a> {
+    fn new(
+        kcl_instance: &'a KclInstance,
+        context: &'a ContextData,
+        manifests: &Arc<str>,
+    ) -> Result<Self> {
+        let template = kcl_instance
+            .spec
+            .config
+            .namespace_template
+            .as_deref()
+            .or(context.namespace_template.as_deref());
+        let namespace = template
+            .map(|template| instance_ext::expand_namespace_template(template, kcl_instance))
+            .transpose()
+            .context(NamespaceTemplateSnafu)?;
+        Ok(Self {
+            kcl_instance,
+            context,
+            namespace,
+            documents: utils::multidoc_channel(manifests.clone(), APPLY_BATCH_SIZE),
+            seen: HashSet::new(),
+        })
+    }
+
+    /// Reads the next rendered object.
+    ///
+    /// # Returns
+    /// None once all the documents were read
+    async fn next(&mut self) -> Result<Option<Rendered>> {
+        let Some(object) = self.documents.recv().await else {
+            return Ok(None);
+        };
+        let mut object = object.context(SplitYamlManifestsSnafu)?;
+        let objects = std::slice::from_mut(&mut object);
+        engine::resolve_api_versions(
+            objects,
+            &self.kcl_instance.spec.config.api_versions,
+            &self.context.discovery,
+        )
+        .await
+        .context(EngineActionSnafu)?;
+        if let Some(namespace) = &self.namespace {
+            engine::set_target_namespace(objects, namespace, &self.context.discovery).await;
+        }
+
+        if self.kcl_instance.spec.config.is_skipped(&object) {
+            return Ok(Some(Rendered::Ignored(object)));
+        }
+        if !self.seen.insert(rendered_key(&object)) {
+            return Ok(Some(Rendered::Duplicate(object)));
+        }
+        Ok(Some(Rendered::Object(object)))
+    }
+
+    /// Reads up to `size` objects to apply, leaving out the ignored objects and duplicates.
+    ///
+    /// # Returns
+    /// None once all the documents were read
+    async fn next_batch(&mut self, size: usize) -> Result<Option<Vec<DynamicObject>>> {
+        let mut batch = Vec::new();
+        while batch.len() < size {
+            match self.next().await? {
+                Some(Rendered::Object(object)) => batch.push(object),
+                Some(Rendered::Ignored(_) | Rendered::Duplicate(_)) => {}
+                None => break,
+            }
+        }
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
+
+/// Rendered objects found by `scan_rendered`.
+struct RenderedSummary {
+    /// Number of objects to apply
+    total: usize,
+    /// The CRDs to apply, applied before the other objects
+    crds: Vec<DynamicObject>,
+    /// The objects ignored or not included
+    ignored: Vec<Gvk>,
+}
+
+/// Reads the rendered manifests once to count the objects to apply and keep the CRDs and the
+/// ignored objects, warning about the duplicates, see `skip_duplicates`.
+async fn scan_rendered(
+    kcl_instance: &Arc<KclInstance>,
+    context: &ContextData,
+    manifests: &Arc<str>,
+) -> Result<RenderedSummary> {
+    let mut pass = RenderedPass::new(kcl_instance, context, manifests)?;
+    let mut summary = RenderedSummary {
+        total: 0,
+        crds: Vec::new(),
+        ignored: Vec::new(),
+    };
+    let mut duplicates = Vec::new();
+    while let Some(rendered) = pass.next().await? {
+        match rendered {
+            Rendered::Object(object) => {
+                summary.total += 1;
+                if engine::is_crd(&object) {
+                    summary.crds.push(object);
+                }
+            }
+            Rendered::Ignored(object) => summary
+                .ignored
+                .push(Gvk::try_from(object).context(RegisterAppliedSnafu)?),
+            Rendered::Duplicate(object) => duplicates.push(engine::object_id(&object)),
+        }
+    }
+    if !summary.ignored.is_empty() {
+        info!(
+            "Skipping {} rendered objects ignored or not included",
+            summary.ignored.len()
+        );
+    }
+    warn_duplicates(kcl_instance, context, &duplicates).await?;
+    Ok(summary)
+}
+
+/// Applies the rendered manifests batch by batch with `Engine::apply_session`, so that only
+/// the CRDs and a batch of objects are held in memory at a time.
+///
+/// Every batch is checked before the first object is applied, in a separate pass over the
+/// manifests, see `ApplySession::check`.
+async fn apply_rendered(
+    kcl_instance: &KclInstance,
+    engine: &Engine,
+    context: &ContextData,
+    manifests: &Arc<str>,
+    crds: Vec<DynamicObject>,
+    correct_drift: bool,
+) -> Result<engine::AppliedObjects> {
+    let mut session = engine
+        .apply_session(kcl_instance, &context.discovery, crds, correct_drift)
+        .context(EngineActionSnafu)?;
+    let mut pass = RenderedPass::new(kcl_instance, context, manifests)?;
+    while let Some(batch) = pass.next_batch(APPLY_BATCH_SIZE).await? {
+        session.check(&batch).await.context(EngineActionSnafu)?;
+    }
+    session.apply_crds().await.context(EngineActionSnafu)?;
+    let mut pass = RenderedPass::new(kcl_instance, context, manifests)?;
+    while let Some(batch) = pass.next_batch(APPLY_BATCH_SIZE).await? {
+        session.apply(&batch).await.context(EngineActionSnafu)?;
+    }
+    session.finish().await.context(EngineActionSnafu)
+}
+
+/// Summarizes the objects that failed to apply out of `total`, e.g.
+/// `1 of 3 objects failed to apply: ConfigMap default/app: <error>`.
+fn failed_summary(failed: &[FailedObject], total: usize) -> String {
+    let failures: Vec<String> = failed
+        .iter()
+        .map(|f| format!("{}: {}", engine::object_id(&f.object), f.reason))
+        .collect();
+    format!(
+        "{} of {} objects failed to apply: {}",
+        failed.len(),
+        total,
+        failures.join("; ")
+    )
+}
+
+/// Whether the last apply used the same source content, arguments and configuration.
+fn is_up_to_date(status: &KclInstanceStatus, config_hash: &str) -> bool {
+    status.last_applied_config_hash.as_deref() == Some(config_hash)
+}
+
+/// Returns the objects of the previous inventory that are no longer rendered. Objects that
+/// are still rendered but ignored or not included are kept.
+fn stale_objects(
+    old_inventory: &HashSet<Gvk>,
+    inventory: &HashSet<Gvk>,
+    ignored: &[Gvk],
+) -> Vec<Gvk> {
+    old_inventory
+        .difference(inventory)
+        .filter(|stale| !ignored.iter().any(|ignored| is_same_object(stale, ignored)))
+        .cloned()
+        .collect()
+}
+
+/// Whether an inventory entry and a rendered object designate the same object, whatever the
+/// served version. Rendered objects without a namespace match any namespace.
+fn is_same_object(stale: &Gvk, rendered: &Gvk) -> bool {
+    stale.group == rendered.group
+        && stale.kind == rendered.kind
+        && stale.name == rendered.name
+        && rendered
+            .namespace
+            .as_ref()
+            .is_none_or(|namespace| stale.namespace.as_ref() == Some(namespace))
+}
+
+/// Runs a reconciliation step, failing with `ReconcileTimeout` if it takes longer than `timeout`.
+async fn with_timeout<T>(timeout: Duration, fut: impl Future<Output = Result<T>>) -> Result<T> {
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(res) => res,
+        Err(_) => ReconcileTimeoutSnafu {
+            timeout: format_duration(timeout).to_string(),
+        }
+        .fail(),
+    }
+}
+
+pub async fn reconcile(
+    kcl_instance: Arc<KclInstance>,
+    context: Arc<ContextData>,
+) -> Result<Action, Error> {
+    if kcl_instance.metadata.deletion_timestamp.is_none() {
+        if let Some(action) = context.pending_retry(&kcl_instance) {
+            return Ok(action);
+        }
+    }
+    let instance_action = determine_action(&kcl_instance);
+    let started = Instant::now();
+    let res = reconcile_instance(kcl_instance.clone(), context.clone(), &instance_action).await;
+    record_reconcile(&context.metrics, &instance_action, &res, started.elapsed());
+    let action = res?;
+    context.reset_failures(&kcl_instance);
+    if kcl_instance.metadata.deletion_timestamp.is_none() {
+        context.reconciles.record(&kcl_instance, Instant::now());
+    }
+    Ok(action)
+}
+
+/// Records the outcome and duration of a reconciliation performing `action` in `metrics`.
+/// Plans are recorded as `noop`, as they never change the cluster.
+fn record_reconcile(
+    metrics: &Metrics,
+    action: &KclInstanceAction,
+    res: &Result<Action, Error>,
+    elapsed: Duration,
+) {
+    match res {
+        Ok(_) => metrics.observe(action.outcome(), elapsed),
+        Err(error) => metrics.observe_error(error.class().into(), elapsed),
+    }
+}
+
+async fn reconcile_instance(
+    kcl_instance: Arc<KclInstance>,
+    context: Arc<ContextData>,
+    action: &KclInstanceAction,
+) -> Result<Action, Error> {
+    let client = context.client.clone();
+    let engine = &context.engine;
+    let name = &kcl_instance.name_any();
+
+    let namespace = kcl_instance
+        .namespace()
+        .context(KclInstanceMissingNamespaceSnafu { name })?;
+
+    // Overlapping reconciliations of the instance wait for the running one to finish
+    let _lock = context
+        .reconcile_locks
+        .lock(ObjectRef::from_obj(kcl_instance.as_ref()))
+        .await;
+
+    match action {
+        KclInstanceAction::Create => {
+            info!("KclInstance {} is being created", name);
+
+            crate::event::publish_event(
+                kcl_instance.clone(),
+                client.clone(),
+                context.reporter.clone(),
+                "Reconcile".into(),
+                "Creating".into(),
+                Some(format!("Start creating resources {}", name)),
+            )
+            .await
+            .context(PublishEventSnafu)?;
+
+            // Add finalizer to prevent resource deletion until we're done with cleanup
+            finalizer::add(client.clone(), name, &namespace)
+                .await
+                .context(AddFinalizerSnafu)?;
+            info!("Added finalizer to resource {}", name);
+
+            with_timeout(
+                kcl_instance.timeout(),
+                process_instance(&kcl_instance, engine, &context, true),
+            )
+            .await?;
+
+            crate::event::publish_event(
+                kcl_instance.clone(),
+                client.clone(),
+                context.reporter.clone(),
+                "Reconcile".into(),
+                "Ready".into(),
                 Some(format!(
                     "Ready to apply all resorces. Next run in {}",
                     format_duration(kcl_instance.interval())
@@ -257,18 +1807,28 @@ pub async fn reconcile(
         KclInstanceAction::Update => {
             info!("Update");
 
-            process_instance(&kcl_instance, engine, &context).await?;
+            with_timeout(
+                kcl_instance.timeout(),
+                process_instance(&kcl_instance, engine, &context, true),
+            )
+            .await?;
 
             Ok(Action::requeue(kcl_instance.interval()))
         }
         KclInstanceAction::Delete => {
             // Delete all subresources created in the `Create` phase
 
-            if let Err(e) = engine
+            match engine
                 .cleanup(kcl_instance.clone(), &context.discovery)
                 .await
             {
-                error!("Failed to cleanup: {}", e)
+                Ok(deleted)
+                    if kcl_instance.spec.config.wait_for_deletion && !deleted.is_empty() =>
+                {
+                    wait_for_deletion(&kcl_instance, &context, deleted).await?
+                }
+                Ok(_) => {}
+                Err(e) => error!("Failed to cleanup: {}", e),
             }
 
             // Anyway delete finalizer, so we can delete the resource
@@ -276,10 +1836,12 @@ pub async fn reconcile(
                 .await
                 .context(DeleteFinalizerSnafu)?;
             info!("Deleted finalizer from resource {}", name);
+            context.reconciles.forget(&kcl_instance);
 
             crate::event::publish_event(
                 kcl_instance.clone(),
                 client.clone(),
+                context.reporter.clone(),
                 "Reconcile".into(),
                 "Deleted".into(),
                 Some("All resources deleted".to_string()),
@@ -291,35 +1853,185 @@ pub async fn reconcile(
         }
         KclInstanceAction::NoOp => {
             info!("NoOp");
+
+            // Only re-render when the source revision or resolved arguments changed
+            with_timeout(
+                kcl_instance.timeout(),
+                process_instance(&kcl_instance, engine, &context, false),
+            )
+            .await?;
+
             Ok(Action::requeue(kcl_instance.interval()))
-        } // TODO: Change interval from KclInstance
+        }
+        KclInstanceAction::Plan => {
+            info!("KclInstance {} is in plan mode", name);
+
+            with_timeout(
+                kcl_instance.timeout(),
+                plan_instance(&kcl_instance, engine, &context),
+            )
+            .await?;
+
+            Ok(Action::requeue(kcl_instance.interval()))
+        }
     }
 }
 
 /// Actions to be taken when a reconciliation fails - for whatever reason.
-/// Prints out the error to `stderr` and requeues the resource for another reconciliation after
-/// five seconds.
+/// Prints out the error to `stderr`, marks the instance as not ready and requeues the resource
+/// depending on the class of the error: invalid specs wait for the instance to change, module
+/// errors are retried at the regular interval and transient errors after the retry interval,
+/// backing off on consecutive failures.
 ///
 /// # Arguments
 /// - `kcl_instance`: The erroneous resource.
 /// - `error`: A reference to the `kube::Error` that occurred during reconciliation.
-/// - `_context`: Unused argument. Context Data "injected" automatically by kube-rs.
+/// - `context`: Context Data "injected" automatically by kube-rs.
 pub fn on_error(
     kcl_instance: Arc<KclInstance>,
     error: &Error,
     context: Arc<ContextData>,
 ) -> Action {
+    if error.is_cancelled() {
+        info!(
+            "Reconciliation of {} was superseded, requeueing",
+            kcl_instance.name_any()
+        );
+        return Action::requeue(Duration::from_secs(1));
+    }
+    if let Error::RenderDeferred { .. } = error {
+        info!(
+            "Render of {} deferred, requeueing in {}",
+            kcl_instance.name_any(),
+            format_duration(RENDER_DEFER_DELAY)
+        );
+        return Action::requeue(RENDER_DEFER_DELAY);
+    }
+
     error!("Reconciliation error:\n{:?}.\n{:?}", error, kcl_instance);
     let client = context.client.clone();
-    let interval = kcl_instance.interval();
+    let failures = context.record_failure(&kcl_instance);
+    context.reconciles.record(&kcl_instance, Instant::now());
+    let mut retry_after = error.retry_after(&kcl_instance, failures, context.max_backoff);
+    // Only retried failures count, the others already wait for the instance to change
+    let retried = retry_after.is_some();
+    let gave_up = retried && context.gives_up(failures);
+    if gave_up {
+        retry_after = None;
+    }
+    context.schedule_retry(&kcl_instance, retry_after, gave_up);
     tokio::spawn(crate::event::publish_event(
-        kcl_instance,
+        kcl_instance.clone(),
         client.clone(),
+        context.reporter.clone(),
         "Reconcile".into(),
-        "Error".into(),
+        error.reason().into(),
         Some(error.to_string()),
     ));
-    Action::requeue(interval)
+    if gave_up {
+        warn!(
+            "Giving up KclInstance {} after {} consecutive failures",
+            kcl_instance.name_any(),
+            failures
+        );
+        tokio::spawn(crate::event::publish_event(
+            kcl_instance.clone(),
+            client.clone(),
+            context.reporter.clone(),
+            "Reconcile".into(),
+            RETRIES_EXHAUSTED_REASON.into(),
+            Some(format!(
+                "Gave up after {} consecutive failures, change the instance to retry it",
+                failures
+            )),
+        ));
+    }
+
+    let reason = error.condition_reason();
+    let stalled = error.class() == ErrorClass::Stalled;
+    let message = match retry_after {
+        Some(interval) => format!("{}. Retrying in {}", error, format_duration(interval)),
+        None if gave_up => format!(
+            "{}. Gave up after {} consecutive failures, retrying once the instance changes",
+            error, failures
+        ),
+        None if stalled => format!(
+            "{}. Retrying once the instance or its source changes",
+            error
+        ),
+        None => format!("{}. Retrying once the instance changes", error),
+    };
+    tokio::spawn(async move {
+        if let Err(e) = context
+            .engine
+            .patch_status_with(kcl_instance, |status, generation| {
+                status.set_condition(READY_CONDITION, false, reason, message.clone(), generation);
+                if gave_up {
+                    status.set_condition(
+                        STALLED_CONDITION,
+                        true,
+                        RETRIES_EXHAUSTED_REASON,
+                        message,
+                        generation,
+                    );
+                } else if stalled {
+                    status.set_condition(STALLED_CONDITION, true, reason, message, generation);
+                } else {
+                    status.remove_condition(STALLED_CONDITION);
+                }
+                if retried {
+                    status.failure_count = failures;
+                }
+            })
+            .await
+        {
+            warn!("Failed to update the status after the error: {}", e);
+        }
+    });
+    retry_after.map_or_else(Action::await_change, Action::requeue)
+}
+
+/// Instances whose source is `source`, so they are reconciled when it changes, e.g. on a new
+/// revision fixing the error that stalled them.
+///
+/// # Arguments
+/// - `store`: The instances known to the controller.
+/// - `source`: The Flux source that changed.
+pub fn instances_of_source<K>(store: &Store<KclInstance>, source: &K) -> Vec<ObjectRef<KclInstance>>
+where
+    K: Resource<DynamicType = ()>,
+{
+    let kind = K::kind(&());
+    let name = source.name_any();
+    let namespace = source.namespace();
+    store
+        .state()
+        .iter()
+        .filter(|instance| references_source(instance, &kind, &name, namespace.as_deref()))
+        .map(|instance| ObjectRef::from_obj(instance.as_ref()))
+        .collect()
+}
+
+/// Whether the `sourceRef` of an instance is the source of the given kind, name and namespace.
+fn references_source(
+    kcl_instance: &KclInstance,
+    kind: &str,
+    name: &str,
+    namespace: Option<&str>,
+) -> bool {
+    let source = &kcl_instance.spec.source;
+    let source_kind = match source.kind.as_deref() {
+        Some("OciRepository") => Some("OCIRepository"),
+        source_kind => source_kind,
+    };
+    let instance_namespace = kcl_instance.namespace();
+    source_kind == Some(kind)
+        && source.name.as_deref() == Some(name)
+        && source
+            .namespace
+            .as_deref()
+            .or(instance_namespace.as_deref())
+            == namespace
 }
 
 fn determine_action(kcl_instance: &KclInstance) -> KclInstanceAction {
@@ -327,6 +2039,11 @@ fn determine_action(kcl_instance: &KclInstance) -> KclInstanceAction {
         return KclInstanceAction::Delete;
     }
 
+    // Checked before the finalizer, which plan mode never adds
+    if kcl_instance.is_plan() {
+        return KclInstanceAction::Plan;
+    }
+
     if kcl_instance
         .meta()
         .finalizers
@@ -344,3 +2061,874 @@ fn determine_action(kcl_instance: &KclInstance) -> KclInstanceAction {
 
     KclInstanceAction::NoOp
 }
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
+
+    use super::*;
+
+    fn gvk(kind: &str, name: &str) -> Gvk {
+        Gvk {
+            name: name.to_string(),
+            group: String::new(),
+            version: "v1".to_string(),
+            kind: kind.to_string(),
+            namespace: Some("default".to_string()),
+            resource_version: None,
+        }
+    }
+
+    #[test]
+    fn test_empty_render_prunes_previous_inventory() {
+        for manifests in ["", "---\n", "\n"] {
+            let objects = multidoc_deserialize(manifests).unwrap();
+            assert!(objects.is_empty(), "{manifests:?} should render 0 objects");
+        }
+
+        let old_inventory = HashSet::from([gvk("ConfigMap", "a"), gvk("Service", "b")]);
+        let mut stale = stale_objects(&old_inventory, &HashSet::new(), &[]);
+        stale.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(stale, [gvk("ConfigMap", "a"), gvk("Service", "b")]);
+    }
+
+    #[test]
+    fn test_digest_pinned_source_reaches_noop() {
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default", "generation": 1 },
+            "spec": {
+                "sourceRef": { "kind": "OciRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        let artefact = |revision: &str| {
+            fluxcd_rs::FluxSourceArtefact::Oci(
+                serde_json::from_value(serde_json::json!({
+                    "digest": "sha256:0f1e",
+                    "lastUpdateTime": "2024-01-01T00:00:00Z",
+                    "path": "ocirepository/default/app/0f1e.tar.gz",
+                    "revision": revision,
+                    "url": "http://source-controller/ocirepository/default/app/0f1e.tar.gz",
+                }))
+                .unwrap(),
+            )
+        };
+        let args = HashMap::new();
+
+        // First reconciliation applies
+        let pinned = artefact("sha256:3c5a");
+        let hash = instance.config_hash(&pinned.content_revision(), &args);
+        let mut status = KclInstanceStatus::default();
+        assert!(!is_up_to_date(&status, &hash));
+        status.last_applied_config_hash = Some(hash);
+
+        // Later ones are NoOp, also when the revision is reported with the tag
+        for revision in ["sha256:3c5a", "latest@sha256:3c5a"] {
+            let hash = instance.config_hash(&artefact(revision).content_revision(), &args);
+            assert!(is_up_to_date(&status, &hash), "{revision}");
+        }
+        let hash = instance.config_hash(&artefact("sha256:9d2b").content_revision(), &args);
+        assert!(!is_up_to_date(&status, &hash));
+    }
+
+    #[test]
+    fn test_stale_objects() {
+        let old_inventory = HashSet::from([gvk("ConfigMap", "a"), gvk("Service", "b")]);
+        let inventory = HashSet::from([gvk("ConfigMap", "a")]);
+        assert_eq!(
+            stale_objects(&old_inventory, &inventory),
+            [gvk("Service", "b")]
+        );
+    }
+
+    #[test]
+    fn test_watcher_config_without_selector() -> Result<()> {
+        let config = watcher_config(None)?;
+        assert_eq!(config.label_selector, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_watcher_config_with_selector() -> Result<()> {
+        let config = watcher_config(Some("shard=a,tier in (web, api),!canary"))?;
+        assert_eq!(
+            config.label_selector.as_deref(),
+            Some("shard=a,tier in (web, api),!canary")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_watcher_config_invalid_selector() {
+        for selector in ["shard=a=b", "=a", "tier in web", "a b", "shard=a,,"] {
+            assert!(
+                watcher_config(Some(selector)).is_err(),
+                "selector {selector:?} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_expires() {
+        let slow = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        };
+        let res = with_timeout(Duration::from_millis(10), slow).await;
+        assert!(matches!(res, Err(Error::ReconcileTimeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_completes() -> Result<()> {
+        let fast = async { Ok(42) };
+        assert_eq!(with_timeout(Duration::from_secs(5), fast).await?, 42);
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_reason() {
+        let cases = [
+            (
+                Error::KclInstanceMissingNamespace {
+                    name: "test".to_string(),
+                },
+                "KclInstanceMissingNamespace",
+            ),
+            (
+                Error::MissingObjectKey {
+                    key: "metadata".to_string(),
+                },
+                "MissingObjectKey",
+            ),
+            (
+                Error::EngineAction {
+                    source: engine::Error::ObjectHasNoKind,
+                },
+                "EngineAction",
+            ),
+            (
+                Error::CannotRenderKclModule {
+                    source: engine::Error::ObjectHasNoSpec,
+                },
+                "CannotRenderKclModule",
+            ),
+            (
+                Error::ProcessArgs {
+                    source: instance_ext::Error::MissingArguments {
+                        name: "args".to_string(),
+                    },
+                },
+                "ProcessArgs",
+            ),
+            (
+                Error::InvalidLabelSelector {
+                    selector: "=".to_string(),
+                },
+                "InvalidLabelSelector",
+            ),
+            (
+                Error::ReconcileTimeout {
+                    timeout: "1s".to_string(),
+                },
+                "ReconcileTimeout",
+            ),
+        ];
+        for (error, reason) in cases {
+            assert_eq!(error.reason(), reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_locks_serialize_per_instance() {
+        let locks = Arc::new(ReconcileLocks::default());
+        let events = Arc::new(Mutex::new(vec![]));
+        let key = ObjectRef::<KclInstance>::new("app").within("default");
+
+        let tasks: Vec<_> = (0..2)
+            .map(|i| {
+                let locks = locks.clone();
+                let events = events.clone();
+                let key = key.clone();
+                tokio::spawn(async move {
+                    let _lock = locks.lock(key).await;
+                    events.lock().unwrap().push(format!("start {i}"));
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    events.lock().unwrap().push(format!("end {i}"));
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        for pair in events.chunks(2) {
+            assert_eq!(pair[0].replace("start", "end"), pair[1]);
+        }
+
+        // Other instances are not blocked
+        let _lock = locks.lock(key).await;
+        let other = ObjectRef::<KclInstance>::new("other").within("default");
+        tokio::time::timeout(Duration::from_secs(1), locks.lock(other))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_error_retry_after() {
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": "./missing",
+                "interval": "5m",
+                "retryInterval": "30s",
+            },
+        }))
+        .unwrap();
+
+        let missing_path = Error::CannotRenderKclModule {
+            source: engine::Error::InvalidModule {
+                path: "./missing".to_string(),
+                reason: "does not exist".to_string(),
+            },
+        };
+        assert_eq!(missing_path.class(), ErrorClass::User);
+        assert_eq!(missing_path.retry_after(&instance, 1, None), None);
+        assert_eq!(missing_path.condition_reason(), INVALID_SPEC_REASON);
+
+        let compile = Error::CannotRenderKclModule {
+            source: engine::Error::KclClientActions {
+                source: kcl_client::Error::ModuleCompile {
+                    message: "expected int, got str(3)".to_string(),
+                },
+            },
+        };
+        // Permanent KCL errors are not requeued, transient ones are
+        assert_eq!(compile.class(), ErrorClass::Stalled);
+        assert_eq!(compile.retry_after(&instance, 3, None), None);
+        assert_eq!(compile.condition_reason(), MODULE_COMPILE_FAILED_REASON);
+        let pull_timeout = Error::CannotRenderKclModule {
+            source: engine::Error::KclClientActions {
+                source: kcl_client::Error::OciPullTimeout {
+                    name: "k8s".to_string(),
+                    timeout: Duration::from_secs(60),
+                },
+            },
+        };
+        assert_eq!(pull_timeout.class(), ErrorClass::System);
+        assert_eq!(
+            pull_timeout.retry_after(&instance, 1, None),
+            Some(Duration::from_secs(30))
+        );
+
+        let export_too_large = Error::ExportRendered {
+            source: crate::export::Error::ExportTooLarge {
+                size: 2_000_000,
+                limit: 1_000_000,
+            },
+        };
+        assert_eq!(export_too_large.class(), ErrorClass::Module);
+        assert_eq!(
+            export_too_large.retry_after(&instance, 3, None),
+            Some(Duration::from_secs(300))
+        );
+
+        let source_not_ready = Error::ArtefactsPathNotFound {
+            source: engine::Error::ArtefactMissing {
+                name: "app".to_string(),
+            },
+        };
+        assert_eq!(source_not_ready.class(), ErrorClass::System);
+        assert_eq!(
+            source_not_ready.retry_after(&instance, 1, None),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            source_not_ready.retry_after(&instance, 3, None),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            source_not_ready.retry_after(&instance, 3, Some(Duration::from_secs(45))),
+            Some(Duration::from_secs(45))
+        );
+        assert_eq!(
+            source_not_ready.condition_reason(),
+            RECONCILIATION_FAILED_REASON
+        );
+    }
+
+    /// Records the size of the batches the apply hooks run on.
+    #[derive(Default)]
+    struct BatchRecorder {
+        checked: Mutex<Vec<usize>>,
+        applied: Mutex<Vec<usize>>,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::hooks::ApplyHook for BatchRecorder {
+        fn name(&self) -> &str {
+            "batch-recorder"
+        }
+
+        async fn pre_apply(
+            &self,
+            _instance: &KclInstance,
+            objects: &[DynamicObject],
+        ) -> crate::hooks::Result<()> {
+            // Every batch is checked before the first object is applied
+            assert!(self.applied.lock().unwrap().is_empty());
+            self.checked.lock().unwrap().push(objects.len());
+            Ok(())
+        }
+
+        async fn post_apply(
+            &self,
+            _instance: &KclInstance,
+            applied: &[DynamicObject],
+        ) -> crate::hooks::Result<()> {
+            self.applied.lock().unwrap().push(applied.len());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_rendered_in_batches() {
+        use wiremock::{
+            matchers::{method, path, path_regex},
+            Mock, MockServer, Request, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "singularName": "configmap",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        let total = 2 * APPLY_BATCH_SIZE + 1;
+        // The API server returns the applied object
+        Mock::given(method("PATCH"))
+            .and(path_regex(
+                "^/api/v1/namespaces/default/configmaps/app-[0-9]+$",
+            ))
+            .respond_with(|request: &Request| {
+                ResponseTemplate::new(200).set_body_raw(request.body.clone(), "application/json")
+            })
+            .expect(total as u64)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let downloader = Downloader::new(
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+            None,
+            None,
+        );
+        let discovery = Arc::new(CachedDiscovery::new(
+            client.clone(),
+            Duration::from_secs(60),
+        ));
+        let recorder = Arc::new(BatchRecorder::default());
+        let engine = Engine::new(client.clone()).with_hook(recorder.clone());
+        let context = ContextData::new(client.clone(), downloader, Engine::new(client), discovery);
+        let instance: Arc<KclInstance> = Arc::new(
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "kcl.evrone.com/v1alpha1",
+                "kind": "KclInstance",
+                "metadata": { "name": "app", "namespace": "default" },
+                "spec": {
+                    "sourceRef": { "kind": "GitRepository", "name": "app" },
+                    "path": ".",
+                },
+            }))
+            .unwrap(),
+        );
+        let manifests: Arc<str> = (0..total)
+            .map(|i| {
+                format!(
+                    "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: app-{i}\n  namespace: default\n"
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("---\n")
+            .into();
+
+        let summary = scan_rendered(&instance, &context, &manifests)
+            .await
+            .unwrap();
+        assert_eq!(summary.total, total);
+        assert!(summary.crds.is_empty() && summary.ignored.is_empty());
+        let applied = apply_rendered(
+            &instance,
+            &engine,
+            &context,
+            &manifests,
+            summary.crds,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(applied.inventory.len(), total);
+        assert_eq!(applied.changed, total);
+
+        let batches = vec![APPLY_BATCH_SIZE, APPLY_BATCH_SIZE, 1];
+        assert_eq!(*recorder.checked.lock().unwrap(), batches);
+        assert_eq!(*recorder.applied.lock().unwrap(), batches);
+    }
+
+    #[tokio::test]
+    async fn test_give_up_threshold() {
+        let client =
+            Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap();
+        let downloader = Downloader::new(
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+            None,
+            None,
+        );
+        let discovery = Arc::new(CachedDiscovery::new(
+            client.clone(),
+            Duration::from_secs(60),
+        ));
+        let context = ContextData::new(client.clone(), downloader, Engine::new(client), discovery)
+            .with_max_failures(3);
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default", "generation": 1 },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+
+        // Status updates reporting a failure don't bypass the backoff
+        assert_eq!(context.record_failure(&instance), 1);
+        assert!(!context.gives_up(1));
+        context.schedule_retry(&instance, Some(Duration::from_secs(60)), false);
+        assert!(context.pending_retry(&instance).is_some());
+        context.schedule_retry(&instance, Some(Duration::ZERO), false);
+        assert_eq!(context.pending_retry(&instance), None);
+
+        // Given up at the threshold, until the instance changes
+        assert_eq!(context.record_failure(&instance), 2);
+        assert_eq!(context.record_failure(&instance), 3);
+        assert!(context.gives_up(3));
+        context.schedule_retry(&instance, None, true);
+        assert_eq!(
+            context.pending_retry(&instance),
+            Some(Action::await_change())
+        );
+
+        // An annotation change resets the count
+        instance
+            .annotations_mut()
+            .insert("reconcile".to_string(), "now".to_string());
+        assert_eq!(context.pending_retry(&instance), None);
+        assert_eq!(context.record_failure(&instance), 1);
+
+        // So does a spec change
+        context.record_failure(&instance);
+        context.record_failure(&instance);
+        context.schedule_retry(&instance, None, true);
+        instance.metadata.generation = Some(2);
+        assert_eq!(context.record_failure(&instance), 1);
+        assert_eq!(context.pending_retry(&instance), None);
+
+        // And a successful reconciliation
+        context.reset_failures(&instance);
+        assert_eq!(context.record_failure(&instance), 1);
+
+        // Given up before a restart, from the status
+        let mut status = KclInstanceStatus {
+            failure_count: 3,
+            ..Default::default()
+        };
+        status.set_condition(READY_CONDITION, false, "Failed", "", 2);
+        status.set_condition(STALLED_CONDITION, true, RETRIES_EXHAUSTED_REASON, "", 2);
+        instance.status = Some(status);
+        context.reset_failures(&instance);
+        assert_eq!(
+            context.pending_retry(&instance),
+            Some(Action::await_change())
+        );
+        context.reset_failures(&instance);
+        assert_eq!(context.record_failure(&instance), 4);
+
+        // Unless the failures are of a previous generation
+        context.reset_failures(&instance);
+        instance.metadata.generation = Some(3);
+        assert_eq!(context.pending_retry(&instance), None);
+        assert_eq!(context.record_failure(&instance), 1);
+
+        let unlimited = ContextData {
+            max_failures: 0,
+            ..context
+        };
+        assert!(!unlimited.gives_up(100));
+    }
+
+    #[test]
+    fn test_references_source() {
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        assert!(references_source(
+            &instance,
+            "GitRepository",
+            "app",
+            Some("default")
+        ));
+        assert!(!references_source(
+            &instance,
+            "GitRepository",
+            "app",
+            Some("flux-system")
+        ));
+        assert!(!references_source(
+            &instance,
+            "GitRepository",
+            "other",
+            Some("default")
+        ));
+        assert!(!references_source(
+            &instance,
+            "OCIRepository",
+            "app",
+            Some("default")
+        ));
+
+        let mut oci = instance.clone();
+        oci.spec.source.kind = Some("OciRepository".to_string());
+        oci.spec.source.namespace = Some("flux-system".to_string());
+        assert!(references_source(
+            &oci,
+            "OCIRepository",
+            "app",
+            Some("flux-system")
+        ));
+    }
+
+    #[test]
+    fn test_instances_of_source() {
+        let (store, mut writer) = kube::runtime::reflector::store::<KclInstance>();
+        for (name, namespace, source) in [
+            (
+                "app",
+                "default",
+                serde_json::json!({ "kind": "GitRepository", "name": "app" }),
+            ),
+            (
+                "shared",
+                "team",
+                serde_json::json!({ "kind": "GitRepository", "name": "app", "namespace": "default" }),
+            ),
+            (
+                "oci",
+                "default",
+                serde_json::json!({ "kind": "OCIRepository", "name": "app" }),
+            ),
+            (
+                "other",
+                "team",
+                serde_json::json!({ "kind": "GitRepository", "name": "app" }),
+            ),
+        ] {
+            let instance: KclInstance = serde_json::from_value(serde_json::json!({
+                "apiVersion": "kcl.evrone.com/v1alpha1",
+                "kind": "KclInstance",
+                "metadata": { "name": name, "namespace": namespace },
+                "spec": { "sourceRef": source, "path": "." },
+            }))
+            .unwrap();
+            writer.apply_watcher_event(&watcher::Event::Apply(instance));
+        }
+        let source: fluxcd_rs::GitRepository = serde_json::from_value(serde_json::json!({
+            "apiVersion": "source.toolkit.fluxcd.io/v1",
+            "kind": "GitRepository",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": { "interval": "1m", "url": "https://example.com/app.git" },
+        }))
+        .unwrap();
+
+        // A new artifact of default/app reconciles the instances referencing it, wherever
+        // they are
+        let mut instances: Vec<_> = instances_of_source(&store, &source)
+            .into_iter()
+            .map(|instance| format!("{}/{}", instance.namespace.unwrap(), instance.name))
+            .collect();
+        instances.sort();
+        assert_eq!(instances, ["default/app", "team/shared"]);
+    }
+
+    #[test]
+    fn test_retry_backoff_capped() {
+        let retry = Duration::from_secs(30);
+        let interval = Duration::from_secs(300);
+        assert_eq!(retry_backoff(retry, interval, 1), retry);
+        assert_eq!(retry_backoff(retry, interval, 2), Duration::from_secs(60));
+        assert_eq!(retry_backoff(retry, interval, 5), interval);
+        assert_eq!(retry_backoff(retry, interval, 100), interval);
+        assert_eq!(retry_backoff(interval, retry, 3), interval);
+    }
+
+    #[test]
+    fn test_ignored_objects_not_applied_nor_pruned() {
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        instance.spec.config.ignore = vec![flux_kcl_operator_crd::ObjectSelector {
+            kind: Some("Job".to_string()),
+            ..Default::default()
+        }];
+        let manifests = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: a\n---\napiVersion: batch/v1\nkind: Job\nmetadata:\n  name: seed\n";
+
+        let (applied, ignored): (Vec<_>, Vec<_>) = multidoc_deserialize(manifests)
+            .unwrap()
+            .into_iter()
+            .partition(|object| !instance.spec.config.is_skipped(object));
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].metadata.name.as_deref(), Some("a"));
+
+        // A job applied before it was ignored stays in place
+        let ignored: Vec<Gvk> = ignored.into_iter().map(|o| o.try_into().unwrap()).collect();
+        let job = Gvk {
+            group: "batch".to_string(),
+            kind: "Job".to_string(),
+            ..gvk("Job", "seed")
+        };
+        let old_inventory = HashSet::from([gvk("ConfigMap", "a"), job, gvk("Service", "b")]);
+        let inventory = HashSet::from([gvk("ConfigMap", "a")]);
+        assert_eq!(
+            stale_objects(&old_inventory, &inventory, &ignored),
+            [gvk("Service", "b")]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_objects_skipped() {
+        let service = |port: u16| {
+            format!("apiVersion: v1\nkind: Service\nmetadata:\n  name: web\n  namespace: default\nspec:\n  ports:\n  - port: {port}\n")
+        };
+        let manifests = [
+            service(80),
+            "apiVersion: v1\nkind: Service\nmetadata:\n  name: web\n  namespace: other\n"
+                .to_string(),
+            service(8080),
+        ]
+        .join("---\n");
+
+        let (objects, duplicates) = dedup_rendered(multidoc_deserialize(&manifests).unwrap());
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].data["spec"]["ports"][0]["port"], 80);
+        assert_eq!(duplicates, ["Service default/web"]);
+    }
+
+    #[test]
+    fn test_plan_mode_skips_finalizer() {
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": {
+                "name": "app",
+                "namespace": "default",
+                "annotations": { "kcl.evrone.com/plan": "true" },
+            },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        assert!(matches!(
+            determine_action(&instance),
+            KclInstanceAction::Plan
+        ));
+
+        // Clearing the annotation resumes the normal reconciliation
+        instance.annotations_mut().clear();
+        assert!(matches!(
+            determine_action(&instance),
+            KclInstanceAction::Create
+        ));
+    }
+
+    #[test]
+    fn test_ready_conditions() {
+        let mut status = KclInstanceStatus::default();
+        status.set_condition(SOURCE_READY_CONDITION, false, "SourceNotReady", "", 1);
+        status.set_condition(APPLIED_CONDITION, true, OBJECTS_APPLIED_REASON, "", 1);
+        let ready = |gates: &[ReadyConditionType]| {
+            let mut status = status.clone();
+            assert!(set_ready(
+                &mut status,
+                gates,
+                "Applied 2 objects".to_string(),
+                1
+            ));
+            status.condition(READY_CONDITION).unwrap().clone()
+        };
+
+        // The reconciliation alone by default
+        let condition = ready(&[]);
+        assert_eq!(condition.status, "True");
+        assert_eq!(condition.message, "Applied 2 objects");
+        assert_eq!(ready(&[ReadyConditionType::Applied]).status, "True");
+
+        let condition = ready(&[ReadyConditionType::Applied, ReadyConditionType::SourceReady]);
+        assert_eq!(condition.status, "False");
+        assert_eq!(condition.reason, READY_CONDITIONS_UNMET_REASON);
+        assert_eq!(
+            condition.message,
+            "Not ready as SourceReady is False (SourceNotReady). Applied 2 objects"
+        );
+
+        // Conditions that are not set hold it back too
+        let mut unset = status.clone();
+        unset.remove_condition(APPLIED_CONDITION);
+        let gates = [ReadyConditionType::Applied];
+        assert!(set_ready(&mut unset, &gates, "Applied".to_string(), 1));
+        let condition = unset.condition(READY_CONDITION).unwrap();
+        assert_eq!(condition.status, "False");
+        assert_eq!(
+            condition.message,
+            "Not ready as Applied is not set. Applied"
+        );
+
+        // Unchanged when set again
+        assert!(!set_ready(&mut unset, &gates, "Applied".to_string(), 1));
+    }
+
+    #[test]
+    fn test_record_reconcile() {
+        let metrics = Metrics::new();
+        let elapsed = Duration::from_millis(300);
+        for (action, outcome) in [
+            (KclInstanceAction::Create, "created"),
+            (KclInstanceAction::Update, "updated"),
+            (KclInstanceAction::NoOp, "noop"),
+            (KclInstanceAction::Plan, "noop"),
+            (KclInstanceAction::Delete, "deleted"),
+        ] {
+            let before = metrics.reconciles(outcome);
+            record_reconcile(&metrics, &action, &Ok(Action::await_change()), elapsed);
+            assert_eq!(metrics.reconciles(outcome), before + 1, "{outcome}");
+        }
+        assert_eq!(metrics.reconciles("noop"), 2);
+
+        let error = Error::KclInstanceMissingNamespace {
+            name: "app".to_string(),
+        };
+        record_reconcile(&metrics, &KclInstanceAction::Update, &Err(error), elapsed);
+        assert_eq!(metrics.reconciles("updated"), 1);
+        assert_eq!(metrics.reconciles("error"), 1);
+        assert_eq!(metrics.errors("user"), 1);
+        assert_eq!(metrics.errors("system"), 0);
+    }
+
+    #[test]
+    fn test_plan_summary() {
+        let plan = Plan {
+            create: vec!["ConfigMap default/a".to_string()],
+            change: vec![],
+            unchanged: 2,
+            diffs: vec![],
+        };
+        assert_eq!(
+            plan_summary("main@sha1:abc", &plan, &[gvk("Service", "b")]),
+            "Plan for revision main@sha1:abc: 1 to create (ConfigMap default/a), 0 to change, \
+             2 unchanged, 1 to prune (Service default/b)"
+        );
+    }
+
+    #[test]
+    fn test_truncate_note() {
+        assert_eq!(truncate_note("short".to_string(), 64), "short");
+        let note = truncate_note("é".repeat(100), 32);
+        assert!(note.len() <= 32);
+        assert!(note.ends_with("\n... (truncated)"));
+    }
+
+    #[test]
+    fn test_source_ready_condition() {
+        let conditions: Vec<Condition> = serde_json::from_value(serde_json::json!([{
+            "type": "Ready",
+            "status": "False",
+            "reason": "GitOperationFailed",
+            "message": "failed to checkout and determine revision",
+            "lastTransitionTime": "2024-01-01T00:00:00Z",
+        }]))
+        .unwrap();
+        let mut status = KclInstanceStatus::default();
+
+        assert!(set_source_ready(
+            &mut status,
+            SourceReadiness::of(Some(&conditions)),
+            2
+        ));
+        let condition = status.condition(SOURCE_READY_CONDITION).unwrap();
+        assert_eq!(condition.status, "False");
+        assert_eq!(condition.reason, "GitOperationFailed");
+        assert_eq!(
+            condition.message,
+            "failed to checkout and determine revision"
+        );
+        // The Ready condition is left to the render and apply
+        assert!(status.condition(READY_CONDITION).is_none());
+
+        // Unchanged readiness doesn't need a status update
+        assert!(!set_source_ready(
+            &mut status,
+            SourceReadiness::of(Some(&conditions)),
+            2
+        ));
+    }
+
+    #[test]
+    fn test_failed_summary() {
+        let failed = ["a", "b"].map(|name| FailedObject {
+            object: serde_json::from_value(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": name, "namespace": "default" },
+            }))
+            .unwrap(),
+            reason: "invalid".to_string(),
+        });
+        assert_eq!(
+            failed_summary(&failed, 3),
+            "2 of 3 objects failed to apply: ConfigMap default/a: invalid; \
+             ConfigMap default/b: invalid"
+        );
+    }
+}
@@ -1,29 +1,60 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
-use flux_kcl_operator_crd::{KclInstance, KclInstanceStatus};
-use fluxcd_rs::{Downloader, FluxSourceArtefact, GitRepository, OCIRepository};
+use flux_kcl_operator_crd::{
+    Gvk, KclInstance, KclInstanceStatus, SyncStrategy, PRUNE_ANNOTATION, READY_CONDITION,
+    SOURCE_CONFIG_MAP_REASON, SOURCE_NOT_FOUND_REASON, SOURCE_NOT_READY_REASON,
+};
+use fluxcd_rs::{
+    Downloader, FluxSourceArtefact, GitRepository, GitRepositoryStatusArtifact, OCIRepository,
+};
 
-use kcl_client::ModClient;
+use k8s_openapi::{api::core::v1::ConfigMap, apimachinery::pkg::apis::meta::v1::Condition};
+use kcl_client::RegistryCredentials;
 use kube::{
-    api::{DeleteParams, DynamicObject, GroupVersionKind, Patch, PatchParams},
+    api::{DeleteParams, DynamicObject, GroupVersionKind, Patch, PatchParams, PostParams},
     core::gvk::ParseGroupVersionError,
-    Api, Client, Discovery, ResourceExt,
+    discovery::Scope,
+    runtime::events::Reporter,
+    Api, Client, Resource, ResourceExt,
 };
+use sha2::{Digest, Sha256};
 use snafu::{OptionExt, ResultExt, Snafu};
 use strum::{EnumDiscriminants, IntoStaticStr};
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, instrument, warn};
 
-use crate::utils::{self, patch_labels};
+use crate::{
+    hooks::{self, ApplyHook},
+    protection::{AllowedNamespaces, ProtectedResources},
+    render::{KclRenderer, Renderer},
+    utils::{self, patch_labels, CachedDiscovery, ManagedByLabel, Unresolved},
+};
 
 pub static OPERATOR_MANAGER: &str = "kcl-instance-controller";
 
+/// Attempts to apply an object whose kind is defined by a CRD applied in the same
+/// reconciliation, while the API server does not serve it yet.
+const CRD_ATTEMPTS: u32 = 10;
+/// Delay between two attempts to apply an object whose CRD was just applied.
+const CRD_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Kind of Flux OCIRepository sources.
+const OCI_REPOSITORY_KIND: &str = "OCIRepository";
+/// Kind of the sources holding a module in the keys of a ConfigMap, see `config_map_files`.
+const CONFIG_MAP_KIND: &str = "ConfigMap";
+
+/// Default user agent of the HTTP and OCI requests of the operator.
+pub const USER_AGENT: &str = concat!("flux-kcl-operator/", env!("CARGO_PKG_VERSION"));
+
 #[derive(Snafu, Debug, EnumDiscriminants)]
 #[strum_discriminants(derive(IntoStaticStr))]
 #[allow(clippy::enum_variant_names)]
+#[snafu(visibility(pub(crate)))]
 pub enum Error {
     #[snafu(display("Failed to download: {}", source))]
     DownloadError {
@@ -57,9 +88,15 @@ pub enum Error {
     #[snafu(display("failed to find kubernetes object"))]
     ObjectHasNotFound { source: kube::Error },
 
+    #[snafu(display("Invalid module at {} in the source: {}", path, reason))]
+    InvalidModule { path: String, reason: String },
+
     #[snafu(display("Failed to make kcl client actions: {}", source))]
     KclClientActions { source: kcl_client::Error },
 
+    #[snafu(display("Renderer {} failed: {}", renderer, message))]
+    RenderFailed { renderer: String, message: String },
+
     #[snafu(display("Failed to compile package: {}", source))]
     CompilePackage { source: anyhow::Error },
 
@@ -69,9 +106,28 @@ pub enum Error {
     #[snafu(display("Failed to apply KCL status: {}", source))]
     ApplyYamlStatus { source: kube::Error },
 
+    #[snafu(display("Failed to serialize KCL status: {}", source))]
+    SerializeStatus { source: serde_json::Error },
+
     #[snafu(display("Failed deserialize yaml manifests: {}", source))]
     WrongYamlManifests { source: serde_yaml::Error },
 
+    #[snafu(display(
+        "Cannot impersonate ServiceAccount {}: the engine has no client configuration",
+        service_account
+    ))]
+    ImpersonationUnavailable { service_account: String },
+
+    #[snafu(display(
+        "Failed to create the client impersonating ServiceAccount {}: {}",
+        service_account,
+        source
+    ))]
+    ImpersonatedClient {
+        service_account: String,
+        source: kube::Error,
+    },
+
     #[snafu(display("Failed to get gvk from obj: {:?}", obj))]
     NoManagedTypeInDynamicObject { obj: String },
 
@@ -81,12 +137,37 @@ pub enum Error {
     #[snafu(display("Failed to parse group version for: {}", name))]
     ParseGroupVersion { name: String },
 
+    #[snafu(display(
+        "Kind {} with apiVersion {} is served by several groups ({}), set its apiVersion in `apiVersions`",
+        kind,
+        api_version,
+        candidates.join(", ")
+    ))]
+    AmbiguousKind {
+        kind: String,
+        api_version: String,
+        candidates: Vec<String>,
+    },
+
+    #[snafu(display(
+        "Insufficient RBAC to discover {}: grant the operator `get` on the discovery endpoints \
+         (nonResourceURLs `/api/*` and `/apis/*`) and access to the kind in its ClusterRole",
+        gvk
+    ))]
+    InsufficientRbacForKind { gvk: String },
+
     #[snafu(display("Failed to deserialize manifests: {}", source))]
     UnableToDeserialize { source: serde_json::Error },
 
     #[snafu(display("Failed to patch KCL module: {}", source))]
     FailedToPatch { source: kube::Error },
 
+    #[snafu(display("Timed out after {:?} applying {}", timeout, object))]
+    FailedToPatchTimeout { object: String, timeout: Duration },
+
+    #[snafu(display("ConfigMap {} changed since its revision was resolved", name))]
+    ConfigMapSourceChanged { name: String },
+
     #[snafu(display("KCL instance {} is missing status", name))]
     KclInstanceMissingStatus { name: String },
 
@@ -95,6 +176,61 @@ pub enum Error {
 
     #[snafu(display("Failed to apply object: {}", source))]
     FailedToApplyObject { source: kube::Error },
+
+    #[snafu(display("Apply hook failed: {}", source))]
+    HookFailed { source: hooks::Error },
+
+    #[snafu(display(
+        "Failed to record the inventory entry of an applied object: {}",
+        source
+    ))]
+    InventoryEntry {
+        source: flux_kcl_operator_crd::Error,
+    },
+
+    #[snafu(display(
+        "{} {} targets namespace {}, which the instance is not allowed to manage",
+        kind,
+        name,
+        namespace
+    ))]
+    NamespaceNotAllowed {
+        kind: String,
+        name: String,
+        namespace: String,
+    },
+
+    #[snafu(display(
+        "Timed out after {:?} waiting for the deletion of {}",
+        timeout,
+        remaining.join(", ")
+    ))]
+    DeletionTimeout {
+        timeout: Duration,
+        remaining: Vec<String>,
+    },
+}
+
+impl Error {
+    /// Whether the error is caused by the work being cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(
+            self,
+            Error::DownloadError {
+                source: fluxcd_rs::downloader::error::DownloaderError::Cancelled
+            } | Error::KclClientActions {
+                source: kcl_client::Error::Cancelled
+            }
+        )
+    }
+
+    /// Returns the KCL client error behind this error, if any.
+    pub fn kcl_client_error(&self) -> Option<&kcl_client::Error> {
+        match self {
+            Error::KclClientActions { source } => Some(source),
+            _ => None,
+        }
+    }
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -107,23 +243,161 @@ type Result<T, E = Error> = std::result::Result<T, E>;
 /// - Interfacing with the Kubernetes API
 pub struct Engine {
     client: Client,
+    protected: ProtectedResources,
+    same_namespace_only: bool,
+    hooks: Vec<Arc<dyn ApplyHook>>,
+    kcl: KclRenderer,
+    renderer: Option<Arc<dyn Renderer>>,
+    reporter: Reporter,
+    managed_by: ManagedByLabel,
+    config: Option<kube::Config>,
 }
 
 impl Engine {
     pub fn new(client: Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            protected: ProtectedResources::default(),
+            same_namespace_only: false,
+            hooks: Vec::new(),
+            kcl: KclRenderer::default(),
+            renderer: None,
+            reporter: crate::event::reporter(None, None),
+            managed_by: ManagedByLabel::default(),
+            config: None,
+        }
+    }
+
+    /// Sets the configuration the client of the engine was built from, required to impersonate
+    /// the `serviceAccountName` of the instances when applying their objects.
+    pub fn with_config(mut self, config: kube::Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// The client applying the objects of an instance, impersonating its `serviceAccountName`
+    /// when set, or the client of the engine.
+    fn apply_client(&self, instance: &KclInstance) -> Result<Client> {
+        let Some(service_account) = instance.spec.service_account_name.as_deref() else {
+            return Ok(self.client.clone());
+        };
+        let namespace = instance.namespace().context(ObjectHasNoNamespaceSnafu)?;
+        let config = self
+            .config
+            .as_ref()
+            .context(ImpersonationUnavailableSnafu { service_account })?;
+        Client::try_from(impersonation_config(config, &namespace, service_account))
+            .context(ImpersonatedClientSnafu { service_account })
+    }
+
+    /// Sets the label marking the applied objects as managed by the operator, which prune and
+    /// cleanup require before deleting an object. Defaults to `ManagedByLabel::default`.
+    pub fn with_managed_by_label(mut self, managed_by: ManagedByLabel) -> Self {
+        self.managed_by = managed_by;
+        self
+    }
+
+    /// The label marking the objects managed by the operator, see `with_managed_by_label`.
+    pub(crate) fn managed_by_label(&self) -> &ManagedByLabel {
+        &self.managed_by
+    }
+
+    /// Sets the resources that prune and cleanup must never delete.
+    pub fn with_protected_resources(mut self, protected: ProtectedResources) -> Self {
+        self.protected = protected;
+        self
+    }
+
+    /// Only allows instances to apply namespaced objects to their own namespace.
+    pub fn with_same_namespace_only(mut self, same_namespace_only: bool) -> Self {
+        self.same_namespace_only = same_namespace_only;
+        self
+    }
+
+    /// Sets the expected media types of the layers of OCI module dependencies.
+    pub fn with_layer_media_types(mut self, media_types: Vec<String>) -> Self {
+        self.kcl = self.kcl.with_layer_media_types(media_types);
+        self
+    }
+
+    /// Sets the user agent of the requests to OCI registries when resolving module
+    /// dependencies. Defaults to `USER_AGENT`.
+    pub fn with_user_agent(mut self, user_agent: &'static str) -> Self {
+        self.kcl = self.kcl.with_user_agent(user_agent);
+        self
+    }
+
+    /// Sets the credentials of the registries of OCI module dependencies, anonymous by default.
+    pub fn with_registry_credentials(mut self, credentials: RegistryCredentials) -> Self {
+        self.kcl = self.kcl.with_registry_credentials(credentials);
+        self
+    }
+
+    /// Sets the number of concurrent pulls of OCI module dependencies from each registry host,
+    /// unlimited when 0.
+    pub fn with_registry_concurrency(mut self, limit: usize) -> Self {
+        self.kcl = self.kcl.with_registry_concurrency(limit);
+        self
+    }
+
+    /// Sets the vendor home the OCI module dependencies are pulled to, the KCL default when
+    /// unset.
+    pub fn with_oci_vendor_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.kcl = self.kcl.with_oci_vendor_dir(dir);
+        self
+    }
+
+    /// Sets the base directory the git module dependencies are cloned to, the OCI vendor home
+    /// when unset.
+    pub fn with_git_vendor_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.kcl = self.kcl.with_git_vendor_dir(dir);
+        self
+    }
+
+    /// Replaces the built-in KCL renderer, see `Renderer`.
+    pub fn with_renderer(mut self, renderer: Arc<dyn Renderer>) -> Self {
+        self.renderer = Some(renderer);
+        self
+    }
+
+    /// Sets the reporter of the events published while applying, see `event::reporter`.
+    pub fn with_reporter(mut self, reporter: Reporter) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Registers a hook running before and after the rendered objects are applied.
+    pub fn with_hook(mut self, hook: Arc<dyn ApplyHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Returns the namespaces the objects rendered for `instance` may be applied to.
+    fn allowed_namespaces(&self, instance: &KclInstance) -> Result<AllowedNamespaces> {
+        Ok(AllowedNamespaces::new(
+            &instance.namespace().context(ObjectHasNoNamespaceSnafu)?,
+            instance.spec.config.allowed_namespaces.clone(),
+            self.same_namespace_only,
+        ))
     }
 
+    /// Deletes the objects in the inventory of an instance, with the client applying its
+    /// objects, see `apply_client`
+    ///
+    /// # Returns
+    /// The deleted objects, which may still be terminating, see `wait_deleted`
     pub(crate) async fn cleanup(
         &self,
         instance: Arc<KclInstance>,
-        discovery: &Discovery,
-    ) -> Result<()> {
+        discovery: &CachedDiscovery,
+    ) -> Result<Vec<DeletedObject>> {
         if instance.spec.suspend.unwrap_or(false) {
             info!("Instance suspended, skipping");
-            return Ok(());
+            return Ok(Vec::new());
         }
 
+        let client = self.apply_client(&instance)?;
+        let mut deleted = Vec::new();
         for item in instance
             .status
             .as_ref()
@@ -139,89 +413,565 @@ impl Engine {
                 kind: item.kind.clone(),
             };
 
-            self.delete_resource(&gvk, &item.name, &item.namespace, discovery)
-                .await?;
+            if let Some(api) = self
+                .delete_object(&client, &gvk, &item.name, &item.namespace, discovery)
+                .await?
+            {
+                deleted.push(DeletedObject {
+                    api,
+                    kind: item.kind.clone(),
+                    name: item.name.clone(),
+                });
+            }
         }
 
-        Ok(())
+        Ok(deleted)
+    }
+
+    /// Waits until the deleted objects are gone from the cluster, e.g. once the finalizers of
+    /// their own children ran
+    ///
+    /// # Arguments
+    /// * `objects` - Objects deleted by `cleanup`
+    /// * `timeout` - Time after which the objects still present fail with `DeletionTimeout`
+    /// * `poll_interval` - Delay between two checks of the objects
+    /// * `on_progress` - Called with the number of remaining objects whenever it changes
+    pub(crate) async fn wait_deleted(
+        &self,
+        mut objects: Vec<DeletedObject>,
+        timeout: Duration,
+        poll_interval: Duration,
+        mut on_progress: impl FnMut(usize),
+    ) -> Result<()> {
+        let started = std::time::Instant::now();
+        let mut reported = objects.len();
+        loop {
+            let mut remaining = Vec::with_capacity(objects.len());
+            for object in objects {
+                match object.api.get_opt(&object.name).await {
+                    Ok(None) => info!("{} {} deleted", object.kind, object.name),
+                    Ok(Some(_)) => remaining.push(object),
+                    Err(e) => {
+                        warn!("Failed to check {} {}: {}", object.kind, object.name, e);
+                        remaining.push(object);
+                    }
+                }
+            }
+            objects = remaining;
+            if objects.is_empty() {
+                return Ok(());
+            }
+            if objects.len() != reported {
+                reported = objects.len();
+                on_progress(reported);
+            }
+            if started.elapsed() >= timeout {
+                return DeletionTimeoutSnafu {
+                    timeout,
+                    remaining: objects
+                        .iter()
+                        .map(|object| format!("{} {}", object.kind, object.name))
+                        .collect::<Vec<_>>(),
+                }
+                .fail();
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
     }
 
+    /// Deletes a resource pruned from an instance, unless it is protected, has pruning disabled
+    /// by `PRUNE_ANNOTATION` or is not managed by the operator
+    ///
+    /// The resource is deleted with the client applying the objects of the instance, see
+    /// `apply_client`.
+    ///
+    /// # Returns
+    /// Whether the resource was deleted
     pub(crate) async fn delete_resource(
         &self,
+        instance: &KclInstance,
         gvk: &GroupVersionKind,
         name: &str,
         namespace: &Option<String>,
-        discovery: &Discovery,
-    ) -> Result<()> {
+        discovery: &CachedDiscovery,
+    ) -> Result<bool> {
+        let client = self.apply_client(instance)?;
+        Ok(self
+            .delete_object(&client, gvk, name, namespace, discovery)
+            .await?
+            .is_some())
+    }
+
+    /// Deletes a resource like `delete_resource`
+    ///
+    /// # Arguments
+    /// * `client` - Client deleting the resource, see `apply_client`
+    ///
+    /// # Returns
+    /// The API of the resource when it was deleted
+    async fn delete_object(
+        &self,
+        client: &Client,
+        gvk: &GroupVersionKind,
+        name: &str,
+        namespace: &Option<String>,
+        discovery: &CachedDiscovery,
+    ) -> Result<Option<Api<DynamicObject>>> {
         info!(
             "Prepare to deleting resource: {} with name: {}",
             gvk.kind, name
         );
 
+        if self.protected.is_protected(gvk, name, namespace.as_deref()) {
+            warn!(
+                "Refusing to delete protected resource: {} {} in namespace {:?}",
+                gvk.kind, name, namespace
+            );
+            return Ok(None);
+        }
+
         // Resolve the API resource and capabilities for this GVK
-        if let Some((ar, caps)) = discovery.resolve_gvk(gvk) {
+        if let Some((ar, caps)) = discovery.resolve_gvk(gvk).await {
             let delete_params = DeleteParams::default();
 
             // Create a dynamic API client for this resource type
-            let api = crate::utils::dynamic_api(
-                ar,
-                caps,
-                self.client.clone(),
-                namespace.as_deref(),
-                false,
-            );
+            let api =
+                crate::utils::dynamic_api(ar, caps, client.clone(), namespace.as_deref(), false);
 
             if let Ok(res) = api.get(name).await {
-                if !utils::is_managed_by(OPERATOR_MANAGER, res.metadata) {
+                if utils::is_prune_disabled(&res.metadata) {
+                    warn!(
+                        "Skipping resource with pruning disabled by {}: {} {}",
+                        PRUNE_ANNOTATION, gvk.kind, name
+                    );
+                    return Ok(None);
+                }
+                if !utils::is_managed_by(&self.managed_by, res.metadata) {
                     warn!("Skipping unmanaged resource: {}", name);
-                    return Ok(());
+                    return Ok(None);
                 }
             }
 
-            let _ = api.delete(name, &delete_params).await.map_err(|e| {
-                error!("Cleanup failed: {}", e);
-                e
-            });
+            match api.delete(name, &delete_params).await {
+                Ok(_) => return Ok(Some(api)),
+                Err(e) => error!("Cleanup failed: {}", e),
+            }
         } else {
             warn!("Failed to resolve gvk: {:?}", gvk);
         }
 
+        Ok(None)
+    }
+
+    /// Checks the rendered objects of an instance before they are applied, see `apply`
+    ///
+    /// # Returns
+    /// The server-side apply parameters of the instance
+    async fn prepare_apply(
+        &self,
+        instance: &KclInstance,
+        objects: &[DynamicObject],
+        discovery: &CachedDiscovery,
+    ) -> Result<PatchParams> {
+        let allowed = self.allowed_namespaces(instance)?;
+        let crds: Vec<DynamicObject> = objects.iter().filter(|o| is_crd(o)).cloned().collect();
+        self.check_objects(instance, objects, &crds, discovery, &allowed)
+            .await?;
+        Ok(apply_params(instance))
+    }
+
+    /// Fails if one of `objects` targets a namespace the instance may not manage, or if a
+    /// pre-apply hook rejects them
+    ///
+    /// # Arguments
+    /// * `crds` - The rendered CRDs, defining the kinds that are not served yet
+    async fn check_objects(
+        &self,
+        instance: &KclInstance,
+        objects: &[DynamicObject],
+        crds: &[DynamicObject],
+        discovery: &CachedDiscovery,
+        allowed: &AllowedNamespaces,
+    ) -> Result<()> {
+        for o in objects {
+            // Unresolvable types are reported by `apply_single`
+            let gvk = o
+                .types
+                .as_ref()
+                .and_then(|types| GroupVersionKind::try_from(types).ok());
+            let resolved = match gvk {
+                Some(gvk) => discovery.resolve_gvk(&gvk).await,
+                None => None,
+            };
+            let namespaced = match resolved {
+                Some((_, caps)) => caps.scope == Scope::Namespaced,
+                // Kinds of the CRDs applied in the same run are not served yet
+                None => crds.iter().any(|crd| defines_namespaced(crd, o)),
+            };
+            check_namespace(o, namespaced, self.client.default_namespace(), allowed)?;
+        }
+
+        for hook in &self.hooks {
+            hook.pre_apply(instance, objects)
+                .await
+                .context(HookFailedSnafu)?;
+        }
         Ok(())
     }
 
+    /// Applies the rendered objects of an instance
+    ///
+    /// Nothing is applied if a single object targets a namespace the instance may not manage,
+    /// or if a pre-apply hook rejects the objects. Post-apply hooks run on the applied objects.
+    /// With `continueOnError`, objects that fail to apply are reported in
+    /// `AppliedObjects::failed` and the others are still applied. With `atomic`, the objects
+    /// applied before a failure are rolled back to their prior state, see `roll_back`.
+    ///
+    /// Renders too large to be held in memory are applied in batches with `apply_session`
+    /// instead.
+    ///
+    /// # Arguments
+    /// * `instance` - KclInstance the objects were rendered for
+    /// * `objects` - The rendered objects
+    /// * `discovery` - Kubernetes API discovery client
+    /// * `correct_drift` - Whether the objects are those applied last time, so the existing
+    ///   objects their apply changes had drifted. A `DriftCorrected` event then lists the
+    ///   corrected fields of each object, see `drifted_fields`
+    #[instrument(skip_all, fields(instance = %instance.name_any(), objects = objects.len()))]
     pub(crate) async fn apply(
         &self,
+        instance: &KclInstance,
         objects: &[DynamicObject],
-        discovery: &Discovery,
-    ) -> Result<Vec<DynamicObject>> {
-        let mut res = Vec::new();
-        for o in objects {
-            let o = self.apply_single(o, discovery).await?;
-            res.push(o);
+        discovery: &CachedDiscovery,
+        correct_drift: bool,
+    ) -> Result<AppliedObjects> {
+        let crds = objects.iter().filter(|o| is_crd(o)).cloned().collect();
+        let mut session = self.apply_session(instance, discovery, crds, correct_drift)?;
+        session.check(objects).await?;
+        session.apply_crds().await?;
+        session.apply(objects).await?;
+        session.finish().await
+    }
+
+    /// Starts applying the rendered objects of an instance in batches, so that large renders
+    /// are never held in memory as a whole
+    ///
+    /// Every batch goes through `ApplySession::check` before the first one is applied, so
+    /// nothing is applied if a single object is rejected. The CRDs are applied first with
+    /// `ApplySession::apply_crds`, then the other objects with `ApplySession::apply`, and
+    /// `ApplySession::finish` reports the outcome, see `apply`.
+    ///
+    /// # Arguments
+    /// * `instance` - KclInstance the objects were rendered for
+    /// * `discovery` - Kubernetes API discovery client
+    /// * `crds` - The rendered CRDs, held until they are applied
+    /// * `correct_drift` - Whether the objects are those applied last time, see `apply`
+    pub(crate) fn apply_session<'a>(
+        &'a self,
+        instance: &'a KclInstance,
+        discovery: &'a CachedDiscovery,
+        crds: Vec<DynamicObject>,
+        correct_drift: bool,
+    ) -> Result<ApplySession<'a>> {
+        Ok(ApplySession {
+            engine: self,
+            instance,
+            discovery,
+            correct_drift,
+            client: self.apply_client(instance)?,
+            pp: apply_params(instance),
+            allowed: self.allowed_namespaces(instance)?,
+            crd_kinds: crds.iter().filter_map(crd_kind).collect(),
+            crds,
+            snapshots: Vec::new(),
+            applied: AppliedObjects {
+                inventory: Vec::new(),
+                changed: 0,
+                failed: Vec::new(),
+                corrected: Vec::new(),
+            },
+        })
+    }
+
+    /// Records the live state of a rendered object before it is applied, to roll it back in
+    /// atomic mode
+    ///
+    /// # Returns
+    /// The snapshot of the object, or None for the objects of a kind defined by a CRD of
+    /// `crd_kinds` that is not served yet, which can't exist and go away with their CRD
+    async fn snapshot(
+        &self,
+        client: &Client,
+        obj: &DynamicObject,
+        discovery: &CachedDiscovery,
+        unmanaged_fields: &[String],
+        crd_kinds: &[(String, String)],
+    ) -> Result<Option<Snapshot>> {
+        let (api, name, _) = match self
+            .object_api(client, obj, discovery, unmanaged_fields)
+            .await
+        {
+            Ok(resolved) => resolved,
+            Err(Error::ParseGroupVersion { .. }) if defined_by(obj, crd_kinds) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let prior = api.get_opt(&name).await.context(FailedToPatchSnafu)?;
+        Ok(Some(Snapshot {
+            api,
+            name,
+            id: object_id(obj),
+            prior,
+        }))
+    }
+
+    /// Reverts the objects applied before a failure in atomic mode, the last applied first:
+    /// created objects are deleted and changed ones replaced with their prior state. This is
+    /// best effort, the objects that can't be reverted are logged and left as applied.
+    ///
+    /// # Returns
+    /// The descriptions of the reverted objects
+    async fn roll_back(&self, snapshots: Vec<Snapshot>) -> Vec<String> {
+        let mut reverted = Vec::new();
+        for snapshot in snapshots.into_iter().rev() {
+            let res = match snapshot.prior {
+                None => snapshot
+                    .api
+                    .delete(&snapshot.name, &DeleteParams::default())
+                    .await
+                    .map(|_| ()),
+                Some(mut prior) => {
+                    // Unconditional replace, as the apply changed the resource version
+                    prior.metadata.resource_version = None;
+                    prior.metadata.managed_fields = None;
+                    snapshot
+                        .api
+                        .replace(&snapshot.name, &PostParams::default(), &prior)
+                        .await
+                        .map(|_| ())
+                }
+            };
+            match res {
+                Ok(()) => {
+                    info!("Rolled back {}", snapshot.id);
+                    reverted.push(snapshot.id);
+                }
+                Err(e) => warn!("Failed to roll back {}: {}", snapshot.id, e),
+            }
+        }
+        reverted
+    }
+
+    /// Applies an object, retrying while its kind is defined by a CRD of `crd_kinds` that the
+    /// API server does not serve yet
+    ///
+    /// A `WaitingForCRD` event is published on the first retry. Each attempt is bounded by the
+    /// `applyTimeout` of the instance, failing with `FailedToPatchTimeout`.
+    ///
+    /// # Arguments
+    /// * `instance` - KclInstance the object was rendered for
+    /// * `client` - Client applying the object, see `apply_client`
+    /// * `crd_kinds` - Group and kind of the CRDs applied in the same run, see `crd_kind`
+    /// * `previous_version` - Known resourceVersion of the object, see `apply_single`
+    #[allow(clippy::too_many_arguments)]
+    async fn apply_waiting_for_crd(
+        &self,
+        instance: &KclInstance,
+        client: &Client,
+        obj: &DynamicObject,
+        discovery: &CachedDiscovery,
+        pp: &PatchParams,
+        crd_kinds: &[(String, String)],
+        previous_version: Option<Option<String>>,
+    ) -> Result<(DynamicObject, bool)> {
+        let unmanaged_fields = &instance.spec.config.unmanaged_fields;
+        let strategy = instance.spec.config.sync_strategy_of(obj);
+        let mut attempt = 1;
+        loop {
+            let applied = self.apply_single(
+                client,
+                obj,
+                discovery,
+                pp,
+                unmanaged_fields,
+                strategy,
+                previous_version.clone(),
+            );
+            let res = match instance.apply_timeout() {
+                Some(timeout) => tokio::time::timeout(timeout, applied)
+                    .await
+                    .ok()
+                    .context(FailedToPatchTimeoutSnafu {
+                        object: object_id(obj),
+                        timeout,
+                    })
+                    .and_then(|res| res),
+                None => applied.await,
+            };
+            match res {
+                Err(Error::ParseGroupVersion { name })
+                    if attempt < CRD_ATTEMPTS && defined_by(obj, crd_kinds) =>
+                {
+                    info!(
+                        "Kind of {} is not served yet, retrying in {:?}",
+                        name, CRD_RETRY_DELAY
+                    );
+                    if attempt == 1 {
+                        let note =
+                            format!("Waiting for the CRD of {} to be served", object_id(obj));
+                        if let Err(e) = crate::event::publish_normal_event(
+                            Arc::new(instance.clone()),
+                            self.client.clone(),
+                            self.reporter.clone(),
+                            "Apply".into(),
+                            "WaitingForCRD".into(),
+                            Some(note),
+                        )
+                        .await
+                        {
+                            warn!("Failed to publish WaitingForCRD event: {}", e);
+                        }
+                    }
+                    attempt += 1;
+                    tokio::time::sleep(CRD_RETRY_DELAY).await;
+                }
+                res => return res,
+            }
         }
-        Ok(res)
     }
 
     /// Applies a Kubernetes manifest to the cluster
     ///
     /// # Arguments
+    /// * `client` - Client applying the object, the engine's or an impersonating one
     /// * `obj` - The DynamicObject to apply
-    /// * `default_namespace` - The default namespace to use if not specified in the object
     /// * `discovery` - Kubernetes API discovery client
+    /// * `pp` - Server-side apply parameters (field manager and force)
+    /// * `unmanaged_fields` - Paths of the fields left out of the apply, see `patch_body`
+    /// * `strategy` - How the object is written, see `sync_object`
+    /// * `previous_version` - resourceVersion of the object before the apply, from its snapshot
+    ///   or the inventory, or None when the object is unknown. The apply counts as a change
+    ///   when it differs from the applied one
     ///
     /// # Returns
-    /// The applied DynamicObject or an error
+    /// The applied DynamicObject, and whether the apply created or changed it, or an error
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn apply_single(
         &self,
+        client: &Client,
+        obj: &DynamicObject,
+        discovery: &CachedDiscovery,
+        pp: &PatchParams,
+        unmanaged_fields: &[String],
+        strategy: SyncStrategy,
+        previous_version: Option<Option<String>>,
+    ) -> Result<(DynamicObject, bool)> {
+        let (api, name, data) = self
+            .object_api(client, obj, discovery, unmanaged_fields)
+            .await?;
+
+        // Server-side applies need no prior state, unlike replaces and creates
+        let previous_version = match strategy {
+            SyncStrategy::Apply => previous_version,
+            _ => api
+                .get_metadata_opt(&name)
+                .await
+                .context(FailedToPatchSnafu)?
+                .map(|previous| previous.metadata.resource_version),
+        };
+
+        let mut applied =
+            sync_object(&api, &name, data, pp, strategy, previous_version.clone()).await?;
+        // Some API servers omit the type meta in responses, which the inventory needs
+        let parsed = applied
+            .types
+            .as_ref()
+            .is_some_and(|types| GroupVersionKind::try_from(types).is_ok());
+        if !parsed {
+            applied.types = obj.types.clone();
+        }
+        let changed = is_changed(previous_version.as_ref(), &applied);
+        Ok((applied, changed))
+    }
+
+    /// Dry-runs the apply of the rendered objects of an instance, changing nothing
+    ///
+    /// The objects go through the same checks and hooks as in `apply`, except the post-apply
+    /// hooks.
+    ///
+    /// # Arguments
+    /// * `instance` - KclInstance the objects were rendered for
+    /// * `objects` - The rendered objects
+    /// * `discovery` - Kubernetes API discovery client
+    ///
+    /// # Returns
+    /// The objects the apply would create or change, and their diffs
+    #[instrument(skip_all, fields(instance = %instance.name_any(), objects = objects.len()))]
+    pub(crate) async fn plan(
+        &self,
+        instance: &KclInstance,
+        objects: &[DynamicObject],
+        discovery: &CachedDiscovery,
+    ) -> Result<Plan> {
+        let pp = self
+            .prepare_apply(instance, objects, discovery)
+            .await?
+            .dry_run();
+        let client = self.apply_client(instance)?;
+
+        let mut plan = Plan::default();
+        for o in objects {
+            let (api, name, data) = self
+                .object_api(
+                    &client,
+                    o,
+                    discovery,
+                    &instance.spec.config.unmanaged_fields,
+                )
+                .await?;
+            let current = api.get_opt(&name).await.context(FailedToPatchSnafu)?;
+            let planned = sync_object(
+                &api,
+                &name,
+                data,
+                &pp,
+                instance.spec.config.sync_strategy_of(o),
+                current
+                    .as_ref()
+                    .map(|current| current.metadata.resource_version.clone()),
+            )
+            .await?;
+            match current {
+                None => {
+                    plan.diffs.push(object_diff(None, &planned));
+                    plan.create.push(object_id(&planned));
+                }
+                Some(current) if has_diff(&current, &planned) => {
+                    plan.diffs.push(object_diff(Some(&current), &planned));
+                    plan.change.push(object_id(&planned));
+                }
+                Some(_) => plan.unchanged += 1,
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Resolves the API of a rendered object and builds its apply patch
+    ///
+    /// # Returns
+    /// The API of the object, its name and the apply patch
+    async fn object_api(
+        &self,
+        client: &Client,
         obj: &DynamicObject,
-        discovery: &Discovery,
-    ) -> Result<DynamicObject> {
+        discovery: &CachedDiscovery,
+        unmanaged_fields: &[String],
+    ) -> Result<(Api<DynamicObject>, String, serde_json::Value)> {
         let mut obj = obj.clone();
         // Extract the name and namespace from the object
         let name = obj.name_any();
         let namespace = obj.namespace();
 
-        obj.metadata.labels = patch_labels(obj.metadata.labels.clone(), OPERATOR_MANAGER);
+        obj.metadata.labels = patch_labels(obj.metadata.labels.clone(), &self.managed_by);
 
         // Get the GroupVersionKind (GVK) from the object's type metadata
         let gvk = obj
@@ -234,82 +984,89 @@ impl Engine {
             .context(FailedToGetGvkSnafu)?;
 
         // Resolve the API resource and capabilities for this GVK
-        let (ar, caps) = discovery
-            .resolve_gvk(&gvk)
-            .context(ParseGroupVersionSnafu { name: &name })?;
-
-        // Create patch parameters for server-side apply
-        let pp = PatchParams::apply(OPERATOR_MANAGER);
+        let (ar, caps) = match discovery.try_resolve_gvk(&gvk).await {
+            Ok(resolved) => resolved,
+            Err(Unresolved::Forbidden) => {
+                return InsufficientRbacForKindSnafu {
+                    gvk: format!("{} {}", gvk.api_version(), gvk.kind),
+                }
+                .fail()
+            }
+            Err(Unresolved::Unknown) => return ParseGroupVersionSnafu { name: &name }.fail(),
+        };
 
         // Create a dynamic API client for this resource type
-        let api =
-            crate::utils::dynamic_api(ar, caps, self.client.clone(), namespace.as_deref(), false);
+        let api = crate::utils::dynamic_api(ar, caps, client.clone(), namespace.as_deref(), false);
 
         // Convert the object to JSON for patching
-        let data: serde_json::Value =
-            serde_json::to_value(&obj).context(UnableToDeserializeSnafu)?;
-
-        // Apply the patch to the cluster
-        api.patch(&name, &pp, &Patch::Apply(&data))
-            .await
-            .context(FailedToPatchSnafu)
+        let data = patch_body(&obj, unmanaged_fields)?;
+        Ok((api, name, data))
     }
 
-    /// Renders KCL configurations and applies them to a Kubernetes cluster
-    ///
-    /// This function does the following:
-    /// - Downloads source files from the configured Git/OCI repository
-    /// - Renders the KCL configuration with provided arguments
-    /// - Applies the resulting manifests to the Kubernetes cluster
-    /// - Updates the KCL instance status
+    /// Renders the manifests of an instance with the renderer of the engine, the KCL one
+    /// unless replaced with `with_renderer`
     ///
     /// # Arguments
     ///
-    /// * `api` - Kubernetes API client for the instance type
     /// * `instance` - KclInstance custom resource containing the configuration
-    /// * `manifests` - String containing rendered YAML manifests
+    /// * `work_dir` - Directory the source artefact was extracted to
+    /// * `args` - Arguments passed to the module
+    /// * `oci_timeout` - Timeout of the OCI pulls of the module dependencies, see `get_artefact`
+    /// * `cancel` - Cancels dependency downloads and the render if it did not start yet
     ///
     /// # Returns
     ///
-    /// The result of applying the manifests or an error
+    /// The rendered manifests or an error
+    #[instrument(skip_all, fields(instance = %instance.name_any()))]
     pub(crate) async fn render(
         &self,
         instance: Arc<KclInstance>,
         work_dir: &Path,
         args: &HashMap<String, String>,
+        oci_timeout: Duration,
+        cancel: &CancellationToken,
     ) -> Result<String> {
-        // Creates a new ModClient instance with the specified work directory path
-        let mut mod_client =
-            ModClient::new(work_dir.join(&instance.spec.path)).context(KclClientActionsSnafu)?;
-
-        // Resolves all dependencies for the KCL configuration
-        let metadata = mod_client
-            .resolve_all_deps(true)
-            .await
-            .context(KclClientActionsSnafu)?;
+        let module_dir = work_dir.join(&instance.spec.path);
+        crate::render::check_module_dir(&module_dir, &instance.spec.path)?;
 
-        // Executes the KCL compiler with resolved metadata and instance arguments
-        let manifests = mod_client
-            .run(metadata, args)
+        let kcl;
+        let renderer: &dyn Renderer = match &self.renderer {
+            Some(renderer) => renderer.as_ref(),
+            None => {
+                kcl = self.kcl.clone().with_oci_timeout(oci_timeout);
+                &kcl
+            }
+        };
+        renderer
+            .render(&instance, &module_dir, work_dir, args, cancel)
             .await
-            .context(KclClientActionsSnafu)?;
-        Ok(manifests)
     }
 
     /// Returns a PathBuf containing the downloaded source location for a KCL instance
     ///
+    /// The files of ConfigMap sources are written to the storage dir of the downloader
+    /// instead, without any download.
+    ///
     /// # Arguments
     ///
     /// * `instance` - KclInstance custom resource containing the source configuration
+    /// * `artefact` - The source artefact to download, see `get_artefact`
     /// * `downloader` - Downloader interface for retrieving source files
+    /// * `cancel` - Cancels the download
     ///
     /// # Returns
     ///
     /// The directory path containing the downloaded source files or an error
+    #[instrument(
+        skip_all,
+        fields(instance = %instance.name_any(), revision = %artefact.revision())
+    )]
     pub(crate) async fn download(
         &self,
         instance: Arc<KclInstance>,
+        artefact: &FluxSourceArtefact,
         downloader: &Downloader,
+        cancel: &CancellationToken,
     ) -> Result<PathBuf> {
         let source = &instance.spec.source;
         let source_name = source.name.as_ref().context(ObjectHasNoNameSnafu)?;
@@ -319,9 +1076,36 @@ impl Engine {
             .or(instance.metadata.namespace.as_ref())
             .context(ObjectHasNoNamespaceSnafu)?;
 
-        let artefact = self.get_artefact(&instance).await?;
+        if let FluxSourceArtefact::ConfigMap(resolved) = artefact {
+            let config_map = Api::<ConfigMap>::namespaced(self.client.clone(), source_namespace)
+                .get(source_name)
+                .await
+                .context(ObjectHasNotFoundSnafu)?;
+            let files = config_map_files(&config_map);
+            let digest = files_digest(&files);
+            // The revision being rendered must be the one resolved
+            if resolved.digest.as_ref() != Some(&digest) {
+                return ConfigMapSourceChangedSnafu {
+                    name: format!("{source_namespace}/{source_name}"),
+                }
+                .fail();
+            }
+            let dir_name = format!("configmap-{}", digest.trim_start_matches("sha256:"));
+            return downloader
+                .store_files(source_namespace, source_name, &dir_name, &files)
+                .await
+                .context(DownloadSnafu);
+        }
+
         downloader
-            .download(&artefact.url(), source_name, source_namespace)
+            .download_from(
+                &artefact.url(),
+                instance.spec.config.source_host.as_deref(),
+                source_name,
+                source_namespace,
+                &instance.spec.config.extract_ignore,
+                cancel,
+            )
             .await
             .context(DownloadSnafu)
     }
@@ -329,7 +1113,8 @@ impl Engine {
     /// Gets the Flux artefact for a KCL instance's source
     ///
     /// Retrieves the artefact from either a GitRepository or OciRepository source
-    /// based on the source configuration in the KclInstance.
+    /// based on the source configuration in the KclInstance, or describes the module held by
+    /// a ConfigMap source, see `config_map_artefact`.
     ///
     /// # Arguments
     ///
@@ -347,42 +1132,89 @@ impl Engine {
     ///
     /// Will return an error if:
     /// - The source name or namespace is missing from the instance
-    /// - The source kind is not GitRepository or OciRepository
+    /// - The source kind is not GitRepository, OciRepository or ConfigMap
     /// - The source object cannot be found in the cluster
     /// - The source has no status or artefact information
     ///
-    async fn get_artefact(&self, instance: &KclInstance) -> Result<FluxSourceArtefact> {
-        let source = &instance.spec.source;
-        let source_name = source.name.as_ref().context(ObjectHasNoNameSnafu)?;
-        let source_namespace = source
-            .namespace
-            .as_ref()
-            .or(instance.metadata.namespace.as_ref())
-            .context(ObjectHasNoNamespaceSnafu)?;
-
-        match source.kind.as_deref() {
-            Some("GitRepository") => Ok(FluxSourceArtefact::Git(
+    /// # Returns
+    ///
+    /// The artefact, and the timeout of the OCI pulls of the module dependencies: the
+    /// `timeout` of an OCIRepository source, or `kcl_client::DEFAULT_OCI_TIMEOUT` for other
+    /// sources
+    pub(crate) async fn get_artefact(
+        &self,
+        instance: &KclInstance,
+        source_ready: &mut Option<SourceReadiness>,
+    ) -> Result<(FluxSourceArtefact, Duration)> {
+        let (source_name, source_namespace) = source_ref(instance)?;
+        let kind = instance.spec.source.kind.as_deref();
+        let mut timeout = kcl_client::DEFAULT_OCI_TIMEOUT;
+        let fetched = match kind {
+            Some("GitRepository") => {
                 Api::<GitRepository>::namespaced(self.client.clone(), source_namespace)
                     .get(source_name)
                     .await
-                    .context(ObjectHasNotFoundSnafu)?
-                    .status
-                    .context(ObjectHasNoStatusSnafu)?
-                    .artifact
-                    .context(ObjectHasNoArtefactSnafu)?,
-            )),
-            Some("OciRepository") => Ok(FluxSourceArtefact::Oci(
+                    .map(|repository| {
+                        repository.status.map(|status| {
+                            (
+                                status.conditions,
+                                status.artifact.map(FluxSourceArtefact::Git),
+                            )
+                        })
+                    })
+            }
+            Some(OCI_REPOSITORY_KIND | "OciRepository") => {
                 Api::<OCIRepository>::namespaced(self.client.clone(), source_namespace)
                     .get(source_name)
                     .await
-                    .context(ObjectHasNotFoundSnafu)?
-                    .status
-                    .context(ObjectHasNoStatusSnafu)?
-                    .artifact
-                    .context(ObjectHasNoArtefactSnafu)?,
-            )),
-            _ => Err(Error::ObjectHasNoKind),
-        }
+                    .map(|repository| {
+                        // Dependencies are pulled within the timeout of the source
+                        timeout = oci_timeout(repository.spec.timeout.as_deref());
+                        repository.status.map(|status| {
+                            (
+                                status.conditions,
+                                status.artifact.map(FluxSourceArtefact::Oci),
+                            )
+                        })
+                    })
+            }
+            Some(CONFIG_MAP_KIND) => {
+                Api::<ConfigMap>::namespaced(self.client.clone(), source_namespace)
+                    .get(source_name)
+                    .await
+                    .map(|config_map| Some((None, Some(config_map_artefact(&config_map)))))
+            }
+            _ => return Err(Error::ObjectHasNoKind),
+        };
+        let status = fetched
+            .inspect_err(|e| {
+                if matches!(e, kube::Error::Api(response) if response.code == 404) {
+                    *source_ready = Some(SourceReadiness {
+                        ready: false,
+                        reason: SOURCE_NOT_FOUND_REASON.to_string(),
+                        message: format!(
+                            "{} {}/{} not found",
+                            kind.unwrap_or_default(),
+                            source_namespace,
+                            source_name
+                        ),
+                    });
+                }
+            })
+            .context(ObjectHasNotFoundSnafu)?;
+        let (conditions, artefact) = status.unwrap_or_default();
+        *source_ready = Some(match artefact {
+            Some(FluxSourceArtefact::ConfigMap(_)) => SourceReadiness {
+                ready: true,
+                reason: SOURCE_CONFIG_MAP_REASON.to_string(),
+                message: format!(
+                    "Module read from ConfigMap {}/{}",
+                    source_namespace, source_name
+                ),
+            },
+            _ => SourceReadiness::of(conditions.as_deref()),
+        });
+        Ok((artefact.context(ObjectHasNoArtefactSnafu)?, timeout))
     }
 
     /// Patches status information for a KclInstance
@@ -390,6 +1222,9 @@ impl Engine {
     /// Updates the status field of a KclInstance custom resource in Kubernetes.
     /// Uses server-side apply to patch the status while preserving other fields.
     ///
+    /// Nothing is patched when the status is unchanged, and the inventory, which may list
+    /// hundreds of objects, is left out of the patch when only other fields changed.
+    ///
     /// # Arguments
     ///
     /// * `instance` - Arc<KclInstance> holding the instance to update
@@ -423,17 +1258,2982 @@ impl Engine {
             .get(&instance.name_any())
             .await
             .context(ObjectHasNotFoundSnafu)?;
-        let mut instance_imt = current.clone();
-        instance_imt.status = Some(KclInstanceStatus {
+        let status = KclInstanceStatus {
             observed_generation: generation,
-            ..status.clone()
-        });
+            ..status
+        };
+        let Some(patch) = status_patch(current.status.as_ref(), &status)? else {
+            return Ok(current);
+        };
 
         // Create patch parameters for server-side apply
         let pp = PatchParams::apply(OPERATOR_MANAGER).validation_strict();
 
-        api.patch_status(&instance.name_any(), &pp, &Patch::Merge(&instance_imt))
-            .await
-            .context(ApplyYamlStatusSnafu)
+        api.patch_status(
+            &instance.name_any(),
+            &pp,
+            &Patch::Merge(serde_json::json!({ "status": patch })),
+        )
+        .await
+        .context(ApplyYamlStatusSnafu)
+    }
+
+    /// Sets a single condition on the status of a KclInstance
+    ///
+    /// Used outside of the regular reconcile flow (e.g. from the error policy), where the rest
+    /// of the status must be kept as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `instance` - Arc<KclInstance> holding the instance to update
+    /// * `type_` - Condition type, e.g. `Ready`
+    /// * `status` - Whether the condition holds
+    /// * `reason` - Machine readable reason for the condition
+    /// * `message` - Human readable message
+    pub(crate) async fn patch_condition(
+        &self,
+        instance: Arc<KclInstance>,
+        type_: &str,
+        status: bool,
+        reason: &str,
+        message: String,
+    ) -> Result<KclInstance> {
+        self.patch_status_with(instance, |instance_status, generation| {
+            instance_status.set_condition(type_, status, reason, message, generation)
+        })
+        .await
+    }
+
+    /// Updates the current status of a KclInstance in place, keeping the rest of it as it is
+    ///
+    /// # Arguments
+    ///
+    /// * `instance` - Arc<KclInstance> holding the instance to update
+    /// * `update` - Changes the current status, given the current generation of the instance
+    pub(crate) async fn patch_status_with(
+        &self,
+        instance: Arc<KclInstance>,
+        update: impl FnOnce(&mut KclInstanceStatus, i64),
+    ) -> Result<KclInstance> {
+        let api = Api::<KclInstance>::namespaced(
+            self.client.clone(),
+            &instance.namespace().context(ObjectHasNoNamespaceSnafu)?,
+        );
+
+        let current = api
+            .get(&instance.name_any())
+            .await
+            .context(ObjectHasNotFoundSnafu)?;
+        let generation = current.metadata.generation.unwrap_or(0);
+        let mut instance_status = current.status.unwrap_or_default();
+        update(&mut instance_status, generation);
+
+        let patch = serde_json::json!({ "status": instance_status });
+        api.patch_status(
+            &instance.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+        .context(ApplyYamlStatusSnafu)
+    }
+}
+
+/// Object deleted by `Engine::cleanup`.
+pub(crate) struct DeletedObject {
+    api: Api<DynamicObject>,
+    kind: String,
+    name: String,
+}
+
+/// Objects applied by `Engine::apply`.
+pub(crate) struct AppliedObjects {
+    /// Inventory entries of the applied objects, with their resourceVersion.
+    pub inventory: Vec<Gvk>,
+    /// Number of objects the apply created or changed.
+    pub changed: usize,
+    /// Objects that failed to apply, only with `continueOnError`.
+    pub failed: Vec<FailedObject>,
+    /// Drifted objects the apply corrected, with their corrected fields, e.g.
+    /// `ConfigMap default/app (data.greeting)`, only when correcting drift.
+    pub corrected: Vec<String>,
+}
+
+/// Object that failed to apply, see `AppliedObjects::failed`.
+pub(crate) struct FailedObject {
+    /// The rendered object.
+    pub object: DynamicObject,
+    /// Error of the apply.
+    pub reason: String,
+}
+
+/// Apply of the rendered objects of an instance in batches, see `Engine::apply_session`.
+pub(crate) struct ApplySession<'a> {
+    engine: &'a Engine,
+    instance: &'a KclInstance,
+    discovery: &'a CachedDiscovery,
+    correct_drift: bool,
+    /// Client applying the objects, see `Engine::apply_client`
+    client: Client,
+    pp: PatchParams,
+    allowed: AllowedNamespaces,
+    /// The rendered CRDs, see `apply_crds`
+    crds: Vec<DynamicObject>,
+    /// Group and kind of the rendered CRDs, see `crd_kind`
+    crd_kinds: Vec<(String, String)>,
+    /// Prior state of the objects changed so far, to roll them back in atomic mode
+    snapshots: Vec<Snapshot>,
+    applied: AppliedObjects,
+}
+
+impl ApplySession<'_> {
+    /// Checks a batch of rendered objects before anything is applied, see
+    /// `Engine::check_objects`.
+    pub(crate) async fn check(&self, objects: &[DynamicObject]) -> Result<()> {
+        self.engine
+            .check_objects(
+                self.instance,
+                objects,
+                &self.crds,
+                self.discovery,
+                &self.allowed,
+            )
+            .await
+    }
+
+    /// Applies the rendered CRDs, so the objects of their kinds can be applied in the same run.
+    pub(crate) async fn apply_crds(&mut self) -> Result<()> {
+        let crds = std::mem::take(&mut self.crds);
+        let res = self.apply_objects(crds.iter()).await;
+        self.crds = crds;
+        res
+    }
+
+    /// Applies a batch of rendered objects, leaving out the CRDs applied by `apply_crds`.
+    /// Post-apply hooks run on the objects of the batch.
+    pub(crate) async fn apply(&mut self, objects: &[DynamicObject]) -> Result<()> {
+        self.apply_objects(objects.iter().filter(|o| !is_crd(o)))
+            .await
+    }
+
+    async fn apply_objects(&mut self, objects: impl Iterator<Item = &DynamicObject>) -> Result<()> {
+        let engine = self.engine;
+        let instance = self.instance;
+        let config = &instance.spec.config;
+        let mut res = Vec::new();
+        for o in objects {
+            let snapshot = if config.atomic || self.correct_drift {
+                engine
+                    .snapshot(
+                        &self.client,
+                        o,
+                        self.discovery,
+                        &config.unmanaged_fields,
+                        &self.crd_kinds,
+                    )
+                    .await
+            } else {
+                Ok(None)
+            };
+            let applied = match snapshot {
+                Ok(snapshot) => {
+                    let previous_version = match &snapshot {
+                        Some(snapshot) => snapshot
+                            .prior
+                            .as_ref()
+                            .map(|prior| prior.metadata.resource_version.clone()),
+                        None => inventory_version(instance, o),
+                    };
+                    engine
+                        .apply_waiting_for_crd(
+                            instance,
+                            &self.client,
+                            o,
+                            self.discovery,
+                            &self.pp,
+                            &self.crd_kinds,
+                            previous_version,
+                        )
+                        .await
+                        .map(|applied| (applied, snapshot))
+                }
+                Err(e) => Err(e),
+            };
+            match applied {
+                Ok(((mut applied, o_changed), snapshot)) => {
+                    // Managed fields are often larger than the object, and nothing uses them
+                    applied.metadata.managed_fields = None;
+                    self.applied.changed += usize::from(o_changed);
+                    let prior = snapshot.as_ref().and_then(|s| s.prior.as_ref());
+                    if let Some(prior) = prior.filter(|_| o_changed && self.correct_drift) {
+                        let fields = drifted_fields(prior, &applied);
+                        if !fields.is_empty() {
+                            self.applied.corrected.push(format!(
+                                "{} ({})",
+                                object_id(o),
+                                fields.join(", ")
+                            ));
+                        }
+                    }
+                    if o_changed && config.atomic {
+                        self.snapshots.extend(snapshot);
+                    }
+                    res.push(applied);
+                }
+                Err(e) if config.atomic => {
+                    warn!("Failed to apply {}, rolling back: {}", object_id(o), e);
+                    let reverted = engine.roll_back(std::mem::take(&mut self.snapshots)).await;
+                    if !reverted.is_empty() {
+                        let note = format!(
+                            "Rolled back {} after {} failed to apply",
+                            reverted.join(", "),
+                            object_id(o)
+                        );
+                        if let Err(e) = crate::event::publish_event(
+                            Arc::new(instance.clone()),
+                            engine.client.clone(),
+                            engine.reporter.clone(),
+                            "Apply".into(),
+                            "RolledBack".into(),
+                            Some(note),
+                        )
+                        .await
+                        {
+                            warn!("Failed to publish RolledBack event: {}", e);
+                        }
+                    }
+                    return Err(e);
+                }
+                Err(e) if config.continue_on_error => {
+                    warn!("Failed to apply {}, continuing: {}", object_id(o), e);
+                    self.applied.failed.push(FailedObject {
+                        object: o.clone(),
+                        reason: e.to_string(),
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !res.is_empty() {
+            for hook in &engine.hooks {
+                hook.post_apply(instance, &res)
+                    .await
+                    .context(HookFailedSnafu)?;
+            }
+        }
+        // Only the inventory entries are kept, not the applied objects
+        for applied in res {
+            self.applied
+                .inventory
+                .push(Gvk::try_from(applied).context(InventoryEntrySnafu)?);
+        }
+        Ok(())
+    }
+
+    /// Reports the corrected drift with a `DriftCorrected` event, see `Engine::apply`.
+    ///
+    /// # Returns
+    /// The outcome of the apply
+    pub(crate) async fn finish(self) -> Result<AppliedObjects> {
+        let corrected = &self.applied.corrected;
+        if !corrected.is_empty() {
+            let note = format!("Corrected drift of {}", corrected.join(", "));
+            info!("{}", note);
+            if let Err(e) = crate::event::publish_normal_event(
+                Arc::new(self.instance.clone()),
+                self.engine.client.clone(),
+                self.engine.reporter.clone(),
+                "Apply".into(),
+                "DriftCorrected".into(),
+                Some(note),
+            )
+            .await
+            {
+                warn!("Failed to publish DriftCorrected event: {}", e);
+            }
+        }
+        Ok(self.applied)
+    }
+}
+
+/// Changes a dry-run apply would make, see `Engine::plan`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Plan {
+    /// Objects that would be created, see `object_id`.
+    pub create: Vec<String>,
+    /// Objects that would be changed.
+    pub change: Vec<String>,
+    /// Number of objects left as they are.
+    pub unchanged: usize,
+    /// Unified diffs of the objects that would be created or changed, see `object_diff`.
+    pub diffs: Vec<String>,
+}
+
+/// Readiness of the Flux source of an instance, reported in its `SourceReady` condition.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SourceReadiness {
+    pub ready: bool,
+    pub reason: String,
+    pub message: String,
+}
+
+impl SourceReadiness {
+    /// Readiness mirroring the `Ready` condition among the `conditions` of a source, or not
+    /// ready when the source has no such condition yet.
+    pub fn of(conditions: Option<&[Condition]>) -> Self {
+        let ready = conditions
+            .unwrap_or_default()
+            .iter()
+            .find(|condition| condition.type_ == READY_CONDITION);
+        match ready {
+            Some(condition) => SourceReadiness {
+                ready: condition.status == "True",
+                reason: if condition.reason.is_empty() {
+                    SOURCE_NOT_READY_REASON.to_string()
+                } else {
+                    condition.reason.clone()
+                },
+                message: condition.message.clone(),
+            },
+            None => SourceReadiness {
+                ready: false,
+                reason: SOURCE_NOT_READY_REASON.to_string(),
+                message: "The source has no Ready condition yet".to_string(),
+            },
+        }
+    }
+}
+
+/// Live state of an object before it was applied in atomic mode, see `Engine::roll_back`.
+struct Snapshot {
+    api: Api<DynamicObject>,
+    name: String,
+    /// Description of the object, see `object_id`
+    id: String,
+    /// The object before the apply, None when the apply created it
+    prior: Option<DynamicObject>,
+}
+
+/// Configuration of a client impersonating the ServiceAccount `name` in `namespace`, with the
+/// user and groups the API server authenticates ServiceAccount tokens with.
+pub(crate) fn impersonation_config(
+    config: &kube::Config,
+    namespace: &str,
+    name: &str,
+) -> kube::Config {
+    let mut config = config.clone();
+    config.auth_info.impersonate = Some(format!("system:serviceaccount:{namespace}:{name}"));
+    config.auth_info.impersonate_groups = Some(vec![
+        "system:serviceaccounts".to_string(),
+        format!("system:serviceaccounts:{namespace}"),
+    ]);
+    config
+}
+
+/// Fields the API server updates on every apply, left out when diffing a dry-run.
+const VOLATILE_FIELDS: &[&str] = &[
+    "metadata.managedFields",
+    "metadata.resourceVersion",
+    "metadata.generation",
+];
+
+/// Whether the dry-run apply of an object differs from the object in the cluster.
+fn has_diff(current: &DynamicObject, planned: &DynamicObject) -> bool {
+    let volatile: Vec<String> = VOLATILE_FIELDS.iter().map(|f| f.to_string()).collect();
+    let strip = |obj: &DynamicObject| {
+        let mut value = serde_json::to_value(obj).unwrap_or_default();
+        utils::strip_fields(&mut value, &volatile);
+        value
+    };
+    strip(current) != strip(planned)
+}
+
+/// Fields the API server sets when creating an object, left out of the diffs.
+const SERVER_FIELDS: &[&str] = &["metadata.uid", "metadata.creationTimestamp", "status"];
+
+/// Unified diff between an object in the cluster and its dry-run apply, like `flux diff`.
+/// Created objects, without `current`, are added as a whole. The values of Secrets are
+/// replaced by markers, so changes show without disclosing them, see `redact_secret`.
+pub(crate) fn object_diff(current: Option<&DynamicObject>, planned: &DynamicObject) -> String {
+    let ignored: Vec<String> = VOLATILE_FIELDS
+        .iter()
+        .chain(SERVER_FIELDS)
+        .map(|f| f.to_string())
+        .collect();
+    let to_value = |obj: &DynamicObject| {
+        let mut value = serde_json::to_value(obj).unwrap_or_default();
+        utils::strip_fields(&mut value, &ignored);
+        value
+    };
+    let mut before = current.map(to_value);
+    let mut after = to_value(planned);
+    redact_secret(before.as_mut(), &mut after);
+    let to_yaml = |value: &serde_json::Value| serde_yaml::to_string(value).unwrap_or_default();
+    let before = before.as_ref().map(to_yaml).unwrap_or_default();
+    let after = to_yaml(&after);
+    let id = object_id(planned);
+    let old_header = match current {
+        Some(_) => format!("a/{id}"),
+        None => "/dev/null".to_string(),
+    };
+    similar::TextDiff::from_lines(&before, &after)
+        .unified_diff()
+        .header(&old_header, &format!("b/{id}"))
+        .to_string()
+}
+
+/// Paths of the fields an apply changed in a drifted object, e.g. `spec.replicas`, from its
+/// live state before and after the apply. Volatile and server-set fields are left out, and
+/// arrays count as a single field.
+fn drifted_fields(before: &DynamicObject, after: &DynamicObject) -> Vec<String> {
+    let ignored: Vec<String> = VOLATILE_FIELDS
+        .iter()
+        .chain(SERVER_FIELDS)
+        .map(|f| f.to_string())
+        .collect();
+    let to_value = |obj: &DynamicObject| {
+        let mut value = serde_json::to_value(obj).unwrap_or_default();
+        utils::strip_fields(&mut value, &ignored);
+        value
+    };
+    let mut fields = Vec::new();
+    changed_paths(&to_value(before), &to_value(after), "", &mut fields);
+    fields
+}
+
+/// Collects into `paths` the paths below `prefix` whose values differ between `before` and
+/// `after`, descending into the objects.
+fn changed_paths(
+    before: &serde_json::Value,
+    after: &serde_json::Value,
+    prefix: &str,
+    paths: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            let keys: std::collections::BTreeSet<&String> =
+                before.keys().chain(after.keys()).collect();
+            for key in keys {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                match (before.get(key), after.get(key)) {
+                    (Some(before), Some(after)) => changed_paths(before, after, &path, paths),
+                    _ => paths.push(path),
+                }
+            }
+        }
+        (before, after) if before != after => paths.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+/// Marker of a Secret value that the apply sets or changes.
+const REDACTED_CHANGED: &str = "(redacted, changed)";
+/// Marker of a Secret value that the apply keeps.
+const REDACTED_UNCHANGED: &str = "(redacted, unchanged)";
+/// Marker of a Secret value in the cluster that the apply changes or removes.
+const REDACTED_PREVIOUS: &str = "(redacted)";
+
+/// Replaces the values of a Secret, `after`, and of its version in the cluster, `before`, by
+/// constant markers telling whether the apply changes them. Unlike digests, the markers can't
+/// be brute-forced back into weak values such as passwords.
+fn redact_secret(mut before: Option<&mut serde_json::Value>, after: &mut serde_json::Value) {
+    use serde_json::{Map, Value};
+
+    if after.get("kind").and_then(Value::as_str) != Some("Secret") {
+        return;
+    }
+    for field in ["data", "stringData"] {
+        let values = |value: &Value| {
+            value
+                .get(field)
+                .and_then(Value::as_object)
+                .cloned()
+                .unwrap_or_default()
+        };
+        let old = before.as_deref().map(values).unwrap_or_default();
+        let new = values(after);
+        let redact = |value: Option<&mut Value>, others: &Map<String, Value>, changed: &str| {
+            let Some(data) = value
+                .and_then(|value| value.get_mut(field))
+                .and_then(Value::as_object_mut)
+            else {
+                return;
+            };
+            for (key, item) in data.iter_mut() {
+                let marker = if others.get(key) == Some(&*item) {
+                    REDACTED_UNCHANGED
+                } else {
+                    changed
+                };
+                *item = marker.into();
+            }
+        };
+        redact(before.as_deref_mut(), &new, REDACTED_PREVIOUS);
+        redact(Some(&mut *after), &old, REDACTED_CHANGED);
+    }
+}
+
+/// Whether the object is a CustomResourceDefinition.
+pub(crate) fn is_crd(obj: &DynamicObject) -> bool {
+    obj.types.as_ref().is_some_and(|types| {
+        types.kind == "CustomResourceDefinition"
+            && types.api_version.starts_with("apiextensions.k8s.io/")
+    })
+}
+
+/// Group and kind of the objects a CRD defines.
+fn crd_kind(crd: &DynamicObject) -> Option<(String, String)> {
+    let spec = crd.data.get("spec")?;
+    Some((
+        spec.get("group")?.as_str()?.to_string(),
+        spec.pointer("/names/kind")?.as_str()?.to_string(),
+    ))
+}
+
+/// Whether the kind of the object is defined by one of `crd_kinds`.
+fn defined_by(obj: &DynamicObject, crd_kinds: &[(String, String)]) -> bool {
+    let Some(gvk) = obj
+        .types
+        .as_ref()
+        .and_then(|types| GroupVersionKind::try_from(types).ok())
+    else {
+        return false;
+    };
+    crd_kinds
+        .iter()
+        .any(|(group, kind)| gvk.group == *group && gvk.kind == *kind)
+}
+
+/// Whether `crd` defines the kind of the object as namespaced, the default scope.
+fn defines_namespaced(crd: &DynamicObject, obj: &DynamicObject) -> bool {
+    let Some(kind) = crd_kind(crd) else {
+        return false;
+    };
+    let cluster_scoped = crd
+        .data
+        .pointer("/spec/scope")
+        .and_then(|scope| scope.as_str())
+        == Some("Cluster");
+    defined_by(obj, &[kind]) && !cluster_scoped
+}
+
+/// Server-side apply parameters of the objects of an instance.
+fn apply_params(instance: &KclInstance) -> PatchParams {
+    let config = &instance.spec.config;
+    let pp = PatchParams::apply(config.field_manager.as_deref().unwrap_or(OPERATOR_MANAGER));
+    if config.force {
+        pp.force()
+    } else {
+        pp
+    }
+}
+
+/// Identifies an object in plans and events, e.g. `ConfigMap default/app`.
+pub(crate) fn object_id(obj: &DynamicObject) -> String {
+    let kind = obj.types.as_ref().map_or("", |types| types.kind.as_str());
+    match obj.namespace() {
+        Some(namespace) => format!("{kind} {namespace}/{}", obj.name_any()),
+        None => format!("{kind} {}", obj.name_any()),
+    }
+}
+
+/// Whether an apply created or changed the object, from its resourceVersion before the apply
+/// (`None` when it did not exist).
+fn is_changed(previous_version: Option<&Option<String>>, applied: &DynamicObject) -> bool {
+    previous_version.is_none_or(|version| version != &applied.metadata.resource_version)
+}
+
+/// The resourceVersion the inventory of `instance` recorded for `obj` when it was last
+/// applied, or None when the object is not in the inventory.
+fn inventory_version(instance: &KclInstance, obj: &DynamicObject) -> Option<Option<String>> {
+    let entry = Gvk::try_from(obj.clone()).ok()?;
+    let inventory = &instance.status.as_ref()?.inventory;
+    inventory
+        .get(&entry)
+        .map(|entry| entry.resource_version.clone())
+}
+
+/// Returns the Flux source kinds that are not served by the cluster, e.g. when the Flux
+/// source-controller CRDs are not installed.
+///
+/// # Arguments
+/// * `discovery` - Kubernetes API discovery client
+pub async fn missing_flux_sources(discovery: &CachedDiscovery) -> Vec<GroupVersionKind> {
+    let mut missing = Vec::new();
+    for gvk in [GitRepository::gvk(&()), OCIRepository::gvk(&())] {
+        if discovery.resolve_gvk(&gvk).await.is_none() {
+            missing.push(gvk);
+        }
+    }
+    missing
+}
+
+/// Merge patch turning the `current` status of an instance into `status`.
+///
+/// # Returns
+/// `None` when the status is unchanged, otherwise the new status without the inventory when
+/// it is unchanged, as the merge patch keeps the fields it omits
+fn status_patch(
+    current: Option<&KclInstanceStatus>,
+    status: &KclInstanceStatus,
+) -> Result<Option<serde_json::Value>> {
+    let same_inventory = current.is_some_and(|current| {
+        current.inventory.len() == status.inventory.len()
+            && current.inventory.iter().all(|entry| {
+                status
+                    .inventory
+                    .get(entry)
+                    .is_some_and(|other| other.resource_version == entry.resource_version)
+            })
+    });
+    if same_inventory && current == Some(status) {
+        return Ok(None);
+    }
+    let mut patch = serde_json::to_value(status).context(SerializeStatusSnafu)?;
+    if same_inventory {
+        if let Some(patch) = patch.as_object_mut() {
+            patch.remove("inventory");
+        }
+    }
+    Ok(Some(patch))
+}
+
+/// Files of the module held by a ConfigMap source: one file per key of its `data` and
+/// `binaryData`, named after the key.
+fn config_map_files(config_map: &ConfigMap) -> BTreeMap<String, Vec<u8>> {
+    let data = config_map
+        .data
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.clone().into_bytes()));
+    let binary_data = config_map
+        .binary_data
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.0.clone()));
+    data.chain(binary_data).collect()
+}
+
+/// Digest of the files of a module in the form of `sha256:<checksum>`, changing with any
+/// file name or content.
+fn files_digest(files: &BTreeMap<String, Vec<u8>>) -> String {
+    let mut hasher = Sha256::new();
+    for (name, content) in files {
+        hasher.update((name.len() as u64).to_be_bytes());
+        hasher.update(name);
+        hasher.update((content.len() as u64).to_be_bytes());
+        hasher.update(content);
+    }
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+/// Describes the module held by a ConfigMap source like a Flux artifact, its revision being
+/// the digest of its files, so the module is rendered again only when they change.
+fn config_map_artefact(config_map: &ConfigMap) -> FluxSourceArtefact {
+    let files = config_map_files(config_map);
+    let digest = files_digest(&files);
+    let path = format!(
+        "configmap/{}/{}",
+        config_map.namespace().unwrap_or_default(),
+        config_map.name_any()
+    );
+    FluxSourceArtefact::ConfigMap(GitRepositoryStatusArtifact {
+        digest: Some(digest.clone()),
+        last_update_time: config_map
+            .metadata
+            .creation_timestamp
+            .as_ref()
+            .map(|time| time.0.to_rfc3339())
+            .unwrap_or_default(),
+        metadata: None,
+        path: path.clone(),
+        revision: digest,
+        size: Some(files.values().map(|content| content.len() as i64).sum()),
+        url: path,
+    })
+}
+
+/// Name and namespace of the Flux source of an instance, which defaults to the namespace of
+/// the instance.
+fn source_ref(instance: &KclInstance) -> Result<(&str, &str)> {
+    let source = &instance.spec.source;
+    let name = source.name.as_deref().context(ObjectHasNoNameSnafu)?;
+    let namespace = source
+        .namespace
+        .as_deref()
+        .or(instance.metadata.namespace.as_deref())
+        .context(ObjectHasNoNamespaceSnafu)?;
+    Ok((name, namespace))
+}
+
+/// Parses the `timeout` of an OCIRepository (e.g. `2m`), falling back to
+/// `kcl_client::DEFAULT_OCI_TIMEOUT` like Flux when it is unset or invalid.
+fn oci_timeout(timeout: Option<&str>) -> Duration {
+    timeout
+        .and_then(|timeout| {
+            humantime::parse_duration(timeout)
+                .inspect_err(|e| warn!("Invalid OCIRepository timeout {}: {}", timeout, e))
+                .ok()
+        })
+        .unwrap_or(kcl_client::DEFAULT_OCI_TIMEOUT)
+}
+
+/// Fails if `obj` is namespaced and targets a namespace that is not allowed.
+/// Namespaced objects without a namespace end up in `default_namespace`.
+fn check_namespace(
+    obj: &DynamicObject,
+    namespaced: bool,
+    default_namespace: &str,
+    allowed: &AllowedNamespaces,
+) -> Result<()> {
+    if !namespaced {
+        return Ok(());
+    }
+    let namespace = obj
+        .metadata
+        .namespace
+        .as_deref()
+        .unwrap_or(default_namespace);
+    if allowed.is_allowed(namespace) {
+        return Ok(());
+    }
+    NamespaceNotAllowedSnafu {
+        kind: obj
+            .types
+            .as_ref()
+            .map(|types| types.kind.clone())
+            .unwrap_or_default(),
+        name: obj.name_any(),
+        namespace,
+    }
+    .fail()
+}
+
+/// Completes the apiVersion of the rendered objects written without a group (e.g. `v1`) for
+/// a kind the core group does not serve, see `apiVersions`
+///
+/// The apiVersion comes from `api_versions` when it maps the kind, or else from the single
+/// group serving the kind.
+///
+/// # Arguments
+/// * `objects` - The rendered objects
+/// * `api_versions` - apiVersion of the rendered objects without a group, by kind
+/// * `discovery` - Kubernetes API discovery client
+///
+/// # Errors
+/// `Error::AmbiguousKind` when several groups serve the kind of an object and it is not mapped
+pub(crate) async fn resolve_api_versions(
+    objects: &mut [DynamicObject],
+    api_versions: &HashMap<String, String>,
+    discovery: &CachedDiscovery,
+) -> Result<()> {
+    for o in objects {
+        let Some(types) = o.types.as_mut() else {
+            continue;
+        };
+        if types.api_version.contains('/') {
+            continue;
+        }
+        if let Some(api_version) = api_versions.get(&types.kind) {
+            types.api_version = api_version.clone();
+            continue;
+        }
+        let core = GroupVersionKind::gvk("", &types.api_version, &types.kind);
+        if discovery.resolve_gvk(&core).await.is_some() {
+            continue;
+        }
+        let candidates: Vec<String> = discovery
+            .api_versions_of(&types.kind)
+            .into_iter()
+            .filter(|api_version| api_version.contains('/'))
+            .collect();
+        match &candidates[..] {
+            [] => {}
+            [api_version] => {
+                info!(
+                    "Resolved apiVersion {} of {} {} to {}",
+                    types.api_version,
+                    types.kind,
+                    o.metadata.name.as_deref().unwrap_or_default(),
+                    api_version
+                );
+                types.api_version = api_version.clone();
+            }
+            _ => {
+                return AmbiguousKindSnafu {
+                    kind: &types.kind,
+                    api_version: &types.api_version,
+                    candidates,
+                }
+                .fail()
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves the namespaced rendered objects to `namespace`, see `namespaceTemplate`
+///
+/// Objects of kinds the API server does not serve (yet) keep their namespace.
+///
+/// # Arguments
+/// * `objects` - The rendered objects
+/// * `namespace` - The target namespace of the instance
+/// * `discovery` - Kubernetes API discovery client
+pub(crate) async fn set_target_namespace(
+    objects: &mut [DynamicObject],
+    namespace: &str,
+    discovery: &CachedDiscovery,
+) {
+    for o in objects {
+        let Some(gvk) = o
+            .types
+            .as_ref()
+            .and_then(|types| GroupVersionKind::try_from(types).ok())
+        else {
+            continue;
+        };
+        if discovery
+            .resolve_gvk(&gvk)
+            .await
+            .is_some_and(|(_, caps)| caps.scope == Scope::Namespaced)
+        {
+            o.metadata.namespace = Some(namespace.to_string());
+        }
+    }
+}
+
+/// Writes an object to the cluster with `strategy`
+///
+/// # Arguments
+/// * `data` - The apply patch body of the object, see `patch_body`
+/// * `pp` - Server-side apply parameters, whose field manager and dry-run also apply to the
+///   other strategies
+/// * `current_version` - resourceVersion of the object in the cluster, `None` when it does not
+///   exist
+///
+/// # Returns
+/// The object as returned by the API server
+async fn sync_object(
+    api: &Api<DynamicObject>,
+    name: &str,
+    data: serde_json::Value,
+    pp: &PatchParams,
+    strategy: SyncStrategy,
+    current_version: Option<Option<String>>,
+) -> Result<DynamicObject> {
+    if strategy == SyncStrategy::Apply {
+        return api
+            .patch(name, pp, &Patch::Apply(&data))
+            .await
+            .context(FailedToPatchSnafu);
+    }
+    let post_params = PostParams {
+        dry_run: pp.dry_run,
+        field_manager: pp.field_manager.clone(),
+    };
+    let mut obj: DynamicObject = serde_json::from_value(data).context(UnableToDeserializeSnafu)?;
+    match (strategy, current_version) {
+        (_, None) => api.create(&post_params, &obj).await,
+        (SyncStrategy::CreateIfAbsent, Some(_)) => api.get(name).await,
+        (_, Some(version)) => {
+            // Replacing with the current resourceVersion fails on concurrent changes
+            obj.metadata.resource_version = version;
+            api.replace(name, &post_params, &obj).await
+        }
+    }
+    .context(FailedToPatchSnafu)
+}
+
+/// Serializes `obj` into the server-side apply patch body, without the `unmanaged_fields`,
+/// so that the field manager doesn't claim ownership of them.
+fn patch_body(obj: &DynamicObject, unmanaged_fields: &[String]) -> Result<serde_json::Value> {
+    let mut data = serde_json::to_value(obj).context(UnableToDeserializeSnafu)?;
+    utils::strip_fields(&mut data, unmanaged_fields);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_map(namespace: Option<&str>) -> DynamicObject {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "app", "namespace": namespace },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_namespace_rejects_out_of_scope() {
+        let allowed = AllowedNamespaces::new("team-a", vec![], true);
+        let res = check_namespace(&config_map(Some("kube-system")), true, "default", &allowed);
+        assert!(matches!(
+            res,
+            Err(Error::NamespaceNotAllowed { namespace, .. }) if namespace == "kube-system"
+        ));
+    }
+
+    #[test]
+    fn test_check_namespace_defaults_to_client_namespace() {
+        let allowed = AllowedNamespaces::new("team-a", vec![], true);
+        assert!(check_namespace(&config_map(None), true, "default", &allowed).is_err());
+        assert!(check_namespace(&config_map(None), true, "team-a", &allowed).is_ok());
+    }
+
+    #[test]
+    fn test_check_namespace_ignores_cluster_scoped() {
+        let allowed = AllowedNamespaces::new("team-a", vec![], true);
+        assert!(check_namespace(&config_map(Some("other")), false, "default", &allowed).is_ok());
+    }
+
+    #[test]
+    fn test_patch_body_strips_unmanaged_fields() -> Result<()> {
+        let service: DynamicObject = serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "clusterIP": "10.0.0.10",
+                "type": "NodePort",
+                "ports": [
+                    { "port": 80, "nodePort": 30080 },
+                    { "port": 443, "nodePort": 30443 },
+                ],
+            },
+        }))
+        .unwrap();
+
+        let data = patch_body(
+            &service,
+            &[
+                "spec.clusterIP".to_string(),
+                "spec.ports.*.nodePort".to_string(),
+                "spec.missing.field".to_string(),
+            ],
+        )?;
+        assert_eq!(
+            data["spec"],
+            serde_json::json!({
+                "type": "NodePort",
+                "ports": [{ "port": 80 }, { "port": 443 }],
+            })
+        );
+        assert_eq!(data["metadata"]["name"], "app");
+
+        // Nothing is stripped by default
+        let data = patch_body(&service, &[])?;
+        assert_eq!(data["spec"]["clusterIP"], "10.0.0.10");
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_patch() -> Result<()> {
+        let mut status = KclInstanceStatus {
+            observed_generation: 2,
+            last_applied_revision: Some("main@sha1:abc".to_string()),
+            ..Default::default()
+        };
+        status.inventory.insert(flux_kcl_operator_crd::Gvk {
+            name: "app".to_string(),
+            group: "apps".to_string(),
+            version: "v1".to_string(),
+            kind: "Deployment".to_string(),
+            namespace: Some("default".to_string()),
+            resource_version: None,
+        });
+
+        // Unchanged status: no patch
+        assert_eq!(status_patch(Some(&status), &status)?, None);
+
+        // Unchanged inventory: left out of the patch
+        let updated = KclInstanceStatus {
+            last_applied_revision: Some("main@sha1:def".to_string()),
+            ..status.clone()
+        };
+        let patch = status_patch(Some(&status), &updated)?.unwrap();
+        assert_eq!(patch["lastAppliedRevision"], "main@sha1:def");
+        assert!(patch.get("inventory").is_none());
+
+        // Changed inventory: sent in full
+        let mut pruned = updated.clone();
+        pruned.inventory.clear();
+        let patch = status_patch(Some(&updated), &pruned)?.unwrap();
+        assert_eq!(patch["inventory"], serde_json::json!([]));
+        let patch = status_patch(None, &updated)?.unwrap();
+        assert_eq!(patch["inventory"].as_array().map(Vec::len), Some(1));
+
+        // Changed resourceVersion: sent in full, so the next apply compares against it
+        let mut reapplied = status.clone();
+        let mut entry = reapplied.inventory.iter().next().unwrap().clone();
+        entry.resource_version = Some("42".to_string());
+        reapplied.inventory.replace(entry);
+        let patch = status_patch(Some(&status), &reapplied)?.unwrap();
+        assert_eq!(patch["inventory"][0]["resourceVersion"], "42");
+        Ok(())
+    }
+
+    #[test]
+    fn test_inventory_version() {
+        let mut applied = config_map(Some("default"));
+        applied.metadata.resource_version = Some("42".to_string());
+        let mut status = KclInstanceStatus::default();
+        status.register_applied(vec![applied]).unwrap();
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        instance.status = Some(status);
+
+        let rendered = config_map(Some("default"));
+        assert_eq!(
+            inventory_version(&instance, &rendered),
+            Some(Some("42".to_string()))
+        );
+        assert_eq!(
+            inventory_version(&instance, &config_map(Some("other"))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_oci_timeout() {
+        assert_eq!(oci_timeout(Some("2m30s")), Duration::from_secs(150));
+        assert_eq!(oci_timeout(None), kcl_client::DEFAULT_OCI_TIMEOUT);
+        assert_eq!(oci_timeout(Some("soon")), kcl_client::DEFAULT_OCI_TIMEOUT);
+    }
+
+    #[test]
+    fn test_is_changed() {
+        let mut applied = config_map(Some("default"));
+        applied.metadata.resource_version = Some("42".to_string());
+
+        assert!(is_changed(None, &applied));
+        assert!(is_changed(Some(&Some("41".to_string())), &applied));
+        assert!(!is_changed(Some(&Some("42".to_string())), &applied));
+    }
+
+    #[tokio::test]
+    async fn test_plan_only_dry_runs() {
+        use wiremock::{
+            matchers::{method, path, query_param, query_param_is_missing},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "singularName": "configmap",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "message": "configmaps \"app\" not found",
+                "reason": "NotFound",
+                "code": 404,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .and(query_param("dryRun", "All"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": "app", "namespace": "default" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // No object is created for real
+        Mock::given(method("PATCH"))
+            .and(query_param_is_missing("dryRun"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+
+        let plan = Engine::new(client)
+            .plan(&instance, &[config_map(Some("default"))], &discovery)
+            .await
+            .unwrap();
+        assert_eq!(
+            plan,
+            Plan {
+                create: vec!["ConfigMap default/app".to_string()],
+                change: vec![],
+                unchanged: 0,
+                diffs: vec![object_diff(None, &config_map(Some("default")))],
+            }
+        );
+    }
+
+    #[test]
+    fn test_object_diff() {
+        let object = |replicas: &str, version: &str| -> DynamicObject {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": {
+                    "name": "app",
+                    "namespace": "default",
+                    "uid": "1234",
+                    "resourceVersion": version,
+                },
+                "data": { "name": "app", "replicas": replicas },
+            }))
+            .unwrap()
+        };
+
+        let diff = object_diff(Some(&object("2", "1")), &object("3", "2"));
+        assert!(diff.starts_with("--- a/ConfigMap default/app\n+++ b/ConfigMap default/app\n"));
+        let changes: Vec<&str> = diff
+            .lines()
+            .skip(2)
+            .filter(|line| line.starts_with(['+', '-']))
+            .collect();
+        assert_eq!(changes, ["-  replicas: '2'", "+  replicas: '3'"]);
+
+        // Created objects are added as a whole, without the fields set by the server
+        let diff = object_diff(None, &object("3", "2"));
+        assert!(diff.starts_with("--- /dev/null\n+++ b/ConfigMap default/app\n"));
+        assert!(diff.contains("+  replicas: '3'\n"));
+        assert!(!diff.contains("uid") && !diff.contains("resourceVersion"));
+
+        // Secret values only show as markers telling whether they change
+        let secret = |password: &str| -> DynamicObject {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": { "name": "app", "namespace": "default" },
+                "stringData": { "password": password, "user": "admin" },
+            }))
+            .unwrap()
+        };
+        let diff = object_diff(None, &secret("hunter2"));
+        assert!(!diff.contains("hunter2") && !diff.contains("admin"));
+        assert!(diff.contains("+  password: (redacted, changed)\n"));
+        let diff = object_diff(Some(&secret("hunter1")), &secret("hunter2"));
+        let changes: Vec<&str> = diff
+            .lines()
+            .skip(2)
+            .filter(|line| line.starts_with(['+', '-']))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "-  password: (redacted)",
+                "+  password: (redacted, changed)"
+            ]
+        );
+        assert!(diff.contains(" user: (redacted, unchanged)\n"));
+    }
+
+    #[tokio::test]
+    async fn test_apply_waits_for_crd() {
+        use wiremock::{
+            matchers::{method, path, path_regex},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let resources = |group_version: &str, name: &str, kind: &str, namespaced: bool| {
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": group_version,
+                "resources": [{
+                    "name": name,
+                    "namespaced": namespaced,
+                    "kind": kind,
+                    "verbs": ["get", "patch"],
+                }],
+            }))
+        };
+        let crd = serde_json::json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": "widgets.example.com" },
+            "spec": { "group": "example.com", "names": { "kind": "Widget", "plural": "widgets" } },
+        });
+        let widget = serde_json::json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Widget",
+            "metadata": { "name": "w1", "namespace": "default" },
+        });
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/apis/apiextensions.k8s.io/v1"))
+            .respond_with(resources(
+                "apiextensions.k8s.io/v1",
+                "customresourcedefinitions",
+                "CustomResourceDefinition",
+                false,
+            ))
+            .mount(&server)
+            .await;
+        // The new kind is only served after a while
+        Mock::given(method("GET"))
+            .and(path("/apis/example.com/v1"))
+            .respond_with(ResponseTemplate::new(404))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/apis/example.com/v1"))
+            .respond_with(resources("example.com/v1", "widgets", "Widget", true))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex(
+                "^/apis/(apiextensions.k8s.io|example.com)/v1/.+",
+            ))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "reason": "NotFound",
+                "code": 404,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(
+                "/apis/apiextensions.k8s.io/v1/customresourcedefinitions/widgets.example.com",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&crd))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/apis/example.com/v1/namespaces/default/widgets/w1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&widget))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/apis/events.k8s.io/v1/namespaces/default/events"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+
+        // The CR comes first in the rendered output
+        let objects = [
+            serde_json::from_value(widget.clone()).unwrap(),
+            serde_json::from_value(crd.clone()).unwrap(),
+        ];
+        let applied = Engine::new(client)
+            .apply(&instance, &objects, &discovery, false)
+            .await
+            .unwrap();
+        assert_eq!(applied.inventory.len(), 2);
+        assert_eq!(applied.changed, 2);
+    }
+
+    #[tokio::test]
+    async fn test_apply_checks_namespace_of_new_crd_kinds() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let crd = |scope: &str| -> DynamicObject {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "apiextensions.k8s.io/v1",
+                "kind": "CustomResourceDefinition",
+                "metadata": { "name": "widgets.example.com" },
+                "spec": {
+                    "group": "example.com",
+                    "names": { "kind": "Widget", "plural": "widgets" },
+                    "scope": scope,
+                },
+            }))
+            .unwrap()
+        };
+        let widget: DynamicObject = serde_json::from_value(serde_json::json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Widget",
+            "metadata": { "name": "w1", "namespace": "kube-system" },
+        }))
+        .unwrap();
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/apis/apiextensions.k8s.io/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "apiextensions.k8s.io/v1",
+                "resources": [{
+                    "name": "customresourcedefinitions",
+                    "namespaced": false,
+                    "kind": "CustomResourceDefinition",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        // The new kind is not served yet
+        Mock::given(method("GET"))
+            .and(path("/apis/example.com/v1"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        // Nothing is applied
+        Mock::given(method("PATCH"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        let engine = Engine::new(client).with_same_namespace_only(true);
+
+        let res = engine
+            .apply(
+                &instance,
+                &[widget.clone(), crd("Namespaced")],
+                &discovery,
+                false,
+            )
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::NamespaceNotAllowed { namespace, .. }) if namespace == "kube-system"
+        ));
+
+        // The kinds of cluster-scoped CRDs have no namespace to check
+        assert!(defines_namespaced(&crd("Namespaced"), &widget));
+        assert!(!defines_namespaced(&crd("Cluster"), &widget));
+    }
+
+    #[tokio::test]
+    async fn test_apply_continue_on_error() {
+        use wiremock::{
+            matchers::{method, path, path_regex},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let config_map = |name: &str| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": name, "namespace": "default" },
+            })
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex("^/api/v1/namespaces/default/configmaps/.+"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "reason": "NotFound",
+                "code": 404,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/invalid"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "message": "ConfigMap \"invalid\" is invalid",
+                "reason": "Invalid",
+                "code": 422,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/valid"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(config_map("valid")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let instance = |continue_on_error: bool| {
+            let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+                "apiVersion": "kcl.evrone.com/v1alpha1",
+                "kind": "KclInstance",
+                "metadata": { "name": "app", "namespace": "default" },
+                "spec": {
+                    "sourceRef": { "kind": "GitRepository", "name": "app" },
+                    "path": ".",
+                },
+            }))
+            .unwrap();
+            instance.spec.config.continue_on_error = continue_on_error;
+            instance
+        };
+        let objects = [
+            serde_json::from_value(config_map("invalid")).unwrap(),
+            serde_json::from_value(config_map("valid")).unwrap(),
+        ];
+        let engine = Engine::new(client);
+
+        let applied = engine
+            .apply(&instance(true), &objects, &discovery, false)
+            .await
+            .unwrap();
+        assert_eq!(applied.inventory.len(), 1);
+        assert_eq!(applied.failed.len(), 1);
+        assert_eq!(
+            object_id(&applied.failed[0].object),
+            "ConfigMap default/invalid"
+        );
+
+        // Without continueOnError, the apply stops at the first failure
+        assert!(engine
+            .apply(&instance(false), &objects, &discovery, false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_api_versions() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        let mount = |route: &str, body: serde_json::Value| {
+            Mock::given(method("GET"))
+                .and(path(route.to_string()))
+                .respond_with(ResponseTemplate::new(200).set_body_json(body))
+        };
+        let resources = |group_version: &str, kind: &str| {
+            serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": group_version,
+                "resources": [{
+                    "name": format!("{}s", kind.to_lowercase()),
+                    "namespaced": true,
+                    "kind": kind,
+                    "verbs": ["get", "patch"],
+                }],
+            })
+        };
+        let group = |name: &str| {
+            serde_json::json!({
+                "name": name,
+                "versions": [{ "groupVersion": format!("{name}/v1"), "version": "v1" }],
+                "preferredVersion": { "groupVersion": format!("{name}/v1"), "version": "v1" },
+            })
+        };
+        mount(
+            "/api",
+            serde_json::json!({ "kind": "APIVersions", "versions": ["v1"] }),
+        )
+        .mount(&server)
+        .await;
+        mount("/api/v1", resources("v1", "ConfigMap"))
+            .mount(&server)
+            .await;
+        mount(
+            "/apis",
+            serde_json::json!({
+                "kind": "APIGroupList",
+                "groups": [group("a.example.com"), group("b.example.com"), group("c.example.com")],
+            }),
+        )
+        .mount(&server)
+        .await;
+        for (name, kind) in [
+            ("a.example.com", "Widget"),
+            ("b.example.com", "Widget"),
+            ("c.example.com", "Gadget"),
+        ] {
+            mount(
+                &format!("/apis/{name}/v1"),
+                resources(&format!("{name}/v1"), kind),
+            )
+            .mount(&server)
+            .await;
+        }
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client, std::time::Duration::from_secs(60));
+        discovery.refresh().await.unwrap();
+        let object = |kind: &str| -> DynamicObject {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": kind,
+                "metadata": { "name": "app", "namespace": "default" },
+            }))
+            .unwrap()
+        };
+        let api_version = |object: &DynamicObject| object.types.clone().unwrap().api_version;
+
+        // Widget is served by two groups, the mapping picks one
+        let mapping = HashMap::from([("Widget".to_string(), "b.example.com/v1".to_string())]);
+        let mut objects = [object("Widget"), object("Gadget"), object("ConfigMap")];
+        resolve_api_versions(&mut objects, &mapping, &discovery)
+            .await
+            .unwrap();
+        assert_eq!(
+            objects.iter().map(api_version).collect::<Vec<_>>(),
+            ["b.example.com/v1", "c.example.com/v1", "v1"]
+        );
+
+        let mut objects = [object("Widget")];
+        let err = resolve_api_versions(&mut objects, &HashMap::new(), &discovery)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::AmbiguousKind { kind, candidates, .. }
+                if kind == "Widget" && candidates == &["a.example.com/v1", "b.example.com/v1"]
+        ));
+        assert!(err.to_string().contains("apiVersions"));
+    }
+
+    #[tokio::test]
+    async fn test_apply_forbidden_discovery() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/apis/example.com/v1"))
+            .respond_with(ResponseTemplate::new(403).set_body_json(serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "message": "forbidden: User \"system:serviceaccount:flux-system:kcl\" cannot get path \"/apis/example.com/v1\"",
+                "reason": "Forbidden",
+                "code": 403,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/apis/unknown.example.com/v1"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "reason": "NotFound",
+                "code": 404,
+            })))
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let engine = Engine::new(client.clone());
+        let widget = |api_version: &str| -> DynamicObject {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": api_version,
+                "kind": "Widget",
+                "metadata": { "name": "w1", "namespace": "default" },
+            }))
+            .unwrap()
+        };
+
+        let Err(err) = engine
+            .object_api(&client, &widget("example.com/v1"), &discovery, &[])
+            .await
+        else {
+            panic!("expected example.com/v1 to be unresolved");
+        };
+        assert!(
+            matches!(&err, Error::InsufficientRbacForKind { gvk } if gvk == "example.com/v1 Widget")
+        );
+        assert!(err.to_string().contains("RBAC"));
+
+        // Kinds that are not served at all are reported as such
+        let Err(err) = engine
+            .object_api(&client, &widget("unknown.example.com/v1"), &discovery, &[])
+            .await
+        else {
+            panic!("expected unknown.example.com/v1 to be unresolved");
+        };
+        assert!(matches!(err, Error::ParseGroupVersion { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_apply_keeps_type_meta() {
+        use flux_kcl_operator_crd::Gvk;
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "reason": "NotFound",
+                "code": 404,
+            })))
+            .mount(&server)
+            .await;
+        // The response lacks the apiVersion and kind
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "metadata": { "name": "app", "namespace": "default", "resourceVersion": "1" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+
+        let applied = Engine::new(client)
+            .apply(&instance, &[config_map(Some("default"))], &discovery, false)
+            .await
+            .unwrap();
+        let mut status = KclInstanceStatus::default();
+        status.inventory.extend(applied.inventory);
+        assert_eq!(
+            status.inventory.into_iter().collect::<Vec<_>>(),
+            [Gvk {
+                name: "app".to_string(),
+                group: String::new(),
+                version: "v1".to_string(),
+                kind: "ConfigMap".to_string(),
+                namespace: Some("default".to_string()),
+                resource_version: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_impersonates_service_account() {
+        use wiremock::{
+            matchers::{header, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        // Only the impersonated apply is accepted
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .and(header(
+                "Impersonate-User",
+                "system:serviceaccount:default:deployer",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(config_map(Some("default"))))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = kube::Config::new(server.uri().parse().unwrap());
+        let client = Client::try_from(config.clone()).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+                "serviceAccountName": "deployer",
+            },
+        }))
+        .unwrap();
+
+        // Impersonation needs the configuration of the client
+        let res = Engine::new(client.clone())
+            .apply(&instance, &[config_map(Some("default"))], &discovery, false)
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::ImpersonationUnavailable { service_account }) if service_account == "deployer"
+        ));
+
+        Engine::new(client)
+            .with_config(config)
+            .apply(&instance, &[config_map(Some("default"))], &discovery, false)
+            .await
+            .unwrap();
+        let requests = server.received_requests().await.unwrap();
+        let patch = requests
+            .iter()
+            .find(|request| request.method.as_str() == "PATCH")
+            .unwrap();
+        let groups: Vec<_> = patch
+            .headers
+            .get_all("Impersonate-Group")
+            .iter()
+            .map(|group| group.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            groups,
+            ["system:serviceaccounts", "system:serviceaccounts:default"]
+        );
+        // Discovery keeps the operator's identity
+        assert!(requests
+            .iter()
+            .filter(|request| request.url.path() == "/api/v1")
+            .all(|request| !request.headers.contains_key("Impersonate-User")));
+    }
+
+    #[tokio::test]
+    async fn test_prune_impersonates_service_account() {
+        use wiremock::{
+            matchers::{header, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let managed = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {
+                "name": "app",
+                "namespace": "default",
+                "labels": { "app.kubernetes.io/managed-by": OPERATOR_MANAGER },
+            },
+        });
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "delete"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(managed.clone()))
+            .mount(&server)
+            .await;
+        // Only the impersonated deletes are accepted, by prune and then by cleanup
+        Mock::given(method("DELETE"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .and(header(
+                "Impersonate-User",
+                "system:serviceaccount:default:deployer",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(managed))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let config = kube::Config::new(server.uri().parse().unwrap());
+        let client = Client::try_from(config.clone()).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let engine = Engine::new(client).with_config(config);
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+                "serviceAccountName": "deployer",
+            },
+        }))
+        .unwrap();
+        let stale = flux_kcl_operator_crd::Gvk {
+            name: "app".to_string(),
+            group: String::new(),
+            version: "v1".to_string(),
+            kind: "ConfigMap".to_string(),
+            namespace: Some("default".to_string()),
+            resource_version: None,
+        };
+
+        assert!(engine
+            .delete_resource(
+                &instance,
+                &stale.clone().into(),
+                &stale.name,
+                &stale.namespace,
+                &discovery
+            )
+            .await
+            .unwrap());
+
+        let mut status = KclInstanceStatus::default();
+        status.inventory.insert(stale);
+        instance.status = Some(status);
+        let deleted = engine
+            .cleanup(Arc::new(instance), &discovery)
+            .await
+            .unwrap();
+        assert_eq!(deleted.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_apply_timeout_names_object() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(config_map(Some("default"))))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // The apply of this one is held up, e.g. by a slow admission webhook
+        let mut slow = config_map(Some("default"));
+        slow.metadata.name = Some("slow".into());
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/slow"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(&slow)
+                    .set_delay(std::time::Duration::from_secs(5)),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        instance.spec.config.apply_timeout = Some("100ms".to_string());
+
+        let started = std::time::Instant::now();
+        let res = Engine::new(client)
+            .apply(
+                &instance,
+                &[config_map(Some("default")), slow],
+                &discovery,
+                false,
+            )
+            .await;
+        match res {
+            Err(Error::FailedToPatchTimeout { object, timeout }) => {
+                assert_eq!(object, "ConfigMap default/slow");
+                assert_eq!(timeout, std::time::Duration::from_millis(100));
+            }
+            res => panic!("expected FailedToPatchTimeout, got {res:?}"),
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_apply_reports_corrected_drift() {
+        use wiremock::{
+            matchers::{method, path, path_regex},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let live = |greeting: &str, version: &str| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": {
+                    "name": "app",
+                    "namespace": "default",
+                    "resourceVersion": version,
+                    "labels": { "team": "a" },
+                },
+                "data": { "greeting": greeting },
+            })
+        };
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        // Someone edited the greeting since the last apply
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(live("edited", "1")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(live("hello", "2")))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path_regex("/events$"))
+            .respond_with(ResponseTemplate::new(201))
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        let desired: DynamicObject = serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "app", "namespace": "default" },
+            "data": { "greeting": "hello" },
+        }))
+        .unwrap();
+
+        let engine = Engine::new(client);
+        let applied = engine
+            .apply(&instance, &[desired.clone()], &discovery, true)
+            .await
+            .unwrap();
+        assert_eq!(applied.corrected, ["ConfigMap default/app (data.greeting)"]);
+        let events: Vec<String> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path().ends_with("/events"))
+            .map(|request| String::from_utf8_lossy(&request.body).into_owned())
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].contains("DriftCorrected"));
+        assert!(events[0].contains("Corrected drift of ConfigMap default/app (data.greeting)"));
+
+        // Changes of the desired state are not drift
+        let applied = engine
+            .apply(&instance, &[desired], &discovery, false)
+            .await
+            .unwrap();
+        assert_eq!(applied.changed, 1);
+        assert!(applied.corrected.is_empty());
+    }
+
+    #[test]
+    fn test_drifted_fields() {
+        let object =
+            |value: serde_json::Value| -> DynamicObject { serde_json::from_value(value).unwrap() };
+        let before = object(serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": "web", "resourceVersion": "1", "labels": { "a": "1" } },
+            "spec": { "replicas": 5, "template": { "spec": { "containers": [{ "name": "a" }] } } },
+        }));
+        let after = object(serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {
+                "name": "web",
+                "resourceVersion": "2",
+                "labels": { "a": "1", "b": "2" },
+            },
+            "spec": { "replicas": 2, "template": { "spec": { "containers": [{ "name": "b" }] } } },
+        }));
+        assert_eq!(
+            drifted_fields(&before, &after),
+            [
+                "metadata.labels.b",
+                "spec.replicas",
+                "spec.template.spec.containers"
+            ]
+        );
+        assert!(drifted_fields(&before, &before).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_atomic_apply_rolls_back() {
+        use wiremock::{
+            matchers::{body_partial_json, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let config_map = |name: &str, version: &str, greeting: &str| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": name, "namespace": "default", "resourceVersion": version },
+                "data": { "greeting": greeting },
+            })
+        };
+        let not_found = ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "status": "Failure",
+            "reason": "NotFound",
+            "code": 404,
+        }));
+        let invalid = ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "status": "Failure",
+            "reason": "Invalid",
+            "code": 422,
+        }));
+        let rendered: Vec<DynamicObject> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                serde_json::from_value(serde_json::json!({
+                    "apiVersion": "v1",
+                    "kind": "ConfigMap",
+                    "metadata": { "name": name, "namespace": "default" },
+                    "data": { "greeting": "new" },
+                }))
+                .unwrap()
+            })
+            .collect();
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        instance.spec.config.atomic = true;
+
+        // `a` is created, or updated when `existing`, then `b` fails to apply
+        for existing in [false, true] {
+            let server = MockServer::start().await;
+            Mock::given(method("GET"))
+                .and(path("/api/v1"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "kind": "APIResourceList",
+                    "groupVersion": "v1",
+                    "resources": [{
+                        "name": "configmaps",
+                        "namespaced": true,
+                        "kind": "ConfigMap",
+                        "verbs": ["get", "patch", "update", "delete"],
+                    }],
+                })))
+                .mount(&server)
+                .await;
+            let a = "/api/v1/namespaces/default/configmaps/a";
+            let b = "/api/v1/namespaces/default/configmaps/b";
+            Mock::given(method("GET"))
+                .and(path(a))
+                .respond_with(if existing {
+                    ResponseTemplate::new(200).set_body_json(config_map("a", "1", "old"))
+                } else {
+                    not_found.clone()
+                })
+                .mount(&server)
+                .await;
+            Mock::given(method("PATCH"))
+                .and(path(a))
+                .respond_with(ResponseTemplate::new(200).set_body_json(config_map("a", "2", "new")))
+                .expect(1)
+                .mount(&server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(b))
+                .respond_with(not_found.clone())
+                .mount(&server)
+                .await;
+            Mock::given(method("PATCH"))
+                .and(path(b))
+                .respond_with(invalid.clone())
+                .expect(1)
+                .mount(&server)
+                .await;
+            // Created objects are deleted, updated ones restored
+            Mock::given(method("DELETE"))
+                .and(path(a))
+                .respond_with(ResponseTemplate::new(200).set_body_json(config_map("a", "2", "new")))
+                .expect(u64::from(!existing))
+                .mount(&server)
+                .await;
+            Mock::given(method("PUT"))
+                .and(path(a))
+                .and(body_partial_json(serde_json::json!({
+                    "data": { "greeting": "old" },
+                })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(config_map("a", "3", "old")))
+                .expect(u64::from(existing))
+                .mount(&server)
+                .await;
+            // `b` was never applied
+            Mock::given(method("DELETE"))
+                .and(path(b))
+                .respond_with(ResponseTemplate::new(200))
+                .expect(0)
+                .mount(&server)
+                .await;
+
+            let client =
+                Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+            let discovery =
+                CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+            let res = Engine::new(client)
+                .apply(&instance, &rendered, &discovery, false)
+                .await;
+            assert!(res.is_err(), "existing: {existing}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_managed_by_label() {
+        use wiremock::{
+            matchers::{body_partial_json, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let config_map = |name: &str, labels: serde_json::Value| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": name, "namespace": "default", "labels": labels },
+            })
+        };
+        let owned = serde_json::json!({ "example.com/owner": "platform" });
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "patch", "delete"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        // Apply labels the object with the configured label
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(config_map("app", owned.clone())),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .and(body_partial_json(serde_json::json!({
+                "metadata": { "labels": owned.clone() },
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(config_map("app", owned.clone())),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Cleanup only deletes the objects carrying the configured label
+        Mock::given(method("DELETE"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(config_map("app", owned.clone())),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/legacy"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(config_map(
+                "legacy",
+                serde_json::json!({ "app.kubernetes.io/managed-by": OPERATOR_MANAGER }),
+            )))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/api/v1/namespaces/default/configmaps/legacy"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let engine = Engine::new(client).with_managed_by_label(ManagedByLabel {
+            key: "example.com/owner".to_string(),
+            value: "platform".to_string(),
+        });
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+
+        let objects = [serde_json::from_value(config_map("app", serde_json::json!({}))).unwrap()];
+        engine
+            .apply(&instance, &objects, &discovery, false)
+            .await
+            .unwrap();
+
+        let gvk = GroupVersionKind::gvk("", "v1", "ConfigMap");
+        let namespace = Some("default".to_string());
+        assert!(engine
+            .delete_resource(&instance, &gvk, "app", &namespace, &discovery)
+            .await
+            .unwrap());
+        assert!(!engine
+            .delete_resource(&instance, &gvk, "legacy", &namespace, &discovery)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_prune_disabled_objects_kept() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "persistentvolumeclaims",
+                    "namespaced": true,
+                    "kind": "PersistentVolumeClaim",
+                    "verbs": ["get", "delete"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/api/v1/namespaces/default/persistentvolumeclaims/data",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "PersistentVolumeClaim",
+                "metadata": {
+                    "name": "data",
+                    "namespace": "default",
+                    "labels": { "app.kubernetes.io/managed-by": OPERATOR_MANAGER },
+                    "annotations": { PRUNE_ANNOTATION: "disabled" },
+                },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(
+                "/api/v1/namespaces/default/persistentvolumeclaims/data",
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let engine = Engine::new(client);
+        let pvc = flux_kcl_operator_crd::Gvk {
+            name: "data".to_string(),
+            group: String::new(),
+            version: "v1".to_string(),
+            kind: "PersistentVolumeClaim".to_string(),
+            namespace: Some("default".to_string()),
+            resource_version: None,
+        };
+
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+
+        // Pruned once no longer rendered
+        assert!(!engine
+            .delete_resource(
+                &instance,
+                &pvc.clone().into(),
+                &pvc.name,
+                &pvc.namespace,
+                &discovery
+            )
+            .await
+            .unwrap());
+
+        // Deleted with the instance
+        let mut status = KclInstanceStatus::default();
+        status.inventory.insert(pvc);
+        instance.status = Some(status);
+        let deleted = engine
+            .cleanup(Arc::new(instance), &discovery)
+            .await
+            .unwrap();
+        assert!(deleted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_deleted() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let config_map = |name: &str| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": {
+                    "name": name,
+                    "namespace": "default",
+                    "labels": { "app.kubernetes.io/managed-by": OPERATOR_MANAGER },
+                },
+            })
+        };
+        let not_found = ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "status": "Failure",
+            "reason": "NotFound",
+            "code": 404,
+        }));
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["get", "delete"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        for name in ["app", "stuck"] {
+            Mock::given(method("DELETE"))
+                .and(path(format!(
+                    "/api/v1/namespaces/default/configmaps/{name}"
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(config_map(name)))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        // Still terminating at the ownership check and the first two polls, then gone
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(config_map("app")))
+            .up_to_n_times(3)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/app"))
+            .respond_with(not_found)
+            .mount(&server)
+            .await;
+        // Never goes away, e.g. blocked by a finalizer
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/stuck"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(config_map("stuck")))
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let engine = Engine::new(client);
+        let instance = |name: &str| {
+            let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+                "apiVersion": "kcl.evrone.com/v1alpha1",
+                "kind": "KclInstance",
+                "metadata": { "name": "app", "namespace": "default" },
+                "spec": {
+                    "sourceRef": { "kind": "GitRepository", "name": "app" },
+                    "path": ".",
+                },
+            }))
+            .unwrap();
+            let mut status = KclInstanceStatus::default();
+            status.inventory.insert(flux_kcl_operator_crd::Gvk {
+                name: name.to_string(),
+                group: String::new(),
+                version: "v1".to_string(),
+                kind: "ConfigMap".to_string(),
+                namespace: Some("default".to_string()),
+                resource_version: None,
+            });
+            instance.status = Some(status);
+            Arc::new(instance)
+        };
+
+        let deleted = engine.cleanup(instance("app"), &discovery).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        let mut progress = Vec::new();
+        engine
+            .wait_deleted(
+                deleted,
+                Duration::from_secs(5),
+                Duration::from_millis(10),
+                |remaining| progress.push(remaining),
+            )
+            .await
+            .unwrap();
+        // The only object was gone before any progress to report
+        assert!(progress.is_empty());
+
+        let deleted = engine.cleanup(instance("stuck"), &discovery).await.unwrap();
+        let res = engine
+            .wait_deleted(
+                deleted,
+                Duration::from_millis(50),
+                Duration::from_millis(10),
+                |_| {},
+            )
+            .await;
+        match res {
+            Err(Error::DeletionTimeout { remaining, .. }) => {
+                assert_eq!(remaining, ["ConfigMap stuck"])
+            }
+            res => panic!("expected DeletionTimeout, got {res:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_sync_strategies() {
+        use wiremock::{
+            matchers::{body_partial_json, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let config_map = |name: &str, version: Option<&str>| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": name, "namespace": "default", "resourceVersion": version },
+            })
+        };
+        let not_found = ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "status": "Failure",
+            "reason": "NotFound",
+            "code": 404,
+        }));
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [{
+                    "name": "configmaps",
+                    "namespaced": true,
+                    "kind": "ConfigMap",
+                    "verbs": ["create", "get", "patch", "update"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+        for name in ["replaced", "kept"] {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/api/v1/namespaces/default/configmaps/{name}"
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(config_map(name, Some("1"))))
+                .mount(&server)
+                .await;
+        }
+        for name in ["created", "applied"] {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/api/v1/namespaces/default/configmaps/{name}"
+                )))
+                .respond_with(not_found.clone())
+                .mount(&server)
+                .await;
+        }
+        // Replaced with the current resourceVersion
+        Mock::given(method("PUT"))
+            .and(path("/api/v1/namespaces/default/configmaps/replaced"))
+            .and(body_partial_json(serde_json::json!({
+                "metadata": { "resourceVersion": "1" },
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(config_map("replaced", Some("2"))),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/namespaces/default/configmaps"))
+            .and(body_partial_json(serde_json::json!({
+                "metadata": { "name": "created" },
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(config_map("created", Some("1"))),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/configmaps/applied"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(config_map("applied", Some("1"))),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client.clone(), std::time::Duration::from_secs(60));
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        instance.spec.config.sync_strategy = SyncStrategy::CreateIfAbsent;
+        instance.spec.config.sync_strategies = serde_json::from_value(serde_json::json!([
+            { "target": { "name": "replaced" }, "strategy": "replace" },
+            { "target": { "name": "applied" }, "strategy": "apply" },
+        ]))
+        .unwrap();
+        let objects: Vec<DynamicObject> = ["replaced", "kept", "created", "applied"]
+            .into_iter()
+            .map(|name| serde_json::from_value(config_map(name, None)).unwrap())
+            .collect();
+
+        let applied = Engine::new(client)
+            .apply(&instance, &objects, &discovery, false)
+            .await
+            .unwrap();
+        assert_eq!(applied.inventory.len(), 4);
+        // The existing object of ‘create-if-absent’ is left as it is
+        assert_eq!(applied.changed, 3);
+    }
+
+    #[tokio::test]
+    async fn test_missing_flux_sources() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        // Only GitRepository is served, OCIRepository v1beta2 is unknown
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/apis/source.toolkit.fluxcd.io/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "kind": "APIResourceList",
+                "apiVersion": "v1",
+                "groupVersion": "source.toolkit.fluxcd.io/v1",
+                "resources": [{
+                    "name": "gitrepositories",
+                    "singularName": "gitrepository",
+                    "namespaced": true,
+                    "kind": "GitRepository",
+                    "verbs": ["get", "list", "watch"],
+                }],
+            })))
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let discovery = CachedDiscovery::new(client, std::time::Duration::from_secs(60));
+        let missing = missing_flux_sources(&discovery).await;
+        assert_eq!(missing, [OCIRepository::gvk(&())]);
+    }
+
+    /// Renders a ConfigMap holding the render arguments, without running KCL.
+    struct StaticRenderer;
+
+    #[async_trait::async_trait]
+    impl Renderer for StaticRenderer {
+        fn name(&self) -> &str {
+            "static"
+        }
+
+        async fn render(
+            &self,
+            _instance: &KclInstance,
+            module_dir: &Path,
+            _source_dir: &Path,
+            args: &HashMap<String, String>,
+            _cancel: &CancellationToken,
+        ) -> Result<String> {
+            if !module_dir.ends_with("app") {
+                return RenderFailedSnafu {
+                    renderer: self.name(),
+                    message: format!("unexpected module {}", module_dir.display()),
+                }
+                .fail();
+            }
+            Ok(format!(
+                "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: {}\n",
+                args["name"]
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_render_with_custom_renderer() {
+        let source_dir = std::env::temp_dir().join(format!("renderer-{}", std::process::id()));
+        std::fs::create_dir_all(source_dir.join("app")).unwrap();
+        let client =
+            Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap();
+        let engine = Engine::new(client).with_renderer(Arc::new(StaticRenderer));
+        let instance: Arc<KclInstance> = Arc::new(
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "kcl.evrone.com/v1alpha1",
+                "kind": "KclInstance",
+                "metadata": { "name": "app", "namespace": "default" },
+                "spec": {
+                    "sourceRef": { "kind": "GitRepository", "name": "app" },
+                    "path": "./app",
+                },
+            }))
+            .unwrap(),
+        );
+
+        let args = HashMap::from([("name".to_string(), "greeting".to_string())]);
+        let manifests = engine
+            .render(
+                instance,
+                &source_dir,
+                &args,
+                kcl_client::DEFAULT_OCI_TIMEOUT,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        let objects = utils::multidoc_deserialize(&manifests).unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].name_any(), "greeting");
+
+        std::fs::remove_dir_all(source_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_render_config_map_source() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        let module = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "module", "namespace": "default" },
+            "data": {
+                "kcl.mod": "[package]\nname = \"module\"\nedition = \"v0.10.0\"\nversion = \"0.0.1\"\n",
+                "main.k": "apiVersion = \"v1\"\nkind = \"ConfigMap\"\nmetadata = {name = option(\"name\")}\n",
+            },
+        });
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/module"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&module))
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let engine = Engine::new(client);
+        let instance: Arc<KclInstance> = Arc::new(
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "kcl.evrone.com/v1alpha1",
+                "kind": "KclInstance",
+                "metadata": { "name": "app", "namespace": "default" },
+                "spec": {
+                    "sourceRef": { "kind": "ConfigMap", "name": "module" },
+                    "path": ".",
+                },
+            }))
+            .unwrap(),
+        );
+
+        let mut source_ready = None;
+        let (artefact, oci_timeout) = engine
+            .get_artefact(&instance, &mut source_ready)
+            .await
+            .unwrap();
+        assert_eq!(oci_timeout, kcl_client::DEFAULT_OCI_TIMEOUT);
+        assert!(artefact.revision().starts_with("sha256:"));
+        assert!(source_ready.unwrap().ready);
+
+        // No download, the files are written to the storage dir
+        let storage_dir =
+            std::env::temp_dir().join(format!("configmap-source-{}", std::process::id()));
+        let downloader = Downloader::new(
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+            Some("http://127.0.0.1:1".to_string()),
+            Some(storage_dir.clone()),
+        );
+        let cancel = CancellationToken::new();
+        let work_dir = engine
+            .download(instance.clone(), &artefact, &downloader, &cancel)
+            .await
+            .unwrap();
+        assert!(work_dir.starts_with(storage_dir.join("default/module")));
+        assert!(work_dir.join("main.k").is_file());
+
+        let args = HashMap::from([("name".to_string(), "greeting".to_string())]);
+        let manifests = engine
+            .render(
+                instance,
+                &work_dir,
+                &args,
+                kcl_client::DEFAULT_OCI_TIMEOUT,
+                &cancel,
+            )
+            .await
+            .unwrap();
+        let objects = utils::multidoc_deserialize(&manifests).unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].name_any(), "greeting");
+
+        std::fs::remove_dir_all(storage_dir).unwrap();
+    }
+}
+
+#[cfg(all(test, feature = "integration"))]
+mod integration_tests {
+    use fluxcd_rs::{
+        testing::{temp_storage_dir, MockSourceController},
+        GitRepositoryStatusArtifact,
+    };
+    use reqwest_middleware::ClientBuilder;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_download_and_render() {
+        let source_controller = MockSourceController::start().await;
+        let url = source_controller
+            .serve_fixture("/gitrepository/default/hello/abc.tar.gz", "hello-kcl")
+            .await
+            .unwrap();
+        let storage_dir = temp_storage_dir();
+        let downloader = Downloader::new(
+            ClientBuilder::new(reqwest::Client::new()).build(),
+            None,
+            Some(storage_dir.clone()),
+        );
+
+        // The client is never used by download and render
+        let client =
+            Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap();
+        let engine = Engine::new(client);
+        let instance: Arc<KclInstance> = Arc::new(
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "kcl.evrone.com/v1alpha1",
+                "kind": "KclInstance",
+                "metadata": { "name": "hello", "namespace": "default" },
+                "spec": {
+                    "sourceRef": { "kind": "GitRepository", "name": "hello" },
+                    "path": ".",
+                },
+            }))
+            .unwrap(),
+        );
+        let artefact = FluxSourceArtefact::Git(
+            serde_json::from_value::<GitRepositoryStatusArtifact>(serde_json::json!({
+                "lastUpdateTime": "2024-01-01T00:00:00Z",
+                "path": "gitrepository/default/hello/abc.tar.gz",
+                "revision": "main@sha1:abc",
+                "url": url,
+            }))
+            .unwrap(),
+        );
+
+        let cancel = CancellationToken::new();
+        let work_dir = engine
+            .download(instance.clone(), &artefact, &downloader, &cancel)
+            .await
+            .unwrap();
+        let args = HashMap::from([("name".to_string(), "greeting".to_string())]);
+        let manifests = engine
+            .render(
+                instance,
+                &work_dir,
+                &args,
+                kcl_client::DEFAULT_OCI_TIMEOUT,
+                &cancel,
+            )
+            .await
+            .unwrap();
+
+        let objects = utils::multidoc_deserialize(&manifests).unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].types.as_ref().unwrap().kind, "ConfigMap");
+        assert_eq!(objects[0].name_any(), "greeting");
+
+        std::fs::remove_dir_all(storage_dir).unwrap();
     }
 }
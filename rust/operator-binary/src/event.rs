@@ -20,15 +20,69 @@ pub enum Error {
     PublishEvent { source: kube::Error },
 }
 
+/// Reporter of the operator's events, telling operator variants and replicas apart.
+///
+/// # Arguments:
+/// - `component`: Reporting component, defaults to the operator manager name.
+/// - `instance`: Reporting instance, usually the pod name.
+pub fn reporter(component: Option<String>, instance: Option<String>) -> Reporter {
+    Reporter {
+        controller: component.unwrap_or_else(|| crate::engine::OPERATOR_MANAGER.to_string()),
+        instance,
+    }
+}
+
+/// Publishes a warning event on `instance`, attributed to `reporter`.
 pub async fn publish_event(
     instance: Arc<KclInstance>,
     client: Client,
+    reporter: Reporter,
     action: String,
     reason: String,
     note: Option<String>,
 ) -> Result<(), Error> {
-    let reporter: Reporter = crate::engine::OPERATOR_MANAGER.into();
+    publish(
+        instance,
+        client,
+        reporter,
+        action,
+        reason,
+        note,
+        EventType::Warning,
+    )
+    .await
+}
 
+/// Publishes an informational event, for outcomes worth surfacing that are not failures.
+pub async fn publish_normal_event(
+    instance: Arc<KclInstance>,
+    client: Client,
+    reporter: Reporter,
+    action: String,
+    reason: String,
+    note: Option<String>,
+) -> Result<(), Error> {
+    publish(
+        instance,
+        client,
+        reporter,
+        action,
+        reason,
+        note,
+        EventType::Normal,
+    )
+    .await
+}
+
+async fn publish(
+    instance: Arc<KclInstance>,
+    client: Client,
+    reporter: Reporter,
+    action: String,
+    reason: String,
+    note: Option<String>,
+    type_: EventType,
+) -> Result<(), Error> {
     let object_ref = ObjectRef::from_obj(instance.as_ref());
 
     let recorder = Recorder::new(client.to_owned(), reporter, object_ref.into());
@@ -37,7 +91,7 @@ pub async fn publish_event(
             action,
             reason,
             note,
-            type_: EventType::Warning,
+            type_,
             secondary: None,
         })
         .await
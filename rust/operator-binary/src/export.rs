@@ -0,0 +1,401 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use flux_kcl_operator_crd::{KclInstance, RenderedExport, RenderedExportKind};
+use k8s_openapi::{
+    api::core::v1::{ConfigMap, Secret},
+    ByteString,
+};
+use kube::{
+    api::{ObjectMeta, Patch, PatchParams},
+    Api, Client, Resource, ResourceExt,
+};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use strum::{EnumDiscriminants, IntoStaticStr};
+
+use crate::{
+    engine::OPERATOR_MANAGER,
+    utils::{patch_labels, ManagedByLabel},
+};
+
+/// Key of the rendered output in the export.
+pub const RENDERED_KEY: &str = "rendered.yaml";
+/// Key of the encrypted rendered output in Secret exports, see `ExportKey`.
+pub const ENCRYPTED_RENDERED_KEY: &str = "rendered.yaml.enc";
+/// Annotation naming the cipher of encrypted exports.
+pub const ENCRYPTION_ANNOTATION: &str = "kcl.evrone.com/encryption";
+const ENCRYPTION: &str = "aes-256-gcm";
+
+/// Largest rendered output exported. ConfigMaps and Secrets are limited to 1 MiB in total, all
+/// keys included, so the output is not split across keys but rejected past this size.
+pub const MAX_EXPORT_SIZE: usize = 1_000_000;
+
+const NONCE_SIZE: usize = 12;
+
+#[derive(Snafu, Debug, EnumDiscriminants)]
+#[strum_discriminants(derive(IntoStaticStr))]
+pub enum Error {
+    #[snafu(display("Failed to read export encryption key {}: {}", path.display(), source))]
+    ReadKey {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "Export encryption key {} must be 32 bytes long, got {}",
+        path.display(),
+        len
+    ))]
+    InvalidKeyLength { path: PathBuf, len: usize },
+
+    #[snafu(display(
+        "Rendered output of {} bytes exceeds the export limit of {} bytes",
+        size,
+        limit
+    ))]
+    ExportTooLarge { size: usize, limit: usize },
+
+    #[snafu(display("Only Secret exports can be encrypted"))]
+    EncryptedConfigMap,
+
+    #[snafu(display("Encrypted exports require the operator --export-encryption-key-file"))]
+    MissingKey,
+
+    #[snafu(display("Failed to encrypt the rendered output"))]
+    Encrypt,
+
+    #[snafu(display("Failed to decrypt the exported output"))]
+    Decrypt,
+
+    #[snafu(display("Instance {} has no namespace", name))]
+    MissingNamespace { name: String },
+
+    #[snafu(display("Failed to write export {}: {}", name, source))]
+    WriteExport { name: String, source: kube::Error },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// AES-256-GCM key encrypting exported rendered output.
+///
+/// Encrypted exports hold the random nonce followed by the ciphertext.
+#[derive(Clone)]
+pub struct ExportKey(Aes256Gcm);
+
+impl ExportKey {
+    /// Reads a key of 32 raw bytes, e.g. generated with `head -c 32 /dev/urandom`.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let key = std::fs::read(path).context(ReadKeySnafu { path })?;
+        Aes256Gcm::new_from_slice(&key)
+            .ok()
+            .map(ExportKey)
+            .context(InvalidKeyLengthSnafu {
+                path,
+                len: key.len(),
+            })
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .ok()
+            .context(EncryptSnafu)?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Decrypts an encrypted export, e.g. for audit tooling.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        ensure!(data.len() >= NONCE_SIZE, DecryptSnafu);
+        let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+            .context(DecryptSnafu)
+    }
+}
+
+/// Records the rendered output of an instance in its export ConfigMap or Secret, owned by the
+/// instance and server-side applied with the operator's field manager.
+///
+/// # Arguments
+/// * `client` - Kubernetes client
+/// * `instance` - KclInstance the output was rendered for
+/// * `export` - The export configuration of the instance
+/// * `manifests` - The rendered output
+/// * `key` - Key of encrypted exports, see `--export-encryption-key-file`
+/// * `managed_by` - Label marking the export as managed by the operator
+pub async fn export_rendered(
+    client: &Client,
+    instance: &KclInstance,
+    export: &RenderedExport,
+    manifests: &str,
+    key: Option<&ExportKey>,
+    managed_by: &ManagedByLabel,
+) -> Result<()> {
+    let namespace = instance.namespace().context(MissingNamespaceSnafu {
+        name: instance.name_any(),
+    })?;
+    let pp = PatchParams::apply(OPERATOR_MANAGER).force();
+    match export.kind {
+        RenderedExportKind::ConfigMap => {
+            let config_map = export_config_map(instance, export, manifests, managed_by)?;
+            let name = config_map.name_any();
+            Api::<ConfigMap>::namespaced(client.clone(), &namespace)
+                .patch(&name, &pp, &Patch::Apply(&config_map))
+                .await
+                .context(WriteExportSnafu { name })?;
+        }
+        RenderedExportKind::Secret => {
+            let secret = export_secret(instance, export, manifests, key, managed_by)?;
+            let name = secret.name_any();
+            Api::<Secret>::namespaced(client.clone(), &namespace)
+                .patch(&name, &pp, &Patch::Apply(&secret))
+                .await
+                .context(WriteExportSnafu { name })?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds the ConfigMap export of the rendered output.
+fn export_config_map(
+    instance: &KclInstance,
+    export: &RenderedExport,
+    manifests: &str,
+    managed_by: &ManagedByLabel,
+) -> Result<ConfigMap> {
+    ensure!(!export.encrypt, EncryptedConfigMapSnafu);
+    check_size(manifests.len())?;
+    Ok(ConfigMap {
+        metadata: export_metadata(instance, export, None, managed_by),
+        data: Some(BTreeMap::from([(
+            RENDERED_KEY.to_string(),
+            manifests.to_string(),
+        )])),
+        ..Default::default()
+    })
+}
+
+/// Builds the Secret export of the rendered output, encrypted when the export says so.
+fn export_secret(
+    instance: &KclInstance,
+    export: &RenderedExport,
+    manifests: &str,
+    key: Option<&ExportKey>,
+    managed_by: &ManagedByLabel,
+) -> Result<Secret> {
+    let (key_name, data, encryption) = if export.encrypt {
+        let key = key.context(MissingKeySnafu)?;
+        let data = key.encrypt(manifests.as_bytes())?;
+        (ENCRYPTED_RENDERED_KEY, data, Some(ENCRYPTION))
+    } else {
+        (RENDERED_KEY, manifests.as_bytes().to_vec(), None)
+    };
+    check_size(data.len())?;
+    Ok(Secret {
+        metadata: export_metadata(instance, export, encryption, managed_by),
+        data: Some(BTreeMap::from([(key_name.to_string(), ByteString(data))])),
+        type_: Some("Opaque".to_string()),
+        ..Default::default()
+    })
+}
+
+fn check_size(size: usize) -> Result<()> {
+    ensure!(
+        size <= MAX_EXPORT_SIZE,
+        ExportTooLargeSnafu {
+            size,
+            limit: MAX_EXPORT_SIZE,
+        }
+    );
+    Ok(())
+}
+
+/// Metadata of the export of `instance`, owned by the instance so it is deleted with it.
+fn export_metadata(
+    instance: &KclInstance,
+    export: &RenderedExport,
+    encryption: Option<&str>,
+    managed_by: &ManagedByLabel,
+) -> ObjectMeta {
+    ObjectMeta {
+        name: Some(
+            export
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{}-rendered", instance.name_any())),
+        ),
+        namespace: instance.namespace(),
+        labels: patch_labels(None, managed_by),
+        annotations: encryption.map(|encryption| {
+            BTreeMap::from([(ENCRYPTION_ANNOTATION.to_string(), encryption.to_string())])
+        }),
+        owner_references: instance.controller_owner_ref(&()).map(|owner| vec![owner]),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance() -> KclInstance {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default", "uid": "1234" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap()
+    }
+
+    fn secret_export(encrypt: bool) -> RenderedExport {
+        RenderedExport {
+            kind: RenderedExportKind::Secret,
+            name: None,
+            encrypt,
+        }
+    }
+
+    #[test]
+    fn test_export_secret_encrypted() {
+        let key = ExportKey(Aes256Gcm::new_from_slice(&[7; 32]).unwrap());
+        let manifests = "apiVersion: v1\nkind: ConfigMap\n";
+
+        let secret = export_secret(
+            &instance(),
+            &secret_export(true),
+            manifests,
+            Some(&key),
+            &ManagedByLabel::default(),
+        )
+        .unwrap();
+        assert_eq!(secret.name_any(), "app-rendered");
+        assert_eq!(
+            secret
+                .labels()
+                .get("app.kubernetes.io/managed-by")
+                .map(String::as_str),
+            Some(OPERATOR_MANAGER)
+        );
+        assert_eq!(
+            secret
+                .annotations()
+                .get(ENCRYPTION_ANNOTATION)
+                .map(String::as_str),
+            Some(ENCRYPTION)
+        );
+        assert_eq!(secret.owner_references()[0].name, "app");
+        let data = &secret.data.unwrap()[ENCRYPTED_RENDERED_KEY];
+        assert_ne!(data.0, manifests.as_bytes());
+        assert_eq!(key.decrypt(&data.0).unwrap(), manifests.as_bytes());
+
+        // Encrypted exports need a key, and a Secret
+        assert!(matches!(
+            export_secret(
+                &instance(),
+                &secret_export(true),
+                manifests,
+                None,
+                &ManagedByLabel::default()
+            ),
+            Err(Error::MissingKey)
+        ));
+        let config_map_export = RenderedExport {
+            encrypt: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            export_config_map(
+                &instance(),
+                &config_map_export,
+                manifests,
+                &ManagedByLabel::default()
+            ),
+            Err(Error::EncryptedConfigMap)
+        ));
+    }
+
+    #[test]
+    fn test_export_too_large() {
+        let manifests = "#".repeat(MAX_EXPORT_SIZE + 1);
+        assert!(matches!(
+            export_secret(
+                &instance(),
+                &secret_export(false),
+                &manifests,
+                None,
+                &ManagedByLabel::default()
+            ),
+            Err(Error::ExportTooLarge { .. })
+        ));
+        assert!(matches!(
+            export_config_map(
+                &instance(),
+                &RenderedExport::default(),
+                &manifests,
+                &ManagedByLabel::default()
+            ),
+            Err(Error::ExportTooLarge { .. })
+        ));
+        assert!(export_config_map(
+            &instance(),
+            &RenderedExport::default(),
+            "#",
+            &ManagedByLabel::default()
+        )
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_export_rendered_secret() {
+        use wiremock::{
+            matchers::{body_partial_json, method, path, query_param},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/v1/namespaces/default/secrets/audit"))
+            .and(query_param("fieldManager", OPERATOR_MANAGER))
+            .and(body_partial_json(serde_json::json!({
+                "kind": "Secret",
+                "data": { "rendered.yaml": "a2luZDogQ29uZmlnTWFw" },
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": { "name": "audit", "namespace": "default" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let export = RenderedExport {
+            name: Some("audit".to_string()),
+            ..secret_export(false)
+        };
+        export_rendered(
+            &client,
+            &instance(),
+            &export,
+            "kind: ConfigMap",
+            None,
+            &ManagedByLabel::default(),
+        )
+        .await
+        .unwrap();
+    }
+}
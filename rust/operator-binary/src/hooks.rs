@@ -0,0 +1,138 @@
+use async_trait::async_trait;
+use flux_kcl_operator_crd::KclInstance;
+use kube::{api::DynamicObject, ResourceExt};
+use snafu::Snafu;
+use strum::{EnumDiscriminants, IntoStaticStr};
+
+use crate::utils;
+
+/// Maximum length of an object name.
+const MAX_NAME_LENGTH: usize = 253;
+
+#[derive(Snafu, Debug, EnumDiscriminants)]
+#[strum_discriminants(derive(IntoStaticStr))]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[snafu(display("{} rejected the manifests: {}", hook, message))]
+    Rejected { hook: String, message: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Extension point of the apply pipeline, e.g. to gate the rendered manifests on
+/// policies (OPA, kubeconform) before they reach the cluster.
+///
+/// Hooks are registered with `Engine::with_hook` and run in registration order. A failing
+/// `pre_apply` fails the reconciliation before anything is applied.
+#[async_trait]
+pub trait ApplyHook: Send + Sync {
+    /// Name of the hook, used in errors and logs.
+    fn name(&self) -> &str;
+
+    /// Runs on the rendered objects before they are applied, in batches for large renders,
+    /// all of them before the first object is applied.
+    async fn pre_apply(&self, _instance: &KclInstance, _objects: &[DynamicObject]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs on the objects returned by the API server after they were applied, batch by batch
+    /// for large renders.
+    async fn post_apply(&self, _instance: &KclInstance, _applied: &[DynamicObject]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Built-in pre-apply hook checking that the rendered objects are well-formed Kubernetes
+/// objects: type metadata, name, namespace, label and annotation formats.
+pub struct SchemaValidator;
+
+pub const SCHEMA_VALIDATOR: &str = "schema-validator";
+
+#[async_trait]
+impl ApplyHook for SchemaValidator {
+    fn name(&self) -> &str {
+        SCHEMA_VALIDATOR
+    }
+
+    async fn pre_apply(&self, _instance: &KclInstance, objects: &[DynamicObject]) -> Result<()> {
+        let problems: Vec<String> = objects.iter().flat_map(validate_object).collect();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        RejectedSnafu {
+            hook: SCHEMA_VALIDATOR,
+            message: problems.join("; "),
+        }
+        .fail()
+    }
+}
+
+/// Returns the problems of a single object.
+fn validate_object(obj: &DynamicObject) -> Vec<String> {
+    let name = obj.name_any();
+    let mut problems = vec![];
+    match &obj.types {
+        Some(types) if !types.api_version.is_empty() && !types.kind.is_empty() => {}
+        _ => problems.push(format!("{name}: apiVersion and kind are required")),
+    }
+    if name.is_empty() {
+        problems.push("object without metadata.name".to_string());
+    } else if name.len() > MAX_NAME_LENGTH {
+        problems.push(format!("{name}: name is longer than {MAX_NAME_LENGTH}"));
+    }
+    if let Some(namespace) = &obj.metadata.namespace {
+        if !utils::is_valid_dns_label(namespace) {
+            problems.push(format!("{name}: invalid namespace {namespace:?}"));
+        }
+    }
+    for (key, value) in obj.labels() {
+        if !utils::is_valid_label_key(key) || !utils::is_valid_label_value(value) {
+            problems.push(format!("{name}: invalid label {key}={value:?}"));
+        }
+    }
+    for key in obj.annotations().keys() {
+        if !utils::is_valid_label_key(key) {
+            problems.push(format!("{name}: invalid annotation key {key}"));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(value: serde_json::Value) -> DynamicObject {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_validate_object_valid() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {
+                "name": "web",
+                "namespace": "team-a",
+                "labels": { "app.kubernetes.io/name": "web" },
+                "annotations": { "example.com/owner": "platform team" },
+            },
+        }));
+        assert!(validate_object(&obj).is_empty());
+    }
+
+    #[test]
+    fn test_validate_object_problems() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {
+                "name": "cfg",
+                "namespace": "Team_A",
+                "labels": { "tier": "front end" },
+            },
+        }));
+        let problems = validate_object(&obj);
+        assert_eq!(problems.len(), 2, "{problems:?}");
+    }
+}
@@ -1,11 +1,17 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Component, Path, PathBuf},
+};
 
 use async_trait::async_trait;
-use flux_kcl_operator_crd::{ArgumentsReferenceKind, KclInstance};
+use flux_kcl_operator_crd::{
+    ArgSourceState, ArgSourceStatus, ArgumentsReference, ArgumentsReferenceKind, KclInstance,
+    ARTIFACT_METADATA_ARGUMENT_PREFIX,
+};
 use k8s_openapi::api::core::v1::{ConfigMap, Secret};
-use kube::{Api, Client};
+use kube::{Api, Client, ResourceExt};
 
-use snafu::Snafu;
+use snafu::{OptionExt, ResultExt, Snafu};
 use strum::{EnumDiscriminants, IntoStaticStr};
 
 #[derive(Snafu, Debug, EnumDiscriminants)]
@@ -14,16 +20,69 @@ use strum::{EnumDiscriminants, IntoStaticStr};
 pub enum Error {
     #[snafu(display("Failed to get arguments from reference {}", name))]
     MissingArguments { name: String },
+
+    #[snafu(display("Failed to get arguments reference {}: {}", name, source))]
+    GetArgumentsReference { name: String, source: kube::Error },
+
+    #[snafu(display("Arguments reference {} has no key {}", name, key))]
+    MissingArgumentsKey { name: String, key: String },
+
+    #[snafu(display("Key {} of arguments reference {} is not valid UTF-8", key, name))]
+    NonUtf8Arguments { name: String, key: String },
+
+    #[snafu(display(
+        "Key {} of arguments reference {} is not valid YAML: {}",
+        key,
+        name,
+        source
+    ))]
+    InvalidArgumentsValue {
+        name: String,
+        key: String,
+        source: serde_yaml::Error,
+    },
+
+    #[snafu(display("Failed to serialize argument: {}", source))]
+    SerializeArgument { source: serde_json::Error },
+
+    #[snafu(display("Values file {} must be a relative path inside the source", path))]
+    InvalidValuesFilePath { path: String },
+
+    #[snafu(display("Failed to read values file {}: {}", path.display(), source))]
+    ReadValuesFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Values file {} is not a valid YAML mapping: {}", path.display(), source))]
+    InvalidValuesFile {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+
+    #[snafu(display("Invalid namespace template {}: {}", template, reason))]
+    InvalidNamespaceTemplate { template: String, reason: String },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[async_trait]
 pub trait InstanceExt {
+    /// Collects the arguments of the instance and of its `argumentsFrom` references.
+    ///
+    /// # Arguments
+    /// * `client` - Kubernetes client
+    /// * `namespace` - Namespace of the references
+    /// * `statuses` - Receives the resolution status of every reference, also when one of
+    ///   them fails
+    ///
+    /// # Returns
+    /// The arguments, or the error of the first reference that failed to resolve
     async fn get_all_args(
         &self,
         client: &Client,
         namespace: &str,
+        statuses: &mut Vec<ArgSourceStatus>,
     ) -> Result<HashMap<String, String>>;
 }
 
@@ -33,44 +92,565 @@ impl InstanceExt for KclInstance {
         &self,
         client: &Client,
         namespace: &str,
+        statuses: &mut Vec<ArgSourceStatus>,
     ) -> Result<HashMap<String, String>> {
         let mut args: HashMap<String, String> = self.spec.config.arguments.clone();
+        let mut first_error = None;
         for arg_ref in self.spec.config.arguments_from.iter() {
-            let map = match arg_ref.kind {
-                ArgumentsReferenceKind::Secret => {
-                    Api::<Secret>::namespaced(client.clone(), namespace)
-                        .get(&arg_ref.name)
-                        .await
-                        .map(|i| {
-                            let mut data = BTreeMap::new();
-                            data.extend(i.string_data.unwrap_or_default());
-
-                            data.extend(
-                                i.data
-                                    .unwrap_or_default()
-                                    .into_iter()
-                                    .map(|(k, v)| (k, String::from_utf8_lossy(&v.0).to_string())),
-                            );
-                            data
-                        })
+            match resolve_reference(client, namespace, arg_ref).await {
+                Ok(Some(ref_args)) => {
+                    args.extend(ref_args);
+                    statuses.push(ArgSourceStatus::new(
+                        arg_ref,
+                        ArgSourceState::Resolved,
+                        None,
+                    ));
                 }
-                ArgumentsReferenceKind::ConfigMap => {
-                    Api::<ConfigMap>::namespaced(client.clone(), namespace)
-                        .get(&arg_ref.name)
-                        .await
-                        .map(|i| i.data.unwrap_or_default())
+                Ok(None) => statuses.push(ArgSourceStatus::new(
+                    arg_ref,
+                    ArgSourceState::OptionalMissing,
+                    None,
+                )),
+                Err(e) => {
+                    statuses.push(ArgSourceStatus::new(
+                        arg_ref,
+                        ArgSourceState::Error,
+                        Some(e.to_string()),
+                    ));
+                    first_error.get_or_insert(e);
                 }
-            };
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(args),
+        }
+    }
+}
 
-            if let Ok(map) = map {
-                args.extend(map);
-            } else if !arg_ref.optional {
-                return MissingArgumentsSnafu {
+/// Resolves the arguments of an ArgumentsReference.
+///
+/// # Returns
+/// The arguments, `None` when the referent is optional and missing
+async fn resolve_reference(
+    client: &Client,
+    namespace: &str,
+    arg_ref: &ArgumentsReference,
+) -> Result<Option<HashMap<String, String>>> {
+    let data = match arg_ref.kind {
+        ArgumentsReferenceKind::Secret => Api::<Secret>::namespaced(client.clone(), namespace)
+            .get_opt(&arg_ref.name)
+            .await
+            .context(GetArgumentsReferenceSnafu {
+                name: &arg_ref.name,
+            })?
+            .map(|secret| secret_data(&arg_ref.name, secret))
+            .transpose()?,
+        ArgumentsReferenceKind::ConfigMap => {
+            Api::<ConfigMap>::namespaced(client.clone(), namespace)
+                .get_opt(&arg_ref.name)
+                .await
+                .context(GetArgumentsReferenceSnafu {
                     name: &arg_ref.name,
-                }
-                .fail();
-            }
+                })?
+                .map(|config_map| config_map.data.unwrap_or_default())
+        }
+    };
+
+    match data {
+        Some(data) => resolve_arguments(arg_ref, data).map(Some),
+        None if arg_ref.optional => Ok(None),
+        None => MissingArgumentsSnafu {
+            name: &arg_ref.name,
+        }
+        .fail(),
+    }
+}
+
+/// Adds the selected source artifact metadata to `args`, without overriding explicit arguments.
+///
+/// # Arguments
+/// * `args` - Arguments collected by `get_all_args`
+/// * `keys` - Metadata keys selected by `artifactMetadata`
+/// * `metadata` - Metadata of the source artifact
+pub(crate) fn add_artifact_metadata_args(
+    args: &mut HashMap<String, String>,
+    keys: &[String],
+    metadata: &BTreeMap<String, String>,
+) {
+    for key in keys {
+        if let Some(value) = metadata.get(key) {
+            args.entry(format!("{ARTIFACT_METADATA_ARGUMENT_PREFIX}{key}"))
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// Reads the arguments of the `valuesFiles` of an instance, later files overriding earlier ones.
+///
+/// # Arguments
+/// * `module_dir` - Directory of the module in the extracted source, the files are relative to it
+/// * `files` - Values files listed by `valuesFiles`
+pub(crate) fn read_values_files(
+    module_dir: &Path,
+    files: &[String],
+) -> Result<HashMap<String, String>> {
+    let mut args = HashMap::new();
+    for file in files {
+        let relative = Path::new(file);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return InvalidValuesFilePathSnafu { path: file }.fail();
         }
-        return Ok(args);
+        args.extend(read_values_file(&module_dir.join(relative))?);
+    }
+    Ok(args)
+}
+
+/// Reads the arguments of a YAML values file, a mapping of argument names to values.
+pub fn read_values_file(path: &Path) -> Result<HashMap<String, String>> {
+    let content = std::fs::read_to_string(path).context(ReadValuesFileSnafu { path })?;
+    let values: Option<serde_yaml::Mapping> =
+        serde_yaml::from_str(&content).context(InvalidValuesFileSnafu { path })?;
+    values
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, value)| Ok((argument_value(key)?, argument_value(value)?)))
+        .collect()
+}
+
+/// Merges the arguments of a render, by increasing precedence: the operator defaults, the
+/// values files, and the arguments of the instance.
+pub(crate) fn merge_args(
+    defaults: &HashMap<String, String>,
+    values: HashMap<String, String>,
+    instance: HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut args = defaults.clone();
+    args.extend(values);
+    args.extend(instance);
+    args
+}
+
+/// Expands the namespace template of an instance, e.g. `tenant-{name}`.
+///
+/// `{name}` and `{namespace}` expand to the name and namespace of the instance, and
+/// `{labels.<key>}` to the value of its label `<key>`.
+///
+/// # Returns
+/// The namespace, or an error on unknown placeholders, missing labels, or when the result is
+/// not a valid namespace name (RFC 1123 label).
+pub fn expand_namespace_template(template: &str, instance: &KclInstance) -> Result<String> {
+    let invalid = |reason: String| InvalidNamespaceTemplateSnafu { template, reason };
+    let mut namespace = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        namespace.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .context(invalid("unclosed placeholder".to_string()))?;
+        let placeholder = &rest[start + 1..start + end];
+        let value = match placeholder {
+            "name" => instance.name_any(),
+            "namespace" => instance.namespace().unwrap_or_default(),
+            _ => match placeholder.strip_prefix("labels.") {
+                Some(key) => instance
+                    .labels()
+                    .get(key)
+                    .cloned()
+                    .context(invalid(format!("the instance has no label {key}")))?,
+                None => return invalid(format!("unknown placeholder {{{placeholder}}}")).fail(),
+            },
+        };
+        namespace.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    namespace.push_str(rest);
+
+    if !is_dns_label(&namespace) {
+        return invalid(format!("{namespace:?} is not a valid namespace name")).fail();
+    }
+    Ok(namespace)
+}
+
+/// Whether `name` is a valid RFC 1123 label, as namespace names must be.
+fn is_dns_label(name: &str) -> bool {
+    let alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    (1..=63).contains(&name.len())
+        && name.chars().all(|c| alphanumeric(c) || c == '-')
+        && name.starts_with(alphanumeric)
+        && name.ends_with(alphanumeric)
+}
+
+/// Collects the data of a Secret, failing on values that are not valid UTF-8.
+fn secret_data(name: &str, secret: Secret) -> Result<BTreeMap<String, String>> {
+    let mut data = BTreeMap::new();
+    data.extend(secret.string_data.unwrap_or_default());
+    for (key, value) in secret.data.unwrap_or_default() {
+        let value = String::from_utf8(value.0)
+            .ok()
+            .context(NonUtf8ArgumentsSnafu { name, key: &key })?;
+        data.insert(key, value);
+    }
+    Ok(data)
+}
+
+/// Turns the data of an arguments reference into KCL arguments.
+///
+/// - Without `argumentsKey`, every key of the data becomes an argument.
+/// - With `argumentsKey` and `targetPath`, the value is passed as-is as the `targetPath` argument.
+/// - With `argumentsKey` only, a YAML/JSON mapping value is flattened into one argument per
+///   top-level key (nested values are passed as JSON), and a scalar value is passed as the
+///   `argumentsKey` argument.
+pub(crate) fn resolve_arguments(
+    arg_ref: &ArgumentsReference,
+    mut data: BTreeMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let Some(key) = &arg_ref.arguments_key else {
+        return Ok(data.into_iter().collect());
+    };
+    let value = data.remove(key).context(MissingArgumentsKeySnafu {
+        name: &arg_ref.name,
+        key,
+    })?;
+
+    if let Some(target_path) = &arg_ref.target_path {
+        return Ok(HashMap::from([(target_path.clone(), value)]));
+    }
+
+    let parsed: serde_yaml::Value =
+        serde_yaml::from_str(&value).context(InvalidArgumentsValueSnafu {
+            name: &arg_ref.name,
+            key,
+        })?;
+    match parsed {
+        serde_yaml::Value::Mapping(mapping) => mapping
+            .into_iter()
+            .map(|(k, v)| Ok((argument_value(k)?, argument_value(v)?)))
+            .collect(),
+        _ => Ok(HashMap::from([(key.clone(), value)])),
+    }
+}
+
+/// Renders a YAML value as a KCL argument value: scalars as plain strings, structures as JSON.
+fn argument_value(value: serde_yaml::Value) -> Result<String> {
+    Ok(match value {
+        serde_yaml::Value::String(s) => s,
+        serde_yaml::Value::Bool(b) => b.to_string(),
+        serde_yaml::Value::Number(n) => n.to_string(),
+        serde_yaml::Value::Null => String::new(),
+        value => serde_json::to_string(&value).context(SerializeArgumentSnafu)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::ByteString;
+
+    use super::*;
+
+    fn reference(arguments_key: Option<&str>, target_path: Option<&str>) -> ArgumentsReference {
+        ArgumentsReference {
+            name: "values".to_string(),
+            kind: ArgumentsReferenceKind::ConfigMap,
+            arguments_key: arguments_key.map(str::to_string),
+            target_path: target_path.map(str::to_string),
+            optional: false,
+        }
+    }
+
+    fn data(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_arguments_flat() -> Result<()> {
+        let args = resolve_arguments(
+            &reference(None, None),
+            data(&[("env", "dev"), ("replicas", "3")]),
+        )?;
+        assert_eq!(args.len(), 2);
+        assert_eq!(args["env"], "dev");
+        assert_eq!(args["replicas"], "3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_arguments_structured_flattened() -> Result<()> {
+        let yaml = "env: prod\nreplicas: 3\ndebug: false\nlabels:\n  team: platform\n";
+        let args = resolve_arguments(
+            &reference(Some("arguments.yaml"), None),
+            data(&[("arguments.yaml", yaml), ("other", "ignored")]),
+        )?;
+        assert_eq!(args.len(), 4);
+        assert_eq!(args["env"], "prod");
+        assert_eq!(args["replicas"], "3");
+        assert_eq!(args["debug"], "false");
+        assert_eq!(args["labels"], r#"{"team":"platform"}"#);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_arguments_json_flattened() -> Result<()> {
+        let args = resolve_arguments(
+            &reference(Some("values.json"), None),
+            data(&[("values.json", r#"{"env": "prod", "ports": [80, 443]}"#)]),
+        )?;
+        assert_eq!(args["env"], "prod");
+        assert_eq!(args["ports"], "[80,443]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_arguments_scalar_at_target_path() -> Result<()> {
+        let args = resolve_arguments(
+            &reference(Some("image"), Some("app.image")),
+            data(&[("image", "nginx:1.27")]),
+        )?;
+        assert_eq!(
+            args,
+            HashMap::from([("app.image".into(), "nginx:1.27".into())])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_arguments_scalar_without_target_path() -> Result<()> {
+        let args = resolve_arguments(&reference(Some("env"), None), data(&[("env", "dev")]))?;
+        assert_eq!(args, HashMap::from([("env".into(), "dev".into())]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_arguments_missing_key() {
+        let res = resolve_arguments(&reference(Some("missing"), None), data(&[]));
+        assert!(matches!(res, Err(Error::MissingArgumentsKey { .. })));
+    }
+
+    #[test]
+    fn test_add_artifact_metadata_args() {
+        let metadata = data(&[
+            ("org.opencontainers.image.revision", "main@sha1:abc"),
+            (
+                "org.opencontainers.image.source",
+                "https://example.com/repo",
+            ),
+        ]);
+        let mut args = HashMap::from([(
+            "artifact.org.opencontainers.image.source".to_string(),
+            "explicit".to_string(),
+        )]);
+        add_artifact_metadata_args(
+            &mut args,
+            &[
+                "org.opencontainers.image.revision".to_string(),
+                "org.opencontainers.image.source".to_string(),
+                "missing".to_string(),
+            ],
+            &metadata,
+        );
+        assert_eq!(args.len(), 2);
+        assert_eq!(
+            args["artifact.org.opencontainers.image.revision"],
+            "main@sha1:abc"
+        );
+        assert_eq!(args["artifact.org.opencontainers.image.source"], "explicit");
+    }
+
+    #[test]
+    fn test_read_values_files_precedence() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("values-files-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("envs")).unwrap();
+        std::fs::write(
+            dir.join("values.yaml"),
+            "env: dev\nreplicas: 1\nlabels:\n  team: platform\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("envs/prod.yaml"), "env: prod\nreplicas: 3\n").unwrap();
+
+        let args = read_values_files(
+            &dir,
+            &["values.yaml".to_string(), "envs/prod.yaml".to_string()],
+        )?;
+        assert_eq!(args.len(), 3);
+        assert_eq!(args["env"], "prod");
+        assert_eq!(args["replicas"], "3");
+        assert_eq!(args["labels"], r#"{"team":"platform"}"#);
+
+        let res = read_values_files(&dir, &["missing.yaml".to_string()]);
+        assert!(matches!(res, Err(Error::ReadValuesFile { .. })));
+        let res = read_values_files(&dir, &["../values.yaml".to_string()]);
+        assert!(matches!(res, Err(Error::InvalidValuesFilePath { .. })));
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_data_rejects_binary() {
+        let secret = Secret {
+            data: Some(BTreeMap::from([(
+                "cert".to_string(),
+                ByteString(vec![0xff, 0xfe, 0x00]),
+            )])),
+            ..Default::default()
+        };
+        let res = secret_data("values", secret);
+        assert!(matches!(res, Err(Error::NonUtf8Arguments { .. })));
+    }
+
+    #[test]
+    fn test_merge_args_precedence() {
+        let defaults = HashMap::from([
+            ("cluster".to_string(), "eu-1".to_string()),
+            ("region".to_string(), "eu-west-1".to_string()),
+            ("env".to_string(), "dev".to_string()),
+        ]);
+        let values = HashMap::from([
+            ("env".to_string(), "staging".to_string()),
+            ("region".to_string(), "eu-central-1".to_string()),
+        ]);
+        let instance = HashMap::from([("env".to_string(), "prod".to_string())]);
+
+        let args = merge_args(&defaults, values, instance);
+        assert_eq!(args.len(), 3);
+        assert_eq!(args["cluster"], "eu-1");
+        assert_eq!(args["region"], "eu-central-1");
+        assert_eq!(args["env"], "prod");
+    }
+
+    #[test]
+    fn test_expand_namespace_template() -> Result<()> {
+        let instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": {
+                "name": "acme",
+                "namespace": "tenants",
+                "labels": { "team": "platform", "env": "Prod" },
+            },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            expand_namespace_template("tenant-{name}", &instance)?,
+            "tenant-acme"
+        );
+        assert_eq!(
+            expand_namespace_template("{namespace}-{labels.team}", &instance)?,
+            "tenants-platform"
+        );
+        assert_eq!(expand_namespace_template("shared", &instance)?, "shared");
+
+        for template in [
+            "tenant-{owner}",
+            "tenant-{labels.missing}",
+            "tenant-{name",
+            // Not valid namespace names
+            "{labels.env}",
+            "{name}-",
+            "tenant_{name}",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    expand_namespace_template(template, &instance),
+                    Err(Error::InvalidNamespaceTemplate { .. })
+                ),
+                "{template}"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_all_args_statuses() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/values"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": "values", "namespace": "default" },
+                "data": { "env": "dev" },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Status",
+                "status": "Failure",
+                "reason": "NotFound",
+                "code": 404,
+            })))
+            .mount(&server)
+            .await;
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+
+        let mut instance: KclInstance = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": "app", "namespace": "default" },
+            "spec": {
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+                "path": ".",
+            },
+        }))
+        .unwrap();
+        let named = |name: &str, optional: bool| ArgumentsReference {
+            name: name.to_string(),
+            optional,
+            ..reference(None, None)
+        };
+
+        // Resolved and optional missing references
+        instance.spec.config.arguments_from = vec![named("values", false), named("extra", true)];
+        let mut statuses = Vec::new();
+        let args = instance
+            .get_all_args(&client, "default", &mut statuses)
+            .await
+            .unwrap();
+        assert_eq!(
+            args,
+            HashMap::from([("env".to_string(), "dev".to_string())])
+        );
+        assert_eq!(
+            statuses,
+            vec![
+                ArgSourceStatus::new(&named("values", false), ArgSourceState::Resolved, None),
+                ArgSourceStatus::new(&named("extra", true), ArgSourceState::OptionalMissing, None),
+            ]
+        );
+
+        // A required missing reference fails, the others still get a status
+        instance.spec.config.arguments_from = vec![named("missing", false), named("values", false)];
+        let mut statuses = Vec::new();
+        let res = instance
+            .get_all_args(&client, "default", &mut statuses)
+            .await;
+        assert!(matches!(res, Err(Error::MissingArguments { .. })));
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].name, "missing");
+        assert_eq!(statuses[0].state, ArgSourceState::Error);
+        assert_eq!(
+            statuses[0].message.as_deref(),
+            Some("Failed to get arguments from reference missing")
+        );
+        assert_eq!(statuses[1].state, ArgSourceState::Resolved);
     }
 }
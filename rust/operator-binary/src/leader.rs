@@ -0,0 +1,223 @@
+use std::{future::Future, time::Duration};
+
+use k8s_openapi::{
+    api::coordination::v1::{Lease, LeaseSpec},
+    apimachinery::pkg::apis::meta::v1::MicroTime,
+    chrono::{self, Utc},
+};
+use kube::{
+    api::{ObjectMeta, PostParams},
+    Api, Client,
+};
+use snafu::{ResultExt, Snafu};
+use strum::{EnumDiscriminants, IntoStaticStr};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+pub const DEFAULT_LEASE_NAME: &str = "flux-kcl-operator-leader";
+
+/// How long a lease is valid without being renewed.
+const LEASE_DURATION: Duration = Duration::from_secs(15);
+/// How often the leader renews the lease.
+const RENEW_INTERVAL: Duration = Duration::from_secs(5);
+/// How often standbys try to acquire the lease.
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Snafu, Debug, EnumDiscriminants)]
+#[strum_discriminants(derive(IntoStaticStr))]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[snafu(display("Failed to get lease {}: {}", name, source))]
+    GetLease { name: String, source: kube::Error },
+
+    #[snafu(display("Failed to update lease {}: {}", name, source))]
+    UpdateLease { name: String, source: kube::Error },
+
+    #[snafu(display("Lost leadership of lease {}", name))]
+    LeadershipLost { name: String },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Lease based leader election, so that only one operator replica runs the controllers.
+pub struct LeaderElection {
+    api: Api<Lease>,
+    lease_name: String,
+    identity: String,
+}
+
+impl LeaderElection {
+    /// Constructs a new leader election for the given lease.
+    ///
+    /// # Arguments:
+    /// - `client`: Kubernetes client used to manage the lease.
+    /// - `namespace`: Namespace the lease lives in, usually the operator's namespace.
+    /// - `lease_name`: Name of the `Lease` object shared by all replicas.
+    /// - `identity`: Unique identity of this replica, e.g. the pod name.
+    pub fn new(client: Client, namespace: &str, lease_name: &str, identity: &str) -> Self {
+        Self {
+            api: Api::namespaced(client, namespace),
+            lease_name: lease_name.to_string(),
+            identity: identity.to_string(),
+        }
+    }
+
+    /// Waits until this replica becomes the leader, then runs `task` while renewing the lease.
+    ///
+    /// The lease is released when `task` completes. If the lease cannot be renewed within the
+    /// lease duration, `task` is dropped and `LeadershipLost` is returned, so the replica can
+    /// exit and let a standby take over.
+    pub async fn run<F>(&self, task: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        info!(
+            "Waiting to acquire lease {} as {}",
+            self.lease_name, self.identity
+        );
+        while !self.try_acquire_or_renew().await? {
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+        info!("Acquired lease {}, starting controllers", self.lease_name);
+
+        tokio::select! {
+            _ = task => {
+                info!("Controllers stopped, releasing lease {}", self.lease_name);
+                self.release().await
+            }
+            res = self.keep_renewing() => res,
+        }
+    }
+
+    /// Renews the lease until it is lost or could not be renewed within the lease duration.
+    async fn keep_renewing(&self) -> Result<()> {
+        let mut last_renew = Instant::now();
+        loop {
+            tokio::time::sleep(RENEW_INTERVAL).await;
+            match self.try_acquire_or_renew().await {
+                Ok(true) => last_renew = Instant::now(),
+                Ok(false) => {
+                    return LeadershipLostSnafu {
+                        name: &self.lease_name,
+                    }
+                    .fail()
+                }
+                Err(e) if last_renew.elapsed() < LEASE_DURATION => {
+                    warn!("Failed to renew lease {}: {}", self.lease_name, e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Tries to acquire the lease or renew it if already held.
+    ///
+    /// # Returns
+    /// `true` when this replica holds the lease after the call.
+    pub async fn try_acquire_or_renew(&self) -> Result<bool> {
+        let now = Utc::now();
+        let lease = self
+            .api
+            .get_opt(&self.lease_name)
+            .await
+            .context(GetLeaseSnafu {
+                name: &self.lease_name,
+            })?;
+
+        let Some(mut lease) = lease else {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(self.lease_name.clone()),
+                    ..Default::default()
+                },
+                spec: Some(LeaseSpec {
+                    holder_identity: Some(self.identity.clone()),
+                    lease_duration_seconds: Some(LEASE_DURATION.as_secs() as i32),
+                    acquire_time: Some(MicroTime(now)),
+                    renew_time: Some(MicroTime(now)),
+                    lease_transitions: Some(0),
+                    ..Default::default()
+                }),
+            };
+            return self.ignore_conflict(self.api.create(&PostParams::default(), &lease).await);
+        };
+
+        let mut spec = lease.spec.take().unwrap_or_default();
+        let held_by_us = spec.holder_identity.as_deref() == Some(self.identity.as_str());
+        if !held_by_us && !is_lease_expired(&spec, now) {
+            return Ok(false);
+        }
+
+        if !held_by_us {
+            info!(
+                "Taking over lease {} from {:?}",
+                self.lease_name, spec.holder_identity
+            );
+            spec.holder_identity = Some(self.identity.clone());
+            spec.acquire_time = Some(MicroTime(now));
+            spec.lease_transitions = Some(spec.lease_transitions.unwrap_or(0) + 1);
+        }
+        spec.lease_duration_seconds = Some(LEASE_DURATION.as_secs() as i32);
+        spec.renew_time = Some(MicroTime(now));
+        lease.spec = Some(spec);
+
+        // The resourceVersion from the read guards against concurrent takeovers
+        self.ignore_conflict(
+            self.api
+                .replace(&self.lease_name, &PostParams::default(), &lease)
+                .await,
+        )
+    }
+
+    /// Releases the lease if held by this replica, so standbys can take over immediately.
+    pub async fn release(&self) -> Result<()> {
+        let Some(mut lease) = self
+            .api
+            .get_opt(&self.lease_name)
+            .await
+            .context(GetLeaseSnafu {
+                name: &self.lease_name,
+            })?
+        else {
+            return Ok(());
+        };
+
+        let mut spec = lease.spec.take().unwrap_or_default();
+        if spec.holder_identity.as_deref() != Some(self.identity.as_str()) {
+            return Ok(());
+        }
+        spec.holder_identity = None;
+        spec.renew_time = None;
+        lease.spec = Some(spec);
+
+        self.ignore_conflict(
+            self.api
+                .replace(&self.lease_name, &PostParams::default(), &lease)
+                .await,
+        )
+        .map(|_| ())
+    }
+
+    /// Maps a write result to whether it succeeded, treating conflicts as a lost race.
+    fn ignore_conflict(&self, result: Result<Lease, kube::Error>) -> Result<bool> {
+        match result {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(source) => Err(Error::UpdateLease {
+                name: self.lease_name.clone(),
+                source,
+            }),
+        }
+    }
+}
+
+fn is_lease_expired(spec: &LeaseSpec, now: chrono::DateTime<Utc>) -> bool {
+    let Some(renew_time) = &spec.renew_time else {
+        return true;
+    };
+    let duration = spec
+        .lease_duration_seconds
+        .map(i64::from)
+        .unwrap_or(LEASE_DURATION.as_secs() as i64);
+    renew_time.0 + chrono::Duration::seconds(duration) < now
+}
@@ -1,6 +1,20 @@
+pub mod admission;
 pub mod controller;
 pub mod engine;
 pub mod event;
+pub mod export;
 pub mod finalizer;
+pub mod hooks;
 pub mod instance_ext;
+pub mod leader;
+pub mod metrics;
+pub mod preflight;
+pub mod protection;
+pub mod rbac;
+pub mod render;
+pub mod staleness;
+#[cfg(feature = "otel")]
+pub mod telemetry;
 pub(crate) mod utils;
+
+pub use utils::{CachedDiscovery, ManagedByLabel};
@@ -1,17 +1,36 @@
-use std::{env, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    sync::Arc,
+};
 
 use clap::{Parser, Subcommand};
-use flux_kcl_operator::controller::{self, ContextData};
+use flux_kcl_operator::{
+    admission::RenderAdmission,
+    controller::{self, ContextData},
+    engine, event,
+    export::ExportKey,
+    hooks, instance_ext,
+    leader::{self, LeaderElection},
+    metrics, preflight,
+    protection::{self, ProtectedResources},
+    rbac, CachedDiscovery, ManagedByLabel,
+};
 use flux_kcl_operator_crd::KclInstance;
-use futures::stream::StreamExt;
+use fluxcd_rs::{GitRepository, OCIRepository};
+use futures::{future, stream::StreamExt};
 use kube::{
-    runtime::{watcher::Config, Controller},
-    Api, Client, CustomResourceExt, Discovery,
+    core::NamespaceResourceScope,
+    runtime::{
+        watcher::{self, Config},
+        Controller, WatchStreamExt,
+    },
+    Api, Client, CustomResourceExt, Resource,
 };
 use reqwest_middleware::ClientBuilder;
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use tracing::{error, info};
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+use tracing::{error, info, warn};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, layer::SubscriberExt, EnvFilter, Registry};
 
 #[derive(Parser)]
 #[command(version, about)]
@@ -25,6 +44,231 @@ struct Cli {
     #[arg(long, env = "KCL_STORAGE_DIR")]
     storage_dir: Option<std::path::PathBuf>,
 
+    /// Directory the source artifacts are downloaded and extracted to, the storage dir when
+    /// unset.
+    #[arg(long, env = "KCL_ARTIFACTS_DIR")]
+    artifacts_dir: Option<std::path::PathBuf>,
+
+    /// Default vendor home of the KCL module dependencies, created at startup, instead of the
+    /// KCL one under the home directory, which may not be writable in containers.
+    #[arg(long, env = "KCL_VENDOR_HOME")]
+    vendor_home: Option<std::path::PathBuf>,
+
+    /// Vendor home the OCI module dependencies are pulled to, the vendor home when unset.
+    #[arg(long, env = "KCL_OCI_VENDOR_DIR")]
+    oci_vendor_dir: Option<std::path::PathBuf>,
+
+    /// Base directory the git module dependencies are cloned to, e.g. on a larger volume. The
+    /// OCI vendor home when unset.
+    #[arg(long, env = "KCL_GIT_VENDOR_DIR")]
+    git_vendor_dir: Option<std::path::PathBuf>,
+
+    /// Octal mode of the directories created in the storage dir, e.g. `0750`.
+    #[arg(long, env = "KCL_STORAGE_DIR_MODE", value_parser = parse_mode)]
+    storage_dir_mode: Option<u32>,
+
+    /// Comma separated list of namespaces to watch. Watches all namespaces when empty.
+    #[arg(
+        long,
+        visible_alias = "watch-namespace",
+        env = "WATCH_NAMESPACES",
+        value_delimiter = ',',
+        value_parser = parse_namespace
+    )]
+    namespaces: Vec<String>,
+
+    /// Label selector restricting which KclInstances are reconciled, e.g. `shard=a`.
+    #[arg(long, env = "KCL_INSTANCE_SELECTOR")]
+    selector: Option<String>,
+
+    /// Comma separated namespaces whose resources are never pruned or cleaned up.
+    #[arg(
+        long,
+        env = "KCL_PROTECTED_NAMESPACES",
+        value_delimiter = ',',
+        default_values = protection::DEFAULT_PROTECTED_NAMESPACES.iter().copied()
+    )]
+    protected_namespaces: Vec<String>,
+
+    /// Comma separated names of the operator's own ClusterRoles/ClusterRoleBindings,
+    /// which are never pruned or cleaned up.
+    #[arg(
+        long,
+        env = "KCL_PROTECTED_CLUSTER_RBAC",
+        value_delimiter = ',',
+        default_values = protection::DEFAULT_PROTECTED_CLUSTER_RBAC.iter().copied()
+    )]
+    protected_cluster_rbac: Vec<String>,
+
+    /// Comma separated media types of the layers of OCI module dependencies. Packages with a
+    /// single layer are accepted whatever its media type.
+    #[arg(
+        long,
+        env = "KCL_OCI_LAYER_MEDIA_TYPES",
+        value_delimiter = ',',
+        default_values = kcl_client::DEFAULT_LAYER_MEDIA_TYPES.iter().copied()
+    )]
+    oci_layer_media_types: Vec<String>,
+
+    /// Cloud provider of the credentials used to pull OCI module dependencies: `generic`
+    /// (anonymous) or `aws` (ECR, requires the `aws` feature).
+    #[arg(long, env = "KCL_REGISTRY_PROVIDER", default_value = "generic")]
+    registry_provider: kcl_client::RegistryProvider,
+
+    /// Maximum number of concurrent pulls of OCI module dependencies from each registry host,
+    /// to avoid being throttled by registries such as ghcr.io. Unlimited when 0.
+    #[arg(long, env = "KCL_REGISTRY_CONCURRENCY", default_value = "0")]
+    registry_concurrency: usize,
+
+    /// Validate the rendered manifests (names, namespaces, labels and annotations) before
+    /// applying them.
+    #[arg(long, env = "KCL_VALIDATE_MANIFESTS")]
+    validate_manifests: bool,
+
+    /// Only allow instances to apply namespaced objects to their own namespace, for
+    /// multi-tenant clusters.
+    #[arg(long, env = "KCL_SAME_NAMESPACE_ONLY")]
+    same_namespace_only: bool,
+
+    /// Key of the label marking the applied objects as managed by the operator. Prune and
+    /// cleanup only delete the objects carrying it.
+    #[arg(
+        long,
+        env = "KCL_MANAGED_BY_LABEL_KEY",
+        default_value = "app.kubernetes.io/managed-by"
+    )]
+    managed_by_label_key: String,
+
+    /// Value of the label marking the applied objects as managed by the operator.
+    #[arg(
+        long,
+        env = "KCL_MANAGED_BY_LABEL_VALUE",
+        default_value = "kcl-instance-controller"
+    )]
+    managed_by_label_value: String,
+
+    /// Only run the controllers while holding a Lease, for HA deployments with several replicas.
+    #[arg(long, env = "KCL_ENABLE_LEADER_ELECTION")]
+    enable_leader_election: bool,
+
+    /// Namespace of the leader election Lease. Defaults to the client's namespace.
+    #[arg(long, env = "POD_NAMESPACE")]
+    leader_election_namespace: Option<String>,
+
+    /// Name of the leader election Lease.
+    #[arg(long, default_value = leader::DEFAULT_LEASE_NAME)]
+    leader_election_id: String,
+
+    /// Identity of this replica in the leader election. Defaults to the hostname.
+    #[arg(long, env = "POD_NAME")]
+    leader_election_identity: Option<String>,
+
+    /// Fail at startup when the Flux source CRDs (GitRepository and OCIRepository) are not
+    /// installed, instead of only warning about them.
+    #[arg(long, env = "KCL_REQUIRE_FLUX")]
+    require_flux: bool,
+
+    /// Don't watch the Flux sources. By default a new artifact of a source reconciles the
+    /// instances referencing it right away, without it they wait for their next interval.
+    #[arg(long, env = "KCL_NO_WATCH_SOURCES")]
+    no_watch_sources: bool,
+
+    /// Interval between two refreshes of the API discovery, e.g. `5m`. Kinds missing from
+    /// the discovery, such as newly installed CRDs, are also discovered on demand.
+    #[arg(
+        long,
+        env = "KCL_DISCOVERY_TTL",
+        default_value = "5m",
+        value_parser = humantime::parse_duration
+    )]
+    discovery_ttl: std::time::Duration,
+
+    /// Soft limit of the memory taken by the concurrent renders, e.g. `2Gi`. Renders are
+    /// weighted by the size of their source artifact, and renders that would exceed the limit
+    /// are requeued. Unlimited when unset.
+    #[arg(long, env = "KCL_MAX_RENDER_MEMORY", value_parser = parse_memory)]
+    max_render_memory: Option<u64>,
+
+    /// Maximum size of the source artifacts, e.g. `512Mi`. Larger artifacts are rejected
+    /// before being written to disk.
+    #[arg(
+        long,
+        env = "KCL_MAX_ARTIFACT_SIZE",
+        default_value = "1Gi",
+        value_parser = parse_memory
+    )]
+    max_artifact_size: u64,
+
+    /// Consecutive failed downloads of a source after which its downloads fail fast for
+    /// `--source-failure-cooldown`, instead of hitting a source that is down. 0 disables it.
+    #[arg(long, env = "KCL_SOURCE_FAILURE_THRESHOLD", default_value_t = fluxcd_rs::downloader::DEFAULT_FAILURE_THRESHOLD)]
+    source_failure_threshold: u32,
+
+    /// Time the downloads of a failing source fail fast, e.g. `5m`. A single download then
+    /// probes whether the source recovered.
+    #[arg(
+        long,
+        env = "KCL_SOURCE_FAILURE_COOLDOWN",
+        default_value = "5m",
+        value_parser = humantime::parse_duration
+    )]
+    source_failure_cooldown: std::time::Duration,
+
+    /// Address to serve the Prometheus metrics on, at `/metrics`, e.g. `0.0.0.0:8080`. The
+    /// metrics are not served when unset.
+    #[arg(long, env = "KCL_METRICS_ADDR")]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Consecutive retried failures after which an instance is given up: it is marked
+    /// `Stalled` and only reconciled again once its spec or annotations change. 0 never gives up.
+    #[arg(long, env = "KCL_MAX_FAILURES", default_value_t = 0)]
+    max_failures: u32,
+
+    /// Ceiling of the exponential backoff of the retries after system errors, e.g. `2m`. The
+    /// instance's `interval` when unset.
+    #[arg(long, env = "KCL_MAX_RETRY_BACKOFF", value_parser = humantime::parse_duration)]
+    max_retry_backoff: Option<std::time::Duration>,
+
+    /// Argument passed to every instance, e.g. `cluster=eu-1`. Repeat the flag for several
+    /// arguments, values may contain commas, e.g. `hosts=a,b`. Instances override them with
+    /// their values files and arguments.
+    #[arg(long = "default-arg", env = "KCL_DEFAULT_ARG", value_parser = parse_key_value)]
+    default_args: Vec<(String, String)>,
+
+    /// YAML file of arguments passed to every instance, e.g. mounted from a ConfigMap.
+    /// `--default-arg` arguments override it.
+    #[arg(long, env = "KCL_DEFAULT_ARGS_FILE")]
+    default_args_file: Option<std::path::PathBuf>,
+
+    /// Namespace the objects of instances without `namespaceTemplate` are applied to, e.g.
+    /// `tenant-{name}`, see `namespaceTemplate`.
+    #[arg(long, env = "KCL_NAMESPACE_TEMPLATE")]
+    namespace_template: Option<String>,
+
+    /// File of the 32 byte key encrypting the exported rendered output of instances with
+    /// `exportRendered.encrypt`, e.g. mounted from a Secret.
+    #[arg(long, env = "KCL_EXPORT_ENCRYPTION_KEY_FILE")]
+    export_encryption_key_file: Option<std::path::PathBuf>,
+
+    /// User agent of the requests to source-controller and OCI registries.
+    #[arg(long, env = "KCL_USER_AGENT", default_value = engine::USER_AGENT)]
+    user_agent: String,
+
+    /// Component reporting the operator's events, to tell operator variants apart in shared
+    /// clusters. Defaults to `kcl-instance-controller`.
+    #[arg(long, env = "KCL_EVENT_REPORTER")]
+    event_reporter: Option<String>,
+
+    /// Instance reporting the operator's events. Defaults to the pod name (or the hostname).
+    #[arg(long, env = "KCL_EVENT_REPORTER_INSTANCE")]
+    event_reporter_instance: Option<String>,
+
+    /// OTLP gRPC endpoint the reconcile traces are exported to, e.g. `http://otel-collector:4317`.
+    /// Traces are not exported when unset.
+    #[cfg(feature = "otel")]
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -35,89 +279,539 @@ enum Commands {
     Crd,
     /// Run the operator
     Run,
+    /// Render a live instance from its current source artifact and print the objects a
+    /// reconciliation would apply, without changing anything in the cluster
+    Preview {
+        /// Name of the instance
+        name: String,
+        /// Namespace of the instance, defaults to the namespace of the kubeconfig context
+        #[arg(short, long)]
+        namespace: Option<String>,
+    },
+    /// Render a local KCL module and print the ClusterRole and Roles with the verbs the operator
+    /// needs on the kinds it renders, to scope the operator's RBAC
+    Rbac {
+        /// Directory of the KCL module
+        path: std::path::PathBuf,
+        /// Name of the generated ClusterRole and Roles
+        #[arg(long, default_value = "flux-kcl-operator-apply")]
+        name: String,
+        /// Argument of the module, e.g. `env=prod`. Repeat the flag for several arguments
+        #[arg(short, long = "arg", value_parser = parse_key_value)]
+        args: Vec<(String, String)>,
+    },
+    /// Check the cluster connection, the Flux source CRDs, the writability of the storage dir
+    /// and vendor homes, the reachability of an OCI registry and the KCL runtime, print a
+    /// report and exit with an error if any check failed
+    Preflight {
+        /// OCI registry of the module dependencies to check, a host or a URL
+        #[arg(long, default_value = "ghcr.io")]
+        registry: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    init_logger()?;
-
     let cli = Cli::parse();
 
-    match cli.command {
+    init_logger(&cli)?;
+
+    match &cli.command {
         Commands::Crd => {
             println!("{}", serde_yaml::to_string(&KclInstance::crd())?);
             Ok(())
         }
         Commands::Run => {
-            let client = Client::try_default().await?;
-
-            let discovery = Discovery::new(client.clone())
-                .run()
-                .await
-                .expect("Failed to create discovery client");
-
-            let context: Arc<ContextData> = init_context(client.clone(), cli, discovery);
-
-            let api_kcl_instance: Api<KclInstance> = Api::all(client.clone());
-
-            // Run the operator's controller in a loop, processing each instance of the custom resource
-            Controller::new(api_kcl_instance.clone(), Config::default())
-                .run(controller::reconcile, controller::on_error, context)
-                .for_each(|reconciliation_result| async move {
-                    match reconciliation_result {
-                        Ok(resource) => {
-                            info!("Reconciliation successful. Resource: {:?}", resource);
-                        }
-                        Err(err) => {
-                            error!("Reconciliation error: {:?}", err);
-                        }
+            // Fail fast on an invalid selector before connecting to the cluster
+            let watcher_config = controller::watcher_config(cli.selector.as_deref())?;
+
+            let config = kube::Config::infer().await?;
+            let client = Client::try_from(config.clone())?;
+
+            // Discover the APIs in the background, so large clusters don't delay startup
+            let discovery = Arc::new(CachedDiscovery::new(client.clone(), cli.discovery_ttl));
+            tokio::spawn(discovery.clone().refresh_periodically());
+
+            // Instances can't be rendered without the Flux sources, so say it at boot
+            let missing = engine::missing_flux_sources(&discovery).await;
+            if !missing.is_empty() {
+                let kinds: Vec<String> = missing
+                    .iter()
+                    .map(|gvk| format!("{}/{} {}", gvk.group, gvk.version, gvk.kind))
+                    .collect();
+                let message = format!(
+                    "Flux source CRDs are not installed: {}. Install the Flux source-controller, \
+                     instances referencing these kinds will fail to reconcile",
+                    kinds.join(", ")
+                );
+                if cli.require_flux {
+                    return Err(message.into());
+                }
+                warn!("{}", message);
+            }
+
+            let apis = watched_apis::<KclInstance>(&client, &cli.namespaces);
+            // The sources are watched in the same namespaces as the instances, by a single
+            // watch per kind and namespace
+            let mut source_apis = if missing.is_empty() && !cli.no_watch_sources {
+                let git = watched_apis::<GitRepository>(&client, &cli.namespaces);
+                let oci = watched_apis::<OCIRepository>(&client, &cli.namespaces);
+                git.into_iter().zip(oci).map(Some).collect::<Vec<_>>()
+            } else {
+                vec![None; apis.len()]
+            }
+            .into_iter();
+
+            let leader_election = cli.enable_leader_election.then(|| {
+                let namespace = cli
+                    .leader_election_namespace
+                    .clone()
+                    .unwrap_or_else(|| client.default_namespace().to_string());
+                let identity = cli
+                    .leader_election_identity
+                    .clone()
+                    .or_else(|| env::var("HOSTNAME").ok())
+                    .unwrap_or_else(|| format!("flux-kcl-operator-{}", rand::random::<u32>()));
+                LeaderElection::new(
+                    client.clone(),
+                    &namespace,
+                    &cli.leader_election_id,
+                    &identity,
+                )
+            });
+
+            let metrics_addr = cli.metrics_addr;
+            let context: Arc<ContextData> = init_context(config, client.clone(), cli, discovery)?;
+
+            // Served by every replica, including the ones waiting for the leadership
+            if let Some(addr) = metrics_addr {
+                let listener = tokio::net::TcpListener::bind(addr).await?;
+                info!("Serving the metrics at http://{}/metrics", addr);
+                let registry = context.metrics().registry().clone();
+                tokio::spawn(async move {
+                    if let Err(e) = metrics::serve(listener, registry).await {
+                        error!("Failed to serve the metrics: {}", e);
                     }
-                })
+                });
+            }
+
+            // Run one controller per watched namespace (or a single cluster-wide one)
+            let controllers = async {
+                futures::future::join_all(apis.into_iter().map(|api| {
+                    run_controller(
+                        api,
+                        watcher_config.clone(),
+                        context.clone(),
+                        source_apis.next().flatten(),
+                    )
+                }))
                 .await;
+            };
+
+            match leader_election {
+                Some(leader_election) => leader_election.run(controllers).await?,
+                None => controllers.await,
+            }
+
+            // Flush the traces not exported yet
+            #[cfg(feature = "otel")]
+            opentelemetry::global::shutdown_tracer_provider();
+            Ok(())
+        }
+        Commands::Preflight { registry } => {
+            let mut checks = Vec::new();
+            match kube::Config::infer().await {
+                Ok(config) => {
+                    let client = Client::try_from(config)?;
+                    let cluster = preflight::check_cluster(&client).await;
+                    let connected = cluster.passed;
+                    checks.push(cluster);
+                    // Discovery would only repeat the connection failure
+                    if connected {
+                        let discovery = CachedDiscovery::new(client, cli.discovery_ttl);
+                        checks.push(preflight::check_flux_sources(&discovery).await);
+                    }
+                }
+                Err(e) => checks.push(preflight::Check::fail(
+                    "cluster",
+                    format!("no kubeconfig or in-cluster configuration: {}", e),
+                )),
+            }
+
+            let storage_dir = cli
+                .artifacts_dir
+                .clone()
+                .or(cli.storage_dir.clone())
+                .unwrap_or_else(|| fluxcd_rs::downloader::DEFAULT_STORAGE_DIR.into());
+            checks.push(preflight::check_writable("storage dir", &storage_dir));
+            let vendor_home = cli
+                .vendor_home
+                .clone()
+                .unwrap_or_else(kcl_client::vendor_home);
+            checks.push(preflight::check_writable("vendor home", &vendor_home));
+            for (name, dir) in [
+                ("oci vendor dir", &cli.oci_vendor_dir),
+                ("git vendor dir", &cli.git_vendor_dir),
+            ] {
+                if let Some(dir) = dir {
+                    checks.push(preflight::check_writable(name, dir));
+                }
+            }
+
+            let http = reqwest::Client::builder()
+                .user_agent(cli.user_agent.clone())
+                .timeout(std::time::Duration::from_secs(10))
+                .build()?;
+            checks.push(preflight::check_registry(&http, registry).await);
+            checks.push(preflight::check_kcl_runtime(&storage_dir).await);
+
+            let (report, passed) = preflight::report(&checks);
+            print!("{}", report);
+            if !passed {
+                return Err("Preflight checks failed".into());
+            }
+            Ok(())
+        }
+        Commands::Rbac { path, name, args } => {
+            let args = args.iter().cloned().collect();
+            print!("{}", rbac::render_rbac(path, name, &args).await?);
+            Ok(())
+        }
+        Commands::Preview { name, namespace } => {
+            let name = name.clone();
+            let config = kube::Config::infer().await?;
+            let client = Client::try_from(config.clone())?;
+            let namespace = namespace
+                .clone()
+                .unwrap_or_else(|| client.default_namespace().to_string());
+            let instance = Api::<KclInstance>::namespaced(client.clone(), &namespace)
+                .get(&name)
+                .await?;
+
+            let discovery = Arc::new(CachedDiscovery::new(client.clone(), cli.discovery_ttl));
+            let context = init_context(config, client, cli, discovery)?;
+            let (revision, manifests) =
+                controller::preview_instance(Arc::new(instance), &context).await?;
+            info!("Rendered {}/{} at revision {}", namespace, name, revision);
+            print!("{}", manifests);
             Ok(())
         }
     }
 }
 
+/// Builds the APIs of the `K` resources the operator should watch, e.g. the `KclInstance`s or
+/// their Flux sources.
+///
+/// # Arguments
+/// * `client` - The Kubernetes client
+/// * `namespaces` - Namespaces to watch, see `parse_namespace`
+///
+/// # Returns
+/// A namespaced API per distinct watched namespace, or a single cluster-wide API when no
+/// namespaces are given
+fn watched_apis<K>(client: &Client, namespaces: &[String]) -> Vec<Api<K>>
+where
+    K: Resource<Scope = NamespaceResourceScope>,
+    K::DynamicType: Default,
+{
+    let plural = K::plural(&Default::default()).to_string();
+    // A namespace given twice is watched once
+    let namespaces: BTreeSet<&str> = namespaces.iter().map(String::as_str).collect();
+
+    if namespaces.is_empty() {
+        info!("Watching {} in all namespaces", plural);
+        return vec![Api::all(client.clone())];
+    }
+
+    namespaces
+        .into_iter()
+        .map(|ns| {
+            info!("Watching {} in namespace {}", plural, ns);
+            Api::namespaced(client.clone(), ns)
+        })
+        .collect()
+}
+
+/// Runs the operator's controller in a loop, processing each instance of the custom resource
+///
+/// # Arguments
+/// * `sources` - APIs of the Flux sources in the namespace of `api`, to reconcile the
+///   instances of a source when it changes. Sources are not watched when their CRDs are not
+///   installed
+async fn run_controller(
+    api: Api<KclInstance>,
+    config: Config,
+    context: Arc<ContextData>,
+    sources: Option<(Api<GitRepository>, Api<OCIRepository>)>,
+) {
+    // The controller only sees a change once the running reconciliation finished,
+    // so watch on the side to cancel reconciliations of changed instances early
+    let superseded = watcher::watcher(api.clone(), config.clone())
+        .default_backoff()
+        .applied_objects()
+        .for_each({
+            let context = context.clone();
+            move |instance| {
+                if let Ok(instance) = instance {
+                    context.cancel_superseded(&instance);
+                }
+                future::ready(())
+            }
+        });
+
+    let mut controller = Controller::new(api, config);
+    if let Some((git_repositories, oci_repositories)) = sources {
+        let store = controller.store();
+        controller = controller
+            .watches(git_repositories, Config::default(), {
+                let store = store.clone();
+                move |source| controller::instances_of_source(&store, &source)
+            })
+            .watches(oci_repositories, Config::default(), move |source| {
+                controller::instances_of_source(&store, &source)
+            });
+    }
+    let controller = controller
+        .shutdown_on_signal()
+        .run(controller::reconcile, controller::on_error, context.clone())
+        .for_each(|reconciliation_result| async move {
+            match reconciliation_result {
+                Ok(resource) => {
+                    info!("Reconciliation successful. Resource: {:?}", resource);
+                }
+                Err(err) => {
+                    error!("Reconciliation error: {:?}", err);
+                }
+            }
+        });
+
+    tokio::select! {
+        _ = controller => {}
+        _ = superseded => {}
+        _ = report_staleness(context) => {}
+    }
+}
+
+/// Interval between two scans of the reconciliations' staleness.
+const STALENESS_REPORT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Periodically logs how far behind the reconciliations are: the time since the oldest last
+/// reconciliation, and the instances not reconciled within their interval.
+async fn report_staleness(context: Arc<ContextData>) {
+    let mut ticks = tokio::time::interval(STALENESS_REPORT_INTERVAL);
+    loop {
+        ticks.tick().await;
+        let staleness = context.reconciles().staleness(std::time::Instant::now());
+        context.metrics().observe_staleness(&staleness);
+        let Some(oldest) = staleness.oldest else {
+            continue;
+        };
+        let oldest = humantime::format_duration(std::time::Duration::from_secs(oldest.as_secs()));
+        if staleness.overdue > 0 {
+            warn!(
+                instances = staleness.instances,
+                overdue = staleness.overdue,
+                "Reconciliations are falling behind: {} of {} instances not reconciled within their interval, oldest reconciliation {} ago",
+                staleness.overdue,
+                staleness.instances,
+                oldest
+            );
+        } else {
+            info!(
+                instances = staleness.instances,
+                overdue = 0,
+                "Reconciliations are up to date, oldest reconciliation {} ago",
+                oldest
+            );
+        }
+    }
+}
+
 /// Initializes the context data for the operator.
 ///
 /// # Arguments
+/// * `config` - The configuration the Kubernetes client was built from, to impersonate with
 /// * `client` - The Kubernetes client
 /// * `cli` - The command line arguments
 ///
 /// # Returns
-/// A new `Arc<ContextData>` containing the initialized context
-fn init_context(client: kube::Client, cli: Cli, discovery: Discovery) -> Arc<ContextData> {
+/// A new `Arc<ContextData>` containing the initialized context, or an error if the
+/// storage directory is not usable
+fn init_context(
+    config: kube::Config,
+    client: kube::Client,
+    cli: Cli,
+    discovery: Arc<CachedDiscovery>,
+) -> Result<Arc<ContextData>, Box<dyn std::error::Error>> {
     let retry_policy =
         ExponentialBackoff::builder().build_with_max_retries(cli.http_retry.unwrap_or(1));
-    let http_client = ClientBuilder::new(reqwest::Client::new())
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
+    // Leaked once at startup, as the OCI client requires a static user agent
+    let user_agent: &'static str = Box::leak(cli.user_agent.into_boxed_str());
+    let http_client =
+        ClientBuilder::new(reqwest::Client::builder().user_agent(user_agent).build()?)
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build();
+
+    let mut downloader = fluxcd_rs::downloader::Downloader::new(
+        http_client,
+        cli.source_host,
+        cli.artifacts_dir.or(cli.storage_dir),
+    )
+    .with_max_artifact_size(cli.max_artifact_size)
+    .with_circuit_breaker(cli.source_failure_threshold, cli.source_failure_cooldown);
+    if let Some(mode) = cli.storage_dir_mode {
+        downloader = downloader.with_dir_mode(mode);
+    }
+    downloader.init()?;
+    if let Some(vendor_home) = &cli.vendor_home {
+        let vendor_home = kcl_client::set_default_vendor_home(vendor_home, cli.storage_dir_mode)?;
+        info!("Using vendor home {}", vendor_home.display());
+    }
+    let reporter = event::reporter(
+        cli.event_reporter,
+        cli.event_reporter_instance
+            .or_else(|| env::var("POD_NAME").ok())
+            .or_else(|| env::var("HOSTNAME").ok()),
+    );
+
+    let mut engine = engine::Engine::new(client.clone())
+        .with_config(config)
+        .with_user_agent(user_agent)
+        .with_reporter(reporter.clone())
+        .with_protected_resources(ProtectedResources::new(
+            cli.protected_namespaces,
+            cli.protected_cluster_rbac,
+        ))
+        .with_same_namespace_only(cli.same_namespace_only)
+        .with_managed_by_label(ManagedByLabel {
+            key: cli.managed_by_label_key,
+            value: cli.managed_by_label_value,
+        })
+        .with_layer_media_types(cli.oci_layer_media_types)
+        .with_oci_vendor_dir(cli.oci_vendor_dir)
+        .with_git_vendor_dir(cli.git_vendor_dir)
+        .with_registry_concurrency(cli.registry_concurrency)
+        .with_registry_credentials(kcl_client::RegistryCredentials::new(cli.registry_provider)?);
+    if cli.validate_manifests {
+        engine = engine.with_hook(Arc::new(hooks::SchemaValidator));
+    }
+
+    let mut default_args = match &cli.default_args_file {
+        Some(path) => instance_ext::read_values_file(path)?,
+        None => HashMap::new(),
+    };
+    default_args.extend(cli.default_args);
+    let mut context = ContextData::new(client, downloader, engine, discovery)
+        .with_reporter(reporter)
+        .with_default_args(default_args)
+        .with_namespace_template(cli.namespace_template)
+        .with_max_failures(cli.max_failures)
+        .with_max_backoff(cli.max_retry_backoff);
+    if let Some(max_render_memory) = cli.max_render_memory {
+        context = context.with_render_admission(RenderAdmission::new(max_render_memory));
+    }
+    if let Some(path) = &cli.export_encryption_key_file {
+        context = context.with_export_key(ExportKey::from_file(path)?);
+    }
+    Ok(Arc::new(context))
+}
+
+/// Parses a `key=value` argument.
+fn parse_key_value(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("invalid argument {arg}, expected key=value"))
+}
 
-    let downloader =
-        fluxcd_rs::downloader::Downloader::new(http_client, cli.source_host, cli.storage_dir);
-    let engine = flux_kcl_operator::engine::Engine::new(client.clone());
+/// Parses a namespace of `--namespaces`, rejecting the empty entries of e.g. `a,,b`.
+fn parse_namespace(namespace: &str) -> Result<String, String> {
+    let namespace = namespace.trim();
+    if namespace.is_empty() {
+        return Err("empty namespace, expected a comma separated list of namespaces".to_string());
+    }
+    Ok(namespace.to_string())
+}
+
+/// Parses an octal file mode such as `0750` or `0o750`.
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map_err(|e| format!("invalid octal mode {mode}: {e}"))
+}
 
-    Arc::new(ContextData::new(client, downloader, engine, discovery))
+/// Parses a memory quantity in bytes, with an optional `Ki`, `Mi` or `Gi` suffix.
+fn parse_memory(memory: &str) -> Result<u64, String> {
+    let (number, unit) = [("Ki", 1 << 10), ("Mi", 1 << 20), ("Gi", 1 << 30)]
+        .into_iter()
+        .find_map(|(suffix, unit)| memory.strip_suffix(suffix).map(|number| (number, unit)))
+        .unwrap_or((memory, 1));
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| format!("invalid memory quantity {memory}"))
 }
 
-/// Initializes a logger with environment filters and formatting.
+/// Initializes a logger with environment filters and formatting, exporting the traces to
+/// the OTLP endpoint when one is configured.
+///
+/// # Arguments
+/// * `cli` - The command line arguments
 ///
 /// # Returns
 /// * `Ok(())` if logger initialization was successful
 /// * `Err` if there was an error setting up the logger
-fn init_logger() -> Result<(), Box<dyn std::error::Error>> {
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+fn init_logger(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     let filter_layer = match env::var("RUST_LOG") {
         Ok(e) => EnvFilter::new(e),
         _ => "info".into(),
     };
 
+    // Previews, RBAC and preflight reports are printed on stdout, so log to stderr
+    let writer = match cli.command {
+        Commands::Preview { .. } | Commands::Rbac { .. } | Commands::Preflight { .. } => {
+            BoxMakeWriter::new(std::io::stderr)
+        }
+        _ => BoxMakeWriter::new(std::io::stdout),
+    };
     let fmt_layer = tracing_subscriber::fmt::layer()
         .with_target(false)
-        .with_line_number(true);
+        .with_line_number(true)
+        .with_writer(writer);
 
     let subscriber = Registry::default().with(filter_layer).with(fmt_layer);
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(
+        cli.otlp_endpoint
+            .as_deref()
+            .map(flux_kcl_operator::telemetry::layer)
+            .transpose()?,
+    );
 
     tracing::subscriber::set_global_default(subscriber)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_namespaces_flag() {
+        let parse = |namespaces: &str| {
+            Cli::try_parse_from(["flux-kcl-operator", "--namespaces", namespaces, "run"])
+                .map(|cli| cli.namespaces)
+        };
+        assert_eq!(parse("a, b").unwrap(), ["a", "b"]);
+        assert!(parse("a,,b").is_err());
+        assert!(parse("a,").is_err());
+
+        // Each namespace is watched once
+        let client =
+            Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap();
+        let namespaces = parse("a,b,a").unwrap();
+        assert_eq!(watched_apis::<KclInstance>(&client, &namespaces).len(), 2);
+        assert_eq!(watched_apis::<KclInstance>(&client, &[]).len(), 1);
+    }
+}
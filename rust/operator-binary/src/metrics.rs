@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tracing::debug;
+
+use crate::staleness::Staleness;
+
+/// Buckets of the reconcile duration histogram, in seconds: from no-op reconciliations taking
+/// well under a second to renders and applies of large modules taking minutes.
+pub const RECONCILE_DURATION_BUCKETS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+/// Outcome label of the reconciliations that failed.
+pub const ERROR_OUTCOME: &str = "error";
+
+/// Outcome label of the renders admitted by the render admission.
+pub const ADMITTED_OUTCOME: &str = "admitted";
+/// Outcome label of the renders deferred by the render admission, which are requeued.
+pub const DEFERRED_OUTCOME: &str = "deferred";
+
+/// Time a client of the metrics endpoint has to send its request and read the response.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Prometheus metrics of the reconciliations, registered in their own registry so they can be
+/// gathered and served by the embedding binary.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// Reconciliations by outcome: `created`, `updated`, `noop`, `deleted` or `error`.
+    reconciles: IntCounterVec,
+    /// Failed reconciliations by error class: `user`, `module`, `stalled` or `system`.
+    errors: IntCounterVec,
+    /// Duration of the reconciliations by outcome.
+    duration: HistogramVec,
+    /// Instances reconciled since the operator started, see `Staleness`.
+    instances: IntGauge,
+    /// Instances not reconciled within their interval.
+    overdue: IntGauge,
+    /// Time since the oldest last reconciliation of an instance.
+    oldest: Gauge,
+    /// Renders by admission outcome: `admitted` or `deferred`, see `RenderAdmission`.
+    render_admissions: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates the metrics and registers them in a new registry.
+    pub fn new() -> Self {
+        let reconciles = IntCounterVec::new(
+            Opts::new(
+                "kcl_reconcile_total",
+                "Reconciliations of KclInstances by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid reconcile counter");
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "kcl_reconcile_errors_total",
+                "Failed reconciliations of KclInstances by error class",
+            ),
+            &["class"],
+        )
+        .expect("valid reconcile error counter");
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "kcl_reconcile_duration_seconds",
+                "Duration of the reconciliations of KclInstances by outcome",
+            )
+            .buckets(RECONCILE_DURATION_BUCKETS.to_vec()),
+            &["outcome"],
+        )
+        .expect("valid reconcile duration histogram");
+        let instances = IntGauge::new(
+            "kcl_instances",
+            "KclInstances reconciled since the operator started",
+        )
+        .expect("valid instances gauge");
+        let overdue = IntGauge::new(
+            "kcl_instances_overdue",
+            "KclInstances not reconciled within their interval",
+        )
+        .expect("valid overdue instances gauge");
+        let oldest = Gauge::new(
+            "kcl_reconcile_oldest_seconds",
+            "Time since the oldest last reconciliation of a KclInstance",
+        )
+        .expect("valid oldest reconcile gauge");
+        let render_admissions = IntCounterVec::new(
+            Opts::new(
+                "kcl_render_admissions_total",
+                "Renders admitted or deferred under the render memory limit",
+            ),
+            &["outcome"],
+        )
+        .expect("valid render admission counter");
+
+        let registry = Registry::new();
+        for collector in [
+            Box::new(reconciles.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(errors.clone()),
+            Box::new(duration.clone()),
+            Box::new(instances.clone()),
+            Box::new(overdue.clone()),
+            Box::new(oldest.clone()),
+            Box::new(render_admissions.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metrics registered once");
+        }
+
+        Metrics {
+            registry,
+            reconciles,
+            errors,
+            duration,
+            instances,
+            overdue,
+            oldest,
+            render_admissions,
+        }
+    }
+
+    /// The registry of the metrics, to gather them e.g. for a `/metrics` endpoint, see `serve`.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Records a successful reconciliation with `outcome`, taking `elapsed`.
+    pub fn observe(&self, outcome: &str, elapsed: Duration) {
+        self.reconciles.with_label_values(&[outcome]).inc();
+        self.duration
+            .with_label_values(&[outcome])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a reconciliation failed with an error of `class`, taking `elapsed`.
+    pub fn observe_error(&self, class: &str, elapsed: Duration) {
+        self.observe(ERROR_OUTCOME, elapsed);
+        self.errors.with_label_values(&[class]).inc();
+    }
+
+    /// Number of the reconciliations recorded with `outcome`.
+    pub fn reconciles(&self, outcome: &str) -> u64 {
+        self.reconciles.with_label_values(&[outcome]).get()
+    }
+
+    /// Number of the failed reconciliations recorded with an error of `class`.
+    pub fn errors(&self, class: &str) -> u64 {
+        self.errors.with_label_values(&[class]).get()
+    }
+
+    /// Records a render admitted, or deferred when `admitted` is false, by the render admission.
+    pub fn observe_render_admission(&self, admitted: bool) {
+        let outcome = if admitted {
+            ADMITTED_OUTCOME
+        } else {
+            DEFERRED_OUTCOME
+        };
+        self.render_admissions.with_label_values(&[outcome]).inc();
+    }
+
+    /// Number of the renders recorded with the admission `outcome`.
+    pub fn render_admissions(&self, outcome: &str) -> u64 {
+        self.render_admissions.with_label_values(&[outcome]).get()
+    }
+
+    /// Records the staleness of the reconciliations, see `ReconcileTracker::staleness`.
+    pub fn observe_staleness(&self, staleness: &Staleness) {
+        self.instances.set(staleness.instances as i64);
+        self.overdue.set(staleness.overdue as i64);
+        self.oldest
+            .set(staleness.oldest.unwrap_or_default().as_secs_f64());
+    }
+}
+
+/// Serves the metrics of `registry` in the Prometheus text format at `/metrics`, on the
+/// connections accepted by `listener`.
+pub async fn serve(listener: TcpListener, registry: Registry) -> std::io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(SCRAPE_TIMEOUT, respond(stream, &registry)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => debug!("Failed to serve the metrics: {}", e),
+                Err(_) => debug!("Timed out serving the metrics"),
+            }
+        });
+    }
+}
+
+/// Answers a single HTTP request with the metrics of `registry`, or a `404` for any path
+/// but `/metrics`.
+async fn respond(mut stream: TcpStream, registry: &Registry) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    {
+        let mut reader = BufReader::new(&mut stream);
+        reader.read_line(&mut request_line).await?;
+        // Skip the headers, up to the blank line
+        let mut header = String::new();
+        while reader.read_line(&mut header).await? > 2 {
+            header.clear();
+        }
+    }
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|target| target.split('?').next())
+        .unwrap_or_default();
+    let (status, content_type, body) = if path == "/metrics" {
+        let encoder = TextEncoder::new();
+        let mut body = Vec::new();
+        encoder
+            .encode(&registry.gather(), &mut body)
+            .map_err(std::io::Error::other)?;
+        ("200 OK", encoder.format_type().to_string(), body)
+    } else {
+        (
+            "404 Not Found",
+            "text/plain".to_string(),
+            b"Not found\n".to_vec(),
+        )
+    };
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serve_metrics() {
+        let metrics = Metrics::new();
+        metrics.observe("created", Duration::from_millis(200));
+        metrics.observe_render_admission(true);
+        metrics.observe_render_admission(false);
+        metrics.observe_render_admission(false);
+        assert_eq!(metrics.render_admissions(DEFERRED_OUTCOME), 2);
+        metrics.observe_staleness(&Staleness {
+            instances: 3,
+            oldest: Some(Duration::from_secs(90)),
+            overdue: 1,
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, metrics.registry().clone()));
+
+        let response = reqwest::get(format!("http://{addr}/metrics"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("kcl_reconcile_total{outcome=\"created\"} 1"));
+        assert!(body.contains("kcl_render_admissions_total{outcome=\"deferred\"} 2"));
+        assert!(body.contains("kcl_instances 3"));
+        assert!(body.contains("kcl_instances_overdue 1"));
+        assert!(body.contains("kcl_reconcile_oldest_seconds 90"));
+
+        let response = reqwest::get(format!("http://{addr}/other")).await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{create_dir_all, remove_dir_all, remove_file, File},
+    path::Path,
+};
+
+use flux_kcl_operator_crd::KclInstance;
+use kube::Client;
+use reqwest::StatusCode;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    engine,
+    render::{KclRenderer, Renderer},
+    utils::CachedDiscovery,
+};
+
+/// Outcome of a single preflight check.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    /// What was checked, e.g. `storage dir`
+    pub name: String,
+    pub passed: bool,
+    /// What was found, or how to fix it when the check failed
+    pub detail: String,
+}
+
+impl Check {
+    pub fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            passed: true,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn fail(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            passed: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+        write!(f, "{} {}: {}", status, self.name, self.detail)
+    }
+}
+
+/// Checks that the API server answers with the operator's credentials.
+pub async fn check_cluster(client: &Client) -> Check {
+    match client.apiserver_version().await {
+        Ok(version) => Check::pass(
+            "cluster",
+            format!("connected to Kubernetes {}", version.git_version),
+        ),
+        Err(e) => Check::fail("cluster", format!("cannot reach the API server: {}", e)),
+    }
+}
+
+/// Checks that the Flux source CRDs are installed, see `engine::missing_flux_sources`.
+pub async fn check_flux_sources(discovery: &CachedDiscovery) -> Check {
+    let missing = engine::missing_flux_sources(discovery).await;
+    if missing.is_empty() {
+        return Check::pass("flux sources", "GitRepository and OCIRepository are served");
+    }
+    let kinds: Vec<String> = missing
+        .iter()
+        .map(|gvk| format!("{}/{} {}", gvk.group, gvk.version, gvk.kind))
+        .collect();
+    Check::fail(
+        "flux sources",
+        format!(
+            "{} not served, install the Flux source-controller",
+            kinds.join(", ")
+        ),
+    )
+}
+
+/// Checks that `path` is, or can be created as, a writable directory.
+pub fn check_writable(name: &str, path: &Path) -> Check {
+    let probe = path.join(".preflight-probe");
+    let writable = create_dir_all(path)
+        .and_then(|_| File::create(&probe))
+        .and_then(|_| remove_file(&probe));
+    match writable {
+        Ok(()) => Check::pass(name, format!("{} is writable", path.display())),
+        Err(e) => Check::fail(name, format!("{} is not writable: {}", path.display(), e)),
+    }
+}
+
+/// Checks that the OCI registry `registry` (a host, or a URL for plain HTTP registries)
+/// answers an anonymous request to its API. A `401` passes, as registries ask for
+/// credentials before serving the packages, while a `403` usually means that a proxy or
+/// firewall blocks it.
+pub async fn check_registry(http: &reqwest::Client, registry: &str) -> Check {
+    let name = format!("registry {}", registry);
+    let base = if registry.contains("://") {
+        registry.trim_end_matches('/').to_string()
+    } else {
+        format!("https://{}", registry)
+    };
+    match http.head(format!("{}/v2/", base)).send().await {
+        Ok(response)
+            if response.status().is_success() || response.status() == StatusCode::UNAUTHORIZED =>
+        {
+            Check::pass(name, format!("reachable ({})", response.status()))
+        }
+        Ok(response) => Check::fail(name, format!("answered {}", response.status())),
+        Err(e) => Check::fail(name, format!("unreachable: {}", e)),
+    }
+}
+
+/// Checks that the KCL runtime works by rendering a trivial module written to `work_dir`.
+pub async fn check_kcl_runtime(work_dir: &Path) -> Check {
+    const NAME: &str = "kcl runtime";
+    let module_dir = work_dir.join(format!("preflight-{}", std::process::id()));
+    let written = create_dir_all(&module_dir).and_then(|_| {
+        std::fs::write(
+            module_dir.join("kcl.mod"),
+            "[package]\nname = \"preflight\"\nedition = \"v0.10.0\"\nversion = \"0.0.1\"\n",
+        )?;
+        std::fs::write(module_dir.join("main.k"), "preflight = \"ok\"\n")
+    });
+    if let Err(e) = written {
+        return Check::fail(NAME, format!("cannot write the test module: {}", e));
+    }
+
+    let instance: KclInstance = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kcl.evrone.com/v1alpha1",
+        "kind": "KclInstance",
+        "metadata": { "name": "preflight" },
+        "spec": {
+            "sourceRef": { "kind": "GitRepository", "name": "preflight" },
+            "path": ".",
+        },
+    }))
+    .expect("valid instance");
+    let rendered = KclRenderer::default()
+        .render(
+            &instance,
+            &module_dir,
+            &module_dir,
+            &HashMap::new(),
+            &CancellationToken::new(),
+        )
+        .await;
+    let _ = remove_dir_all(&module_dir);
+    match rendered {
+        Ok(manifests) if manifests.contains("preflight: ok") => {
+            Check::pass(NAME, "compiled a test module")
+        }
+        Ok(manifests) => Check::fail(NAME, format!("unexpected output: {}", manifests.trim())),
+        Err(e) => Check::fail(NAME, format!("cannot compile a test module: {}", e)),
+    }
+}
+
+/// Formats the checks as a report, one line per check.
+///
+/// # Returns
+/// The report, and whether every check passed
+pub fn report(checks: &[Check]) -> (String, bool) {
+    let report = checks
+        .iter()
+        .map(|check| format!("{}\n", check))
+        .collect::<String>();
+    (report, checks.iter().all(|check| check.passed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_cluster() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "major": "1",
+                "minor": "31",
+                "gitVersion": "v1.31.0",
+                "gitCommit": "",
+                "gitTreeState": "clean",
+                "buildDate": "2024-08-13T07:28:49Z",
+                "goVersion": "go1.22.5",
+                "compiler": "gc",
+                "platform": "linux/amd64",
+            })))
+            .mount(&server)
+            .await;
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let check = check_cluster(&client).await;
+        assert!(check.passed, "{check}");
+        assert!(check.detail.contains("v1.31.0"));
+
+        let client =
+            Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap();
+        assert!(!check_cluster(&client).await.passed);
+    }
+
+    #[tokio::test]
+    async fn test_check_flux_sources() {
+        let server = MockServer::start().await;
+        let client = Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap();
+        let check = check_flux_sources(&CachedDiscovery::new(
+            client.clone(),
+            Duration::from_secs(60),
+        ))
+        .await;
+        assert!(!check.passed);
+        assert!(check.detail.contains("GitRepository"));
+
+        let resources = |group_version: &str, kind: &str, plural: &str| {
+            serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": group_version,
+                "resources": [{
+                    "name": plural,
+                    "namespaced": true,
+                    "kind": kind,
+                    "verbs": ["get", "list", "watch"],
+                }],
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/apis/source.toolkit.fluxcd.io/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(resources(
+                "source.toolkit.fluxcd.io/v1",
+                "GitRepository",
+                "gitrepositories",
+            )))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/apis/source.toolkit.fluxcd.io/v1beta2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(resources(
+                "source.toolkit.fluxcd.io/v1beta2",
+                "OCIRepository",
+                "ocirepositories",
+            )))
+            .mount(&server)
+            .await;
+        let check =
+            check_flux_sources(&CachedDiscovery::new(client, Duration::from_secs(60))).await;
+        assert!(check.passed, "{check}");
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let dir = std::env::temp_dir().join(format!("preflight-writable-{}", std::process::id()));
+        assert!(check_writable("storage dir", &dir.join("storage")).passed);
+
+        // A file where the directory should be
+        let file = dir.join("file");
+        std::fs::write(&file, "").unwrap();
+        let check = check_writable("vendor home", &file);
+        assert!(!check.passed);
+        assert!(check.to_string().starts_with("FAIL vendor home: "));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_registry() {
+        let http = reqwest::Client::new();
+        for (status, passed) in [(200, true), (401, true), (403, false)] {
+            let server = MockServer::start().await;
+            Mock::given(method("HEAD"))
+                .and(path("/v2/"))
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&server)
+                .await;
+            let check = check_registry(&http, &server.uri()).await;
+            assert_eq!(check.passed, passed, "{check}");
+        }
+        assert!(!check_registry(&http, "http://127.0.0.1:1").await.passed);
+    }
+
+    #[tokio::test]
+    async fn test_check_kcl_runtime() {
+        let check = check_kcl_runtime(&std::env::temp_dir()).await;
+        assert!(check.passed, "{check}");
+    }
+
+    #[test]
+    fn test_report() {
+        let checks = [
+            Check::pass("cluster", "connected to Kubernetes v1.31.0"),
+            Check::fail("storage dir", "/tmp/kcl is not writable"),
+        ];
+        let (report, passed) = report(&checks);
+        assert_eq!(
+            report,
+            "PASS cluster: connected to Kubernetes v1.31.0\nFAIL storage dir: /tmp/kcl is not writable\n"
+        );
+        assert!(!passed);
+    }
+}
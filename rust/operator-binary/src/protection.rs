@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use kube::api::GroupVersionKind;
+
+/// Namespaces whose resources are never pruned or cleaned up by default.
+pub const DEFAULT_PROTECTED_NAMESPACES: &[&str] = &["kube-system", "kube-public", "flux-system"];
+
+/// Cluster-scoped RBAC objects of the operator itself, which are never pruned or cleaned up.
+pub const DEFAULT_PROTECTED_CLUSTER_RBAC: &[&str] = &["flux-kcl-operator"];
+
+const RBAC_GROUP: &str = "rbac.authorization.k8s.io";
+
+/// Safety rail for the destructive code paths (prune and cleanup): resources matching
+/// it are never deleted, even if they ended up in an instance inventory.
+#[derive(Clone, Debug)]
+pub struct ProtectedResources {
+    namespaces: HashSet<String>,
+    cluster_rbac: HashSet<String>,
+}
+
+impl Default for ProtectedResources {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_PROTECTED_NAMESPACES.iter().map(|ns| ns.to_string()),
+            DEFAULT_PROTECTED_CLUSTER_RBAC
+                .iter()
+                .map(|name| name.to_string()),
+        )
+    }
+}
+
+impl ProtectedResources {
+    /// Constructs a new set of protected resources.
+    ///
+    /// # Arguments:
+    /// - `namespaces`: Namespaces whose resources (and the namespaces themselves) are never deleted.
+    /// - `cluster_rbac`: Names of ClusterRoles and ClusterRoleBindings that are never deleted.
+    pub fn new(
+        namespaces: impl IntoIterator<Item = String>,
+        cluster_rbac: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            namespaces: namespaces.into_iter().collect(),
+            cluster_rbac: cluster_rbac.into_iter().collect(),
+        }
+    }
+
+    /// Whether the resource must not be deleted.
+    pub fn is_protected(
+        &self,
+        gvk: &GroupVersionKind,
+        name: &str,
+        namespace: Option<&str>,
+    ) -> bool {
+        if namespace.is_some_and(|ns| self.namespaces.contains(ns)) {
+            return true;
+        }
+
+        match (gvk.group.as_str(), gvk.kind.as_str()) {
+            ("", "Namespace") => self.namespaces.contains(name),
+            (RBAC_GROUP, "ClusterRole" | "ClusterRoleBinding") => self.cluster_rbac.contains(name),
+            _ => false,
+        }
+    }
+}
+
+/// Namespaces the namespaced objects rendered for an instance may be applied to.
+#[derive(Clone, Debug)]
+pub struct AllowedNamespaces {
+    instance_namespace: String,
+    namespaces: HashSet<String>,
+    same_namespace_only: bool,
+}
+
+impl AllowedNamespaces {
+    /// Constructs the allowed namespaces of an instance.
+    ///
+    /// # Arguments:
+    /// - `instance_namespace`: Namespace of the KclInstance.
+    /// - `namespaces`: `allowedNamespaces` of the instance, empty allows every namespace.
+    /// - `same_namespace_only`: Only allow the instance's own namespace, for multi-tenant setups.
+    pub fn new(
+        instance_namespace: &str,
+        namespaces: impl IntoIterator<Item = String>,
+        same_namespace_only: bool,
+    ) -> Self {
+        Self {
+            instance_namespace: instance_namespace.to_string(),
+            namespaces: namespaces.into_iter().collect(),
+            same_namespace_only,
+        }
+    }
+
+    /// Whether objects may be applied to `namespace`.
+    pub fn is_allowed(&self, namespace: &str) -> bool {
+        if self.same_namespace_only && namespace != self.instance_namespace {
+            return false;
+        }
+        self.namespaces.is_empty() || self.namespaces.contains(namespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gvk(group: &str, kind: &str) -> GroupVersionKind {
+        GroupVersionKind::gvk(group, "v1", kind)
+    }
+
+    #[test]
+    fn test_protected_namespaces() {
+        let protected = ProtectedResources::default();
+        assert!(protected.is_protected(&gvk("", "ConfigMap"), "cfg", Some("kube-system")));
+        assert!(protected.is_protected(&gvk("apps", "Deployment"), "app", Some("flux-system")));
+        assert!(!protected.is_protected(&gvk("apps", "Deployment"), "app", Some("default")));
+    }
+
+    #[test]
+    fn test_protected_namespace_objects() {
+        let protected = ProtectedResources::default();
+        assert!(protected.is_protected(&gvk("", "Namespace"), "kube-public", None));
+        assert!(!protected.is_protected(&gvk("", "Namespace"), "team-a", None));
+    }
+
+    #[test]
+    fn test_protected_cluster_rbac() {
+        let protected = ProtectedResources::default();
+        let role = gvk(RBAC_GROUP, "ClusterRole");
+        assert!(protected.is_protected(&role, "flux-kcl-operator", None));
+        assert!(!protected.is_protected(&role, "team-a-reader", None));
+        assert!(!protected.is_protected(&gvk("", "ConfigMap"), "flux-kcl-operator", None));
+    }
+
+    #[test]
+    fn test_custom_protected_resources() {
+        let protected = ProtectedResources::new(vec!["prod".to_string()], vec![]);
+        assert!(protected.is_protected(&gvk("", "Secret"), "db", Some("prod")));
+        assert!(!protected.is_protected(&gvk("", "Secret"), "db", Some("kube-system")));
+    }
+
+    #[test]
+    fn test_allowed_namespaces_default_allows_all() {
+        let allowed = AllowedNamespaces::new("team-a", vec![], false);
+        assert!(allowed.is_allowed("team-a"));
+        assert!(allowed.is_allowed("team-b"));
+    }
+
+    #[test]
+    fn test_allowed_namespaces_list() {
+        let allowed = AllowedNamespaces::new(
+            "team-a",
+            vec!["team-a".to_string(), "team-a-jobs".to_string()],
+            false,
+        );
+        assert!(allowed.is_allowed("team-a-jobs"));
+        assert!(!allowed.is_allowed("kube-system"));
+    }
+
+    #[test]
+    fn test_allowed_namespaces_same_namespace_only() {
+        let allowed = AllowedNamespaces::new("team-a", vec!["team-b".to_string()], true);
+        assert!(!allowed.is_allowed("team-b"));
+        assert!(!allowed.is_allowed("team-a"));
+        assert!(AllowedNamespaces::new("team-a", vec![], true).is_allowed("team-a"));
+    }
+}
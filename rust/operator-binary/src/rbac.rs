@@ -0,0 +1,229 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+use flux_kcl_operator_crd::KclInstance;
+use k8s_openapi::api::rbac::v1::{ClusterRole, PolicyRule, Role};
+use kube::{
+    api::{ApiResource, DynamicObject, GroupVersionKind, ObjectMeta},
+    core::gvk::ParseGroupVersionError,
+    ResourceExt,
+};
+use snafu::{OptionExt, ResultExt, Snafu};
+use strum::{EnumDiscriminants, IntoStaticStr};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    engine,
+    render::{KclRenderer, Renderer},
+    utils::multidoc_deserialize,
+};
+
+/// Verbs the operator needs on the kinds it applies: `get` to tell whether an apply changed an
+/// object, `patch` for server-side apply, `create` for the `create-if-absent` strategy and
+/// `delete` for prune and cleanup. The `replace` strategy additionally needs `update`.
+pub const APPLY_VERBS: &[&str] = &["get", "create", "patch", "delete"];
+
+#[derive(Snafu, Debug, EnumDiscriminants)]
+#[strum_discriminants(derive(IntoStaticStr))]
+pub enum Error {
+    #[snafu(display("Failed to render the module at {}: {}", path, source))]
+    Render { path: String, source: engine::Error },
+
+    #[snafu(display("Failed to parse the rendered manifests: {}", source))]
+    ParseManifests { source: anyhow::Error },
+
+    #[snafu(display("Rendered object {} has no apiVersion or kind", name))]
+    MissingTypeMeta { name: String },
+
+    #[snafu(display("Rendered object {} has an invalid apiVersion: {}", name, source))]
+    InvalidApiVersion {
+        name: String,
+        source: ParseGroupVersionError,
+    },
+
+    #[snafu(display("Failed to serialize the RBAC: {}", source))]
+    SerializeRbac { source: serde_yaml::Error },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Renders the KCL module at `path` locally and generates the RBAC applying its objects, see
+/// `rbac_manifests`.
+///
+/// # Arguments
+/// * `path` - Directory of the KCL module
+/// * `name` - Name of the generated ClusterRole and Roles
+/// * `args` - Arguments of the module
+///
+/// # Returns
+/// The ClusterRole and Roles as a multi-document YAML stream
+pub async fn render_rbac(
+    path: &Path,
+    name: &str,
+    args: &HashMap<String, String>,
+) -> Result<String> {
+    let instance: KclInstance = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kcl.evrone.com/v1alpha1",
+        "kind": "KclInstance",
+        "metadata": { "name": name },
+        "spec": {
+            "sourceRef": { "kind": "GitRepository", "name": name },
+            "path": ".",
+        },
+    }))
+    .expect("valid instance");
+    let manifests = KclRenderer::default()
+        .render(&instance, path, path, args, &CancellationToken::new())
+        .await
+        .context(RenderSnafu {
+            path: path.display().to_string(),
+        })?;
+    let objects = multidoc_deserialize(&manifests).context(ParseManifestsSnafu)?;
+    rbac_manifests(&objects, name)
+}
+
+/// Generates the least-privilege RBAC applying `objects`: a Role per namespace granting
+/// `APPLY_VERBS` on the resources of the objects in that namespace, and a ClusterRole for the
+/// objects without a namespace, e.g. cluster-scoped ones or those defaulting to the namespace
+/// of the instance.
+///
+/// Resources are derived from the kinds without discovery, so the plurals of irregular kinds
+/// may need fixing.
+///
+/// # Returns
+/// The ClusterRole and Roles as a multi-document YAML stream, with one rule per API group
+pub fn rbac_manifests(objects: &[DynamicObject], name: &str) -> Result<String> {
+    // Resources by API group, by namespace
+    let mut resources: BTreeMap<Option<String>, BTreeMap<String, BTreeSet<String>>> =
+        BTreeMap::new();
+    for object in objects {
+        let types = object.types.as_ref().context(MissingTypeMetaSnafu {
+            name: object.name_any(),
+        })?;
+        let gvk = GroupVersionKind::try_from(types).context(InvalidApiVersionSnafu {
+            name: object.name_any(),
+        })?;
+        let resource = ApiResource::from_gvk(&gvk);
+        resources
+            .entry(object.metadata.namespace.clone())
+            .or_default()
+            .entry(resource.group)
+            .or_default()
+            .insert(resource.plural);
+    }
+
+    let rules = |groups: BTreeMap<String, BTreeSet<String>>| {
+        groups
+            .into_iter()
+            .map(|(group, resources)| PolicyRule {
+                api_groups: Some(vec![group]),
+                resources: Some(resources.into_iter().collect()),
+                verbs: APPLY_VERBS.iter().map(|verb| verb.to_string()).collect(),
+                ..PolicyRule::default()
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut documents = Vec::new();
+    for (namespace, groups) in resources {
+        let metadata = ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: namespace.clone(),
+            ..ObjectMeta::default()
+        };
+        let document = match namespace {
+            None => serde_yaml::to_string(&ClusterRole {
+                metadata,
+                rules: Some(rules(groups)),
+                ..ClusterRole::default()
+            }),
+            Some(_) => serde_yaml::to_string(&Role {
+                metadata,
+                rules: Some(rules(groups)),
+            }),
+        };
+        documents.push(document.context(SerializeRbacSnafu)?);
+    }
+    Ok(documents.join("---\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render_rbac() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/fixtures/rbac");
+        let rbac = render_rbac(&path, "app-applier", &HashMap::new())
+            .await
+            .unwrap();
+
+        let documents: Vec<serde_yaml::Value> = serde_yaml::Deserializer::from_str(&rbac)
+            .map(|document| serde::Deserialize::deserialize(document).unwrap())
+            .collect();
+        assert_eq!(documents.len(), 2);
+
+        // Cluster-scoped objects first
+        let cluster_role: ClusterRole = serde_yaml::from_value(documents[0].clone()).unwrap();
+        assert_eq!(cluster_role.metadata.name.as_deref(), Some("app-applier"));
+        let rules = cluster_role.rules.unwrap();
+        let summary: Vec<_> = rules
+            .iter()
+            .map(|rule| {
+                (
+                    rule.api_groups.clone().unwrap(),
+                    rule.resources.clone().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (vec![String::new()], vec!["namespaces".to_string()]),
+                (
+                    vec!["networking.k8s.io".to_string()],
+                    vec!["ingressclasses".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(rules[0].verbs, ["get", "create", "patch", "delete"]);
+
+        // Then a Role per namespace, each resource listed once
+        let role: Role = serde_yaml::from_value(documents[1].clone()).unwrap();
+        assert_eq!(role.metadata.namespace.as_deref(), Some("app"));
+        let summary: Vec<_> = role
+            .rules
+            .unwrap()
+            .iter()
+            .map(|rule| {
+                (
+                    rule.api_groups.clone().unwrap(),
+                    rule.resources.clone().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    vec![String::new()],
+                    vec!["configmaps".to_string(), "services".to_string()]
+                ),
+                (vec!["apps".to_string()], vec!["deployments".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rbac_manifests_requires_type_meta() {
+        let object: DynamicObject = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "app" },
+        }))
+        .unwrap();
+        assert!(matches!(
+            rbac_manifests(&[object], "app"),
+            Err(Error::MissingTypeMeta { name }) if name == "app"
+        ));
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use flux_kcl_operator_crd::{ArgumentType, KclInstance};
+use kcl_client::{ModClient, RegistryCredentials, RegistryLimiter};
+use snafu::{ensure, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{info_span, Instrument};
+
+use crate::engine::{Error, InvalidModuleSnafu, KclClientActionsSnafu, USER_AGENT};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+pub const KCL_RENDERER: &str = "kcl";
+
+/// Renders the manifests of an instance from its downloaded source, KCL by default.
+///
+/// Renderers are registered with `Engine::with_renderer`, e.g. to render the same source with
+/// another tool, or to stub rendering in tests. Errors of other renderers than KCL are expected
+/// to be reported as `Error::RenderFailed`.
+#[async_trait]
+pub trait Renderer: Send + Sync {
+    /// Name of the renderer, used in errors and logs.
+    fn name(&self) -> &str;
+
+    /// Renders the manifests of `instance` as a multi-document YAML stream.
+    ///
+    /// # Arguments
+    /// * `instance` - KclInstance custom resource containing the render configuration
+    /// * `module_dir` - Directory of the module to render, at the instance path in the source
+    /// * `source_dir` - Directory the source artefact was extracted to
+    /// * `args` - Arguments of the module
+    /// * `cancel` - Cancels the render if it did not start yet
+    async fn render(
+        &self,
+        instance: &KclInstance,
+        module_dir: &Path,
+        source_dir: &Path,
+        args: &HashMap<String, String>,
+        cancel: &CancellationToken,
+    ) -> Result<String>;
+}
+
+/// Built-in renderer running KCL modules, resolving their dependencies with `ModClient`.
+#[derive(Clone)]
+pub struct KclRenderer {
+    layer_media_types: Vec<String>,
+    oci_client: Arc<kcl_client::OciClient>,
+    registry_credentials: Arc<RegistryCredentials>,
+    registry_limiter: Arc<RegistryLimiter>,
+    oci_timeout: Duration,
+    /// Vendor home of the OCI module dependencies, the KCL default when unset.
+    oci_vendor_dir: Option<PathBuf>,
+    /// Base directory of the git module dependency clones, the OCI vendor home when unset.
+    git_vendor_dir: Option<PathBuf>,
+}
+
+impl Default for KclRenderer {
+    fn default() -> Self {
+        Self {
+            layer_media_types: kcl_client::DEFAULT_LAYER_MEDIA_TYPES
+                .iter()
+                .map(|media_type| media_type.to_string())
+                .collect(),
+            oci_client: kcl_client::new_oci_client(USER_AGENT),
+            registry_credentials: Arc::default(),
+            registry_limiter: Arc::default(),
+            oci_timeout: kcl_client::DEFAULT_OCI_TIMEOUT,
+            oci_vendor_dir: None,
+            git_vendor_dir: None,
+        }
+    }
+}
+
+impl KclRenderer {
+    /// Sets the expected media types of the layers of OCI module dependencies.
+    pub fn with_layer_media_types(mut self, media_types: Vec<String>) -> Self {
+        self.layer_media_types = media_types;
+        self
+    }
+
+    /// Sets the user agent of the requests to OCI registries. Defaults to `USER_AGENT`.
+    pub fn with_user_agent(mut self, user_agent: &'static str) -> Self {
+        self.oci_client = kcl_client::new_oci_client(user_agent);
+        self
+    }
+
+    /// Sets the credentials of the registries of OCI module dependencies.
+    pub fn with_registry_credentials(mut self, credentials: RegistryCredentials) -> Self {
+        self.registry_credentials = Arc::new(credentials);
+        self
+    }
+
+    /// Sets the number of concurrent pulls of OCI module dependencies from each registry host,
+    /// across all renders. Unlimited when 0, the default.
+    pub fn with_registry_concurrency(mut self, limit: usize) -> Self {
+        self.registry_limiter = Arc::new(RegistryLimiter::new(limit));
+        self
+    }
+
+    /// Sets the timeout of each pull of OCI module dependencies. Defaults to
+    /// `kcl_client::DEFAULT_OCI_TIMEOUT`.
+    pub fn with_oci_timeout(mut self, timeout: Duration) -> Self {
+        self.oci_timeout = timeout;
+        self
+    }
+
+    /// Sets the vendor home the OCI module dependencies are pulled to.
+    pub fn with_oci_vendor_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.oci_vendor_dir = dir;
+        self
+    }
+
+    /// Sets the base directory the git module dependencies are cloned to.
+    pub fn with_git_vendor_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.git_vendor_dir = dir;
+        self
+    }
+}
+
+#[async_trait]
+impl Renderer for KclRenderer {
+    fn name(&self) -> &str {
+        KCL_RENDERER
+    }
+
+    async fn render(
+        &self,
+        instance: &KclInstance,
+        module_dir: &Path,
+        source_dir: &Path,
+        args: &HashMap<String, String>,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        check_kcl_module(
+            module_dir,
+            &instance.spec.path,
+            &instance.spec.config.entries,
+        )?;
+
+        // Creates a new ModClient instance with the specified work directory path
+        let mut mod_client = ModClient::new_with_oci_client(module_dir, self.oci_client.clone())
+            .context(KclClientActionsSnafu)?;
+        mod_client.set_source_root(source_dir);
+        mod_client.set_cancellation_token(cancel.clone());
+        mod_client.set_layer_media_types(self.layer_media_types.clone());
+        mod_client.set_registry_credentials(self.registry_credentials.clone());
+        mod_client.set_registry_limiter(self.registry_limiter.clone());
+        mod_client.set_oci_timeout(self.oci_timeout);
+        if let Some(dir) = &self.oci_vendor_dir {
+            mod_client.set_vendor(dir);
+        }
+        if let Some(dir) = &self.git_vendor_dir {
+            mod_client.set_git_vendor(dir);
+        }
+        mod_client.set_external_packages(instance.spec.config.external_packages.clone());
+        mod_client.set_git_sparse_paths(instance.spec.config.git_sparse_paths.clone());
+        mod_client.set_entries(instance.spec.config.entries.clone());
+        mod_client.set_argument_types(
+            instance
+                .spec
+                .config
+                .argument_types
+                .iter()
+                .map(|(name, type_)| (name.clone(), kcl_argument_type(*type_)))
+                .collect(),
+        );
+        mod_client
+            .set_overrides(instance.spec.config.overrides.clone())
+            .context(KclClientActionsSnafu)?;
+        if let Some(filter) = &instance.spec.config.semver_filter {
+            mod_client
+                .set_semver_filter(filter)
+                .context(KclClientActionsSnafu)?;
+        }
+
+        // Resolves all dependencies for the KCL configuration
+        let metadata = mod_client
+            .resolve_all_deps(true)
+            .instrument(info_span!("resolve_dependencies"))
+            .await
+            .context(KclClientActionsSnafu)?;
+
+        // Executes the KCL compiler with resolved metadata and instance arguments
+        let manifests = mod_client
+            .run(metadata, args)
+            .instrument(info_span!("kcl_exec"))
+            .await
+            .context(KclClientActionsSnafu)?;
+        Ok(manifests)
+    }
+}
+
+/// Checks that the module path of an instance is a directory of the source, for renderers
+/// to get a precise error rather than a generic one when `path` points at the wrong place.
+///
+/// # Arguments
+/// * `module_dir` - Directory of the module in the extracted source
+/// * `path` - Path of the module as written in the instance spec
+///
+/// # Returns
+/// `Error::InvalidModule` when the path does not exist or is not a directory
+pub(crate) fn check_module_dir(module_dir: &Path, path: &str) -> Result<()> {
+    ensure!(
+        module_dir.exists(),
+        InvalidModuleSnafu {
+            path,
+            reason: "the path does not exist"
+        }
+    );
+    ensure!(
+        module_dir.is_dir(),
+        InvalidModuleSnafu {
+            path,
+            reason: "the path is a file, not a directory"
+        }
+    );
+    Ok(())
+}
+
+/// Checks that a module directory holds a KCL module before running it: a `kcl.mod` file, and
+/// the entry files of the instance. Glob patterns among the entries are checked by `ModClient`.
+///
+/// # Arguments
+/// * `module_dir` - Directory of the module in the extracted source
+/// * `path` - Path of the module as written in the instance spec
+/// * `entries` - Entry files of the instance, relative to the module
+///
+/// # Returns
+/// `Error::InvalidModule` naming the first missing file
+fn check_kcl_module(module_dir: &Path, path: &str, entries: &[String]) -> Result<()> {
+    ensure!(
+        module_dir.join("kcl.mod").is_file(),
+        InvalidModuleSnafu {
+            path,
+            reason: "the directory has no kcl.mod"
+        }
+    );
+    if let Some(entry) = entries
+        .iter()
+        .filter(|entry| !entry.contains(['*', '?', '[']))
+        .find(|entry| !module_dir.join(entry).is_file())
+    {
+        return InvalidModuleSnafu {
+            path,
+            reason: format!("the entry file {entry} does not exist"),
+        }
+        .fail();
+    }
+    Ok(())
+}
+
+/// Maps the argument type of the CRD to the one of the KCL client.
+fn kcl_argument_type(type_: ArgumentType) -> kcl_client::ArgumentType {
+    match type_ {
+        ArgumentType::String => kcl_client::ArgumentType::String,
+        ArgumentType::Int => kcl_client::ArgumentType::Int,
+        ArgumentType::Float => kcl_client::ArgumentType::Float,
+        ArgumentType::Bool => kcl_client::ArgumentType::Bool,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(res: Result<()>) -> String {
+        match res {
+            Err(Error::InvalidModule { reason, .. }) => reason,
+            res => panic!("expected InvalidModule, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_check_module() {
+        let source_dir = std::env::temp_dir().join(format!("check-module-{}", std::process::id()));
+        std::fs::create_dir_all(source_dir.join("app")).unwrap();
+        std::fs::write(source_dir.join("main.k"), "a = 1").unwrap();
+        let module_dir = source_dir.join("app");
+
+        assert_eq!(
+            reason(check_module_dir(&source_dir.join("missing"), "./missing")),
+            "the path does not exist"
+        );
+        assert_eq!(
+            reason(check_module_dir(&source_dir.join("main.k"), "./main.k")),
+            "the path is a file, not a directory"
+        );
+        check_module_dir(&module_dir, "./app").unwrap();
+
+        assert_eq!(
+            reason(check_kcl_module(&module_dir, "./app", &[])),
+            "the directory has no kcl.mod"
+        );
+        std::fs::write(module_dir.join("kcl.mod"), "[package]").unwrap();
+        check_kcl_module(&module_dir, "./app", &[]).unwrap();
+        check_kcl_module(&module_dir, "./app", &["k8s/*.k".to_string()]).unwrap();
+        assert_eq!(
+            reason(check_kcl_module(
+                &module_dir,
+                "./app",
+                &["main.k".to_string()]
+            )),
+            "the entry file main.k does not exist"
+        );
+
+        std::fs::remove_dir_all(source_dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use flux_kcl_operator_crd::KclInstance;
+use kube::runtime::reflector::ObjectRef;
+
+/// Time an instance may stay unreconciled past its interval before it is counted as overdue,
+/// as requeues and the queue add some delay to every reconciliation.
+pub const OVERDUE_GRACE: Duration = Duration::from_secs(30);
+
+/// Last reconciliations of the instances, to tell whether the operator falls behind.
+///
+/// Only the instances reconciled since the operator started are tracked.
+#[derive(Default)]
+pub struct ReconcileTracker {
+    /// Time of the last reconciliation and interval, by instance.
+    last: Mutex<HashMap<ObjectRef<KclInstance>, (Instant, Duration)>>,
+}
+
+/// Staleness of the reconciliations at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Staleness {
+    /// Number of tracked instances.
+    pub instances: usize,
+    /// Time since the oldest last reconciliation, `None` when no instance is tracked.
+    pub oldest: Option<Duration>,
+    /// Number of instances not reconciled within their interval (plus `OVERDUE_GRACE`).
+    pub overdue: usize,
+}
+
+impl ReconcileTracker {
+    /// Records a reconciliation of `instance`, successful or not, at `now`.
+    pub fn record(&self, instance: &KclInstance, now: Instant) {
+        self.last
+            .lock()
+            .unwrap()
+            .insert(ObjectRef::from_obj(instance), (now, instance.interval()));
+    }
+
+    /// Stops tracking `instance`, once it is deleted.
+    pub fn forget(&self, instance: &KclInstance) {
+        self.last
+            .lock()
+            .unwrap()
+            .remove(&ObjectRef::from_obj(instance));
+    }
+
+    /// Scans the tracked instances for their staleness at `now`.
+    pub fn staleness(&self, now: Instant) -> Staleness {
+        let last = self.last.lock().unwrap();
+        let ages = last
+            .values()
+            .map(|(time, interval)| (now.saturating_duration_since(*time), *interval));
+        Staleness {
+            instances: last.len(),
+            oldest: ages.clone().map(|(age, _)| age).max(),
+            overdue: ages
+                .filter(|(age, interval)| *age > *interval + OVERDUE_GRACE)
+                .count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(name: &str, interval: &str) -> KclInstance {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "kcl.evrone.com/v1alpha1",
+            "kind": "KclInstance",
+            "metadata": { "name": name, "namespace": "default" },
+            "spec": {
+                "interval": interval,
+                "path": "./",
+                "sourceRef": { "kind": "GitRepository", "name": "app" },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_staleness() {
+        let tracker = ReconcileTracker::default();
+        let start = Instant::now();
+        assert_eq!(
+            tracker.staleness(start),
+            Staleness {
+                instances: 0,
+                oldest: None,
+                overdue: 0,
+            }
+        );
+
+        let slow = instance("slow", "10m");
+        tracker.record(&instance("fast", "1m"), start);
+        tracker.record(&slow, start + Duration::from_secs(60));
+
+        let now = start + Duration::from_secs(180);
+        assert_eq!(
+            tracker.staleness(now),
+            Staleness {
+                instances: 2,
+                oldest: Some(Duration::from_secs(180)),
+                overdue: 1,
+            }
+        );
+
+        tracker.forget(&slow);
+        tracker.record(&instance("fast", "1m"), now);
+        assert_eq!(tracker.staleness(now).overdue, 0);
+        assert_eq!(tracker.staleness(now).instances, 1);
+    }
+}
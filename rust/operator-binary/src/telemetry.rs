@@ -0,0 +1,40 @@
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Service name of the exported traces.
+const SERVICE_NAME: &str = "flux-kcl-operator";
+
+/// Builds a tracing layer exporting the spans to an OTLP collector over gRPC.
+///
+/// The tracer provider is registered globally, so it can be flushed with
+/// `opentelemetry::global::shutdown_tracer_provider` on exit.
+///
+/// # Arguments
+/// * `endpoint` - OTLP gRPC endpoint, e.g. `http://otel-collector:4317`
+pub fn layer<S>(
+    endpoint: &str,
+) -> Result<OpenTelemetryLayer<S, trace::Tracer>, opentelemetry::trace::TraceError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::Config::default()
+                .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)])),
+        )
+        .install_batch(runtime::Tokio)?;
+    let tracer = provider.tracer(SERVICE_NAME);
+    opentelemetry::global::set_tracer_provider(provider);
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
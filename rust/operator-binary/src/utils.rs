@@ -1,10 +1,134 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use kube::{
-    api::{ApiResource, DynamicObject, ObjectMeta},
-    discovery::{ApiCapabilities, Scope},
-    Api, Client,
+    api::{ApiResource, DynamicObject, GroupVersionKind, ObjectMeta},
+    discovery::{self, ApiCapabilities, Scope},
+    Api, Client, Discovery,
 };
+use tracing::{info, warn};
+
+use flux_kcl_operator_crd::{PRUNE_ANNOTATION, PRUNE_DISABLED};
+
+use crate::engine::OPERATOR_MANAGER;
+
+/// API discovery shared by the reconciliations, refreshed in the background.
+///
+/// The full discovery of large clusters is slow, so it doesn't block startup: kinds missing
+/// from the cache (not discovered yet, or CRDs installed since the last refresh) are resolved
+/// one by one and cached until the next refresh.
+pub struct CachedDiscovery {
+    client: Client,
+    ttl: Duration,
+    discovery: Arc<RwLock<Discovery>>,
+    resolved: RwLock<HashMap<GroupVersionKind, (ApiResource, ApiCapabilities)>>,
+}
+
+impl CachedDiscovery {
+    /// Constructs an empty discovery cache, see `refresh_periodically`.
+    ///
+    /// # Arguments:
+    /// - `client`: Kubernetes client used to discover the APIs.
+    /// - `ttl`: Interval between two full refreshes.
+    pub fn new(client: Client, ttl: Duration) -> Self {
+        Self {
+            discovery: Arc::new(RwLock::new(Discovery::new(client.clone()))),
+            client,
+            ttl,
+            resolved: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Resolves the API resource and capabilities of `gvk`, discovering it on a cache miss.
+    pub async fn resolve_gvk(
+        &self,
+        gvk: &GroupVersionKind,
+    ) -> Option<(ApiResource, ApiCapabilities)> {
+        self.try_resolve_gvk(gvk).await.ok()
+    }
+
+    /// Resolves `gvk` like `resolve_gvk`, telling why it could not be resolved.
+    pub async fn try_resolve_gvk(
+        &self,
+        gvk: &GroupVersionKind,
+    ) -> Result<(ApiResource, ApiCapabilities), Unresolved> {
+        if let Some(resolved) = self.cached(gvk) {
+            return Ok(resolved);
+        }
+        match discovery::pinned_kind(&self.client, gvk).await {
+            Ok(resolved) => {
+                self.resolved
+                    .write()
+                    .unwrap()
+                    .insert(gvk.clone(), resolved.clone());
+                Ok(resolved)
+            }
+            Err(kube::Error::Api(response)) if response.code == 403 => {
+                warn!("Discovery of {:?} is forbidden: {}", gvk, response.message);
+                Err(Unresolved::Forbidden)
+            }
+            Err(e) => {
+                warn!("Failed to discover {:?}: {}", gvk, e);
+                Err(Unresolved::Unknown)
+            }
+        }
+    }
+
+    /// API versions of the groups serving `kind` in their preferred version, according to the
+    /// last full discovery, e.g. `["networking.k8s.io/v1"]` for `Ingress`.
+    pub fn api_versions_of(&self, kind: &str) -> Vec<String> {
+        let discovery = self.discovery.read().unwrap();
+        let mut api_versions: Vec<String> = discovery
+            .groups()
+            .flat_map(|group| group.recommended_resources())
+            .filter(|(ar, _)| ar.kind == kind)
+            .map(|(ar, _)| ar.api_version)
+            .collect();
+        api_versions.sort();
+        api_versions.dedup();
+        api_versions
+    }
+
+    fn cached(&self, gvk: &GroupVersionKind) -> Option<(ApiResource, ApiCapabilities)> {
+        if let Some(resolved) = self.discovery.read().unwrap().resolve_gvk(gvk) {
+            return Some(resolved);
+        }
+        self.resolved.read().unwrap().get(gvk).cloned()
+    }
+
+    /// Runs a full discovery and replaces the cache with it.
+    pub async fn refresh(&self) -> Result<(), kube::Error> {
+        let discovery = Discovery::new(self.client.clone()).run().await?;
+        *self.discovery.write().unwrap() = discovery;
+        self.resolved.write().unwrap().clear();
+        Ok(())
+    }
+
+    /// Refreshes the cache right away and then after every `ttl`, keeping the previous
+    /// discovery when a refresh fails. Runs forever, so spawn it.
+    pub async fn refresh_periodically(self: Arc<Self>) {
+        loop {
+            match self.refresh().await {
+                Ok(()) => info!("Refreshed API discovery"),
+                Err(e) => warn!("Failed to refresh API discovery: {}", e),
+            }
+            tokio::time::sleep(self.ttl).await;
+        }
+    }
+}
+
+/// Why a kind could not be resolved, see `CachedDiscovery::try_resolve_gvk`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Unresolved {
+    /// The API server refused to list the group version of the kind, as the RBAC of the
+    /// operator doesn't allow it.
+    Forbidden,
+    /// The kind is not served, or its discovery failed otherwise.
+    Unknown,
+}
 
 pub fn dynamic_api(
     ar: ApiResource,
@@ -22,35 +146,401 @@ pub fn dynamic_api(
     }
 }
 
+/// Parses the objects of a multi-document YAML stream, failing on the first document that is
+/// not valid YAML or not an object.
 pub fn multidoc_deserialize(data: &str) -> anyhow::Result<Vec<DynamicObject>> {
+    multidoc_iter(data).collect()
+}
+
+/// Parses the objects of a multi-document YAML stream on a blocking thread, sending each one as
+/// soon as it is parsed, so that large streams are never held in memory as parsed objects.
+/// At most `bound` parsed objects wait to be received, and the parsing stops when the receiver
+/// is dropped or after the first error, see `multidoc_iter`.
+pub fn multidoc_channel(
+    data: Arc<str>,
+    bound: usize,
+) -> tokio::sync::mpsc::Receiver<anyhow::Result<DynamicObject>> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(bound);
+    tokio::task::spawn_blocking(move || {
+        for object in multidoc_iter(&data) {
+            if sender.blocking_send(object).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Parses the documents of a multi-document YAML stream one at a time, see
+/// `multidoc_deserialize`.
+///
+/// Empty documents are skipped, e.g. from stray `---` separators, and a leading byte order
+/// mark is ignored. Errors name the document they occurred in, counting from 1, and end the
+/// iteration, as the stream can't be parsed past a syntax error.
+fn multidoc_iter(data: &str) -> impl Iterator<Item = anyhow::Result<DynamicObject>> + '_ {
     use serde::Deserialize;
-    let mut docs = vec![];
-    for de in serde_yaml::Deserializer::from_str(data) {
-        docs.push(serde_yaml::from_value(serde_yaml::Value::deserialize(de)?)?);
+
+    let data = data.strip_prefix('\u{feff}').unwrap_or(data);
+    serde_yaml::Deserializer::from_str(data)
+        .enumerate()
+        .scan(false, |failed, (index, de)| {
+            if *failed {
+                return None;
+            }
+            let res = serde_yaml::Value::deserialize(de)
+                .and_then(|value| match value {
+                    // Empty output and empty documents render as null
+                    serde_yaml::Value::Null => Ok(None),
+                    value => serde_yaml::from_value(value).map(Some),
+                })
+                .map_err(|e| anyhow::anyhow!("document {}: {}", index + 1, e));
+            *failed = res.is_err();
+            Some(res)
+        })
+        .filter_map(Result::transpose)
+}
+
+/// Default key of the label marking the objects managed by the operator.
+pub const MANAGED_BY_LABEL_KEY: &str = "app.kubernetes.io/managed-by";
+
+/// Label marking the objects managed by the operator. Prune and cleanup only delete the
+/// objects carrying it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManagedByLabel {
+    pub key: String,
+    pub value: String,
+}
+
+impl Default for ManagedByLabel {
+    /// `app.kubernetes.io/managed-by: kcl-instance-controller`
+    fn default() -> Self {
+        Self {
+            key: MANAGED_BY_LABEL_KEY.to_string(),
+            value: OPERATOR_MANAGER.to_string(),
+        }
     }
-    Ok(docs)
 }
 
 pub fn patch_labels(
     labels: Option<BTreeMap<String, String>>,
-    manager: &str,
+    managed_by: &ManagedByLabel,
 ) -> Option<BTreeMap<String, String>> {
-    if let Some(labels) = labels {
-        let patch = BTreeMap::from([(
-            "app.kubernetes.io/managed-by".to_string(),
-            manager.to_string(),
-        )]);
-        Some(labels.into_iter().chain(patch).collect())
-    } else {
-        patch_labels(Some(BTreeMap::new()), manager)
+    let patch = BTreeMap::from([(managed_by.key.clone(), managed_by.value.clone())]);
+    Some(
+        labels
+            .unwrap_or_default()
+            .into_iter()
+            .chain(patch)
+            .collect(),
+    )
+}
+
+pub fn is_managed_by(managed_by: &ManagedByLabel, meta: ObjectMeta) -> bool {
+    meta.labels
+        .and_then(|labels| labels.get(&managed_by.key).cloned())
+        .is_some_and(|value| value == managed_by.value)
+}
+
+/// Whether the object has pruning disabled by `PRUNE_ANNOTATION`, so the operator never
+/// deletes it.
+pub fn is_prune_disabled(meta: &ObjectMeta) -> bool {
+    meta.annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(PRUNE_ANNOTATION))
+        .is_some_and(|value| value == PRUNE_DISABLED)
+}
+
+/// Validates a Kubernetes label selector string, e.g. `app=web,tier in (frontend,backend),!canary`.
+///
+/// Supports equality (`=`, `==`, `!=`), set (`in`, `notin`) and existence (`key`, `!key`)
+/// requirements separated by commas.
+pub fn is_valid_label_selector(selector: &str) -> bool {
+    split_top_level(selector)
+        .iter()
+        .all(|requirement| is_valid_requirement(requirement.trim()))
+}
+
+fn split_top_level(selector: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (idx, ch) in selector.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&selector[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&selector[start..]);
+    parts
+}
+
+fn is_valid_requirement(requirement: &str) -> bool {
+    if let Some(key) = requirement.strip_prefix('!') {
+        return is_valid_label_key(key.trim());
+    }
+
+    for op in [" notin ", " in "] {
+        if let Some((key, values)) = requirement.split_once(op) {
+            let values = values.trim();
+            return is_valid_label_key(key.trim())
+                && values.starts_with('(')
+                && values.ends_with(')')
+                && values[1..values.len() - 1]
+                    .split(',')
+                    .all(|value| is_valid_label_value(value.trim()));
+        }
+    }
+
+    for op in ["!=", "==", "="] {
+        if let Some((key, value)) = requirement.split_once(op) {
+            return is_valid_label_key(key.trim()) && is_valid_label_value(value.trim());
+        }
+    }
+
+    is_valid_label_key(requirement)
+}
+
+pub(crate) fn is_valid_label_key(key: &str) -> bool {
+    let name = match key.split_once('/') {
+        Some((prefix, name)) => {
+            if prefix.is_empty()
+                || prefix.len() > 253
+                || !prefix
+                    .split('.')
+                    .all(|label| is_valid_label_name(label, &['-']))
+            {
+                return false;
+            }
+            name
+        }
+        None => key,
+    };
+    !name.is_empty() && is_valid_label_value(name)
+}
+
+pub(crate) fn is_valid_label_value(value: &str) -> bool {
+    value.is_empty() || is_valid_label_name(value, &['-', '_', '.'])
+}
+
+fn is_valid_label_name(name: &str, extra: &[char]) -> bool {
+    let alnum = |c: char| c.is_ascii_alphanumeric();
+    !name.is_empty()
+        && name.len() <= 63
+        && name.starts_with(alnum)
+        && name.ends_with(alnum)
+        && name.chars().all(|c| alnum(c) || extra.contains(&c))
+}
+
+/// Validates a DNS-1123 label, the format of namespace names.
+pub(crate) fn is_valid_dns_label(name: &str) -> bool {
+    is_valid_label_name(name, &['-']) && !name.chars().any(|c| c.is_ascii_uppercase())
+}
+
+/// Removes the fields at `paths`, in dot notation (e.g. `spec.clusterIP`), from `value`.
+///
+/// Numeric segments index into lists and `*` matches every item of a list. Paths that don't
+/// exist in `value` are ignored.
+pub fn strip_fields(value: &mut serde_json::Value, paths: &[String]) {
+    for path in paths {
+        strip_field(value, &path.split('.').collect::<Vec<_>>());
+    }
+}
+
+fn strip_field(value: &mut serde_json::Value, segments: &[&str]) {
+    use serde_json::Value;
+
+    match (value, segments) {
+        (Value::Object(map), [field]) => {
+            map.remove(*field);
+        }
+        (Value::Object(map), [segment, rest @ ..]) => {
+            if let Some(value) = map.get_mut(*segment) {
+                strip_field(value, rest);
+            }
+        }
+        (Value::Array(items), ["*", rest @ ..]) if !rest.is_empty() => {
+            for item in items {
+                strip_field(item, rest);
+            }
+        }
+        (Value::Array(items), [index, rest @ ..]) if !rest.is_empty() => {
+            if let Some(item) = index.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                strip_field(item, rest);
+            }
+        }
+        _ => {}
     }
 }
 
-pub fn is_managed_by(operator_name: &str, meta: ObjectMeta) -> bool {
-    if let Some(labels) = meta.labels {
-        if let Some(managed_by) = labels.get("app.kubernetes.io/managed-by") {
-            return managed_by == operator_name;
+#[cfg(test)]
+mod tests {
+    use kube::ResourceExt;
+
+    use super::*;
+
+    const CONFIG_MAP: &str = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: app\n";
+
+    #[test]
+    fn test_multidoc_deserialize_separators() {
+        for manifests in [
+            String::new(),
+            "---\n".to_string(),
+            "---\n---\n...\n---\n".to_string(),
+            "# only a comment\n".to_string(),
+        ] {
+            assert!(
+                multidoc_deserialize(&manifests).unwrap().is_empty(),
+                "{manifests:?}"
+            );
+        }
+
+        for manifests in [
+            format!("{CONFIG_MAP}---\n{CONFIG_MAP}"),
+            format!("---\n---\n{CONFIG_MAP}---\n---\n{CONFIG_MAP}---\n"),
+            format!("{CONFIG_MAP}...\n---\n{CONFIG_MAP}...\n"),
+            format!("\u{feff}{CONFIG_MAP}---\n{CONFIG_MAP}"),
+            format!("{CONFIG_MAP}---\n\u{feff}{CONFIG_MAP}"),
+            format!("{CONFIG_MAP}---\n{CONFIG_MAP}").replace('\n', "\r\n"),
+        ] {
+            let objects = multidoc_deserialize(&manifests).unwrap();
+            assert_eq!(objects.len(), 2, "{manifests:?}");
+            assert!(objects.iter().all(|object| object.name_any() == "app"));
+        }
+    }
+
+    #[test]
+    fn test_multidoc_deserialize_errors() {
+        for (manifests, document) in [
+            (format!("{CONFIG_MAP}---\nkind: [unterminated\n"), 2),
+            (
+                format!("{CONFIG_MAP}---\n---\n{CONFIG_MAP}kind: Secret\n"),
+                3,
+            ),
+            (format!("- {CONFIG_MAP}"), 1),
+            ("just a string\n".to_string(), 1),
+        ] {
+            let err = multidoc_deserialize(&manifests).unwrap_err();
+            assert!(
+                err.to_string().starts_with(&format!("document {document}")),
+                "{manifests:?}: {err:#}"
+            );
+        }
+
+        // The iteration ends at the first error
+        let manifests = format!("{CONFIG_MAP}---\nkind: [\n---\n{CONFIG_MAP}");
+        let results: Vec<_> = multidoc_iter(&manifests).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multidoc_channel() {
+        let manifests: Arc<str> = format!("{CONFIG_MAP}---\n{CONFIG_MAP}---\nkind: [\n").into();
+        let mut receiver = multidoc_channel(manifests, 1);
+        assert!(receiver.recv().await.unwrap().is_ok());
+        assert!(receiver.recv().await.unwrap().is_ok());
+        let err = receiver.recv().await.unwrap().unwrap_err();
+        assert!(err.to_string().starts_with("document 3"), "{err:#}");
+        // Nothing is parsed past the error
+        assert!(receiver.recv().await.is_none());
+    }
+
+    /// Generates YAML streams from valid, broken and odd fragments: separators in any position,
+    /// byte order marks, CRLF line endings, duplicate keys and truncated documents.
+    fn arbitrary_manifests(rng: &mut impl rand::Rng) -> String {
+        use rand::seq::SliceRandom;
+
+        const FRAGMENTS: &[&str] = &[
+            CONFIG_MAP,
+            "apiVersion: v1\nkind: Secret\nmetadata:\n  name: app\nstringData:\n  key: value\n",
+            "---\n",
+            "--- \n",
+            "...\n",
+            "---\n---\n",
+            "\n",
+            "# comment\n",
+            "\u{feff}",
+            "metadata:\n  name: a\nmetadata:\n  name: b\n",
+            "kind: [\n",
+            "kind: {a: \n",
+            "key: 'unterminated\n",
+            "  indented: badly\n",
+            "- item\n",
+            "&anchor {a: *anchor}\n",
+            "*undefined\n",
+            "!!binary notbase64\n",
+            "\t tab: value\n",
+            "{}\n",
+            "null\n",
+            "~\n",
+            "\"\\ud800\"\n",
+            "? complex\n: key\n",
+        ];
+        let mut manifests: String = (0..rng.gen_range(0..8))
+            .map(|_| *FRAGMENTS.choose(rng).unwrap())
+            .collect();
+        if rng.gen_bool(0.2) {
+            manifests = manifests.replace('\n', "\r\n");
         }
+        if rng.gen_bool(0.2) && !manifests.is_empty() {
+            let mut end = rng.gen_range(0..manifests.len());
+            while !manifests.is_char_boundary(end) {
+                end -= 1;
+            }
+            manifests.truncate(end);
+        }
+        manifests
+    }
+
+    #[test]
+    fn test_multidoc_deserialize_arbitrary() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1183);
+        for _ in 0..5_000 {
+            let manifests = arbitrary_manifests(&mut rng);
+            // Either valid objects or an error naming the document, never a panic
+            match multidoc_deserialize(&manifests) {
+                Ok(objects) => {
+                    let documents =
+                        manifests.matches("---").count() + manifests.matches("...").count() + 1;
+                    assert!(objects.len() <= documents, "{manifests:?}");
+                }
+                Err(err) => assert!(
+                    err.to_string().starts_with("document "),
+                    "{manifests:?}: {err:#}"
+                ),
+            }
+            // And the lazy iteration stops after an error
+            let errors = multidoc_iter(&manifests).filter(Result::is_err).count();
+            assert!(errors <= 1, "{manifests:?}");
+        }
+    }
+
+    #[test]
+    fn test_multidoc_iter_is_lazy() {
+        let count = 20_000;
+        let mut manifests: String = (0..count)
+            .map(|i| format!("apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: cm-{i}\n---\n"))
+            .collect();
+        manifests.push_str("kind: [unterminated\n");
+
+        // Documents are parsed as they are consumed, the broken last one is never reached
+        let first: Vec<_> = multidoc_iter(&manifests)
+            .take(2)
+            .map(|object| object.unwrap().name_any())
+            .collect();
+        assert_eq!(first, ["cm-0", "cm-1"]);
+
+        let objects: Vec<_> = multidoc_iter(&manifests).take(count).collect();
+        assert_eq!(objects.len(), count);
+        assert!(objects.iter().all(|object| object.is_ok()));
+        assert!(multidoc_deserialize(&manifests).is_err());
     }
-    false
 }